@@ -0,0 +1,108 @@
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+/// Source of the current time.
+///
+/// Anything that depends on "now" (which quarter to report, run IDs in output
+/// names) reads it through a `Clock` instead of calling `Utc::now()`, so tests
+/// and replays can pin it with a `FixedClock`.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Today's date in `tz`, or in the machine's local timezone if none is configured
+    fn today(&self, tz: Option<Tz>) -> NaiveDate {
+        match tz {
+            Some(tz) => self.now().with_timezone(&tz).date_naive(),
+            None => self.now().with_timezone(&Local).date_naive(),
+        }
+    }
+}
+
+/// The real system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock stopped at one instant (a Stripe test clock's frozen time, a
+/// replayed run, a test)
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(DateTime<Utc>);
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        FixedClock(now)
+    }
+
+    /// A clock fixed at a Unix timestamp; `None` if it is out of range
+    pub fn from_timestamp(secs: i64) -> Option<Self> {
+        DateTime::from_timestamp(secs, 0).map(FixedClock)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+
+    /// Without a configured timezone a fixed instant is read in UTC, so the
+    /// result doesn't depend on the machine it runs on
+    fn today(&self, tz: Option<Tz>) -> NaiveDate {
+        match tz {
+            Some(tz) => self.0.with_timezone(&tz).date_naive(),
+            None => self.0.date_naive(),
+        }
+    }
+}
+
+/// A clock that reads one date as today in every timezone (`--as-of`), so a
+/// run reports the periods it would have on that date
+#[derive(Debug, Clone, Copy)]
+pub struct DateClock(NaiveDate);
+
+impl DateClock {
+    pub fn new(date: NaiveDate) -> Self {
+        DateClock(date)
+    }
+}
+
+impl Clock for DateClock {
+    /// Noon UTC on the date, for run IDs and timestamps
+    fn now(&self) -> DateTime<Utc> {
+        self.0.and_hms_opt(12, 0, 0).expect("valid time").and_utc()
+    }
+
+    fn today(&self, _tz: Option<Tz>) -> NaiveDate {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_clock_today() {
+        // 2026-01-01T03:00:00Z is still New Year's Eve in Chicago
+        let clock = FixedClock::from_timestamp(1767236400).unwrap();
+        assert_eq!(clock.today(None), NaiveDate::from_ymd_opt(2026, 1, 1).unwrap());
+        assert_eq!(
+            clock.today(Some(chrono_tz::America::Chicago)),
+            NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_date_clock_today_in_any_timezone() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+        let clock = DateClock::new(date);
+        assert_eq!(clock.today(None), date);
+        assert_eq!(clock.today(Some(chrono_tz::Pacific::Kiritimati)), date);
+        assert_eq!(clock.today(Some(chrono_tz::Pacific::Pago_Pago)), date);
+        assert_eq!(clock.now().timestamp(), 1767614400);
+    }
+}
@@ -0,0 +1,129 @@
+use super::Profile;
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variables tried, in order, when the profile names no key
+pub const FALLBACK_ENV_VARS: [&str; 2] = ["STRIPE_PROD_API_KEY", "STRIPE_API_KEY"];
+
+/// Where a Stripe API key is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// Stored in the config file (`api_key`)
+    Inline(String),
+    /// A file holding only the key (`api_key_file`, `--api-key-file`); `-`
+    /// reads it from stdin
+    File(PathBuf),
+    /// A command that prints the key (`api_key_cmd`, `--api-key-cmd`), e.g.
+    /// `op read op://Finance/Stripe/credential`
+    Command(String),
+    /// An environment variable (`api_key_env`, else `FALLBACK_ENV_VARS`)
+    Env(String),
+}
+
+impl KeySource {
+    /// The key sources a profile names, first wins: `api_key`,
+    /// `api_key_file`, `api_key_cmd`, then `api_key_env`
+    pub fn for_profile(profile: &Profile) -> Option<KeySource> {
+        if let Some(key) = &profile.api_key {
+            Some(KeySource::Inline(key.clone()))
+        } else if let Some(path) = &profile.api_key_file {
+            Some(KeySource::File(path.clone()))
+        } else if let Some(command) = &profile.api_key_cmd {
+            Some(KeySource::Command(command.clone()))
+        } else {
+            profile.api_key_env.as_ref().map(|var| KeySource::Env(var.clone()))
+        }
+    }
+
+    /// Where the profile's key comes from: its own source, else the first
+    /// fallback variable that is set
+    pub fn resolve(profile: &Profile) -> Result<KeySource> {
+        if let Some(source) = Self::for_profile(profile) {
+            return Ok(source);
+        }
+        FALLBACK_ENV_VARS
+            .iter()
+            .find(|var| std::env::var_os(var).is_some())
+            .map(|var| KeySource::Env(var.to_string()))
+            .ok_or_else(|| anyhow!("Neither STRIPE_PROD_API_KEY nor STRIPE_API_KEY environment variable is set"))
+    }
+
+    /// Read the key, trimmed of surrounding whitespace
+    pub fn read(&self) -> Result<String> {
+        let key = match self {
+            KeySource::Inline(key) => key.clone(),
+            KeySource::File(path) if path == Path::new("-") => read_stdin()?,
+            KeySource::File(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read API key file {}", path.display()))?,
+            KeySource::Command(command) => run_command(command)?,
+            KeySource::Env(var) => std::env::var(var).map_err(|_| anyhow!("Environment variable {} is not set", var))?,
+        };
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            bail!("The API key from {} is empty", self);
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Inline(_) => write!(f, "the profile's api_key"),
+            KeySource::File(path) if path == Path::new("-") => write!(f, "stdin"),
+            KeySource::File(path) => write!(f, "{}", path.display()),
+            KeySource::Command(command) => write!(f, "`{}`", command),
+            KeySource::Env(var) => write!(f, "{}", var),
+        }
+    }
+}
+
+/// Stdin can only be read once, and some commands build more than one client
+fn read_stdin() -> Result<String> {
+    static KEY: OnceLock<String> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(key.clone());
+    }
+    let mut key = String::new();
+    std::io::stdin().read_to_string(&mut key).context("Failed to read the API key from stdin")?;
+    Ok(KEY.get_or_init(|| key).clone())
+}
+
+/// Run `command` through the shell and take what it prints as the key
+fn run_command(command: &str) -> Result<String> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let output = std::process::Command::new(shell)
+        .args([flag, command])
+        .stdin(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run API key command `{}`", command))?;
+    if !output.status.success() {
+        bail!("API key command `{}` failed ({})", command, output.status);
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("API key command `{}` printed invalid UTF-8", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_sources() {
+        let path = std::env::temp_dir().join(format!("stripe-{}.key", std::process::id()));
+        std::fs::write(&path, "sk_test_file\n").unwrap();
+
+        let profile = Profile { api_key_file: Some(path.clone()), api_key_env: Some("UNUSED".to_string()), ..Default::default() };
+        let source = KeySource::resolve(&profile).unwrap();
+        assert_eq!(source, KeySource::File(path.clone()));
+        assert_eq!(source.read().unwrap(), "sk_test_file");
+        std::fs::remove_file(&path).unwrap();
+        assert!(source.read().is_err());
+
+        let command = KeySource::Command("echo sk_test_cmd".to_string());
+        assert_eq!(command.read().unwrap(), "sk_test_cmd");
+        assert!(KeySource::Command("exit 3".to_string()).read().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::report::Period;
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// A period's return as submitted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filing {
+    /// Config profile the report was generated with
+    pub profile: String,
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    pub filed_on: NaiveDate,
+    /// Confirmation number given by the tax authority
+    pub confirmation: String,
+    /// The report file that was filed, as given when marking it
+    pub report: PathBuf,
+    /// SHA-256 of the report file, hex
+    pub checksum: String,
+}
+
+/// Record of the periods filed so far, kept next to the config file
+/// (`filings.toml`) so regenerating a filed period is noticed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filings {
+    #[serde(default, rename = "filing")]
+    pub filings: Vec<Filing>,
+}
+
+/// SHA-256 of a file's contents, hex
+pub fn checksum(path: &Path) -> Result<String> {
+    let contents = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Sha256::digest(&contents).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+impl Filings {
+    /// `filings.toml` in the directory of the config file at `config_path`
+    pub fn path_for(config_path: &Path) -> PathBuf {
+        config_path.with_file_name("filings.toml")
+    }
+
+    /// Load the filings at `path`; a missing file means nothing has been filed
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| format!("Failed to parse filings {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Filings::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read filings {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize filings")?;
+        std::fs::write(path, contents).with_context(|| format!("Failed to write filings {}", path.display()))
+    }
+
+    /// The filing of `period` under `profile`, if it was filed
+    pub fn get(&self, profile: &str, period: &Period) -> Option<&Filing> {
+        let label = period.label();
+        self.filings.iter().find(|f| f.profile == profile && f.period == label)
+    }
+
+    /// Add a filing, replacing any earlier one for the same profile and
+    /// period, which is returned. Filings are kept by profile, then period.
+    pub fn record(&mut self, filing: Filing) -> Option<Filing> {
+        let replaced = self
+            .filings
+            .iter()
+            .position(|f| f.profile == filing.profile && f.period == filing.period)
+            .map(|index| self.filings.remove(index));
+        self.filings.push(filing);
+        self.filings.sort_by_key(|f| (f.profile.clone(), f.period.parse::<Period>().ok()));
+        replaced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filing(profile: &str, period: &str, confirmation: &str) -> Filing {
+        Filing {
+            profile: profile.to_string(),
+            period: period.to_string(),
+            filed_on: NaiveDate::from_ymd_opt(2025, 10, 20).unwrap(),
+            confirmation: confirmation.to_string(),
+            report: PathBuf::from("tax-report.tsv"),
+            checksum: "00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_filings_round_trip_and_replace() {
+        let mut filings = Filings::default();
+        assert!(filings.record(filing("default", "Q2-2026", "A1")).is_none());
+        assert!(filings.record(filing("default", "Q3-2025", "B2")).is_none());
+        assert!(filings.record(filing("acme", "Q3-2025", "C3")).is_none());
+        let replaced = filings.record(filing("default", "Q3-2025", "B3")).unwrap();
+        assert_eq!(replaced.confirmation, "B2");
+
+        let order: Vec<(&str, &str)> = filings.filings.iter().map(|f| (f.profile.as_str(), f.period.as_str())).collect();
+        assert_eq!(order, vec![("acme", "Q3-2025"), ("default", "Q3-2025"), ("default", "Q2-2026")]);
+        let period: Period = "Q3-2025".parse().unwrap();
+        assert_eq!(filings.get("default", &period).unwrap().confirmation, "B3");
+        assert!(filings.get("default", &period.next()).is_none());
+
+        let path = std::env::temp_dir().join(format!("filings-{}.toml", std::process::id()));
+        filings.save(&path).unwrap();
+        let loaded = Filings::load(&path).unwrap();
+        let sum = checksum(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, filings);
+        assert_eq!(sum.len(), 64);
+        assert_eq!(Filings::load(&path).unwrap(), Filings::default());
+    }
+}
@@ -0,0 +1,483 @@
+pub mod credentials;
+pub mod filings;
+pub mod wizard;
+
+use crate::report::{
+    AccountMapping, CustomerAliases, CustomerExclusions, Letterhead, OutputFormat, RateTable, TaxabilityRule,
+};
+use crate::stripe::KeyMode;
+use credentials::KeySource;
+use anyhow::{anyhow, Context, Result};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the profile used when none is selected
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// How often sales tax returns are filed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilingFrequency {
+    Monthly,
+    Quarterly,
+    Annual,
+}
+
+impl std::str::FromStr for FilingFrequency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "monthly" => Ok(FilingFrequency::Monthly),
+            "quarterly" => Ok(FilingFrequency::Quarterly),
+            "annual" | "annually" | "yearly" => Ok(FilingFrequency::Annual),
+            other => Err(anyhow!("Unknown filing frequency '{}' (expected monthly, quarterly or annual)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for FilingFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilingFrequency::Monthly => write!(f, "monthly"),
+            FilingFrequency::Quarterly => write!(f, "quarterly"),
+            FilingFrequency::Annual => write!(f, "annual"),
+        }
+    }
+}
+
+/// What a report API token (`serve`) may do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// Fetch reports
+    #[default]
+    Read,
+    /// Fetch reports and sync the store they're built from
+    Admin,
+}
+
+impl TokenScope {
+    /// Whether a token with this scope may do what `needed` allows
+    pub fn allows(self, needed: TokenScope) -> bool {
+        self == TokenScope::Admin || needed == TokenScope::Read
+    }
+}
+
+impl std::fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenScope::Read => write!(f, "read"),
+            TokenScope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// A report API token handed to one client, e.g. the finance team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportToken {
+    /// Who holds the token, for the server's logs
+    pub name: String,
+    /// The token itself (prefer `token_env`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Environment variable holding the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    /// `read` (the default) or `admin`
+    #[serde(default)]
+    pub scope: TokenScope,
+}
+
+impl ReportToken {
+    /// The token, from `token` or the `token_env` variable
+    pub fn resolve(&self) -> Result<String> {
+        match (&self.token, &self.token_env) {
+            (Some(token), _) => Ok(token.clone()),
+            (None, Some(var)) => std::env::var(var)
+                .map_err(|_| anyhow!("Report token '{}': environment variable {} is not set", self.name, var)),
+            (None, None) => Err(anyhow!("Report token '{}' needs `token` or `token_env`", self.name)),
+        }
+    }
+}
+
+/// Settings for one Stripe account / business entity
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Environment variable holding the Stripe API key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// API key stored directly in the config file (prefer `api_key_env`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// File holding only the API key (`-` for stdin)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<PathBuf>,
+    /// Command printing the API key, e.g. `op read op://Finance/Stripe/credential`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_cmd: Option<String>,
+    /// Whether this profile is expected to use a live or test key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<KeyMode>,
+    /// Fail when Stripe objects are missing fields reports depend on, or
+    /// have fields they shouldn't, instead of defaulting them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_parse: bool,
+    /// Two-letter code of the state the business is based in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home_state: Option<String>,
+    /// States where the business is registered to collect sales tax
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registered_states: Vec<String>,
+    /// How often returns are filed in states without their own entry in
+    /// `filing_frequencies`; quarterly if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filing_frequency: Option<FilingFrequency>,
+    /// Month (1-12) the fiscal year starts in, for fiscal quarters and years;
+    /// January (calendar quarters) if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fiscal_year_start: Option<u32>,
+    /// How often returns are filed, keyed by two-letter state code
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub filing_frequencies: BTreeMap<String, FilingFrequency>,
+    /// Format used when `--format` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_format: Option<OutputFormat>,
+    /// Directory reports are written to when `--output` is not given.
+    /// May contain `{year}`, `{quarter}`, `{profile}` and `{run_id}` placeholders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+    /// Title printed above the report; may contain placeholders like `output_dir`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_title: Option<String>,
+    /// IANA timezone (e.g. "America/Chicago") used for period boundaries; UTC if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Sales tax taxpayer/permit numbers, keyed by two-letter state code
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub taxpayer_numbers: BTreeMap<String, String>,
+    /// Parent entity for customers that file under another account, keyed by
+    /// Stripe customer ID (used by `--rollup parent`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parent_accounts: BTreeMap<String, String>,
+    /// Customer metadata key naming the parent entity; `parent_account` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_metadata_key: Option<String>,
+    /// Taxability category per Stripe product ID (e.g. `data_processing`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub product_categories: BTreeMap<String, String>,
+    /// Partial-taxability rules by state and category, added to (or replacing)
+    /// the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taxability_rules: Vec<TaxabilityRule>,
+    /// Exact combined tax rates (percent) by state code or 5-digit ZIP code,
+    /// narrowing the bundled ranges used by `--check-rates`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tax_rates: BTreeMap<String, f64>,
+    /// Percentage points of the taxable amount `--check-rates` allows for rounding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_rate_tolerance: Option<f64>,
+    /// Stripe customer IDs left out of reports (internal, test and demo accounts)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_customers: Vec<String>,
+    /// Customer metadata values whose customers are left out of reports,
+    /// e.g. `internal = "true"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub exclude_metadata: BTreeMap<String, String>,
+    /// Display names for customers, keyed by Stripe customer name or ID
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub customer_aliases: BTreeMap<String, String>,
+    /// Mixed into the customer pseudonyms of `--anonymize`, so they can't be
+    /// matched to names by hashing guesses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymize_salt: Option<String>,
+    /// Revenue account and tax rate names for accounting imports (`--format xero`)
+    #[serde(default, skip_serializing_if = "AccountMapping::is_empty")]
+    pub accounting: AccountMapping,
+    /// Company name, address and signers printed on PDF reports (`--format pdf`)
+    #[serde(default, skip_serializing_if = "Letterhead::is_empty")]
+    pub letterhead: Letterhead,
+    /// Tokens that may use the report API (`serve`), each with a scope
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub report_tokens: Vec<ReportToken>,
+}
+
+impl Profile {
+    /// Resolve the API key from the profile's key source (see
+    /// `KeySource::for_profile`). Returns `None` if it names none.
+    pub fn resolve_api_key(&self) -> Result<Option<String>> {
+        KeySource::for_profile(self).map(|source| source.read()).transpose()
+    }
+
+    /// How often returns are filed in each state the profile files in: those
+    /// in `filing_frequencies`, plus the registered states (or the home state)
+    /// at `filing_frequency`
+    pub fn state_filing_frequencies(&self) -> BTreeMap<String, FilingFrequency> {
+        let default = self.filing_frequency.unwrap_or(FilingFrequency::Quarterly);
+        let mut frequencies: BTreeMap<String, FilingFrequency> = BTreeMap::new();
+        let states = if self.registered_states.is_empty() {
+            self.home_state.as_slice()
+        } else {
+            &self.registered_states
+        };
+        for state in states {
+            frequencies.insert(state.trim().to_uppercase(), default);
+        }
+        for (state, frequency) in &self.filing_frequencies {
+            frequencies.insert(state.trim().to_uppercase(), *frequency);
+        }
+        frequencies
+    }
+
+    /// Customers the profile leaves out of reports
+    pub fn customer_exclusions(&self) -> CustomerExclusions {
+        CustomerExclusions::default()
+            .with_customers(&self.exclude_customers)
+            .with_metadata(&self.exclude_metadata)
+    }
+
+    /// Customer display names from `customer_aliases`
+    pub fn aliases(&self) -> CustomerAliases {
+        CustomerAliases::default().with_aliases(&self.customer_aliases)
+    }
+
+    /// Month (1-12) the fiscal year starts in, 1 if unset
+    pub fn fiscal_start_month(&self) -> Result<u32> {
+        match self.fiscal_year_start {
+            None => Ok(1),
+            Some(month @ 1..=12) => Ok(month),
+            Some(month) => Err(anyhow!("fiscal_year_start must be a month from 1 to 12, got {}", month)),
+        }
+    }
+
+    /// The bundled tax rates with the profile's own rates and tolerance applied
+    pub fn rate_table(&self) -> RateTable {
+        let table = RateTable::default().with_overrides(&self.tax_rates);
+        match self.tax_rate_tolerance {
+            Some(tolerance) => table.with_tolerance(tolerance),
+            None => table,
+        }
+    }
+
+    /// Parse the configured timezone, if any
+    pub fn timezone(&self) -> Result<Option<Tz>> {
+        self.timezone
+            .as_deref()
+            .map(|name| name.parse::<Tz>().map_err(|_| anyhow!("Unknown timezone '{}' in config profile", name)))
+            .transpose()
+    }
+}
+
+/// The config file: a set of named profiles
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Key mode given on the command line (`--live` or `--test`), which
+    /// overrides the selected profile's `mode`
+    #[serde(skip)]
+    pub mode: Option<KeyMode>,
+    /// Key source given on the command line (`--api-key-file` or
+    /// `--api-key-cmd`), which overrides the selected profile's
+    #[serde(skip)]
+    pub key_source: Option<KeySource>,
+    /// `--strict-parse`, which turns on the selected profile's `strict_parse`
+    #[serde(skip)]
+    pub strict_parse: bool,
+}
+
+impl Config {
+    /// Default config location: `$XDG_CONFIG_HOME/stripe-tax-reporter/config.toml`,
+    /// falling back to `~/.config/stripe-tax-reporter/config.toml`
+    pub fn default_path() -> Result<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let home = std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .ok_or_else(|| anyhow!("Cannot locate home directory for the config file"))?;
+                PathBuf::from(home).join(".config")
+            }
+        };
+        Ok(base.join("stripe-tax-reporter").join("config.toml"))
+    }
+
+    /// Load the config at `path`; a missing file is an empty config
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read config file {}", path.display())),
+        }
+    }
+
+    /// Write the config to `path`, creating parent directories. The file is
+    /// made owner-readable only, since profiles may contain API keys.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create config directory {}", parent.display()))?;
+        }
+
+        let contents = toml::to_string_pretty(self).context("Failed to serialize config")?;
+
+        // The file may hold an API key: create it readable only by the user,
+        // and tighten an existing file before the key is written to it
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path).with_context(|| format!("Failed to write config file {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(contents.as_bytes()).with_context(|| format!("Failed to write config file {}", path.display()))
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Pick the profile to run with. A profile named explicitly (`--profile`)
+    /// must exist; otherwise the `default` profile is used if present, else
+    /// an empty profile (environment-variable configuration).
+    pub fn select(&self, name: Option<&str>) -> Result<Profile> {
+        match name {
+            Some(name) => self.profile(name).cloned().map(|profile| self.with_overrides(profile)).ok_or_else(|| {
+                let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                if available.is_empty() {
+                    anyhow!("Profile '{}' not found: no profiles are configured (run init-wizard)", name)
+                } else {
+                    anyhow!("Profile '{}' not found (available: {})", name, available.join(", "))
+                }
+            }),
+            None => Ok(self.with_overrides(self.profile(DEFAULT_PROFILE).cloned().unwrap_or_default())),
+        }
+    }
+
+    fn with_overrides(&self, mut profile: Profile) -> Profile {
+        profile.mode = self.mode.or(profile.mode);
+        profile.strict_parse |= self.strict_parse;
+        if let Some(source) = &self.key_source {
+            profile.api_key = None;
+            profile.api_key_file = None;
+            profile.api_key_cmd = None;
+            profile.api_key_env = None;
+            match source {
+                KeySource::Inline(key) => profile.api_key = Some(key.clone()),
+                KeySource::File(path) => profile.api_key_file = Some(path.clone()),
+                KeySource::Command(command) => profile.api_key_cmd = Some(command.clone()),
+                KeySource::Env(var) => profile.api_key_env = Some(var.clone()),
+            }
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            DEFAULT_PROFILE.to_string(),
+            Profile {
+                api_key_env: Some("STRIPE_PROD_API_KEY".to_string()),
+                mode: Some(KeyMode::Live),
+                home_state: Some("TX".to_string()),
+                registered_states: vec!["TX".to_string(), "CA".to_string()],
+                filing_frequency: Some(FilingFrequency::Quarterly),
+                default_format: Some(OutputFormat::Csv),
+                output_dir: Some(PathBuf::from("reports")),
+                ..Default::default()
+            },
+        );
+
+        let toml = toml::to_string_pretty(&config).unwrap();
+        assert!(toml.contains("[profiles.default]"));
+        assert!(toml.contains("default_format = \"csv\""));
+
+        let parsed: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed, config);
+
+        let path = std::env::temp_dir().join(format!("stripe-config-{}.toml", std::process::id()));
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_config_file_is_empty() {
+        let config = Config::load(Path::new("/nonexistent/stripe-tax-reporter/config.toml")).unwrap();
+        assert!(config.profiles.is_empty());
+    }
+
+    #[test]
+    fn test_select_named_profile() {
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.default]
+            api_key_env = "STRIPE_PROD_API_KEY"
+
+            [profiles.second-entity]
+            api_key_env = "ENTITY2_STRIPE_KEY"
+            timezone = "America/Chicago"
+            taxpayer_numbers = { TX = "32012345678" }
+            "#,
+        )
+        .unwrap();
+
+        let profile = config.select(Some("second-entity")).unwrap();
+        assert_eq!(profile.api_key_env.as_deref(), Some("ENTITY2_STRIPE_KEY"));
+        assert_eq!(profile.timezone().unwrap(), Some(chrono_tz::America::Chicago));
+        assert_eq!(profile.taxpayer_numbers.get("TX").map(String::as_str), Some("32012345678"));
+
+        assert_eq!(config.select(None).unwrap().api_key_env.as_deref(), Some("STRIPE_PROD_API_KEY"));
+
+        let err = config.select(Some("missing")).unwrap_err().to_string();
+        assert!(err.contains("available: default, second-entity"));
+    }
+
+    #[test]
+    fn test_report_tokens() {
+        let config: Config = toml::from_str(
+            r#"
+            [[profiles.default.report_tokens]]
+            name = "finance"
+            token = "fin-123"
+
+            [[profiles.default.report_tokens]]
+            name = "ops"
+            token_env = "STRIPE_TAX_TEST_UNSET_TOKEN"
+            scope = "admin"
+            "#,
+        )
+        .unwrap();
+
+        let tokens = &config.select(None).unwrap().report_tokens;
+        assert_eq!(tokens[0].scope, TokenScope::Read);
+        assert_eq!(tokens[0].resolve().unwrap(), "fin-123");
+        assert_eq!(tokens[1].scope, TokenScope::Admin);
+        assert!(tokens[1].resolve().unwrap_err().to_string().contains("STRIPE_TAX_TEST_UNSET_TOKEN"));
+
+        assert!(TokenScope::Admin.allows(TokenScope::Read));
+        assert!(TokenScope::Read.allows(TokenScope::Read));
+        assert!(!TokenScope::Read.allows(TokenScope::Admin));
+    }
+}
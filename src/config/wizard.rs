@@ -0,0 +1,332 @@
+use super::credentials::KeySource;
+use super::{Config, FilingFrequency, Profile, DEFAULT_PROFILE};
+use crate::report::OutputFormat;
+use crate::stripe::KeyMode;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+/// Walk the user through creating (or updating) a config profile.
+///
+/// Prompts are read from `input` and written to `output` so the wizard can be
+/// scripted. Values from an existing profile of the same name are offered as
+/// defaults, except its API key, which is never shown. With `hide_secrets`
+/// (input from a terminal) a pasted key is read from the terminal without
+/// echo. Returns the profile name and the resulting profile; saving is left
+/// to the caller.
+pub fn run_wizard<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+    hide_secrets: bool,
+) -> Result<(String, Profile)> {
+    let mut prompter = Prompter { input, output, hide_secrets };
+
+    prompter.say("Stripe Tax Reporter setup")?;
+    prompter.say("Press Enter to accept the default shown in brackets.\n")?;
+
+    let name = prompter.ask("Profile name", Some(DEFAULT_PROFILE))?;
+    let existing = config.profile(&name).cloned().unwrap_or_default();
+    if config.profile(&name).is_some() {
+        prompter.say(&format!("Updating existing profile '{}'.", name))?;
+    }
+
+    // Start from the existing profile so settings the wizard doesn't ask about are kept
+    let mut profile = existing.clone();
+
+    // API key: reference an environment variable (recommended), a file or a
+    // command, or store it in the config. The profile's current source is
+    // the default, so re-running the wizard keeps it.
+    let default_source = match KeySource::for_profile(&existing) {
+        Some(KeySource::Inline(_)) => "paste",
+        Some(KeySource::File(_)) => "file",
+        Some(KeySource::Command(_)) => "cmd",
+        Some(KeySource::Env(_)) | None => "env",
+    };
+    let key_value = loop {
+        let source = prompter.ask(
+            "Provide the Stripe API key via environment variable, pasted, a file or a command (env/paste/file/cmd)",
+            Some(default_source),
+        )?;
+        match source.to_lowercase().as_str() {
+            "env" => {
+                let default_var = existing.api_key_env.as_deref().unwrap_or("STRIPE_PROD_API_KEY");
+                let var = prompter.ask("Environment variable holding the key", Some(default_var))?;
+                let value = std::env::var(&var).ok();
+                if value.is_none() {
+                    prompter.say(&format!("Note: {} is not set in this shell; set it before generating reports.", var))?;
+                }
+                profile.api_key_env = Some(var);
+                profile.api_key = None;
+                profile.api_key_file = None;
+                profile.api_key_cmd = None;
+                break value;
+            }
+            "paste" => {
+                let key = match &existing.api_key {
+                    Some(current) => prompter
+                        .ask_secret("Stripe secret key (Enter keeps the current key)")?
+                        .unwrap_or_else(|| current.clone()),
+                    None => loop {
+                        match prompter.ask_secret("Stripe secret key")? {
+                            Some(key) => break key,
+                            None => prompter.say("A value is required.")?,
+                        }
+                    },
+                };
+                profile.api_key = Some(key.clone());
+                profile.api_key_env = None;
+                profile.api_key_file = None;
+                profile.api_key_cmd = None;
+                prompter.say("The key will be stored in the config file (readable only by you).")?;
+                break Some(key);
+            }
+            "file" => {
+                let default_path = existing.api_key_file.as_ref().map(|path| path.display().to_string());
+                let path = PathBuf::from(prompter.ask("File holding only the key", default_path.as_deref())?);
+                let value = KeySource::File(path.clone()).read().ok();
+                if value.is_none() {
+                    prompter.say(&format!("Note: can't read a key from {} yet; create it before generating reports.", path.display()))?;
+                }
+                profile.api_key_file = Some(path);
+                profile.api_key = None;
+                profile.api_key_env = None;
+                profile.api_key_cmd = None;
+                break value;
+            }
+            "cmd" => {
+                let command = prompter.ask(
+                    "Command printing the key, e.g. op read op://Finance/Stripe/credential",
+                    existing.api_key_cmd.as_deref(),
+                )?;
+                prompter.say("The command runs each time the key is needed; it isn't run now.")?;
+                profile.api_key_cmd = Some(command);
+                profile.api_key = None;
+                profile.api_key_env = None;
+                profile.api_key_file = None;
+                break None;
+            }
+            other => prompter.say(&format!("Please answer 'env', 'paste', 'file' or 'cmd' (got '{}').", other))?,
+        }
+    };
+
+    // Live/test detection from the key prefix
+    let detected = key_value.as_deref().and_then(KeyMode::detect);
+    match detected {
+        Some(mode) => prompter.say(&format!("Detected a {} mode key.", mode))?,
+        None if key_value.is_some() => prompter.say("Could not tell whether this is a live or test key.")?,
+        None => {}
+    }
+    let default_mode = detected.or(existing.mode).unwrap_or(KeyMode::Live).to_string();
+    let mode = loop {
+        let answer = prompter.ask("Mode (live/test)", Some(&default_mode))?;
+        match answer.to_lowercase().as_str() {
+            "live" => break KeyMode::Live,
+            "test" => break KeyMode::Test,
+            other => prompter.say(&format!("Please answer 'live' or 'test' (got '{}').", other))?,
+        }
+    };
+    if let Some(detected) = detected
+        && detected != mode
+    {
+        prompter.say(&format!("Warning: the key looks like a {} key but the profile is set to {} mode.", detected, mode))?;
+    }
+    profile.mode = Some(mode);
+
+    // Home and registered states
+    let home_state = loop {
+        let answer = prompter.ask("Home state (two-letter code)", Some(existing.home_state.as_deref().unwrap_or("TX")))?;
+        match parse_state(&answer) {
+            Some(state) => break state,
+            None => prompter.say(&format!("'{}' is not a two-letter state code.", answer))?,
+        }
+    };
+
+    let default_registered = if existing.registered_states.is_empty() {
+        home_state.clone()
+    } else {
+        existing.registered_states.join(",")
+    };
+    profile.registered_states = loop {
+        let answer = prompter.ask("States you are registered to collect tax in (comma-separated)", Some(&default_registered))?;
+        let states: Option<Vec<String>> = answer.split(',').filter(|s| !s.trim().is_empty()).map(parse_state).collect();
+        match states {
+            Some(states) if !states.is_empty() => break states,
+            _ => prompter.say("Enter one or more two-letter state codes, e.g. TX,CA")?,
+        }
+    };
+    profile.home_state = Some(home_state);
+
+    // Filing frequency
+    let default_frequency = existing.filing_frequency.unwrap_or(FilingFrequency::Quarterly).to_string();
+    profile.filing_frequency = Some(loop {
+        let answer = prompter.ask("Filing frequency (monthly/quarterly/annual)", Some(&default_frequency))?;
+        match answer.parse() {
+            Ok(frequency) => break frequency,
+            Err(e) => prompter.say(&e.to_string())?,
+        }
+    });
+
+    // Default output format
+    let format_names: Vec<String> = OutputFormat::value_variants()
+        .iter()
+        .filter_map(|f| f.to_possible_value().map(|v| v.get_name().to_string()))
+        .collect();
+    let default_format = existing.default_format.unwrap_or(OutputFormat::Tsv);
+    let default_format_name = default_format.to_possible_value().map(|v| v.get_name().to_string());
+    profile.default_format = Some(loop {
+        let answer = prompter.ask(&format!("Default format ({})", format_names.join("/")), default_format_name.as_deref())?;
+        match OutputFormat::from_str(&answer, true) {
+            Ok(format) => break format,
+            Err(_) => prompter.say(&format!("Unknown format '{}'.", answer))?,
+        }
+    });
+
+    // Output directory (blank keeps writing to stdout)
+    let default_dir = existing.output_dir.as_ref().map(|d| d.display().to_string()).unwrap_or_default();
+    let dir = prompter.ask("Output directory for reports (blank for stdout)", Some(&default_dir))?;
+    profile.output_dir = if dir.is_empty() { None } else { Some(PathBuf::from(dir)) };
+
+    Ok((name, profile))
+}
+
+fn parse_state(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(s.to_uppercase())
+    } else {
+        None
+    }
+}
+
+/// Question-and-answer over a reader and writer, shared by the interactive commands
+pub(crate) struct Prompter<'a, R, W> {
+    pub(crate) input: &'a mut R,
+    pub(crate) output: &'a mut W,
+    /// Read secrets from the terminal without echo instead of from `input`
+    pub(crate) hide_secrets: bool,
+}
+
+impl<R: BufRead, W: Write> Prompter<'_, R, W> {
+    pub(crate) fn say(&mut self, message: &str) -> Result<()> {
+        writeln!(self.output, "{}", message)?;
+        Ok(())
+    }
+
+    /// Ask a question; an empty answer takes the default (if any)
+    pub(crate) fn ask(&mut self, question: &str, default: Option<&str>) -> Result<String> {
+        loop {
+            match default {
+                Some(default) if !default.is_empty() => write!(self.output, "{} [{}]: ", question, default)?,
+                _ => write!(self.output, "{}: ", question)?,
+            }
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(anyhow!("Cancelled (end of input)"));
+            }
+
+            let answer = line.trim();
+            if !answer.is_empty() {
+                return Ok(answer.to_string());
+            }
+            if let Some(default) = default {
+                return Ok(default.to_string());
+            }
+            self.say("A value is required.")?;
+        }
+    }
+
+    /// Ask for a secret, without echo when `hide_secrets` is set and with no
+    /// default shown; an empty answer is `None`
+    pub(crate) fn ask_secret(&mut self, question: &str) -> Result<Option<String>> {
+        write!(self.output, "{}: ", question)?;
+        self.output.flush()?;
+
+        let line = if self.hide_secrets {
+            console::Term::stdout().read_secure_line()?
+        } else {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(anyhow!("Cancelled (end of input)"));
+            }
+            line
+        };
+        let answer = line.trim();
+        Ok((!answer.is_empty()).then(|| answer.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wizard_builds_profile_from_answers() {
+        let answers = "acme\npaste\nsk_test_abc123\n\n\ntx, ca\nmonthly\ncsv\nreports\n";
+        let mut input = Cursor::new(answers);
+        let mut output = Vec::new();
+
+        let (name, profile) = run_wizard(&mut input, &mut output, &Config::default(), false).unwrap();
+
+        assert_eq!(name, "acme");
+        assert_eq!(profile.api_key.as_deref(), Some("sk_test_abc123"));
+        // Mode defaults to the detected prefix
+        assert_eq!(profile.mode, Some(KeyMode::Test));
+        assert_eq!(profile.home_state.as_deref(), Some("TX"));
+        assert_eq!(profile.registered_states, vec!["TX", "CA"]);
+        assert_eq!(profile.filing_frequency, Some(FilingFrequency::Monthly));
+        assert_eq!(profile.default_format, Some(OutputFormat::Csv));
+        assert_eq!(profile.output_dir, Some(PathBuf::from("reports")));
+
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("Detected a test mode key."));
+    }
+
+    #[test]
+    fn test_wizard_reprompts_invalid_answers_and_cancels_on_eof() {
+        let mut input = Cursor::new("default\nsomething\n");
+        let mut output = Vec::new();
+
+        let result = run_wizard(&mut input, &mut output, &Config::default(), false);
+        assert!(result.is_err());
+        assert!(String::from_utf8(output).unwrap().contains("Please answer 'env', 'paste', 'file' or 'cmd'"));
+    }
+
+    #[test]
+    fn test_wizard_keeps_stored_key_without_showing_it() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            DEFAULT_PROFILE.to_string(),
+            Profile { api_key: Some("sk_live_secret".to_string()), ..Default::default() },
+        );
+        let mut input = Cursor::new("\n\n\n\n\n\n\n\n\n");
+        let mut output = Vec::new();
+
+        let (_, profile) = run_wizard(&mut input, &mut output, &config, false).unwrap();
+        assert_eq!(profile.api_key.as_deref(), Some("sk_live_secret"));
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("Enter keeps the current key"));
+        assert!(!transcript.contains("sk_live_secret"));
+    }
+
+    #[test]
+    fn test_wizard_keeps_key_command_by_default() {
+        let command = "op read op://Finance/Stripe/credential";
+        let mut config = Config::default();
+        config.profiles.insert(
+            DEFAULT_PROFILE.to_string(),
+            Profile { api_key_cmd: Some(command.to_string()), mode: Some(KeyMode::Live), ..Default::default() },
+        );
+        let mut input = Cursor::new("\n\n\n\n\n\n\n\n\n");
+        let mut output = Vec::new();
+
+        let (_, profile) = run_wizard(&mut input, &mut output, &config, false).unwrap();
+        assert_eq!(profile.api_key_cmd.as_deref(), Some(command));
+        assert_eq!((profile.api_key, profile.api_key_env, profile.api_key_file), (None, None, None));
+        assert_eq!(profile.mode, Some(KeyMode::Live));
+    }
+}
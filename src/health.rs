@@ -0,0 +1,153 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Whether a server (`listen` or `serve`) should still be sent traffic. It
+/// stops being ready once shutdown starts, while in-flight requests finish.
+#[derive(Debug, Default)]
+pub struct Health {
+    draining: AtomicBool,
+}
+
+impl Health {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn ready(&self) -> bool {
+        !self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Stop reporting ready, e.g. once a shutdown signal arrives
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait for Ctrl-C or SIGTERM, then stop reporting ready and keep serving
+    /// for `grace`, so load balancers see `/readyz` fail before connections
+    /// are refused. Passed to `with_graceful_shutdown`, which then lets
+    /// in-flight requests finish.
+    pub async fn shutdown_signal(self: Arc<Self>, grace: Duration) {
+        let ctrl_c = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        #[cfg(unix)]
+        let terminate = async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut signal) => {
+                    signal.recv().await;
+                }
+                Err(e) => {
+                    tracing::warn!("Can't listen for SIGTERM: {}", e);
+                    std::future::pending::<()>().await
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = terminate => {}
+        }
+        tracing::info!("Shutting down in {}, once in-flight requests finish", humantime::format_duration(grace));
+        self.drain_for(grace).await;
+    }
+
+    /// Stop reporting ready, then wait out `grace` while still serving
+    pub async fn drain_for(&self, grace: Duration) {
+        self.drain();
+        tokio::time::sleep(grace).await;
+    }
+}
+
+/// `GET /healthz` (the process is up), `/readyz` (503 once shutting down) and
+/// `/version`, which need no credentials
+pub fn routes(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .with_state(health)
+}
+
+async fn readyz(State(health): State<Arc<Health>>) -> impl IntoResponse {
+    if health.ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "shutting down")
+    }
+}
+
+async fn version() -> Json<serde_json::Value> {
+    Json(serde_json::json!({"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")}))
+}
+
+/// Serve `app` on a random local port for tests, returning its base URL
+#[cfg(test)]
+pub(crate) async fn spawn_test_server(app: Router) -> String {
+    let socket = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(socket, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_health_endpoints() {
+        let health = Health::new();
+        let base = spawn_test_server(routes(health.clone())).await;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("{}/healthz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = client.get(format!("{}/readyz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let version: serde_json::Value = client.get(format!("{}/version", base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+
+        // Draining fails readiness but not liveness
+        health.drain();
+        let response = client.get(format!("{}/readyz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 503);
+        let response = client.get(format!("{}/healthz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_ready_fails_before_shutdown() {
+        let health = Health::new();
+        let socket = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", socket.local_addr().unwrap());
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = {
+            let health = health.clone();
+            async move {
+                let _ = signal.await;
+                health.drain_for(Duration::from_millis(500)).await;
+            }
+        };
+        let server = tokio::spawn(async move { axum::serve(socket, routes(health)).with_graceful_shutdown(shutdown).await });
+        let client = reqwest::Client::new();
+
+        trigger.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // Still serving during the grace period, but no longer ready
+        let response = client.get(format!("{}/readyz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 503);
+        let response = client.get(format!("{}/healthz", base)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(format!("{}/healthz", base)).await.is_err());
+    }
+}
@@ -1,5 +1,11 @@
+pub mod clock;
 pub mod stripe;
 pub mod report;
+pub mod source;
+pub mod config;
+pub mod health;
+pub mod http;
 
 pub use stripe::StripeClient;
-pub use report::{get_previous_quarter, ReportGenerator, format_as_tsv};
+pub use report::{get_previous_quarter, get_previous_quarter_from, build_report, Period, ReportGenerator, GroupedReport, format_as_tsv, OutputFormat, ReportFormatter};
+pub use source::{FixtureSource, InvoiceSource, SqliteSource, Store, StoreSource};
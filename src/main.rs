@@ -1,11 +1,34 @@
-use clap::Parser;
-use anyhow::Result;
+use clap::{Args as ClapArgs, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-mod stripe;
-mod report;
-
-use report::{get_previous_quarter, ReportGenerator, format_as_tsv};
-use stripe::StripeClient;
+use stripe_tax_reporter::report::{
+    build_connected_report, build_payout_report, build_report, build_report_from_run, by_product, check_columns,
+    find_address_problems, format_address, load_aliases, load_exclude_list, load_section_totals, parse_corrections,
+    parse_metadata_filter, prompt_corrections, resume_report, run_review, section_totals, tag_report_period, taggable_invoices,
+    template, write_audit_file, write_split_by_state, AddressAudit, Amendment, Anonymizer, Archive, ArchiveManifest,
+    Basis, Checkpoint, Column, CompareFormat, Comparison, CreditBasis, CronSchedule,
+    DateFormat, Detail, Drift, ExternalSales, FilingCalendar, FilingDiscount, GroupBy, GroupedReport, OutputFormat,
+    OutputSink, PayoutFormat, Period, PeriodLength, RegistrationCheck, ReportFormatter, ReportGenerator,
+    ReportOptions, ReportProgress, ReviewDecisions, ReviewOutcome, Rollup, Severity, StateChange, Statement,
+    StatementFormat, Taxability, TemplateContext, TemplateFormatter, Totals, Validation,
+};
+use stripe_tax_reporter::report::schedule::notify;
+use stripe_tax_reporter::report::server::{self as report_server, ApiToken, ReportServer};
+use stripe_tax_reporter::clock::{Clock, DateClock, FixedClock, SystemClock};
+use stripe_tax_reporter::config::credentials::KeySource;
+use stripe_tax_reporter::http::{ServeOptions, TlsFiles};
+use stripe_tax_reporter::config::filings::{self, Filing, Filings};
+use stripe_tax_reporter::config::{wizard, Config, FilingFrequency, Profile, TokenScope, DEFAULT_PROFILE};
+use stripe_tax_reporter::source::events::{replay_events, EVENT_RETENTION_SECS, EVENT_TYPES};
+use stripe_tax_reporter::source::fixture::Fixture;
+use stripe_tax_reporter::source::{
+    CustomerScope, FixtureSource, InvoiceSource, RecordingSource, SelectedSource, SqliteStore, StoreBackend, StoreSource,
+    TestClockScope,
+};
+use stripe_tax_reporter::stripe::{ApiUsage, KeyMode, StripeClient};
 
 #[derive(Parser, Debug)]
 #[command(name = "Stripe Tax Reporter")]
@@ -14,122 +37,2462 @@ use stripe::StripeClient;
 struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Config file (default: ~/.config/stripe-tax-reporter/config.toml)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Config profile to use (default: the `default` profile, if configured)
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Log level or filter directive, e.g. `debug` or `stripe_tax_reporter=trace`
+    /// (default: $RUST_LOG, else info)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<String>,
+
+    /// Log output format
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Run as if today were this date, YYYY-MM-DD, to reproduce the default
+    /// period and file names of an earlier (or later) run
+    #[arg(long, global = true, value_name = "DATE")]
+    as_of: Option<chrono::NaiveDate>,
+
+    /// Require a live Stripe key (sk_live_ or rk_live_), failing before any
+    /// request otherwise (overrides the profile's mode)
+    #[arg(long, global = true, conflicts_with = "test")]
+    live: bool,
+
+    /// Require a test Stripe key (sk_test_ or rk_test_)
+    #[arg(long, global = true)]
+    test: bool,
+
+    /// Read the Stripe API key from this file, or from stdin with `-`
+    /// (overrides the profile's key)
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "api_key_cmd")]
+    api_key_file: Option<PathBuf>,
+
+    /// Run this shell command and use what it prints as the Stripe API key,
+    /// e.g. "op read op://Finance/Stripe/credential" (overrides the profile's key)
+    #[arg(long, global = true, value_name = "COMMAND")]
+    api_key_cmd: Option<String>,
+
+    /// Stop with an error instead of making more than this many Stripe API
+    /// requests, as a guard against runaway runs
+    #[arg(long, global = true, value_name = "N")]
+    max_requests: Option<usize>,
+
+    /// Check the invoices, customers, charges and balance transactions Stripe
+    /// returns against the fields reports depend on, failing with each
+    /// object's missing or unexpected fields instead of defaulting them
+    #[arg(long, global = true)]
+    strict_parse: bool,
+
+    /// Print the Stripe API requests made per endpoint, with failures and
+    /// latency, to stderr at the end of the run
+    #[arg(long, global = true)]
+    api_usage: bool,
+
+    /// Options for the default `generate` command
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event, for log collectors
+    Json,
 }
 
 #[derive(Parser, Debug)]
 enum Commands {
     /// Generate tax report for previous fiscal quarter
-    Generate,
+    Generate(Box<GenerateArgs>),
+    /// Interactively create or update a config profile
+    InitWizard,
+    /// Show the Stripe account the API key belongs to: its business name,
+    /// ID and default currency, and whether the key is live or test
+    Account,
+    /// Print a shell completion script, e.g.
+    /// `stripe-tax-reporter completions bash > /etc/bash_completion.d/stripe-tax-reporter`
+    Completions(CompletionsArgs),
+    /// Write a customer-facing statement of one customer's invoices and the
+    /// tax collected per state for a calendar year
+    Statement(StatementArgs),
+    /// List the payouts that arrived in the previous fiscal quarter with the
+    /// balance transactions in each, tying invoices to the deposit they were
+    /// paid out in
+    Payouts(PayoutsArgs),
+    /// Compare two periods' per-state sales, tax and fees, flagging states
+    /// that newly appear or disappear
+    Compare(CompareArgs),
+    /// Regenerate a period archived with `generate --archive-dir` from
+    /// current data and list every invoice that changed since, exiting with
+    /// an error if any did
+    Verify(VerifyArgs),
+    /// Regenerate a period archived with `generate --archive-dir` and write
+    /// only what an amended return has to correct: the change in sales and
+    /// tax due per state, and the invoices behind it
+    Amend(VerifyArgs),
+    /// Browse a period's invoices by state and the invoices left out, exclude
+    /// or annotate invoices, and save the decisions for `generate --review`
+    Review(ReviewArgs),
+    /// Record which periods have been filed, and list them
+    Filings {
+        #[command(subcommand)]
+        command: FilingsCommand,
+    },
+    /// Find the customers invoiced in a period whose address has no usable
+    /// state, and save corrected addresses to them in Stripe, entered at a
+    /// prompt or read from a CSV
+    FixAddresses(FixAddressesArgs),
+    /// Audit the customers with paid invoices in a period before filing it
+    Customers(CustomersArgs),
+    /// Fetch and process a period's invoices without writing a report, and
+    /// list data-quality problems with their revenue impact, exiting with an
+    /// error if any would block filing
+    Validate(ValidateArgs),
+    /// Compare the states reported in a period with the account's Stripe Tax
+    /// registrations, flagging sales into unregistered states and
+    /// registrations without sales
+    Registrations(RegistrationsArgs),
+    /// List upcoming sales tax return due dates per state, at each state's
+    /// filing frequency (profile filing_frequencies), optionally as an ICS
+    /// calendar with reminders
+    Deadlines(DeadlinesArgs),
+    /// Receive Stripe webhooks and store paid invoices, refunds and credit
+    /// notes as they happen, so `generate --sqlite` needs no API paging
+    Listen(ListenArgs),
+    /// Replay a period's invoice.paid, charge.refunded and credit_note.created
+    /// events from the Stripe Events API into the store, to fill gaps when
+    /// `listen` wasn't running
+    BackfillEvents(BackfillEventsArgs),
+    /// Serve quarterly reports over HTTP at GET /reports/{year}/{quarter}
+    /// for dashboards and other tools, behind an API key
+    Serve(ServeArgs),
+    /// Keep running and regenerate the previous quarter's report into the
+    /// archive directory on a cron schedule, optionally posting each outcome
+    /// to a chat webhook
+    Schedule(Box<ScheduleArgs>),
+}
+
+#[derive(ClapArgs, Debug)]
+struct CompletionsArgs {
+    /// Shell to generate the script for
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ScheduleArgs {
+    /// When to run, as a five-field cron expression in the profile's timezone
+    /// (else UTC), e.g. "0 6 5 1,4,7,10 *" for 06:00 on the 5th after each quarter
+    #[arg(long, value_name = "EXPR")]
+    cron: String,
+
+    /// Post each run's outcome to this chat webhook (Slack-compatible `{"text": ...}`)
+    #[arg(long, value_name = "URL")]
+    notify_webhook: Option<String>,
+
+    /// Also run once at startup
+    #[arg(long)]
+    run_now: bool,
+
+    /// Options for each run, as for `generate`; --archive-dir is required
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    bind: std::net::SocketAddr,
+
+    /// Admin key clients may send as `Authorization: Bearer <key>` or
+    /// `X-API-Key`, else TAX_REPORT_API_KEY; the profile's `report_tokens`
+    /// are accepted as well
+    #[arg(long, value_name = "KEY")]
+    api_key: Option<String>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+}
+
+/// Where a command reads Stripe objects from: a fixture, a store, or else the
+/// API with the profile's key
+#[derive(ClapArgs, Debug, Clone)]
+struct SourceArgs {
+    /// Read from a JSON fixture file instead of the Stripe API
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["sqlite", "postgres"])]
+    fixture: Option<PathBuf>,
+
+    /// Read from a local SQLite store (e.g. one kept by `listen`) instead of the Stripe API
+    #[arg(long, value_name = "PATH", conflicts_with = "postgres")]
+    sqlite: Option<PathBuf>,
+
+    /// Read from a shared Postgres store instead of the Stripe API
+    /// (builds with the `postgres` feature)
+    #[arg(long, value_name = "URL")]
+    postgres: Option<String>,
+
+    /// Act on behalf of a Stripe Connect account (sets the `Stripe-Account` header)
+    #[arg(long, value_name = "ACCOUNT_ID", conflicts_with_all = ["fixture", "sqlite", "postgres"])]
+    connected_account: Option<String>,
+}
+
+impl SourceArgs {
+    async fn open(&self, profile: &Profile) -> Result<SelectedSource> {
+        if let Some(path) = &self.fixture {
+            return Ok(SelectedSource::Fixture(FixtureSource::from_path(path)?));
+        }
+        if let Some(store) = open_store(self.sqlite.as_deref(), self.postgres.as_deref()).await? {
+            return Ok(SelectedSource::Store(store));
+        }
+        Ok(SelectedSource::Stripe(self.stripe_client(profile)?))
+    }
+
+    /// The profile's API client, acting for `--connected-account` if given
+    fn stripe_client(&self, profile: &Profile) -> Result<StripeClient> {
+        let client = stripe_client(profile)?;
+        Ok(match &self.connected_account {
+            Some(account) => client.for_account(account),
+            None => client,
+        })
+    }
+}
+
+/// HTTPS and shutdown options shared by `serve` and `listen`
+#[derive(ClapArgs, Debug)]
+struct HttpArgs {
+    /// Serve HTTPS with this PEM certificate chain instead of plain HTTP
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM private key for --tls-cert
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// After SIGTERM, how long /readyz fails before new connections are
+    /// refused, so load balancers can stop sending traffic first
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, default_value = "5s")]
+    shutdown_grace: Duration,
+}
+
+impl HttpArgs {
+    fn options(self, bind: std::net::SocketAddr) -> ServeOptions {
+        let tls = self.tls_cert.zip(self.tls_key).map(|(cert, key)| TlsFiles { cert, key });
+        ServeOptions { bind, tls, shutdown_grace: self.shutdown_grace }
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+struct BackfillEventsArgs {
+    /// Period to replay, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    /// Store events in a local SQLite store
+    #[arg(long, value_name = "PATH", conflicts_with = "postgres", required_unless_present = "postgres")]
+    sqlite: Option<PathBuf>,
+
+    /// Store events in a shared Postgres store
+    #[arg(long, value_name = "URL")]
+    postgres: Option<String>,
+
+    /// Hide status messages
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ListenArgs {
+    /// Store events in a local SQLite store
+    #[arg(long, value_name = "PATH", conflicts_with = "postgres", required_unless_present = "postgres")]
+    sqlite: Option<PathBuf>,
+
+    /// Store events in a shared Postgres store
+    #[arg(long, value_name = "URL")]
+    postgres: Option<String>,
+
+    /// Address to listen on; Stripe posts to /webhook
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8787")]
+    bind: std::net::SocketAddr,
+
+    /// The endpoint's signing secret (whsec_...), else STRIPE_WEBHOOK_SECRET
+    #[arg(long, value_name = "SECRET")]
+    webhook_secret: Option<String>,
+
+    #[command(flatten)]
+    http: HttpArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct DeadlinesArgs {
+    /// First day to list due dates from, YYYY-MM-DD (default: today)
+    #[arg(long, value_name = "DATE")]
+    from: Option<chrono::NaiveDate>,
+
+    /// How many months ahead of --from to list
+    #[arg(long, value_name = "MONTHS", default_value_t = 12)]
+    months: u32,
+
+    /// Only these states (default: every state the profile files in)
+    #[arg(long, value_name = "STATE", value_delimiter = ',')]
+    state: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the list to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Also write the due dates as an iCalendar file to import into a calendar
+    #[arg(long, value_name = "PATH")]
+    ics: Option<PathBuf>,
+
+    /// Days before each due date the calendar reminds you (0 for none)
+    #[arg(long, value_name = "DAYS", default_value_t = 7, requires = "ics")]
+    remind_days: u32,
+
+    /// Hide status messages
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct RegistrationsArgs {
+    /// Period to check, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the comparison to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Read invoices and registrations from a JSON fixture file instead of the Stripe API
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["sqlite", "postgres"])]
+    fixture: Option<PathBuf>,
+
+    /// Read invoices from a local SQLite store (registrations still come from Stripe)
+    #[arg(long, value_name = "PATH", conflicts_with = "postgres")]
+    sqlite: Option<PathBuf>,
+
+    /// Read invoices from a shared Postgres store (registrations still come from Stripe)
+    #[arg(long, value_name = "URL")]
+    postgres: Option<String>,
+
+    /// Act on behalf of a Stripe Connect account (sets the `Stripe-Account` header)
+    #[arg(long, value_name = "ACCOUNT_ID", conflicts_with = "fixture")]
+    connected_account: Option<String>,
+
+    /// Hide the progress bar and status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ValidateArgs {
+    /// Period to check, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    /// Check as for `generate --convert-to-usd`: non-USD invoices aren't a currency mismatch
+    #[arg(long)]
+    convert_to_usd: bool,
+
+    /// Check as for `generate --include-invoiceitems`
+    #[arg(long)]
+    include_invoiceitems: bool,
+
+    /// Check as for `generate --basis`
+    #[arg(long, value_enum)]
+    basis: Option<Basis>,
+
+    /// Check as for `generate --lookback-days`
+    #[arg(long, value_name = "DAYS", requires = "basis")]
+    lookback_days: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the findings to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide the progress bar and status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct CustomersArgs {
+    /// List the customers whose own address has no usable state, the address
+    /// their invoices fall back to, and the revenue at stake
+    #[arg(long, required = true)]
+    missing_address: bool,
+
+    /// Check the customers with paid invoices in this period, e.g. Q3-2025 or 2025
+    /// (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the list to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct FixAddressesArgs {
+    /// Check the customers with paid invoices in this period, e.g. Q3-2025 or 2025
+    /// (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    /// Read corrections from a CSV with a header row: customer, line1, line2,
+    /// city, state, postal_code, country. Only listed customers are updated
+    #[arg(long, value_name = "PATH")]
+    from_csv: Option<PathBuf>,
+
+    /// Show the changes without saving them to Stripe
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Save the changes without asking for confirmation
+    #[arg(long, short)]
+    yes: bool,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ReviewArgs {
+    /// Period to review, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    /// Where review decisions are kept (default: tax-report-<period>.review.json).
+    /// Earlier decisions in the file are loaded for editing
+    #[arg(long, value_name = "PATH")]
+    decisions: Option<PathBuf>,
+
+    /// After saving, also write the reviewed report to this file
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Format of the reviewed report (default: the profile's default_format, else tsv)
+    #[arg(long, value_enum, requires = "output")]
+    format: Option<OutputFormat>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide the progress bar and status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum FilingsCommand {
+    /// Record that a period's return was filed, with a checksum of the report submitted
+    Mark(MarkArgs),
+    /// List the filed periods of every profile
+    List,
+}
+
+#[derive(ClapArgs, Debug)]
+struct MarkArgs {
+    /// Period filed, e.g. Q3-2025 or 2025
+    #[arg(long, value_name = "PERIOD")]
+    period: Period,
+
+    /// Confirmation number from the tax authority
+    #[arg(long, value_name = "NUMBER")]
+    confirmation: String,
+
+    /// The report file that was filed
+    #[arg(long, value_name = "PATH")]
+    report: PathBuf,
+
+    /// Date the return was filed, YYYY-MM-DD (default: today)
+    #[arg(long, value_name = "DATE")]
+    filed_on: Option<chrono::NaiveDate>,
+
+    /// Replace an earlier record of the same period
+    #[arg(long)]
+    replace: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct VerifyArgs {
+    /// Archived period, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    /// Directory the report was archived to with `generate --archive-dir`
+    #[arg(long, value_name = "DIR")]
+    archive_dir: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the differences to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct CompareArgs {
+    /// Later period, e.g. Q3-2025 or 2025 (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    current: Option<Period>,
+
+    /// Earlier period (default: the one before --current)
+    #[arg(long, value_name = "PERIOD")]
+    previous: Option<Period>,
+
+    /// Use a report saved with `generate --format json` for the current
+    /// period instead of generating it
+    #[arg(long, value_name = "PATH")]
+    current_report: Option<PathBuf>,
+
+    /// Use a saved JSON report for the previous period
+    #[arg(long, value_name = "PATH")]
+    previous_report: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = CompareFormat::Tsv)]
+    format: CompareFormat,
+
+    /// Write the comparison to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct PayoutsArgs {
+    /// Reconcile the payouts arriving in this period, e.g. Q3-2025 or 2025
+    /// (default: the previous quarter)
+    #[arg(long, value_name = "PERIOD")]
+    period: Option<Period>,
+
+    #[arg(long, value_enum, default_value_t = PayoutFormat::Tsv)]
+    format: PayoutFormat,
+
+    /// Write the reconciliation to a file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+struct StatementArgs {
+    /// Stripe customer ID
+    #[arg(long, value_name = "CUSTOMER_ID")]
+    customer: String,
+
+    /// Calendar year (default: last year)
+    #[arg(long)]
+    year: Option<i32>,
+
+    #[arg(long, value_enum, default_value_t = StatementFormat::Pdf)]
+    format: StatementFormat,
+
+    /// Where to write the statement (default: statement-<customer>-<year>.<format>)
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Hide the progress bar and status messages
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct GenerateArgs {
+    /// Output format (default: the profile's default_format, else tsv).
+    /// Repeat together with --output to write several formats from one fetch
+    #[arg(long, value_enum)]
+    format: Vec<OutputFormat>,
+
+    /// Write the report to a file instead of stdout (`.gz` paths are gzip-compressed).
+    /// May contain {year}, {quarter}, {profile} and {run_id} placeholders.
+    /// Defaults to the profile's output_dir when one is configured.
+    /// When repeated, the Nth --output receives the Nth --format
+    #[arg(long, short, value_name = "PATH")]
+    output: Vec<String>,
+
+    /// Write one CSV per state plus summary.csv into this directory instead of a
+    /// single report (same placeholders as --output)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "format"])]
+    split_by_state: Option<String>,
+
+    /// Render the report through a Tera template instead of a built-in
+    /// format, for bespoke filing worksheets. Written to --output, else the
+    /// profile's output_dir (named for the template, e.g. worksheet.csv.tera
+    /// writes a .csv), else stdout
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["format", "split_by_state"])]
+    template: Option<PathBuf>,
+
+    /// Title printed above the report (same placeholders as --output;
+    /// default: the profile's report_title)
+    #[arg(long, value_name = "TEMPLATE")]
+    title: Option<String>,
+
+    /// Add sales and tax per month to each state: a table in each TSV and
+    /// CSV state section, a table after the HTML summary, and a `months`
+    /// array on each JSON state. For reconciling against monthly books.
+    #[arg(long)]
+    monthly_breakdown: bool,
+
+    /// Leave out the invoice rows: only each state's invoice count and
+    /// subtotals, and the grand total
+    #[arg(long, conflicts_with = "split_by_state")]
+    summary: bool,
+
+    /// Report the previous calendar quarter, or the previous calendar year
+    /// with each quarter's sales and tax per state in the summary
+    #[arg(long, value_enum, default_value_t = PeriodLength::Quarter)]
+    period: PeriodLength,
+
+    /// How invoice dates are written in TSV, CSV and HTML reports (JSON
+    /// dates are always ISO 8601)
+    #[arg(long, value_enum, default_value_t = DateFormat::Us)]
+    date_format: DateFormat,
+
+    /// Add Invoice Number and Invoice ID columns to each row, to trace it back
+    /// to Stripe (default: true for CSV, false for TSV; JSON always has both)
+    #[arg(long, value_name = "BOOL")]
+    invoice_columns: Option<bool>,
+
+    /// Choose and order the columns of the invoice rows in TSV and CSV
+    /// reports, e.g. `date,customer,state,licenses,tax,total`. Subtotals, the
+    /// state summary and the HTML table keep the money columns chosen, in order.
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "COLUMNS")]
+    columns: Vec<Column>,
+
+    /// Add a State Source column to TSV and CSV rows, naming where each
+    /// invoice's state came from (customer address, card billing address,
+    /// invoice address or ZIP code), to audit destination sourcing
+    #[arg(long)]
+    state_source: bool,
+
+    /// Add a Plan column to TSV and CSV rows, naming the subscription plan
+    /// (price nickname) each invoice bills
+    #[arg(long)]
+    plan: bool,
+
+    /// Month (1-12) the fiscal year starts in, to report fiscal quarters and
+    /// years instead of calendar ones (default: profile fiscal_year_start)
+    #[arg(long, value_name = "MONTH", value_parser = clap::value_parser!(u32).range(1..=12))]
+    fiscal_year_start: Option<u32>,
+
+    /// Follow the Fees column with card processing, ACH processing, Stripe
+    /// Tax and other fees, split from each balance transaction's fee details
+    #[arg(long)]
+    fee_breakdown: bool,
+
+    /// Follow the Tax column with State Tax and Local Tax (county, city and
+    /// district), split by the jurisdictions of each invoice's tax rates
+    #[arg(long)]
+    tax_breakdown: bool,
+
+    /// Convert non-USD invoices to USD at the exchange rate Stripe applied to
+    /// the charge, instead of reporting them in separate per-currency sections
+    #[arg(long)]
+    convert_to_usd: bool,
+
+    /// How rows are aggregated within each state: one per invoice, or one per
+    /// parent account for customers mapped to a parent (profile parent_accounts
+    /// or the customer's parent_account metadata)
+    #[arg(long, value_enum, default_value_t = Rollup::Invoice)]
+    rollup: Rollup,
+
+    /// How the report is broken down into sections with subtotals: by state,
+    /// customer, month, country or subscription plan, or by state followed by
+    /// revenue and tax subtotals per product
+    #[arg(long, value_enum, default_value_t = GroupBy::State)]
+    group_by: GroupBy,
+
+    /// Count one-off invoice items (setup fees, overages) in Licenses alongside
+    /// subscription lines
+    #[arg(long)]
+    include_invoiceitems: bool,
+
+    /// Also report paid Checkout Sessions and standalone PaymentIntents that
+    /// no invoice covers
+    #[arg(long)]
+    include_payments: bool,
+
+    /// How to report invoices settled partly with credit notes or customer
+    /// credit balance: at the invoice amount, or only the share paid in cash
+    #[arg(long, value_enum, default_value_t = CreditBasis::Invoice)]
+    credit_basis: CreditBasis,
+
+    /// Accounting basis: accrual includes invoices finalized in the period
+    /// whether paid or not; cash includes only amounts collected in the
+    /// period, partial payments included. Without it, paid invoices are
+    /// included by creation date.
+    #[arg(long, value_enum)]
+    basis: Option<Basis>,
+
+    /// With --basis, how many days before the period to look for invoices
+    /// finalized or paid in it
+    #[arg(long, value_name = "DAYS", requires = "basis")]
+    lookback_days: Option<u32>,
+
+    /// Flag invoices whose tax doesn't match the expected rate at their
+    /// destination (bundled state ranges, narrowed by the profile's tax_rates)
+    #[arg(long)]
+    check_rates: bool,
+
+    /// Follow the money columns of TSV and CSV reports with each row's and
+    /// subtotal's effective tax rate (tax / taxable sales), marking rows
+    /// outside the expected rate at their destination (implies --check-rates)
+    #[arg(long)]
+    effective_rate: bool,
+
+    /// Take the Texas timely filing discount (0.5%), or that plus the
+    /// prepayment discount (1.75% in all), off the TX tax and add a table of
+    /// tax due after discount per state
+    #[arg(long, value_enum, value_name = "DISCOUNT")]
+    texas_discount: Option<FilingDiscount>,
+
+    /// What each row stands for: an invoice, or one subscription line of an
+    /// invoice (product, description, quantity, amount and tax)
+    #[arg(long, value_enum, default_value_t = Detail::Invoice, conflicts_with = "rollup")]
+    detail: Detail,
+
+    /// Where invoices are read from
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Aggregate every connected account into one report with an Account column
+    #[arg(long, conflicts_with_all = ["fixture", "sqlite", "postgres", "connected_account"])]
+    all_connected_accounts: bool,
+
+    /// Refuse to run unless the report is built from the Stripe API with a
+    /// live key, so numbers from test data can't end up in a filing
+    #[arg(long, conflicts_with_all = ["fixture", "sqlite", "postgres"])]
+    require_live: bool,
+
+    /// Build the report from a Stripe Report Run for the period instead of
+    /// listing invoices: one CSV download rather than several API calls per
+    /// invoice, for very large accounts
+    #[arg(
+        long,
+        requires = "report_type",
+        conflicts_with_all = [
+            "fixture", "sqlite", "postgres", "all_connected_accounts", "test_clock", "resume", "basis", "include_payments",
+            "allow_writes"
+        ]
+    )]
+    report_run: bool,
+
+    /// Report type to run with `--report-run`; its report needs a `tax` column
+    #[arg(long, value_name = "TYPE", requires = "report_run")]
+    report_type: Option<String>,
+
+    /// After generating, tag each reported invoice in Stripe with
+    /// `tax_report_period=Q<n>-<year>` metadata (requires a key with write access)
+    #[arg(long, conflicts_with_all = ["fixture", "sqlite", "postgres"])]
+    allow_writes: bool,
+
+    /// Report relative to a Stripe test clock's simulated time, including only
+    /// invoices generated under that clock (test mode keys only)
+    #[arg(long, value_name = "CLOCK_ID", conflicts_with = "all_connected_accounts")]
+    test_clock: Option<String>,
+
+    /// Report invoices with no state under an UNKNOWN section instead of skipping them
+    #[arg(long)]
+    allow_missing_state: bool,
+
+    /// Leave out invoices of customers with this metadata value, e.g.
+    /// `internal=true` (repeatable; added to the profile's exclude_metadata)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_metadata_filter)]
+    exclude_metadata: Vec<(String, String)>,
+
+    /// Leave out invoices of the customer IDs listed in this file, one per
+    /// line (added to the profile's exclude_customers)
+    #[arg(long, value_name = "PATH")]
+    exclude_list: Option<PathBuf>,
+
+    /// Report customers under the display names in this TOML file of
+    /// `"Stripe name or customer ID" = "Display name"` (added to the
+    /// profile's customer_aliases)
+    #[arg(long, value_name = "PATH")]
+    customer_aliases: Option<PathBuf>,
+
+    /// Replace customer names with stable pseudonyms (`Customer 3f2a9c1d`) and
+    /// leave out tax IDs, to share the report without the client list.
+    /// Amounts, states and invoice IDs are kept; the archive keeps the names.
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Add sales made outside Stripe from a CSV file with date, customer,
+    /// state, amount and tax columns (amounts in dollars); rows dated
+    /// outside the period are ignored
+    #[arg(long, value_name = "PATH")]
+    merge_csv: Option<PathBuf>,
+
+    /// Also write a JSON Lines audit trail: one line per record and per
+    /// excluded invoice, with its Stripe customer, charge and balance
+    /// transaction IDs, the raw Stripe amounts and every decision made
+    #[arg(long, value_name = "PATH")]
+    audit_file: Option<PathBuf>,
+
+    /// Time budget such as `10m` or `1h30m`. When it runs out, stop fetching,
+    /// write a clearly marked partial report and save a checkpoint to finish later
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, conflicts_with = "all_connected_accounts")]
+    deadline: Option<Duration>,
+
+    /// Where to save the checkpoint of a partial report
+    /// (default: tax-report-Q<n>-<year>.checkpoint.json)
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<PathBuf>,
+
+    /// Finish a partial report from its checkpoint (same data source and period)
+    #[arg(long, value_name = "PATH", conflicts_with = "all_connected_accounts")]
+    resume: Option<PathBuf>,
+
+    /// Also save the report as JSON, with the Stripe data it was built from and
+    /// the options used, under <DIR>/<period>/ for `verify` to check later
+    #[arg(long, value_name = "DIR")]
+    archive_dir: Option<PathBuf>,
+
+    /// Apply the exclusions and notes saved by `review` to this report
+    #[arg(long, value_name = "PATH")]
+    review: Option<PathBuf>,
+
+    /// Exit with an error instead of writing a report if any invoice had to be excluded
+    #[arg(long)]
+    fail_on_skip: bool,
+
+    /// Fetch and process the period, then print only the counts of invoices,
+    /// states and problems found, without writing, archiving or tagging
+    /// anything. For checking a profile and the connection to Stripe.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Process only the first N invoices fetched, for a quick check of the
+    /// setup. The totals are a sample, so the report can't be archived or
+    /// tagged in Stripe.
+    #[arg(long, value_name = "N", conflicts_with_all = ["archive_dir", "allow_writes", "resume", "report_run"])]
+    limit: Option<usize>,
+
+    /// Hide the progress bar and status messages (warnings are still shown)
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Prefix of the environment variables that set command-line options
+const ENV_PREFIX: &str = "STR_TAX_";
+
+/// Let every option be set from the environment as `STR_TAX_<OPTION>`
+/// (`STR_TAX_FORMAT=csv`, `STR_TAX_SPLIT_BY_STATE=...`), for containers
+/// configured without a command line. Flags on the command line win.
+fn with_env_options(command: clap::Command) -> clap::Command {
+    command
+        .mut_args(|arg| {
+            if arg.is_positional() || arg.get_env().is_some() {
+                return arg;
+            }
+            let var = format!("{}{}", ENV_PREFIX, arg.get_id().as_str().to_uppercase());
+            arg.env(var)
+        })
+        .mut_subcommands(with_env_options)
+}
+
+/// Reject the top-level `generate` options when a subcommand is given, where
+/// they would be ignored (`--fixture x.json compare` would query Stripe).
+/// Global options such as `--profile` go with any subcommand.
+fn reject_generate_options(command: &mut clap::Command, matches: &clap::ArgMatches) {
+    let Some((name, _)) = matches.subcommand() else { return };
+    let given: Vec<String> = GenerateArgs::augment_args(clap::Command::new("generate"))
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(clap::parser::ValueSource::CommandLine))
+        .map(|arg| match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => arg.get_id().to_string(),
+        })
+        .collect();
+    if !given.is_empty() {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("the subcommand '{}' cannot be used with {}", name, given.join(", ")),
+            )
+            .exit();
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    match args.command {
-        Some(Commands::Generate) | None => {
-            // Prefer production API key, fall back to test key
-            let api_key = std::env::var("STRIPE_PROD_API_KEY")
-                .or_else(|_| std::env::var("STRIPE_API_KEY"))
-                .map_err(|_| anyhow::anyhow!("Neither STRIPE_PROD_API_KEY nor STRIPE_API_KEY environment variable is set"))?;
-
-            let (start_date, end_date, quarter, year) = get_previous_quarter();
-            eprintln!("Generating report for Q{} {} ({} to {})", quarter, year, start_date, end_date);
-
-            // Convert dates to Unix timestamps
-            let start_timestamp = start_date.and_hms_opt(0, 0, 0)
-                .ok_or(anyhow::anyhow!("Invalid start date"))?
-                .and_utc()
-                .timestamp();
-
-            let end_timestamp = end_date.and_hms_opt(23, 59, 59)
-                .ok_or(anyhow::anyhow!("Invalid end date"))?
-                .and_utc()
-                .timestamp();
-
-            let client = StripeClient::new(api_key);
-            eprintln!("Fetching invoices from Stripe...");
-
-            let invoices = client.fetch_paid_invoices(start_timestamp, end_timestamp).await?;
-            eprintln!("Retrieved {} invoices", invoices.len());
-
-            let mut generator = ReportGenerator::new();
-
-            // Process each invoice
-            let mut processed = 0;
-            let mut skipped = 0;
-            for invoice in invoices {
-                // Extract customer ID
-                let customer_id = match &invoice.customer {
-                    serde_json::Value::String(s) if !s.is_empty() => s.clone(),
-                    serde_json::Value::Object(obj) => {
-                        if let Some(id) = obj.get("id").and_then(|v| v.as_str()) {
-                            id.to_string()
-                        } else {
-                            eprintln!("Warning: Skipping invoice {}: No customer ID found", invoice.id);
-                            skipped += 1;
-                            continue;
-                        }
-                    }
-                    _ => {
-                        eprintln!("Warning: Skipping invoice {}: No customer ID found", invoice.id);
-                        skipped += 1;
-                        continue;
-                    }
-                };
+    // Variables already set win over the .env file
+    match dotenvy::dotenv() {
+        Ok(_) => {}
+        Err(e) if e.not_found() => {}
+        Err(e) => return Err(e).context("Failed to load .env"),
+    }
+    let mut command = with_env_options(Args::command());
+    let matches = command.get_matches_mut();
+    reject_generate_options(&mut command, &matches);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    init_logging(args.log_level.as_deref(), args.log_format)?;
 
-                // Fetch customer details
-                match client.fetch_customer(&customer_id).await {
-                    Ok(customer) => {
-                        let mut charge_data = None;
-                        let mut balance_transaction = None;
-
-                        if let Some(charge_value) = &invoice.charge {
-                            if let serde_json::Value::String(charge_id) = charge_value {
-                                // Fetch the charge to get its balance_transaction ID and billing address
-                                if let Ok(charge) = client.fetch_charge(charge_id).await {
-                                    // Extract balance_transaction for fees
-                                    if let Some(balance_tx_id) = &charge.balance_transaction {
-                                        if let Ok(bt) = client.fetch_balance_transaction(balance_tx_id).await {
-                                            balance_transaction = Some(bt);
-                                        }
-                                    }
-                                    // Store charge for state fallback
-                                    charge_data = Some(charge);
-                                }
-                            }
-                        }
-
-                        match generator.process_invoice_with_customer(
-                            invoice.clone(),
-                            Some(&customer),
-                            charge_data.as_ref(),
-                            balance_transaction.as_ref()
-                        ) {
-                            Ok(_) => processed += 1,
-                            Err(e) => {
-                                eprintln!("Warning: Skipping invoice {}: {}", invoice.id, e);
-                                skipped += 1;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Warning: Skipping invoice {}: Failed to fetch customer: {}", invoice.id, e);
-                        skipped += 1;
-                    }
+    let config_path = match args.config {
+        Some(path) => path,
+        None => Config::default_path()?,
+    };
+    let mut config = Config::load(&config_path)?;
+    if args.live {
+        config.mode = Some(KeyMode::Live);
+    } else if args.test {
+        config.mode = Some(KeyMode::Test);
+    }
+    config.key_source = match (args.api_key_file, args.api_key_cmd) {
+        (Some(path), _) => Some(KeySource::File(path)),
+        (None, Some(command)) => Some(KeySource::Command(command)),
+        (None, None) => None,
+    };
+    config.strict_parse = args.strict_parse;
+    API_USAGE.get_or_init(|| Arc::new(ApiUsage::new(args.max_requests)));
+    let clock: Box<dyn Clock> = match args.as_of {
+        Some(date) => Box::new(DateClock::new(date)),
+        None => Box::new(SystemClock),
+    };
+    let clock = clock.as_ref();
+
+    // `?` in an arm ends the block, not main, so usage is reported either way
+    let result = async {
+        match args.command {
+            Some(Commands::InitWizard) => init_wizard(config, &config_path),
+            Some(Commands::Account) => account(&config, args.profile.as_deref()).await,
+            Some(Commands::Completions(completions_args)) => completions(completions_args, clock),
+            Some(Commands::Statement(statement_args)) => {
+                statement(statement_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Payouts(payouts_args)) => {
+                payouts(payouts_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Compare(compare_args)) => {
+                compare(compare_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Verify(verify_args)) => {
+                verify(verify_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Amend(amend_args)) => {
+                amend(amend_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Review(review_args)) => {
+                review(review_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Filings { command }) => {
+                filings(command, &config, &config_path, args.profile.as_deref(), clock)
+            }
+            Some(Commands::FixAddresses(fix_args)) => {
+                fix_addresses(fix_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Customers(customers_args)) => {
+                customers(customers_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Validate(validate_args)) => {
+                validate(validate_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Registrations(registrations_args)) => {
+                registrations(registrations_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Deadlines(deadlines_args)) => {
+                deadlines(deadlines_args, &config, args.profile.as_deref(), clock)
+            }
+            Some(Commands::Listen(listen_args)) => listen(listen_args, &config, args.profile.as_deref()).await,
+            Some(Commands::BackfillEvents(backfill_args)) => {
+                backfill_events(backfill_args, &config, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Serve(serve_args)) => serve(serve_args, &config, args.profile.as_deref()).await,
+            Some(Commands::Schedule(schedule_args)) => {
+                schedule(*schedule_args, &config, &config_path, args.profile.as_deref(), clock).await
+            }
+            Some(Commands::Generate(generate_args)) => {
+                let filings = Filings::load(&Filings::path_for(&config_path))?;
+                generate(*generate_args, &config, &filings, args.profile.as_deref(), clock).await?;
+                Ok(())
+            }
+            None => {
+                let filings = Filings::load(&Filings::path_for(&config_path))?;
+                generate(args.generate, &config, &filings, args.profile.as_deref(), clock).await?;
+                Ok(())
+            }
+        }
+    }
+    .await;
+    report_api_usage(args.api_usage);
+    result
+}
+
+/// Print the completion script for a shell. Options taking a period complete
+/// the quarters and years of the last three years.
+fn completions(args: CompletionsArgs, clock: &dyn Clock) -> Result<()> {
+    use chrono::Datelike;
+
+    let today = clock.today(None);
+    let (year, quarter) = (today.year(), today.month0() / 3 + 1);
+    let periods: Vec<String> = (year - 2..=year)
+        .rev()
+        .flat_map(|y| {
+            let last = if y == year { quarter } else { 4 };
+            (1..=last).rev().map(move |q| format!("Q{}-{}", q, y)).chain([y.to_string()])
+        })
+        .collect();
+    let mut command = with_period_values(Args::command(), &periods);
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, &mut command, env!("CARGO_PKG_NAME"), &mut script);
+    std::io::Write::write_all(&mut std::io::stdout(), &script)?;
+    Ok(())
+}
+
+/// Give every `PERIOD` option `periods` as its possible values, which only
+/// the completion script sees
+fn with_period_values(command: clap::Command, periods: &[String]) -> clap::Command {
+    command
+        .mut_args(|arg| {
+            let is_period = arg.get_value_names().is_some_and(|names| names.iter().any(|name| name == "PERIOD"));
+            if is_period && arg.get_possible_values().is_empty() {
+                arg.value_parser(clap::builder::PossibleValuesParser::new(periods.iter().cloned()))
+            } else {
+                arg
+            }
+        })
+        .mut_subcommands(|subcommand| with_period_values(subcommand, periods))
+}
+
+/// Send `tracing` events to stderr, filtered by `--log-level` (or `RUST_LOG`)
+fn init_logging(level: Option<&str>, format: LogFormat) -> Result<()> {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()));
+
+    match format {
+        LogFormat::Text => builder.without_time().with_target(false).init(),
+        LogFormat::Json => builder.json().with_current_span(true).init(),
+    }
+
+    Ok(())
+}
+
+/// Requests made by every Stripe client in this run
+static API_USAGE: OnceLock<Arc<ApiUsage>> = OnceLock::new();
+
+/// Log how many Stripe API requests the run made, and with `--api-usage`
+/// print them per endpoint. A run stopped by `--max-requests` always prints
+/// the one-line summary, whatever the log level.
+fn report_api_usage(per_endpoint: bool) {
+    let Some(usage) = API_USAGE.get() else { return };
+    if usage.total().requests == 0 {
+        return;
+    }
+    if usage.exhausted() {
+        eprintln!("Stripe API usage: {}", usage.brief());
+    } else {
+        tracing::info!("Made {}", usage.brief());
+    }
+    if per_endpoint {
+        eprint!("\n{}", usage.summary());
+    }
+}
+
+/// Client for the Stripe API: the profile's key if configured, else the
+/// production key, falling back to the test key. The key must be of the
+/// profile's mode (or `--live`/`--test`) when one is set.
+fn stripe_client(profile: &Profile) -> Result<StripeClient> {
+    let source = KeySource::resolve(profile)?;
+    let client = StripeClient::new(source.read()?)
+        .with_usage(API_USAGE.get_or_init(Arc::default).clone())
+        .with_strict_parse(profile.strict_parse);
+    if let Some(expected) = profile.mode {
+        client.require_mode(expected).map_err(|e| anyhow::anyhow!("{} (from {})", e, source))?;
+    }
+    match client.key_mode() {
+        Some(mode) => tracing::info!("Using a {} mode key from {}", mode, source),
+        None => tracing::warn!("Can't tell whether the API key from {} is live or test", source),
+    }
+    Ok(client)
+}
+
+/// Print the account the profile's API key belongs to and the key's mode,
+/// to check which account a report would be built from before running it
+async fn account(config: &Config, profile_name: Option<&str>) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let source = KeySource::resolve(&profile)?;
+    let client = stripe_client(&profile)?;
+    let account = client.fetch_account().await?;
+    let mode = match client.key_mode() {
+        Some(mode) => mode.to_string(),
+        None => "unknown".to_string(),
+    };
+
+    println!("Business: {}", account.business_name().unwrap_or("(no name)"));
+    println!("Account:  {}", account.id);
+    if let Some(email) = &account.email {
+        println!("Email:    {}", email);
+    }
+    println!("Currency: {}", account.default_currency.as_deref().unwrap_or("(none)").to_uppercase());
+    if let Some(country) = &account.country {
+        println!("Country:  {}", country);
+    }
+    println!("Key:      {} (from {})", mode, source);
+    Ok(())
+}
+
+/// The local store selected with `--sqlite` or `--postgres`, if any
+async fn open_store(sqlite: Option<&std::path::Path>, postgres: Option<&str>) -> Result<Option<StoreSource<StoreBackend>>> {
+    Ok(match (sqlite, postgres) {
+        (Some(path), _) => Some(StoreSource::new(StoreBackend::Sqlite(SqliteStore::open(path)?))),
+        (None, Some(url)) => Some(StoreSource::new(StoreBackend::connect_postgres(url).await?)),
+        (None, None) => None,
+    })
+}
+
+/// Build the report from `source`, or finish one from a checkpoint
+async fn run_report<S: InvoiceSource>(
+    source: &S,
+    resume: Option<Checkpoint>,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    match resume {
+        Some(checkpoint) => resume_report(source, checkpoint, options, progress).await,
+        None => build_report(source, start, end, options, progress).await,
+    }
+}
+
+/// Build the report like `run_report`, also recording the Stripe data it was
+/// built from when `record` is set
+async fn run_recorded<S: InvoiceSource>(
+    source: &S,
+    record: bool,
+    resume: Option<Checkpoint>,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<(ReportGenerator, Option<Fixture>)> {
+    if !record {
+        return Ok((run_report(source, resume, start, end, options, progress).await?, None));
+    }
+    let recording = RecordingSource::new(source);
+    let generator = run_report(&recording, resume, start, end, options, progress).await?;
+    Ok((generator, Some(recording.into_fixture())))
+}
+
+/// Report options that come from the profile rather than the command line
+fn profile_options(profile: &Profile) -> Result<ReportOptions> {
+    Ok(ReportOptions {
+        registered_states: profile.registered_states.clone(),
+        parent_accounts: profile.parent_accounts.clone(),
+        parent_metadata_key: profile.parent_metadata_key.clone(),
+        taxability: Taxability::new(profile.product_categories.clone(), &profile.taxability_rules),
+        exclusions: profile.customer_exclusions(),
+        customer_aliases: profile.aliases(),
+        request_budget: API_USAGE.get().cloned(),
+        timezone: profile.timezone()?,
+        ..Default::default()
+    })
+}
+
+async fn statement(args: StatementArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let year = match args.year {
+        Some(year) => year,
+        None => chrono::Datelike::year(&clock.today(timezone)) - 1,
+    };
+    let (start, end) = Period::annual(year).bounds_in(timezone)?;
+
+    // Every paid invoice belongs on the statement, including one-off items and
+    // invoices without a state on file
+    let options = ReportOptions {
+        allow_missing_state: true,
+        include_invoiceitems: true,
+        parent_accounts: profile.parent_accounts.clone(),
+        parent_metadata_key: profile.parent_metadata_key.clone(),
+        taxability: Taxability::new(profile.product_categories.clone(), &profile.taxability_rules),
+        request_budget: API_USAGE.get().cloned(),
+        timezone,
+        ..Default::default()
+    };
+
+    progress.status(&format!("Building {} statement for {}", year, args.customer));
+    let source = args.source.open(&profile).await?;
+    let generator = build_report(&CustomerScope::new(&source, &args.customer), start, end, &options, &progress).await?;
+    for skip in generator.skipped() {
+        progress.warn(&format!("Invoice {} left off the statement: {}", skip.invoice_id, skip.reason));
+    }
+
+    let statement = Statement::new(&args.customer, year, generator.get_records());
+    let path = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("statement-{}-{}.{}", args.customer, year, args.format.extension())));
+    let mut sink = OutputSink::create(Some(&path))?;
+    statement.write(args.format, &mut sink)?;
+    sink.finish()?;
+    if path != std::path::Path::new("-") {
+        progress.status(&format!("Wrote statement of {} invoices to {}", statement.invoices.len(), path.display()));
+    }
+
+    Ok(())
+}
+
+async fn payouts(args: PayoutsArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    progress.status(&format!("Reconciling payouts for {}", period));
+
+    let source = args.source.open(&profile).await?;
+    let report = build_payout_report(&source, start, end, &progress).await?;
+    for payout in report.unreconciled() {
+        progress.warn(&format!(
+            "Payout {} differs from its transactions by {:.2}",
+            payout.id,
+            payout.difference as f64 / 100.0
+        ));
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    report.write(args.format, &mut sink)?;
+    sink.finish()
+}
+
+async fn compare(args: CompareArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let current = select_period(args.current, &profile, clock.today(timezone))?;
+    let previous = match args.previous {
+        Some(previous) => previous.in_fiscal_year(current.fiscal_start())?,
+        None => current.previous(),
+    };
+    progress.status(&format!("Comparing {} with {}", current, previous));
+
+    let options = profile_options(&profile)?;
+    // Only opened for a period without a saved report
+    let mut source = None;
+
+    let mut totals = Vec::new();
+    for (period, saved) in [(previous, &args.previous_report), (current, &args.current_report)] {
+        let (start, end) = period.bounds_in(timezone)?;
+        totals.push(match saved {
+            Some(path) => load_section_totals(path)?,
+            None => {
+                if source.is_none() {
+                    source = Some(args.source.open(&profile).await?);
                 }
+                let source = source.as_ref().expect("opened above");
+                report_totals(source, start, end, &options, &progress).await?
             }
+        });
+    }
+    let current_totals = totals.pop().expect("two periods");
+    let previous_totals = totals.pop().expect("two periods");
+
+    let comparison = Comparison::new(previous.label(), previous_totals, current.label(), current_totals);
+    for state in comparison.appeared_or_disappeared() {
+        if state.change == StateChange::New {
+            progress.warn(&format!("{} is new in {} (not reported in {})", state.state, current, previous));
+        } else {
+            progress.warn(&format!("{} was reported in {} but not in {}", state.state, previous, current));
+        }
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    comparison.write(args.format, &mut sink)?;
+    sink.finish()
+}
 
-            eprintln!("Processed {} invoices, skipped {}", processed, skipped);
+/// Per-state subtotals and the grand total of a report for one period
+async fn report_totals<S: InvoiceSource>(
+    source: &S,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<(std::collections::BTreeMap<String, Totals>, Totals)> {
+    let generator = build_report(source, start, end, options, progress).await?;
+    Ok(section_totals(&GroupedReport::by_state(generator.get_records())))
+}
 
-            // Sort records (by state, then date, then customer)
-            generator.sort_records();
+/// An archived period's manifest and how the period regenerated from current
+/// data differs from it
+async fn drift_since_archive(
+    args: &VerifyArgs,
+    profile: &Profile,
+    clock: &dyn Clock,
+    progress: &ReportProgress,
+) -> Result<(Period, ArchiveManifest, Drift)> {
+    let timezone = profile.timezone()?;
 
-            // Format and output as TSV (formatter calculates per-state subtotals internally)
-            let tsv_output = format_as_tsv(generator.get_records());
-            println!("{}", tsv_output);
+    let period = select_period(args.period, profile, clock.today(timezone))?;
+    let archive = Archive::for_period(&args.archive_dir, &period.label());
+    let manifest = archive.manifest()?;
+    let archived = archive.records()?;
+    progress.status(&format!("Regenerating {} to check against the report archived {}", period, manifest.generated_at));
+
+    // Regenerate the same span with the options the archived report used
+    let mut options = profile_options(profile)?;
+    manifest.apply(&mut options);
+    let source = args.source.open(profile).await?;
+    let mut generator = build_report(&source, manifest.start, manifest.end, &options, progress).await?;
+    for record in &manifest.external_sales {
+        generator.add_record(record.clone());
+    }
+    if let Some(review) = &manifest.review {
+        review.apply(&mut generator);
+    }
+
+    let drift = Drift::new(period.label(), &archived, generator.get_records());
+    Ok((period, manifest, drift))
+}
+
+async fn verify(args: VerifyArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+
+    let (period, _, drift) = drift_since_archive(&args, &profile, clock, &progress).await?;
+    if drift.is_empty() {
+        progress.status(&format!("{} matches the archived report", period));
+        return Ok(());
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    drift.write(args.format, &mut sink)?;
+    sink.finish()?;
+    anyhow::bail!(
+        "{} invoice(s) in {} changed since the report was archived (tax {:+.2}); an amended return may be needed",
+        drift.invoices.len(),
+        period,
+        drift.tax_delta() as f64 / 100.0
+    )
+}
+
+async fn amend(args: VerifyArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+
+    let (period, manifest, drift) = drift_since_archive(&args, &profile, clock, &progress).await?;
+    let amendment = Amendment::new(drift, manifest.generated_at);
+    if amendment.is_empty() {
+        progress.status(&format!("No state's sales or tax changed since {} was archived; nothing to amend", period));
+        return Ok(());
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    amendment.write(args.format, &mut sink)?;
+    sink.finish()?;
+    progress.status(&format!(
+        "{} state(s) to amend for {}; net tax due {:+.2}",
+        amendment.jurisdictions.len(),
+        period,
+        amendment.net_tax_due as f64 / 100.0
+    ));
+    Ok(())
+}
 
+async fn review(args: ReviewArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    let path = args.decisions.unwrap_or_else(|| PathBuf::from(format!("tax-report-{}.review.json", period.label())));
+    let mut decisions = ReviewDecisions::load(&path)?;
+
+    let options = profile_options(&profile)?;
+    let source = args.source.open(&profile).await?;
+    let mut generator = build_report(&source, start, end, &options, &progress).await?;
+    generator.sort_records();
+
+    let outcome =
+        run_review(&period.label(), generator.get_records(), generator.skipped(), &mut decisions)?;
+    if outcome == ReviewOutcome::Discard {
+        progress.status("Review closed without saving");
+        return Ok(());
+    }
+    decisions.save(&path)?;
+    progress.status(&format!("Saved review decisions to {}; apply them with generate --review {}", path.display(), path.display()));
+
+    if let Some(output) = &args.output {
+        decisions.apply(&mut generator);
+        let report = GroupedReport::by_state(generator.get_records())
+            .with_skipped(generator.skipped())
+            .with_diagnostics(generator.diagnostics());
+        let format = args.format.or(profile.default_format).unwrap_or(OutputFormat::Tsv);
+        let mut sink = OutputSink::create(Some(output))?;
+        format.formatter().write_report(&report, &mut sink)?;
+        sink.finish()?;
+        progress.status(&format!("Wrote the reviewed report to {}", output.display()));
+    }
+    Ok(())
+}
+
+fn filings(
+    command: FilingsCommand,
+    config: &Config,
+    config_path: &std::path::Path,
+    profile_name: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let path = Filings::path_for(config_path);
+    let mut filings = Filings::load(&path)?;
+
+    match command {
+        FilingsCommand::Mark(args) => {
+            let profile = config.select(profile_name)?;
+            let profile_name = profile_name.unwrap_or(DEFAULT_PROFILE);
+            if let Some(filed) = filings.get(profile_name, &args.period)
+                && !args.replace
+            {
+                anyhow::bail!(
+                    "{} was already marked as filed on {} (confirmation {}); pass --replace to record an amended filing",
+                    args.period,
+                    filed.filed_on,
+                    filed.confirmation
+                );
+            }
+            let filing = Filing {
+                profile: profile_name.to_string(),
+                period: args.period.label(),
+                filed_on: match args.filed_on {
+                    Some(date) => date,
+                    None => clock.today(profile.timezone()?),
+                },
+                confirmation: args.confirmation,
+                checksum: filings::checksum(&args.report)?,
+                report: std::path::absolute(&args.report)?,
+            };
+            println!("Marked {} as filed on {} (report sha256 {})", args.period, filing.filed_on, filing.checksum);
+            filings.record(filing);
+            filings.save(&path)
+        }
+        FilingsCommand::List => {
+            if filings.filings.is_empty() {
+                println!("No periods have been marked as filed ({})", path.display());
+                return Ok(());
+            }
+            println!("Profile\tPeriod\tFiled On\tConfirmation\tReport\tSHA-256");
+            for filing in &filings.filings {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    filing.profile,
+                    filing.period,
+                    filing.filed_on,
+                    filing.confirmation,
+                    filing.report.display(),
+                    filing.checksum
+                );
+            }
             Ok(())
         }
     }
 }
+
+async fn fix_addresses(args: FixAddressesArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    use std::io::Write;
+
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    progress.status(&format!("Checking customer addresses for {}", period));
+
+    let source = args.source.open(&profile).await?;
+    // Corrections go to Stripe, even when problems were found in a store
+    let client = match &source {
+        SelectedSource::Fixture(_) => None,
+        SelectedSource::Store(_) => Some(args.source.stripe_client(&profile)?),
+        SelectedSource::Stripe(client) => Some(client.clone()),
+    };
+    let problems = find_address_problems(&source, start, end, &progress).await?;
+
+    let stdin = std::io::stdin();
+    let corrections = match &args.from_csv {
+        Some(path) => {
+            let csv = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read corrections {}", path.display()))?;
+            parse_corrections(&csv)?
+        }
+        None if problems.is_empty() => {
+            println!("Every customer invoiced in {} has a usable address", period);
+            return Ok(());
+        }
+        None => {
+            println!("{} customer(s) invoiced in {} have no usable state:", problems.len(), period);
+            prompt_corrections(&mut stdin.lock(), &mut std::io::stdout(), &problems)?
+        }
+    };
+    if corrections.is_empty() {
+        println!("No addresses to update");
+        return Ok(());
+    }
+
+    println!("\nAddresses to save:");
+    for correction in &corrections {
+        let current = problems.iter().find(|p| p.customer_id == correction.customer_id);
+        println!(
+            "  {}: {} -> {}",
+            correction.customer_id,
+            match current {
+                Some(problem) => format_address(problem.address.as_ref()),
+                None => "(not flagged)".to_string(),
+            },
+            format_address(Some(&correction.address))
+        );
+    }
+    if args.dry_run {
+        println!("Dry run; nothing was saved");
+        return Ok(());
+    }
+    let Some(client) = &client else {
+        unreachable!("--fixture requires --dry-run");
+    };
+    if !args.yes {
+        print!("Update {} customer(s) in Stripe? [y/N] ", corrections.len());
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        stdin.read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Nothing was saved");
+            return Ok(());
+        }
+    }
+
+    let mut failed = 0;
+    for correction in &corrections {
+        if let Err(e) = client.update_customer_address(&correction.customer_id, &correction.address).await {
+            progress.warn(&format!("{:#}", e));
+            failed += 1;
+        }
+    }
+    let updated = corrections.len() - failed;
+    progress.status(&format!("Updated {} customer address(es) in Stripe", updated));
+    if matches!(source, SelectedSource::Store(_)) && updated > 0 {
+        progress.warn("The local store still has the old addresses until its next sync");
+    }
+    if failed > 0 {
+        anyhow::bail!("{} customer address(es) could not be updated", failed);
+    }
+    Ok(())
+}
+
+async fn customers(args: CustomersArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    progress.status(&format!("Checking customer addresses for {}", period));
+
+    let source = args.source.open(&profile).await?;
+    let problems = find_address_problems(&source, start, end, &progress).await?;
+
+    let audit = AddressAudit::new(period.label(), problems);
+    if audit.customers.is_empty() {
+        progress.status(&format!("Every customer invoiced in {} has a usable address", period));
+    } else {
+        progress.status(&format!(
+            "{} customer(s) without a usable state, {:.2} in revenue ({:.2} with no fallback); fix them with fix-addresses",
+            audit.customers.len(),
+            audit.revenue as f64 / 100.0,
+            audit.unresolved_revenue as f64 / 100.0
+        ));
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    audit.write(args.format, &mut sink)?;
+    sink.finish()
+}
+
+async fn validate(args: ValidateArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    progress.status(&format!("Validating {}", period));
+
+    // Invoices without a state are reported under UNKNOWN so they're counted, not
+    // just skipped. Tax is expected wherever the business is registered, else in
+    // its home state.
+    let registered_states = if profile.registered_states.is_empty() {
+        profile.home_state.iter().cloned().collect()
+    } else {
+        profile.registered_states.clone()
+    };
+    let options = ReportOptions {
+        allow_missing_state: true,
+        registered_states,
+        rates: Some(profile.rate_table()),
+        convert_to_usd: args.convert_to_usd,
+        include_invoiceitems: args.include_invoiceitems,
+        basis: args.basis,
+        lookback_days: args.lookback_days,
+        ..profile_options(&profile)?
+    };
+    let source = args.source.open(&profile).await?;
+    let generator = build_report(&source, start, end, &options, &progress).await?;
+    let validation = Validation::new(period.label(), &generator);
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    validation.write(args.format, &mut sink)?;
+    sink.finish()?;
+
+    let blockers = validation.blockers();
+    if blockers > 0 {
+        anyhow::bail!("{} invoice(s) in {} need fixing before filing", blockers, period);
+    }
+    progress.status(&format!("{} is ready to file", period));
+    Ok(())
+}
+
+async fn listen(args: ListenArgs, config: &Config, profile_name: Option<&str>) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let secret = match args.webhook_secret {
+        Some(secret) => secret,
+        None => std::env::var("STRIPE_WEBHOOK_SECRET")
+            .map_err(|_| anyhow::anyhow!("Pass --webhook-secret or set STRIPE_WEBHOOK_SECRET"))?,
+    };
+    let store = open_store(args.sqlite.as_deref(), args.postgres.as_deref())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Pass --sqlite or --postgres to store events in"))?;
+    // With an API key, invoices are refetched with the expansions reports need
+    let client = match stripe_client(&profile) {
+        Ok(client) => Some(client),
+        Err(e) => {
+            tracing::warn!("{}; storing event payloads as sent", e);
+            None
+        }
+    };
+    stripe_tax_reporter::source::webhook::listen(&args.http.options(args.bind), store, secret, client).await
+}
+
+async fn schedule(
+    args: ScheduleArgs,
+    config: &Config,
+    config_path: &std::path::Path,
+    profile_name: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    if args.generate.archive_dir.is_none() {
+        anyhow::bail!("schedule needs --archive-dir to keep each run's report");
+    }
+    let schedule = CronSchedule::parse(&args.cron, profile.timezone()?)?;
+    let profile_label = profile_name.unwrap_or(DEFAULT_PROFILE);
+
+    let mut run_now = args.run_now;
+    loop {
+        if !run_now {
+            let next = schedule.next_after(chrono::Utc::now())?;
+            tracing::info!("Next report run at {}", next.to_rfc3339());
+            let wait = (next - chrono::Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            }
+        }
+        run_now = false;
+
+        // Reloaded each run so periods filed since the last one are respected
+        let result = match Filings::load(&Filings::path_for(config_path)) {
+            Ok(filings) => generate(args.generate.clone(), config, &filings, profile_name, clock).await,
+            Err(e) => Err(e),
+        };
+        let message = match &result {
+            Ok((period, archived)) => {
+                tracing::info!("Regenerated the {} report", period);
+                schedule_message(*period, profile_label, archived)
+            }
+            Err(e) => {
+                tracing::error!("Scheduled report run failed: {:#}", e);
+                format!("Scheduled sales tax report run ({}) failed: {:#}", profile_label, e)
+            }
+        };
+        if let Some(url) = &args.notify_webhook
+            && let Err(e) = notify(url, &message).await
+        {
+            tracing::warn!("{:#}", e);
+        }
+    }
+}
+
+/// The notification for a scheduled run that generated `period`'s report,
+/// saying where (or why not) it was archived
+fn schedule_message(period: Period, profile_label: &str, archived: &Archived) -> String {
+    let generated = format!("Sales tax report for {} ({}) regenerated", period, profile_label);
+    match archived {
+        Archived::Saved(dir) => format!("{} and archived to {}", generated, dir.display()),
+        Archived::Partial => format!("{}, but not archived because it is partial", generated),
+        Archived::Filed(dir) => {
+            format!("{}; the period is filed, so the report archived in {} was kept", generated, dir.display())
+        }
+        Archived::No => format!("{} (not archived)", generated),
+    }
+}
+
+async fn serve(args: ServeArgs, config: &Config, profile_name: Option<&str>) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let mut tokens = profile
+        .report_tokens
+        .iter()
+        .map(|token| Ok(ApiToken { name: token.name.clone(), key: token.resolve()?, scope: token.scope }))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(key) = args.api_key.or_else(|| std::env::var("TAX_REPORT_API_KEY").ok()) {
+        tokens.push(ApiToken { name: "api-key".to_string(), key, scope: TokenScope::Admin });
+    }
+    let options = profile_options(&profile)?;
+    let http = args.http.options(args.bind);
+
+    // With an API key, admin tokens can sync a store from Stripe's events
+    let source = args.source.open(&profile).await?;
+    let sync_client = match source {
+        SelectedSource::Store(_) => stripe_client(&profile).ok(),
+        _ => None,
+    };
+    report_server::serve(&http, ReportServer { source, options, tokens, sync_client }).await
+}
+
+async fn backfill_events(
+    args: BackfillEventsArgs,
+    config: &Config,
+    profile_name: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    let store = open_store(args.sqlite.as_deref(), args.postgres.as_deref())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Pass --sqlite or --postgres to store events in"))?;
+    let client = stripe_client(&profile)?;
+
+    if start < clock.now().timestamp() - EVENT_RETENTION_SECS {
+        progress.warn(&format!(
+            "Stripe keeps events for 30 days; events from early in {} may no longer be listed",
+            period
+        ));
+    }
+    progress.status(&format!("Fetching {} events", period));
+    let events = client.fetch_events(&EVENT_TYPES, start, end).await?;
+    let summary = replay_events(&store, &events, Some(&client)).await?;
+    progress.status(&format!(
+        "Stored {} of {} events ({} already in the store)",
+        summary.stored,
+        events.len(),
+        summary.duplicates
+    ));
+    Ok(())
+}
+
+async fn registrations(
+    args: RegistrationsArgs,
+    config: &Config,
+    profile_name: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let timezone = profile.timezone()?;
+
+    let period = select_period(args.period, &profile, clock.today(timezone))?;
+    let (start, end) = period.bounds_in(timezone)?;
+    progress.status(&format!("Checking {} against Stripe Tax registrations", period));
+
+    let options = profile_options(&profile)?;
+    let fixture = args.fixture.as_ref().map(FixtureSource::from_path).transpose()?;
+    let store = open_store(args.sqlite.as_deref(), args.postgres.as_deref()).await?;
+    let (generator, registrations) = if let Some(source) = &fixture {
+        (build_report(source, start, end, &options, &progress).await?, source.tax_registrations().to_vec())
+    } else {
+        let mut stripe = stripe_client(&profile)?;
+        if let Some(account) = &args.connected_account {
+            stripe = stripe.for_account(account);
+        }
+        let generator = match &store {
+            Some(source) => build_report(source, start, end, &options, &progress).await?,
+            None => build_report(&stripe, start, end, &options, &progress).await?,
+        };
+        (generator, stripe.fetch_tax_registrations().await?)
+    };
+
+    let check = RegistrationCheck::new(period.label(), &registrations, generator.get_records(), start, end);
+    for state in check.unregistered() {
+        progress.warn(&format!(
+            "{} has {:.2} of sales in {} invoice(s) in {} but no Stripe Tax registration",
+            state.state,
+            state.sales as f64 / 100.0,
+            state.invoices,
+            period
+        ));
+    }
+    for state in check.idle() {
+        progress.warn(&format!("{} is registered ({}) but had no sales in {}", state.state, state.registrations.join(", "), period));
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    check.write(args.format, &mut sink)?;
+    sink.finish()
+}
+
+fn deadlines(args: DeadlinesArgs, config: &Config, profile_name: Option<&str>, clock: &dyn Clock) -> Result<()> {
+    let profile = config.select(profile_name)?;
+    let progress = ReportProgress::new(args.quiet);
+    let mut frequencies = profile.state_filing_frequencies();
+    if !args.state.is_empty() {
+        // States the profile doesn't list are filed at its default frequency
+        let default = profile.filing_frequency.unwrap_or(FilingFrequency::Quarterly);
+        let states: Vec<String> = args.state.iter().map(|s| s.trim().to_uppercase()).collect();
+        for state in &states {
+            frequencies.entry(state.clone()).or_insert(default);
+        }
+        frequencies.retain(|state, _| states.contains(state));
+    }
+    if frequencies.is_empty() {
+        anyhow::bail!(
+            "No states to list deadlines for: set registered_states or filing_frequencies in the profile, or pass --state"
+        );
+    }
+
+    let from = match args.from {
+        Some(from) => from,
+        None => clock.today(profile.timezone()?),
+    };
+    let until = from
+        .checked_add_months(chrono::Months::new(args.months))
+        .ok_or_else(|| anyhow::anyhow!("--months {} is out of range", args.months))?;
+    let calendar = FilingCalendar::new(&frequencies, from, until);
+
+    if let Some(path) = &args.ics {
+        let mut sink = OutputSink::create(Some(path))?;
+        calendar.write_ics(&mut sink, clock.now(), args.remind_days)?;
+        sink.finish()?;
+        progress.status(&format!("Wrote {} deadlines to {}", calendar.deadlines.len(), path.display()));
+    }
+
+    let mut sink = OutputSink::create(args.output.as_deref())?;
+    calendar.write(args.format, &mut sink)?;
+    sink.finish()
+}
+
+/// `period` (e.g. `--period`) placed in the profile's fiscal year, or the last
+/// complete quarter before `today`
+fn select_period(period: Option<Period>, profile: &Profile, today: chrono::NaiveDate) -> Result<Period> {
+    let fiscal_start = profile.fiscal_start_month()?;
+    match period {
+        Some(period) => period.in_fiscal_year(fiscal_start),
+        None => Ok(PeriodLength::Quarter.previous_in_fiscal_year(today, fiscal_start)),
+    }
+}
+
+fn init_wizard(mut config: Config, config_path: &std::path::Path) -> Result<()> {
+    let stdin = std::io::stdin();
+    let hide_secrets = std::io::IsTerminal::is_terminal(&stdin);
+    let (name, profile) = wizard::run_wizard(&mut stdin.lock(), &mut std::io::stdout(), &config, hide_secrets)?;
+
+    config.profiles.insert(name.clone(), profile);
+    config.save(config_path)?;
+    println!("\nSaved profile '{}' to {}", name, config_path.display());
+
+    Ok(())
+}
+
+/// The extension a --template's output is written with: the one before
+/// `.tera` (`worksheet.csv.tera` writes CSV), else txt
+fn template_extension(template: &std::path::Path) -> String {
+    let name = template.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let name = name.strip_suffix(".tera").unwrap_or(&name);
+    match name.rsplit_once('.') {
+        Some((_, extension)) if !extension.is_empty() => extension.to_string(),
+        _ => "txt".to_string(),
+    }
+}
+
+/// What `generate --dry-run` prints instead of the report
+fn print_dry_run(report: &GroupedReport, period: Period) {
+    let count = |severity: Severity| report.diagnostics.iter().filter(|d| d.severity == severity).count();
+    let states: Vec<&str> = report.groups.iter().map(|group| group.state.as_str()).collect();
+    let invoices: usize = report.groups.iter().map(|group| group.records.len()).sum();
+
+    println!("Dry run for {}; no report written", period);
+    println!("Invoices reported: {}", invoices);
+    println!("Invoices excluded: {}", report.skipped.len());
+    println!("States:            {} ({})", states.len(), states.join(", "));
+    println!("Sales:             {:.2}", report.grand_total.licenses as f64 / 100.0);
+    println!("Tax:               {:.2}", report.grand_total.tax as f64 / 100.0);
+    println!("Errors:            {}", count(Severity::Error));
+    println!("Warnings:          {}", count(Severity::Warning));
+}
+
+/// Pair each requested format with where it goes: the matching --output, a
+/// file named after the period in the profile's output_dir, or stdout
+fn output_targets(
+    args: &GenerateArgs,
+    profile: &Profile,
+    context: &TemplateContext,
+    period: Period,
+) -> Result<Vec<(OutputFormat, Option<PathBuf>)>> {
+    let formats = match args.format.as_slice() {
+        [] => vec![profile.default_format.unwrap_or(OutputFormat::Tsv)],
+        formats => formats.to_vec(),
+    };
+
+    let outputs: Vec<Option<PathBuf>> = if !args.output.is_empty() {
+        if args.output.len() != formats.len() {
+            anyhow::bail!(
+                "Got {} --format and {} --output options; give one --output per --format",
+                formats.len(),
+                args.output.len()
+            );
+        }
+        args.output
+            .iter()
+            .map(|path| Ok(Some(PathBuf::from(template::render(path, context)?))))
+            .collect::<Result<_>>()?
+    } else if let Some(dir) = &profile.output_dir {
+        let dir = PathBuf::from(template::render(&dir.to_string_lossy(), context)?);
+        formats
+            .iter()
+            .map(|format| {
+                let extension = match &args.template {
+                    Some(template) => template_extension(template),
+                    None => format.extension().to_string(),
+                };
+                Some(dir.join(format!("tax-report-{}.{}", period.label(), extension)))
+            })
+            .collect()
+    } else if formats.len() == 1 {
+        vec![None]
+    } else {
+        anyhow::bail!("Several --format options need an --output for each (or a profile output_dir)");
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    for path in outputs.iter().flatten() {
+        if !seen.insert(path) {
+            anyhow::bail!("{} is given as the output of more than one format", path.display());
+        }
+    }
+
+    Ok(formats.into_iter().zip(outputs).collect())
+}
+
+/// What happened to a generated report's `--archive-dir` copy
+#[derive(Debug, Clone, PartialEq)]
+enum Archived {
+    /// Saved to this directory
+    Saved(PathBuf),
+    /// Not saved because the report was partial
+    Partial,
+    /// The period is filed, so its archived report in this directory was kept
+    Filed(PathBuf),
+    /// No `--archive-dir`, or a dry run
+    No,
+}
+
+/// Generate the report, returning the period it covered and whether it was archived
+async fn generate(
+    args: GenerateArgs,
+    config: &Config,
+    filings: &Filings,
+    profile_name: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<(Period, Archived)> {
+    let profile = config.select(profile_name)?;
+    check_columns(&args.columns)?;
+
+    let progress = ReportProgress::new(args.quiet);
+    let deadline = args.deadline.map(|budget| Instant::now() + budget);
+
+    let source = args.source.open(&profile).await?;
+    let client = source.stripe();
+    if args.require_live
+        && let Some(client) = &client
+        && client.key_mode() != Some(KeyMode::Live)
+    {
+        anyhow::bail!(
+            "--require-live: the API key from {} is not a live key (sk_live_ or rk_live_)",
+            KeySource::resolve(&profile)?
+        );
+    }
+    let timezone = profile.timezone()?;
+
+    // The reporting period is relative to today, or to the test clock's simulated time
+    let test_clock_time = match &args.test_clock {
+        Some(clock_id) => {
+            if let Some(client) = &client
+                && client.key_mode() == Some(KeyMode::Live)
+            {
+                anyhow::bail!("--test-clock requires a test mode key; test clocks don't exist in live mode");
+            }
+
+            let clock = InvoiceSource::fetch_test_clock(&source, clock_id).await?;
+
+            let frozen = FixedClock::from_timestamp(clock.frozen_time)
+                .ok_or_else(|| anyhow::anyhow!("Test clock {} has an invalid frozen_time", clock_id))?;
+            progress.status(&format!(
+                "Using test clock {} ({}) frozen at {}",
+                clock.id,
+                clock.name.as_deref().unwrap_or("unnamed"),
+                frozen.today(timezone)
+            ));
+            Some(frozen)
+        }
+        None => None,
+    };
+    let today = match &test_clock_time {
+        Some(frozen) => frozen.today(timezone),
+        None => clock.today(timezone),
+    };
+
+    let fiscal_start = match args.fiscal_year_start {
+        Some(month) => month,
+        None => profile.fiscal_start_month()?,
+    };
+    let period = args.period.previous_in_fiscal_year(today, fiscal_start);
+    progress.status(&format!(
+        "Generating report for {} ({} to {})",
+        period,
+        period.start_date(),
+        period.end_date()
+    ));
+    let context = TemplateContext::new(period.year(), period.quarter(), Some(profile_name.unwrap_or(DEFAULT_PROFILE)), clock);
+    // A filed period's report should come from the file that was filed, not a regeneration
+    let filed = filings.get(profile_name.unwrap_or(DEFAULT_PROFILE), &period);
+    if let Some(filing) = filed {
+        progress.warn(&format!(
+            "{} was filed on {} (confirmation {}); this report is regenerated from current data and may not match the one filed",
+            period, filing.filed_on, filing.confirmation
+        ));
+    }
+    if args.split_by_state.is_some() && !matches!(args.group_by, GroupBy::State | GroupBy::Product) {
+        anyhow::bail!("--split-by-state writes one file per state and can't be combined with --group-by {}", args.group_by.header().to_lowercase());
+    }
+    // Resolved before fetching so a bad --format/--output combination fails fast
+    let targets = match &args.split_by_state {
+        Some(_) => Vec::new(),
+        None => output_targets(&args, &profile, &context, period)?,
+    };
+    let template_formatter = args.template.as_deref().map(TemplateFormatter::load).transpose()?;
+
+    // Convert dates to Unix timestamps (day boundaries in the profile's timezone, else UTC)
+    let (start_timestamp, end_timestamp) = period.bounds_in(timezone)?;
+
+    let mut exclusions = profile
+        .customer_exclusions()
+        .with_metadata(args.exclude_metadata.iter().map(|(key, value)| (key, value)));
+    if let Some(path) = &args.exclude_list {
+        exclusions = exclusions.with_customers(load_exclude_list(path)?);
+    }
+
+    let mut customer_aliases = profile.aliases();
+    if let Some(path) = &args.customer_aliases {
+        customer_aliases = customer_aliases.with_aliases(&load_aliases(path)?);
+    }
+
+    let test_clock = args.test_clock.as_deref();
+    let options = ReportOptions {
+        allow_missing_state: args.allow_missing_state,
+        deadline,
+        parent_accounts: profile.parent_accounts.clone(),
+        parent_metadata_key: profile.parent_metadata_key.clone(),
+        convert_to_usd: args.convert_to_usd,
+        line_items: args.detail == Detail::LineItems
+            || args.group_by == GroupBy::Product
+            || targets.iter().any(|(format, _)| *format == OutputFormat::CsvNormalized),
+        include_invoiceitems: args.include_invoiceitems,
+        include_payments: args.include_payments,
+        taxability: Taxability::new(profile.product_categories.clone(), &profile.taxability_rules),
+        credit_basis: args.credit_basis,
+        basis: args.basis,
+        lookback_days: args.lookback_days,
+        registered_states: profile.registered_states.clone(),
+        rates: (args.check_rates || args.effective_rate).then(|| profile.rate_table()),
+        exclusions,
+        customer_aliases,
+        limit: args.limit,
+        request_budget: API_USAGE.get().cloned(),
+        timezone,
+    };
+
+    // Pick up where a run that hit its deadline left off
+    let resume = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path)?;
+            if (checkpoint.start, checkpoint.end) != (start_timestamp, end_timestamp) {
+                anyhow::bail!("Checkpoint {} is for a different reporting period", path.display());
+            }
+            Some(checkpoint)
+        }
+        None => None,
+    };
+
+    // A resumed run only fetches what the checkpoint left, so it can't be
+    // snapshotted; a dry run archives nothing
+    let record = args.archive_dir.is_some() && resume.is_none() && !args.dry_run;
+    let (mut generator, snapshot) = match client {
+        Some(stripe) if args.all_connected_accounts => {
+            (build_connected_report(stripe, start_timestamp, end_timestamp, &options, &progress).await?, None)
+        }
+        Some(stripe) if args.report_run => {
+            let report_type = args.report_type.as_deref().context("--report-run needs --report-type")?;
+            (build_report_from_run(stripe, report_type, start_timestamp, end_timestamp, &options, &progress).await?, None)
+        }
+        _ => {
+            let source = TestClockScope::new(&source, test_clock);
+            run_recorded(&source, record, resume, start_timestamp, end_timestamp, &options, &progress).await?
+        }
+    };
+
+    // Save unfinished work so the report can be completed later. A dry run
+    // leaves checkpoints as they are.
+    let partial = generator.partial();
+    if args.dry_run {
+        if partial.is_some() {
+            progress.warn("The run stopped early; a real run would save a checkpoint to resume from");
+        }
+    } else if partial.is_some() {
+        let path = args
+            .checkpoint
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("tax-report-{}.checkpoint.json", period.label())));
+        Checkpoint::capture(&generator, start_timestamp, end_timestamp).save(&path)?;
+        progress.warn(&format!(
+            "Writing a PARTIAL report; finish it later with --resume {}",
+            path.display()
+        ));
+    } else if let Some(path) = &args.resume {
+        std::fs::remove_file(path)?;
+        progress.status(&format!("Report complete; removed checkpoint {}", path.display()));
+    }
+
+    // Fold in sales from other channels so the filing covers them too
+    let external_sales = match &args.merge_csv {
+        Some(path) => {
+            let sales = ExternalSales::load(path, period.start_date(), period.end_date())?;
+            progress.status(&format!(
+                "Merged {} sales from {}{}",
+                sales.records.len(),
+                path.display(),
+                match sales.outside_period {
+                    0 => String::new(),
+                    n => format!(" ({} rows outside {} ignored)", n, period),
+                }
+            ));
+            for record in &sales.records {
+                generator.add_record(record.clone());
+            }
+            sales.records
+        }
+        None => Vec::new(),
+    };
+
+    // Sort records (by state, then date, then customer)
+    generator.sort_records();
+
+    // A filing must not silently omit revenue: optionally refuse to produce a
+    // report with exclusions, other than customers left out on purpose
+    let unreportable = generator.unreportable();
+    if args.fail_on_skip && !unreportable.is_empty() {
+        let ids: Vec<&str> = unreportable.iter().map(|s| s.invoice_id.as_str()).collect();
+        let amount: i64 = unreportable.iter().map(|s| s.amount).sum();
+        anyhow::bail!(
+            "{} invoice(s) totaling {:.2} could not be reported (--fail-on-skip): {}",
+            unreportable.len(),
+            amount as f64 / 100.0,
+            ids.join(", ")
+        );
+    }
+
+    // Exclusions and notes decided in `review`, after --fail-on-skip so
+    // deliberate exclusions don't fail the run
+    let review = args.review.as_deref().map(ReviewDecisions::load).transpose()?;
+    if let Some(review) = &review {
+        review.apply(&mut generator);
+    }
+    if let Some(path) = args.audit_file.as_ref().filter(|_| !args.dry_run) {
+        write_audit_file(path, &generator)?;
+        progress.status(&format!("Wrote audit trail to {}", path.display()));
+    }
+    let skipped = generator.skipped();
+
+    // Group by state with subtotals, then render in the requested format
+    let mut records = args.detail.apply(&args.rollup.apply(generator.get_records()));
+    let mut reported_skips = skipped.to_vec();
+    if args.anonymize {
+        let anonymizer = Anonymizer::new(profile.anonymize_salt.as_deref());
+        anonymizer.records(&mut records);
+        anonymizer.skipped(&mut reported_skips);
+    }
+    let mut report = GroupedReport::by(args.group_by, &records)
+        .with_skipped(&reported_skips)
+        .with_diagnostics(generator.diagnostics())
+        .with_partial(partial)
+        .with_monthly(args.monthly_breakdown)
+        .with_quarterly(period.is_annual())
+        .with_fiscal_start(period.fiscal_start())
+        .with_summary(args.summary)
+        .with_fee_breakdown(args.fee_breakdown)
+        .with_tax_breakdown(args.tax_breakdown)
+        .with_discount(args.texas_discount)
+        .with_date_format(args.date_format)
+        .with_invoice_columns(args.invoice_columns)
+        .with_columns(args.columns.clone())
+        .with_state_source(args.state_source)
+        .with_plan(args.plan)
+        .with_effective_rate(args.effective_rate)
+        .with_accounting(profile.accounting.clone())
+        .with_letterhead(profile.letterhead.clone())
+        .with_period(period.to_string())
+        .with_line_items(args.detail == Detail::LineItems);
+    if args.group_by == GroupBy::Product {
+        report = report.with_products(by_product(generator.get_records()));
+    }
+    if let Some(unknown) = report.unknown_state() {
+        progress.warn(&format!(
+            "{:.2} of revenue ({} invoices) has no state and is reported under {}",
+            unknown.subtotal.total as f64 / 100.0,
+            unknown.records.len(),
+            unknown.state
+        ));
+    }
+    if let Some(title) = args.title.as_ref().or(profile.report_title.as_ref()) {
+        report = report.with_title(template::render(title, &context)?);
+    }
+    if args.dry_run {
+        print_dry_run(&report, period);
+        return Ok((period, Archived::No));
+    }
+    if let Some(dir) = &args.split_by_state {
+        let dir = PathBuf::from(template::render(dir, &context)?);
+        let written = write_split_by_state(&report, &dir)?;
+        progress.status(&format!("Wrote {} state files and a summary to {}", written.len() - 1, dir.display()));
+    } else {
+        for (format, output) in targets {
+            if let Some(path) = &output
+                && let Some(dir) = path.parent()
+                && !dir.as_os_str().is_empty()
+            {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut sink = OutputSink::create(output.as_deref())?;
+            match &template_formatter {
+                Some(template) => template.write_report(&report, &mut sink)?,
+                None => format.formatter().write_report(&report, &mut sink)?,
+            }
+            sink.finish()?;
+            if let Some(path) = &output {
+                progress.status(&format!("Wrote report to {}", path.display()));
+                if let Some(filing) = filed {
+                    if filings::checksum(path)? == filing.checksum {
+                        progress.status(&format!("{} is identical to the report filed for {}", path.display(), period));
+                    } else {
+                        progress.warn(&format!(
+                            "{} differs from the report filed for {} ({})",
+                            path.display(),
+                            period,
+                            filing.report.display()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    // Keep the report as filed, and the data behind it, for `verify`
+    let mut archiving = Archived::No;
+    if let Some(root) = &args.archive_dir {
+        let archive = Archive::for_period(root, &period.label());
+        if partial.is_some() {
+            progress.warn("Not archiving the report because it is partial");
+            archiving = Archived::Partial;
+        } else if archive.exists() && filed.is_some() {
+            progress.warn(&format!(
+                "Not replacing the archived report of filed period {}; `verify` or `amend` show what changed",
+                period
+            ));
+            archiving = Archived::Filed(archive.dir().to_path_buf());
+        } else {
+            if archive.exists() {
+                progress.warn(&format!("Replacing the archived report for {} in {}", period, archive.dir().display()));
+            }
+            if snapshot.is_none() {
+                progress.warn("Archiving the report without a data snapshot (resumed or multi-account runs aren't recorded)");
+            }
+            let manifest = ArchiveManifest {
+                review: review.clone(),
+                external_sales: external_sales.clone(),
+                ..ArchiveManifest::new(period.label(), clock.now().to_rfc3339(), start_timestamp, end_timestamp, &options)
+            };
+            let archived = GroupedReport::by_state(generator.get_records())
+                .with_skipped(skipped)
+                .with_diagnostics(generator.diagnostics());
+            archive.save(&manifest, &archived, snapshot.as_ref())?;
+            progress.status(&format!("Archived the report to {}", archive.dir().display()));
+            archiving = Archived::Saved(archive.dir().to_path_buf());
+        }
+    }
+
+    // Record which filing covered each invoice, only when explicitly allowed
+    if args.allow_writes && partial.is_some() {
+        progress.warn("Not tagging invoices in Stripe because the report is partial");
+    } else if args.allow_writes
+        && let Some(client) = &client
+    {
+        let label = period.label();
+        // Payments, report-run rows and merged sales have no invoice to tag
+        let invoices = taggable_invoices(generator.get_records());
+        progress.status(&format!("Tagging {} invoices with tax_report_period={}...", invoices.len(), label));
+        let tagged = tag_report_period(client, &invoices, &label).await?;
+        progress.status(&format!("Tagged {} invoices", tagged));
+    }
+
+    Ok((period, archiving))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run_leaves_checkpoint_and_writes_nothing() {
+        let dir = std::env::temp_dir().join(format!("stripe-dry-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fixture = dir.join("fixture.json");
+        std::fs::write(
+            &fixture,
+            r#"{
+                "invoices": [{"id": "in_1", "customer": "cus_1", "status": "paid", "created": 1721000000, "tax": 825,
+                              "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1}]}}],
+                "customers": [{"id": "cus_1", "name": "Lone Star LLC", "address": {"state": "TX", "country": "US"}}]
+            }"#,
+        )
+        .unwrap();
+        let (start, end) = Period::new(2024, 3).unwrap().bounds(&chrono::Utc).unwrap();
+        let checkpoint = dir.join("Q3-2024.checkpoint.json");
+        Checkpoint::capture(&ReportGenerator::new(), start, end).save(&checkpoint).unwrap();
+        let audit = dir.join("audit.jsonl");
+
+        let args = Args::try_parse_from([
+            "stripe-tax-reporter".as_ref(),
+            "--fixture".as_ref(),
+            fixture.as_os_str(),
+            "--resume".as_ref(),
+            checkpoint.as_os_str(),
+            "--audit-file".as_ref(),
+            audit.as_os_str(),
+            "--dry-run".as_ref(),
+            "--quiet".as_ref(),
+        ])
+        .unwrap();
+        let clock = DateClock::new(chrono::NaiveDate::from_ymd_opt(2024, 10, 15).unwrap());
+        let (period, archived) = generate(args.generate, &Config::default(), &Filings::default(), None, &clock).await.unwrap();
+
+        assert_eq!(period.to_string(), Period::new(2024, 3).unwrap().to_string());
+        assert_eq!(archived, Archived::No);
+        assert!(checkpoint.exists(), "a dry run must not remove the checkpoint it resumed");
+        assert!(!audit.exists(), "a dry run must not write the audit file");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_schedule_message_matches_archiving() {
+        let period = Period::new(2024, 3).unwrap();
+        let dir = PathBuf::from("filings/2024-Q3");
+        assert!(schedule_message(period, "default", &Archived::Saved(dir.clone())).ends_with("regenerated and archived to filings/2024-Q3"));
+        let partial = schedule_message(period, "default", &Archived::Partial);
+        assert!(partial.contains("not archived because it is partial"));
+        let filed = schedule_message(period, "default", &Archived::Filed(dir));
+        assert!(filed.contains("the period is filed") && !filed.contains("archived to"));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::config::wizard::Prompter;
+use crate::report::compare::{dollars, CompareFormat};
+use crate::report::formatter::csv_escape;
+use crate::report::generator::{state_source, StateSource};
+use crate::report::pipeline::customer_id;
+use crate::report::progress::ReportProgress;
+use crate::report::states::{is_us_country, normalize_state};
+use crate::source::InvoiceSource;
+use crate::stripe::client::{Address, Customer, StripeInvoice};
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// Why a customer's own address gives no state to report under
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressIssue {
+    NoAddress,
+    NoState,
+    /// The state as entered isn't a USPS state code or name
+    UnrecognizedState(String),
+}
+
+impl fmt::Display for AddressIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressIssue::NoAddress => write!(f, "no address"),
+            AddressIssue::NoState => write!(f, "no state"),
+            AddressIssue::UnrecognizedState(state) => write!(f, "unrecognized state '{}'", state),
+        }
+    }
+}
+
+/// What's wrong with the customer's address for tax reporting, if anything.
+/// Addresses outside the US are fine: they're reported under NON-US.
+pub fn address_issue(customer: &Customer) -> Option<AddressIssue> {
+    let Some(address) = &customer.address else {
+        return Some(AddressIssue::NoAddress);
+    };
+    let fields = [&address.line1, &address.line2, &address.city, &address.postal_code, &address.state, &address.country];
+    if fields.iter().all(|field| field.as_deref().is_none_or(|f| f.trim().is_empty())) {
+        return Some(AddressIssue::NoAddress);
+    }
+    if !is_us_country(address.country.as_deref()) {
+        return None;
+    }
+    match address.state.as_deref().map(str::trim) {
+        None | Some("") => Some(AddressIssue::NoState),
+        Some(state) if normalize_state(state).is_none() => Some(AddressIssue::UnrecognizedState(state.to_string())),
+        Some(_) => None,
+    }
+}
+
+/// The state a customer's invoices are reported under for lack of one on
+/// the customer, and the address it's taken from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fallback {
+    pub source: StateSource,
+    pub state: String,
+}
+
+/// A customer with paid invoices in the period whose address needs fixing
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressProblem {
+    pub customer_id: String,
+    pub name: Option<String>,
+    pub address: Option<Address>,
+    pub issue: AddressIssue,
+    /// Where the state of the customer's latest invoice in the period comes
+    /// from instead; `None` when it would be skipped
+    pub fallback: Option<Fallback>,
+    /// Paid invoices in the period
+    pub invoices: usize,
+    /// Amount paid on those invoices, cents
+    pub revenue: i64,
+}
+
+/// Customers with paid invoices between `start` and `end` (Unix timestamps,
+/// inclusive) whose address gives no usable state, most revenue first.
+/// Only these customers' charges are fetched, to find the fallback.
+pub async fn find_address_problems<S: InvoiceSource>(
+    source: &S,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<Vec<AddressProblem>> {
+    let invoices = source.fetch_paid_invoices(start, end).await?;
+    progress.status(&format!("Checking the customers of {} paid invoices", invoices.len()));
+
+    let mut by_customer: BTreeMap<String, Vec<&StripeInvoice>> = BTreeMap::new();
+    for invoice in invoices.iter().filter(|i| source.in_scope(i)) {
+        if let Ok(id) = customer_id(invoice) {
+            by_customer.entry(id).or_default().push(invoice);
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (id, invoices) in by_customer {
+        let customer = source.fetch_customer(&id).await?;
+        let Some(issue) = address_issue(&customer) else {
+            continue;
+        };
+        let latest = invoices.iter().max_by_key(|i| (i.paid_at.unwrap_or(i.created), &i.id)).expect("grouped invoices");
+        let charge = match &latest.charge {
+            Some(serde_json::Value::String(charge_id)) => source.fetch_charge(charge_id).await.ok(),
+            _ => None,
+        };
+        let fallback = state_source(Some(&customer), charge.as_ref(), latest).map(|(source, state)| Fallback { source, state });
+        problems.push(AddressProblem {
+            customer_id: customer.id,
+            name: customer.name,
+            address: customer.address,
+            issue,
+            fallback,
+            invoices: invoices.len(),
+            revenue: invoices.iter().map(|i| i.amount_paid).sum(),
+        });
+    }
+    problems.sort_by(|a, b| b.revenue.cmp(&a.revenue).then_with(|| a.customer_id.cmp(&b.customer_id)));
+    Ok(problems)
+}
+
+/// The customers whose address needs fixing before a period is filed
+/// (`customers --missing-address`)
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressAudit {
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    pub customers: Vec<AddressProblem>,
+    /// Paid by these customers in the period, cents
+    pub revenue: i64,
+    /// Paid by those of them whose invoices would be skipped, cents
+    pub unresolved_revenue: i64,
+}
+
+impl AddressAudit {
+    pub fn new(period: impl Into<String>, customers: Vec<AddressProblem>) -> Self {
+        AddressAudit {
+            period: period.into(),
+            revenue: customers.iter().map(|c| c.revenue).sum(),
+            unresolved_revenue: customers.iter().filter(|c| c.fallback.is_none()).map(|c| c.revenue).sum(),
+            customers,
+        }
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        row(&["Customer ID", "Customer", "Issue", "Address", "Fallback", "Reported State", "Invoices", "Revenue"])?;
+        for customer in &self.customers {
+            let (fallback, state) = match &customer.fallback {
+                Some(fallback) => (fallback.source.to_string(), fallback.state.as_str()),
+                None => ("none (skipped)".to_string(), ""),
+            };
+            row(&[
+                &customer.customer_id,
+                customer.name.as_deref().unwrap_or(""),
+                &customer.issue.to_string(),
+                &format_address(customer.address.as_ref()),
+                &fallback,
+                state,
+                &customer.invoices.to_string(),
+                &dollars(customer.revenue),
+            ])?;
+        }
+        let invoices: usize = self.customers.iter().map(|c| c.invoices).sum();
+        row(&["TOTAL", "", "", "", "", "", &invoices.to_string(), &dollars(self.revenue)])?;
+        row(&["Without Fallback", "", "", "", "", "", "", &dollars(self.unresolved_revenue)])
+    }
+}
+
+/// A corrected address to save on a customer
+#[derive(Debug, Clone)]
+pub struct AddressCorrection {
+    pub customer_id: String,
+    pub address: Address,
+}
+
+/// Split one CSV line into fields, honouring double-quoted fields
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// A US address must have a recognized state; it's saved as the USPS code
+fn validate(customer_id: &str, mut address: Address) -> Result<Address> {
+    if is_us_country(address.country.as_deref()) {
+        let state = address.state.as_deref().unwrap_or_default();
+        let code = normalize_state(state)
+            .ok_or_else(|| anyhow!("Customer {}: '{}' is not a USPS state code or name", customer_id, state))?;
+        address.state = Some(code.to_string());
+    }
+    Ok(address)
+}
+
+/// Parse corrections from CSV with a header row naming the columns:
+/// `customer` (required), `line1`, `line2`, `city`, `state`, `postal_code`
+/// and `country`, in any order. Empty cells are left unset.
+pub fn parse_corrections(csv: &str) -> Result<Vec<AddressCorrection>> {
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some(header) => split_csv_line(header).iter().map(|h| h.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let Some(customer_column) = column("customer").or_else(|| column("customer_id")) else {
+        bail!("Corrections CSV needs a `customer` column");
+    };
+    for name in &header {
+        if !["customer", "customer_id", "line1", "line2", "city", "state", "postal_code", "country"].contains(&name.as_str()) {
+            bail!("Unknown column `{}` in corrections CSV", name);
+        }
+    }
+
+    lines
+        .map(|line| {
+            let fields = split_csv_line(line);
+            let cell = |name: &str| {
+                column(name)
+                    .and_then(|i| fields.get(i))
+                    .map(|f| f.trim().to_string())
+                    .filter(|f| !f.is_empty())
+            };
+            let customer_id = fields
+                .get(customer_column)
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .ok_or_else(|| anyhow!("Corrections CSV row has no customer: {}", line))?;
+            let address = Address {
+                line1: cell("line1"),
+                line2: cell("line2"),
+                city: cell("city"),
+                state: cell("state"),
+                postal_code: cell("postal_code"),
+                country: cell("country").map(|c| c.to_uppercase()),
+            };
+            Ok(AddressCorrection { address: validate(&customer_id, address)?, customer_id })
+        })
+        .collect()
+}
+
+/// One line describing an address, e.g. `1 Main St, Austin, TX 78701, US`
+pub fn format_address(address: Option<&Address>) -> String {
+    let Some(address) = address else {
+        return "(none)".to_string();
+    };
+    let state_zip: Vec<&str> =
+        [&address.state, &address.postal_code].into_iter().filter_map(|f| f.as_deref()).filter(|f| !f.is_empty()).collect();
+    let state_zip = state_zip.join(" ");
+    let parts: Vec<&str> = [address.line1.as_deref(), address.line2.as_deref(), address.city.as_deref(), Some(state_zip.as_str()), address.country.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.trim().is_empty())
+        .collect();
+    if parts.is_empty() { "(none)".to_string() } else { parts.join(", ") }
+}
+
+/// Ask for a corrected address for each problem customer, offering the
+/// current values as defaults. An empty state skips the customer.
+pub fn prompt_corrections<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    problems: &[AddressProblem],
+) -> Result<Vec<AddressCorrection>> {
+    let mut prompter = Prompter { input, output, hide_secrets: false };
+    let mut corrections = Vec::new();
+
+    for (i, problem) in problems.iter().enumerate() {
+        prompter.say(&format!(
+            "\n[{}/{}] {} ({}): {}, {} invoice(s) totaling {:.2}",
+            i + 1,
+            problems.len(),
+            problem.name.as_deref().unwrap_or("unnamed"),
+            problem.customer_id,
+            problem.issue,
+            problem.invoices,
+            problem.revenue as f64 / 100.0
+        ))?;
+        prompter.say(&format!("Current address: {}", format_address(problem.address.as_ref())))?;
+        if let Some(fallback) = &problem.fallback {
+            prompter.say(&format!("Currently reported under {} from the {}", fallback.state, fallback.source))?;
+        }
+
+        let current = problem.address.clone().unwrap_or_default();
+        let country = prompter.ask("Country", Some(current.country.as_deref().unwrap_or("US")))?.to_uppercase();
+        let state = loop {
+            let answer = prompter.ask("State (Enter to skip this customer)", Some(""))?;
+            if answer.is_empty() || !is_us_country(Some(&country)) {
+                break answer;
+            }
+            match normalize_state(&answer) {
+                Some(code) => break code.to_string(),
+                None => prompter.say(&format!("'{}' is not a USPS state code or name.", answer))?,
+            }
+        };
+        if state.is_empty() {
+            prompter.say("Skipped.")?;
+            continue;
+        }
+
+        let mut field = |question: &str, current: &Option<String>| -> Result<Option<String>> {
+            let answer = prompter.ask(question, Some(current.as_deref().unwrap_or("")))?;
+            Ok(Some(answer).filter(|a| !a.is_empty()))
+        };
+        let address = Address {
+            line1: field("Address line 1", &current.line1)?,
+            line2: field("Address line 2", &current.line2)?,
+            city: field("City", &current.city)?,
+            postal_code: field("Postal code", &current.postal_code)?,
+            state: Some(state),
+            country: Some(country),
+        };
+        corrections.push(AddressCorrection { customer_id: problem.customer_id.clone(), address });
+    }
+
+    Ok(corrections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FixtureSource;
+    use std::io::Cursor;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {"id": "in_1", "customer": "cus_none", "status": "paid", "created": 1728000000, "amount_paid": 5000},
+            {"id": "in_2", "customer": "cus_typo", "status": "paid", "created": 1728000000, "amount_paid": 10825, "charge": "ch_2"},
+            {"id": "in_3", "customer": "cus_typo", "status": "paid", "created": 1727900000, "amount_paid": 10825},
+            {"id": "in_4", "customer": "cus_ok", "status": "paid", "created": 1728000000, "amount_paid": 99900},
+            {"id": "in_5", "customer": "cus_de", "status": "paid", "created": 1728000000, "amount_paid": 100}
+        ],
+        "customers": [
+            {"id": "cus_none", "name": "No Address Co"},
+            {"id": "cus_typo", "name": "Typo LLC", "address": {"line1": "1 Main St", "city": "Austin", "state": "Texass", "postal_code": "78701"}},
+            {"id": "cus_ok", "name": "Fine Inc", "address": {"state": "Texas", "country": "US"}},
+            {"id": "cus_de", "name": "GmbH", "address": {"city": "Berlin", "country": "DE"}}
+        ],
+        "charges": [
+            {"id": "ch_2", "billing_details": {"address": {"state": "TX", "postal_code": "78701", "country": "US"}}}
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn test_problems_found_and_corrected_interactively() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+        let problems = find_address_problems(&source, 1727740800, 1735689599, &ReportProgress::hidden()).await.unwrap();
+        let found: Vec<(&str, String, i64)> =
+            problems.iter().map(|p| (p.customer_id.as_str(), p.issue.to_string(), p.revenue)).collect();
+        assert_eq!(found, vec![
+            ("cus_typo", "unrecognized state 'Texass'".to_string(), 21650),
+            ("cus_none", "no address".to_string(), 5000),
+        ]);
+        let fallback = problems[0].fallback.as_ref().unwrap();
+        assert_eq!((fallback.source, fallback.state.as_str()), (StateSource::CardBillingAddress, "TX"));
+        assert!(problems[1].fallback.is_none());
+
+        let mut tsv = Vec::new();
+        AddressAudit::new("Q4-2024", problems.clone()).write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("\ncus_typo\tTypo LLC\tunrecognized state 'Texass'\t1 Main St, Austin, Texass 78701\tcard billing address\tTX\t2\t216.50\n"));
+        assert!(tsv.contains("\ncus_none\tNo Address Co\tno address\t(none)\tnone (skipped)\t\t1\t50.00\n"));
+        assert!(tsv.ends_with("TOTAL\t\t\t\t\t\t3\t266.50\nWithout Fallback\t\t\t\t\t\t\t50.00\n"));
+
+        // Fix the typo keeping the rest of the address; skip the other customer
+        let answers = "\nTexass\ntexas\n\n\n\n\n\n\n";
+        let mut output = Vec::new();
+        let corrections = prompt_corrections(&mut Cursor::new(answers), &mut output, &problems).unwrap();
+        assert_eq!(corrections.len(), 1);
+        let address = &corrections[0].address;
+        assert_eq!(format_address(Some(address)), "1 Main St, Austin, TX 78701, US");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("'Texass' is not a USPS state code or name."));
+        assert!(output.contains("Skipped."));
+    }
+
+    #[test]
+    fn test_corrections_csv() {
+        let csv = "customer,line1,city,state,postal_code,country\n\
+                   cus_1,\"100 Congress Ave, Suite 2\",Austin,texas,78701,us\n\
+                   cus_2,,Toronto,ON,,CA\n";
+        let corrections = parse_corrections(csv).unwrap();
+        assert_eq!(corrections[0].address.line1.as_deref(), Some("100 Congress Ave, Suite 2"));
+        assert_eq!(corrections[0].address.state.as_deref(), Some("TX"));
+        assert_eq!(corrections[1].address.state.as_deref(), Some("ON"));
+        assert!(corrections[1].address.line1.is_none());
+
+        let error = parse_corrections("customer,state\ncus_1,Texass\n").unwrap_err();
+        assert!(error.to_string().contains("'Texass' is not a USPS state"));
+        assert!(parse_corrections("state\nTX\n").is_err());
+    }
+}
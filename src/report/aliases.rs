@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Display names for customers, replacing the raw Stripe names: trading
+/// names cleaned up (`ACME LLC dba Foo` → `Acme`), and duplicate customers of
+/// one legal entity given the same name (profile `customer_aliases`,
+/// `--customer-aliases`). Keys are Stripe customer IDs or customer names;
+/// names match ignoring case and repeated whitespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomerAliases {
+    by_id: BTreeMap<String, String>,
+    by_name: BTreeMap<String, String>,
+}
+
+impl CustomerAliases {
+    /// Add `aliases`, keyed by customer ID (`cus_...`) or by name, replacing
+    /// any earlier alias for the same key
+    pub fn with_aliases(mut self, aliases: &BTreeMap<String, String>) -> Self {
+        for (key, name) in aliases {
+            let key = key.trim();
+            if key.starts_with("cus_") {
+                self.by_id.insert(key.to_string(), name.trim().to_string());
+            } else {
+                self.by_name.insert(normalize(key), name.trim().to_string());
+            }
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty() && self.by_name.is_empty()
+    }
+
+    /// The name to report a customer under: its ID's alias, else its name's,
+    /// else the name itself
+    pub fn display_name(&self, customer_id: Option<&str>, name: &str) -> String {
+        customer_id
+            .and_then(|id| self.by_id.get(id))
+            .or_else(|| self.by_name.get(&normalize(name)))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+/// Read an alias file: a TOML table of `"Stripe name or customer ID" = "Display name"`
+pub fn load_aliases(path: &Path) -> Result<BTreeMap<String, String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read customer aliases {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Failed to parse customer aliases {}", path.display()))
+}
+
+fn normalize(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_aliased_by_name_and_id() {
+        let path = std::env::temp_dir().join(format!("stripe-tax-reporter-aliases-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "\"ACME LLC dba Foo\" = \"Acme\"\n\"Acme Holdings, LLC\" = \"Acme\"\ncus_acme2 = \"Acme\"\n",
+        )
+        .unwrap();
+        let aliases = CustomerAliases::default().with_aliases(&load_aliases(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(aliases.display_name(None, "acme llc  DBA foo"), "Acme");
+        assert_eq!(aliases.display_name(Some("cus_1"), "Acme Holdings, LLC"), "Acme");
+        assert_eq!(aliases.display_name(Some("cus_acme2"), "A.C.M.E."), "Acme");
+        assert_eq!(aliases.display_name(Some("cus_2"), "Lone Star LLC"), "Lone Star LLC");
+        assert!(CustomerAliases::default().is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::report::archive::{write_invoice_changes, Drift, InvoiceDrift};
+use crate::report::compare::{dollars, signed_dollars, CompareFormat};
+use crate::report::formatter::csv_escape;
+use crate::report::grouping::Totals;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// One jurisdiction's figures as originally reported and as corrected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JurisdictionAmendment {
+    /// Section label, e.g. `TX` or `TX (EUR)`
+    pub state: String,
+    pub reported: Totals,
+    pub corrected: Totals,
+}
+
+impl JurisdictionAmendment {
+    /// Additional tax due (negative: overpaid), cents
+    pub fn net_tax_due(&self) -> i64 {
+        self.corrected.tax - self.reported.tax
+    }
+}
+
+/// What an amended return for a period has to correct: the jurisdictions
+/// whose sales or tax changed since the archived report, and the invoices
+/// behind the changes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Amendment {
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    /// When the original report was generated (RFC 3339)
+    pub reported_at: String,
+    /// Jurisdictions with changed sales, taxable sales or tax, alphabetically
+    pub jurisdictions: Vec<JurisdictionAmendment>,
+    /// Additional tax due across the USD jurisdictions (negative: overpaid), cents
+    pub net_tax_due: i64,
+    pub reported_total: Totals,
+    pub corrected_total: Totals,
+    pub invoices: Vec<InvoiceDrift>,
+}
+
+/// Whether a return would report different figures
+fn amended(reported: &Totals, corrected: &Totals) -> bool {
+    (reported.licenses, reported.taxable_sales, reported.tax) != (corrected.licenses, corrected.taxable_sales, corrected.tax)
+}
+
+impl Amendment {
+    pub fn new(drift: Drift, reported_at: impl Into<String>) -> Self {
+        let jurisdictions = drift
+            .states
+            .states
+            .iter()
+            .filter(|s| amended(&s.previous, &s.current))
+            .map(|s| JurisdictionAmendment { state: s.state.clone(), reported: s.previous, corrected: s.current })
+            .collect();
+        Amendment {
+            net_tax_due: drift.tax_delta(),
+            period: drift.period,
+            reported_at: reported_at.into(),
+            jurisdictions,
+            reported_total: drift.states.previous_total,
+            corrected_total: drift.states.current_total,
+            invoices: drift.invoices,
+        }
+    }
+
+    /// Nothing reported differently, so no amended return is needed
+    pub fn is_empty(&self) -> bool {
+        self.jurisdictions.is_empty()
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        row(&["AMENDED RETURN", &self.period])?;
+        row(&["Originally Reported", &self.reported_at])?;
+        row(&["Net Tax Due", &signed_dollars(self.net_tax_due)])?;
+        row(&[])?;
+
+        row(&["===== JURISDICTIONS ====="])?;
+        row(&[
+            "State",
+            "Reported Sales",
+            "Corrected Sales",
+            "Reported Taxable Sales",
+            "Corrected Taxable Sales",
+            "Reported Tax",
+            "Corrected Tax",
+            "Net Tax Due",
+        ])?;
+        let amounts = |state: &str, reported: &Totals, corrected: &Totals| -> Vec<String> {
+            vec![
+                state.to_string(),
+                dollars(reported.licenses),
+                dollars(corrected.licenses),
+                dollars(reported.taxable_sales),
+                dollars(corrected.taxable_sales),
+                dollars(reported.tax),
+                dollars(corrected.tax),
+                signed_dollars(corrected.tax - reported.tax),
+            ]
+        };
+        for jurisdiction in &self.jurisdictions {
+            let fields = amounts(&jurisdiction.state, &jurisdiction.reported, &jurisdiction.corrected);
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            row(&fields)?;
+        }
+        let fields = amounts("TOTAL", &self.reported_total, &self.corrected_total);
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        row(&fields)?;
+        row(&[])?;
+
+        write_invoice_changes(&self.invoices, &mut row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    fn record(invoice_id: &str, state: &str, licenses: i64, tax: i64, fees: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 10, 4).unwrap(),
+            customer: "Lone Star LLC".to_string(),
+            state: state.to_string(),
+            licenses,
+            taxable_sales: licenses,
+            tax,
+            total: licenses + tax,
+            fees,
+            invoice_id: invoice_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_amendment_lists_only_changed_jurisdictions() {
+        let filed = vec![record("in_1", "TX", 10000, 825, 320), record("in_2", "CA", 5000, 363, 175), record("in_3", "NM", 2000, 150, 88)];
+        // in_1 refunded, in_2 only had its fee corrected, in_4 was backdated into the period
+        let current = vec![record("in_2", "CA", 5000, 363, 150), record("in_3", "NM", 2000, 150, 88), record("in_4", "TX", 4000, 330, 146)];
+        let amendment = Amendment::new(Drift::new("Q4-2024", &filed, &current), "2025-01-02T00:00:00Z");
+
+        let states: Vec<(&str, i64)> = amendment.jurisdictions.iter().map(|j| (j.state.as_str(), j.net_tax_due())).collect();
+        assert_eq!(states, vec![("TX", 330 - 825)]);
+        assert_eq!(amendment.net_tax_due, -495);
+        assert_eq!(amendment.invoices.len(), 3);
+
+        let mut tsv = Vec::new();
+        amendment.write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("AMENDED RETURN\tQ4-2024\nOriginally Reported\t2025-01-02T00:00:00Z\nNet Tax Due\t-4.95\n\n"));
+        assert!(tsv.contains("\nTX\t100.00\t40.00\t100.00\t40.00\t8.25\t3.30\t-4.95\nTOTAL\t170.00\t110.00\t"));
+        assert!(tsv.contains("\n===== INVOICES =====\n"));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use sha2::{Digest, Sha256};
+
+/// Replaces customer names with pseudonyms (`--anonymize`), so reports can
+/// be shared without the client list. A pseudonym is a hash of the name, so
+/// a customer keeps it across reports and formats; amounts, states and
+/// invoice IDs are left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Anonymizer {
+    /// Mixed into every hash (profile `anonymize_salt`), so pseudonyms can't
+    /// be confirmed by hashing a guessed name
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(salt: Option<&str>) -> Self {
+        Anonymizer { salt: salt.unwrap_or_default().to_string() }
+    }
+
+    /// `Customer` and the first 8 hex digits of the salted hash of `name`
+    pub fn pseudonym(&self, name: &str) -> String {
+        let digest = Sha256::new().chain_update(&self.salt).chain_update(name.trim()).finalize();
+        let hex: String = digest.iter().take(4).map(|byte| format!("{:02x}", byte)).collect();
+        format!("Customer {}", hex)
+    }
+
+    /// Pseudonymize the customer and parent names, and drop the tax IDs and
+    /// street address, which identify the customer as well as a name
+    pub fn records(&self, records: &mut [InvoiceRecord]) {
+        for record in records {
+            record.customer = self.pseudonym(&record.customer);
+            record.parent = record.parent.as_deref().map(|parent| self.pseudonym(parent));
+            record.tax_ids.clear();
+            if let Some(address) = &mut record.address {
+                address.line1 = None;
+                address.line2 = None;
+            }
+        }
+    }
+
+    pub fn skipped(&self, skipped: &mut [SkippedInvoice]) {
+        for skip in skipped {
+            skip.customer = self.pseudonym(&skip.customer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_replaced_with_stable_pseudonyms() {
+        let anonymizer = Anonymizer::new(None);
+        let mut records = vec![
+            InvoiceRecord {
+                customer: "Lone Star LLC".to_string(),
+                state: "TX".to_string(),
+                tax: 825,
+                tax_ids: vec!["us_ein 12-3456789".to_string()],
+                ..Default::default()
+            },
+            InvoiceRecord { customer: "Lone Star LLC".to_string(), ..Default::default() },
+            InvoiceRecord { customer: "Golden Gate Inc".to_string(), ..Default::default() },
+        ];
+        anonymizer.records(&mut records);
+
+        assert!(records[0].customer.starts_with("Customer "));
+        assert_eq!(records[0].customer.len(), "Customer ".len() + 8);
+        assert_eq!(records[0].customer, records[1].customer);
+        assert_ne!(records[0].customer, records[2].customer);
+        assert_eq!((records[0].state.as_str(), records[0].tax), ("TX", 825));
+        assert!(records[0].tax_ids.is_empty());
+
+        assert_eq!(anonymizer.pseudonym("Lone Star LLC"), records[0].customer);
+        assert_ne!(Anonymizer::new(Some("s3cret")).pseudonym("Lone Star LLC"), records[0].customer);
+    }
+}
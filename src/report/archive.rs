@@ -0,0 +1,500 @@
+use crate::report::basis::{Basis, CreditBasis};
+use crate::report::compare::{dollars, signed_dollars, CompareFormat, Comparison};
+use crate::report::exclusions::CustomerExclusions;
+use crate::report::formatter::{csv_escape, DateFormat, JsonFormatter, ReportFormatter};
+use crate::report::generator::ReportOptions;
+use crate::report::grouping::{GroupedReport, Totals};
+use crate::report::review::ReviewDecisions;
+use crate::source::fixture::Fixture;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const REPORT_FILE: &str = "report.json";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const RATES_FILE: &str = "rates.json";
+
+/// How an archived report was generated, so `verify` can regenerate the
+/// period the same way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub version: u32,
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    /// When the report was generated (RFC 3339)
+    pub generated_at: String,
+    /// Period covered (Unix timestamps, inclusive)
+    pub start: i64,
+    pub end: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basis: Option<Basis>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookback_days: Option<u32>,
+    #[serde(default)]
+    pub credit_basis: CreditBasis,
+    #[serde(default)]
+    pub convert_to_usd: bool,
+    #[serde(default)]
+    pub include_invoiceitems: bool,
+    #[serde(default)]
+    pub allow_missing_state: bool,
+    /// Whether `snapshot.json` holds the Stripe data the report was built from
+    #[serde(default)]
+    pub snapshot: bool,
+    /// Review decisions applied to the report (`generate --review`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<ReviewDecisions>,
+    /// Customers left out of the report
+    #[serde(default, skip_serializing_if = "CustomerExclusions::is_empty")]
+    pub exclusions: CustomerExclusions,
+    /// Sales merged in from outside Stripe (`generate --merge-csv`), which
+    /// regenerating from Stripe can't reproduce
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external_sales: Vec<InvoiceRecord>,
+}
+
+impl ArchiveManifest {
+    pub fn new(period: impl Into<String>, generated_at: impl Into<String>, start: i64, end: i64, options: &ReportOptions) -> Self {
+        ArchiveManifest {
+            version: ARCHIVE_VERSION,
+            period: period.into(),
+            generated_at: generated_at.into(),
+            start,
+            end,
+            basis: options.basis,
+            lookback_days: options.lookback_days,
+            credit_basis: options.credit_basis,
+            convert_to_usd: options.convert_to_usd,
+            include_invoiceitems: options.include_invoiceitems,
+            allow_missing_state: options.allow_missing_state,
+            snapshot: false,
+            review: None,
+            exclusions: options.exclusions.clone(),
+            external_sales: Vec::new(),
+        }
+    }
+
+    /// Set the options that change which invoices are reported and at what
+    /// amounts to those the archived report was generated with
+    pub fn apply(&self, options: &mut ReportOptions) {
+        options.basis = self.basis;
+        options.lookback_days = self.lookback_days;
+        options.credit_basis = self.credit_basis;
+        options.convert_to_usd = self.convert_to_usd;
+        options.include_invoiceitems = self.include_invoiceitems;
+        options.allow_missing_state = self.allow_missing_state;
+        options.exclusions = self.exclusions.clone();
+    }
+}
+
+/// The rate an invoice was converted to USD at (`--convert-to-usd`), kept in
+/// the archive's `rates.json` so converted figures can be reproduced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedRate {
+    pub invoice_id: String,
+    pub date: NaiveDate,
+    /// Original ISO currency code
+    pub currency: String,
+    /// USD per unit of the original currency
+    pub rate: f64,
+}
+
+/// One period's directory in the `--archive-dir`: the report as generated
+/// (`report.json`), the Stripe data it was built from (`snapshot.json`, which
+/// `--fixture` can replay), the exchange rates converted invoices were
+/// reported at (`rates.json`) and how it was generated (`manifest.json`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Archive {
+    dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ArchivedReport {
+    states: Vec<ArchivedState>,
+}
+
+#[derive(Deserialize)]
+struct ArchivedState {
+    #[serde(default)]
+    records: Vec<InvoiceRecord>,
+}
+
+impl Archive {
+    /// The archive of the period labelled `label` (e.g. `Q3-2025`) under `root`
+    pub fn for_period(root: &Path, label: &str) -> Self {
+        Archive { dir: root.join(label) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn exists(&self) -> bool {
+        self.dir.join(MANIFEST_FILE).exists()
+    }
+
+    pub fn report_path(&self) -> PathBuf {
+        self.dir.join(REPORT_FILE)
+    }
+
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join(SNAPSHOT_FILE)
+    }
+
+    pub fn rates_path(&self) -> PathBuf {
+        self.dir.join(RATES_FILE)
+    }
+
+    /// Write the report as JSON with every record, whatever format was asked
+    /// for, replacing an earlier archive of the period
+    pub fn save(&self, manifest: &ArchiveManifest, report: &GroupedReport<'_>, snapshot: Option<&Fixture>) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create archive {}", self.dir.display()))?;
+
+        let mut json = Vec::new();
+        JsonFormatter.write_report(&report.clone().with_summary(false), &mut json)?;
+        std::fs::write(self.report_path(), json)
+            .with_context(|| format!("Failed to write {}", self.report_path().display()))?;
+
+        match snapshot {
+            Some(snapshot) => {
+                let json = serde_json::to_string(snapshot).context("Failed to serialize data snapshot")?;
+                std::fs::write(self.snapshot_path(), json)
+                    .with_context(|| format!("Failed to write {}", self.snapshot_path().display()))?;
+            }
+            None if self.snapshot_path().exists() => std::fs::remove_file(self.snapshot_path())?,
+            None => {}
+        }
+
+        let rates: Vec<ArchivedRate> = report
+            .converted_records()
+            .filter_map(|record| {
+                let fx = record.fx.as_ref()?;
+                Some(ArchivedRate {
+                    invoice_id: record.invoice_id.clone(),
+                    date: record.date,
+                    currency: fx.currency.clone(),
+                    rate: fx.rate,
+                })
+            })
+            .collect();
+        if !rates.is_empty() {
+            let json = serde_json::to_string_pretty(&rates).context("Failed to serialize exchange rates")?;
+            std::fs::write(self.rates_path(), json)
+                .with_context(|| format!("Failed to write {}", self.rates_path().display()))?;
+        } else if self.rates_path().exists() {
+            std::fs::remove_file(self.rates_path())?;
+        }
+
+        let manifest = ArchiveManifest { snapshot: snapshot.is_some(), ..manifest.clone() };
+        let json = serde_json::to_string_pretty(&manifest).context("Failed to serialize archive manifest")?;
+        let path = self.dir.join(MANIFEST_FILE);
+        std::fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn manifest(&self) -> Result<ArchiveManifest> {
+        let path = self.dir.join(MANIFEST_FILE);
+        let json = std::fs::read_to_string(&path).with_context(|| format!("No archived report in {}", self.dir.display()))?;
+        let manifest: ArchiveManifest =
+            serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?;
+        if manifest.version != ARCHIVE_VERSION {
+            bail!(
+                "Archive {} has unsupported version {} (expected {})",
+                self.dir.display(),
+                manifest.version,
+                ARCHIVE_VERSION
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// The exchange rates the archived report converted invoices at; none if
+    /// nothing was converted
+    pub fn rates(&self) -> Result<Vec<ArchivedRate>> {
+        let path = self.rates_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let json = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The records of the archived report, in report order
+    pub fn records(&self) -> Result<Vec<InvoiceRecord>> {
+        let path = self.report_path();
+        let json = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let report: ArchivedReport =
+            serde_json::from_str(&json).with_context(|| format!("{} is not a JSON tax report", path.display()))?;
+        Ok(report.states.into_iter().flat_map(|s| s.records).collect())
+    }
+}
+
+/// How an invoice's reported figures differ from the archived report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceChange {
+    /// Reported now but not in the archived report
+    Added,
+    /// In the archived report but no longer reported (voided, re-dated,
+    /// moved out of the period)
+    Removed,
+    /// Reported in both, with a different state or amounts (a late refund,
+    /// credit note or address correction)
+    Changed,
+}
+
+/// An invoice row's section and amounts in one of the two reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportedInvoice {
+    /// Section label, e.g. `TX` or `TX (EUR)`
+    pub state: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+}
+
+/// One invoice row that differs between the archived and current report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoiceDrift {
+    pub invoice_id: String,
+    /// Row date; chargebacks and cash-basis payments are dated apart from
+    /// their invoice
+    pub date: NaiveDate,
+    pub customer: String,
+    pub change: InvoiceChange,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<ReportedInvoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<ReportedInvoice>,
+}
+
+impl InvoiceDrift {
+    /// Change in tax, cents
+    pub fn tax_delta(&self) -> i64 {
+        let tax = |reported: &Option<ReportedInvoice>| reported.as_ref().map_or(0, |r| r.totals.tax);
+        tax(&self.current) - tax(&self.archived)
+    }
+}
+
+/// Differences between an archived report and the same period regenerated
+/// from current data
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    pub period: String,
+    /// Invoice rows added, removed or changed, by invoice ID
+    pub invoices: Vec<InvoiceDrift>,
+    /// Per-state subtotals of the archived (`previous`) and current report
+    pub states: Comparison,
+}
+
+/// Rows keyed by invoice and date, with their section and summed amounts
+fn by_invoice(records: &[InvoiceRecord]) -> BTreeMap<(&str, NaiveDate), (&InvoiceRecord, ReportedInvoice)> {
+    let mut rows: BTreeMap<(&str, NaiveDate), (&InvoiceRecord, ReportedInvoice)> = BTreeMap::new();
+    for record in records {
+        let (_, reported) = rows
+            .entry((record.invoice_id.as_str(), record.date))
+            .or_insert_with(|| (record, ReportedInvoice { state: record.section(), totals: Totals::default() }));
+        reported.totals.add(record);
+    }
+    rows
+}
+
+fn section_totals(records: &[InvoiceRecord]) -> (BTreeMap<String, Totals>, Totals) {
+    crate::report::compare::section_totals(&GroupedReport::by_state(records))
+}
+
+impl Drift {
+    pub fn new(period: impl Into<String>, archived: &[InvoiceRecord], current: &[InvoiceRecord]) -> Self {
+        let (before, after) = (by_invoice(archived), by_invoice(current));
+        let mut keys: Vec<&(&str, NaiveDate)> = before.keys().chain(after.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let invoices = keys
+            .into_iter()
+            .filter_map(|key| {
+                let (was, now) = (before.get(key), after.get(key));
+                let change = match (was, now) {
+                    (None, _) => InvoiceChange::Added,
+                    (_, None) => InvoiceChange::Removed,
+                    (Some((_, was)), Some((_, now))) if was == now => return None,
+                    _ => InvoiceChange::Changed,
+                };
+                let (record, _) = now.or(was)?;
+                Some(InvoiceDrift {
+                    invoice_id: record.invoice_id.clone(),
+                    date: record.date,
+                    customer: record.customer.clone(),
+                    change,
+                    archived: was.map(|(_, reported)| reported.clone()),
+                    current: now.map(|(_, reported)| reported.clone()),
+                })
+            })
+            .collect();
+
+        Drift {
+            period: period.into(),
+            invoices,
+            states: Comparison::new("Archived", section_totals(archived), "Current", section_totals(current)),
+        }
+    }
+
+    /// The regenerated report matches the archived one
+    pub fn is_empty(&self) -> bool {
+        self.invoices.is_empty()
+    }
+
+    /// Net change in tax across all states, cents
+    pub fn tax_delta(&self) -> i64 {
+        self.states.current_total.tax - self.states.previous_total.tax
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(format, out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(format, out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, format: CompareFormat, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        write_invoice_changes(&self.invoices, &mut row)?;
+        row(&[])?;
+        row(&["===== STATES ====="])?;
+        self.states.write(format, out)
+    }
+}
+
+/// The `===== INVOICES =====` table of rows added, removed or changed since
+/// the archived report
+pub(crate) fn write_invoice_changes(invoices: &[InvoiceDrift], row: &mut dyn FnMut(&[&str]) -> Result<()>) -> Result<()> {
+    row(&["===== INVOICES ====="])?;
+    row(&[
+        "Invoice",
+        "Date",
+        "Customer",
+        "Change",
+        "Archived State",
+        "Current State",
+        "Archived Total",
+        "Current Total",
+        "Archived Tax",
+        "Current Tax",
+        "Tax Change",
+    ])?;
+    for invoice in invoices {
+        let state = |reported: &Option<ReportedInvoice>| reported.as_ref().map_or("", |r| r.state.as_str()).to_string();
+        let amount = |reported: &Option<ReportedInvoice>, column: fn(&Totals) -> i64| {
+            reported.as_ref().map_or(String::new(), |r| dollars(column(&r.totals)))
+        };
+        let change = match invoice.change {
+            InvoiceChange::Added => "ADDED",
+            InvoiceChange::Removed => "REMOVED",
+            InvoiceChange::Changed => "CHANGED",
+        };
+        row(&[
+            &invoice.invoice_id,
+            &DateFormat::Us.format(invoice.date),
+            &invoice.customer,
+            change,
+            &state(&invoice.archived),
+            &state(&invoice.current),
+            &amount(&invoice.archived, |t| t.total),
+            &amount(&invoice.current, |t| t.total),
+            &amount(&invoice.archived, |t| t.tax),
+            &amount(&invoice.current, |t| t.tax),
+            &signed_dollars(invoice.tax_delta()),
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(invoice_id: &str, state: &str, licenses: i64, tax: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 10, 4).unwrap(),
+            customer: "Lone Star LLC".to_string(),
+            state: state.to_string(),
+            licenses,
+            taxable_sales: licenses,
+            tax,
+            total: licenses + tax,
+            invoice_id: invoice_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip_and_drift() {
+        let root = std::env::temp_dir().join(format!("tax-archive-{}", std::process::id()));
+        let archive = Archive::for_period(&root, "Q4-2024");
+        let mut converted = record("in_3", "CA", 2000, 0);
+        converted.fx = Some(crate::stripe::models::FxConversion { currency: "EUR".to_string(), rate: 1.0842 });
+        let archived = vec![record("in_1", "TX", 10000, 825), record("in_2", "TX", 5000, 413), converted];
+        let options = ReportOptions { allow_missing_state: true, ..Default::default() };
+        let manifest = ArchiveManifest::new("Q4-2024", "2025-01-02T00:00:00Z", 1727740800, 1735689599, &options);
+        archive.save(&manifest, &GroupedReport::by_state(&archived), None).unwrap();
+
+        let loaded = archive.manifest().unwrap();
+        assert_eq!(loaded, manifest);
+        let mut restored = ReportOptions::default();
+        loaded.apply(&mut restored);
+        assert!(restored.allow_missing_state);
+        let records = archive.records().unwrap();
+        let rates = archive.rates().unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!((rates[0].invoice_id.as_str(), rates[0].currency.as_str(), rates[0].rate), ("in_3", "EUR", 1.0842));
+
+        // Re-archiving without conversions leaves no stale rates behind
+        archive.save(&manifest, &GroupedReport::by_state(&archived[..2]), None).unwrap();
+        assert!(archive.rates().unwrap().is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(records.len(), 3);
+
+        // in_2 was refunded down to nothing taxable, in_3 voided, in_4 backdated into the period
+        let current = vec![record("in_1", "TX", 10000, 825), record("in_2", "TX", 5000, 0), record("in_4", "OK", 3000, 135)];
+        let drift = Drift::new("Q4-2024", &records, &current);
+        let changes: Vec<(&str, InvoiceChange)> = drift.invoices.iter().map(|i| (i.invoice_id.as_str(), i.change)).collect();
+        assert_eq!(changes, vec![
+            ("in_2", InvoiceChange::Changed),
+            ("in_3", InvoiceChange::Removed),
+            ("in_4", InvoiceChange::Added),
+        ]);
+        assert_eq!(drift.tax_delta(), -413 + 135);
+        assert!(Drift::new("Q4-2024", &records, &records).is_empty());
+
+        let mut tsv = Vec::new();
+        drift.write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("\nin_2\t10/04/2024\tLone Star LLC\tCHANGED\tTX\tTX\t54.13\t50.00\t4.13\t0.00\t-4.13\n"));
+        assert!(tsv.contains("\nin_3\t10/04/2024\tLone Star LLC\tREMOVED\tCA\t\t20.00\t\t0.00\t\t0.00\n"));
+
+        // Records archived before dates were typed carry MM/DD/YYYY strings
+        let legacy: InvoiceRecord = serde_json::from_value(serde_json::json!({
+            "date": "10/04/2024", "customer": "Lone Star LLC", "users": 1, "state": "TX", "licenses": 10000,
+            "tax": 825, "total": 10825, "fees": 0, "invoice_id": "in_1",
+        }))
+        .unwrap();
+        assert_eq!(legacy.date, records[0].date);
+        assert_eq!(serde_json::to_value(&legacy).unwrap()["date"], "2024-10-04");
+        assert!(tsv.contains("\n===== STATES =====\nState\tArchived Sales\tCurrent Sales\tSales Change\t"));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::report::diagnostics::{Diagnostic, Severity};
+use crate::report::generator::{ReportGenerator, StateSource};
+use crate::stripe::client::{BalanceTransaction, Charge, StripeInvoice};
+use crate::stripe::models::InvoiceRecord;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// The Stripe objects an invoice was reported from, and its amounts as
+/// Stripe has them (cents, in the invoice's currency; the fee in the
+/// settlement currency), kept for the audit file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceRefs {
+    pub invoice_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charge_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance_transaction_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    pub amount_due: i64,
+    pub amount_paid: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<i64>,
+    /// Stripe's IDs for the requests the invoice, charge and balance
+    /// transaction were fetched in, to quote to Stripe support
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub request_ids: Vec<String>,
+}
+
+impl SourceRefs {
+    pub fn new(invoice: &StripeInvoice, charge: Option<&Charge>, balance_transaction: Option<&BalanceTransaction>) -> Self {
+        SourceRefs {
+            invoice_id: invoice.id.clone(),
+            customer_id: invoice.customer_id().map(str::to_string),
+            charge_id: charge.map(|c| c.id.clone()).filter(|id| !id.is_empty()).or_else(|| invoice.charge_id().map(str::to_string)),
+            balance_transaction_id: balance_transaction
+                .map(|bt| bt.id.clone())
+                .filter(|id| !id.is_empty())
+                .or_else(|| charge.and_then(|c| c.balance_transaction.clone())),
+            currency: invoice.currency.clone(),
+            amount_due: invoice.amount_due,
+            amount_paid: invoice.amount_paid,
+            total: invoice.total,
+            tax: invoice.tax,
+            fee: balance_transaction.map(|bt| bt.fee),
+            request_ids: [
+                invoice.request_id.as_ref(),
+                charge.and_then(|c| c.request_id.as_ref()),
+                balance_transaction.and_then(|bt| bt.request_id.as_ref()),
+            ]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        }
+    }
+}
+
+/// A decision made while building a record or excluding an invoice
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Decision {
+    /// The diagnostic code, or `state_source` and `adjustment` for the
+    /// sourcing and figure decisions every record carries
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    pub message: String,
+}
+
+/// One line of the audit file (`--audit-file`): a reported record or an
+/// excluded invoice, with the Stripe objects it came from, the raw amounts
+/// and every decision made along the way
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry<'a> {
+    pub invoice_id: &'a str,
+    /// `reported` or `excluded`
+    pub status: &'static str,
+    pub customer: &'a str,
+    /// Stripe references and raw amounts; absent for sales merged from
+    /// `--merge-csv` and for checkpoints saved before they were kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'a SourceRefs>,
+    /// The record as reported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<&'a InvoiceRecord>,
+    /// Why the invoice was excluded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+    pub decisions: Vec<Decision>,
+}
+
+/// The audit file's lines for a report: one per record, then one per
+/// excluded invoice
+pub fn audit_entries(generator: &ReportGenerator) -> Vec<AuditEntry<'_>> {
+    let source = |invoice_id: &str| generator.sources().iter().find(|s| s.invoice_id == invoice_id);
+    let findings = |invoice_id: &str| {
+        generator
+            .diagnostics()
+            .iter()
+            .filter(|d| d.invoice_id.as_deref() == Some(invoice_id))
+            .map(Decision::from)
+            .collect::<Vec<_>>()
+    };
+
+    let mut entries = Vec::new();
+    for record in generator.get_records() {
+        let mut decisions = Vec::new();
+        if let Some(state_source) = record.state_source {
+            decisions.push(Decision {
+                kind: "state_source".to_string(),
+                severity: None,
+                message: state_decision(record, state_source),
+            });
+        }
+        decisions.extend(findings(&record.invoice_id));
+        decisions.extend(record.adjustments.iter().map(|adjustment| Decision {
+            kind: "adjustment".to_string(),
+            severity: None,
+            message: adjustment.note.clone(),
+        }));
+        entries.push(AuditEntry {
+            invoice_id: &record.invoice_id,
+            status: "reported",
+            customer: &record.customer,
+            source: source(&record.invoice_id),
+            record: Some(record),
+            reason: None,
+            decisions,
+        });
+    }
+    for skip in generator.skipped() {
+        entries.push(AuditEntry {
+            invoice_id: &skip.invoice_id,
+            status: "excluded",
+            customer: &skip.customer,
+            source: source(&skip.invoice_id),
+            record: None,
+            reason: Some(&skip.reason),
+            decisions: findings(&skip.invoice_id),
+        });
+    }
+    entries
+}
+
+/// Write the audit file as JSON Lines
+pub fn write_audit_file(path: &Path, generator: &ReportGenerator) -> Result<()> {
+    let mut out = Vec::new();
+    for entry in audit_entries(generator) {
+        serde_json::to_writer(&mut out, &entry).context("Failed to serialize audit entry")?;
+        writeln!(out)?;
+    }
+    std::fs::write(path, out).with_context(|| format!("Failed to write audit file {}", path.display()))
+}
+
+fn state_decision(record: &InvoiceRecord, source: StateSource) -> String {
+    match record.country.as_deref() {
+        Some(country) if country != "US" => format!("Billed outside the US ({}), per the {}", country, source),
+        _ => format!("Sourced to {} from the {}", record.state, source),
+    }
+}
+
+impl From<&Diagnostic> for Decision {
+    fn from(diagnostic: &Diagnostic) -> Self {
+        let kind = serde_json::to_value(diagnostic.code)
+            .ok()
+            .and_then(|code| code.as_str().map(str::to_string))
+            .unwrap_or_else(|| format!("{:?}", diagnostic.code));
+        Decision { kind, severity: Some(diagnostic.severity), message: diagnostic.message.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::client::{Address, Customer};
+
+    #[test]
+    fn test_audit_entries_carry_sources_and_decisions() {
+        let invoice = StripeInvoice {
+            id: "in_1".to_string(),
+            customer: serde_json::json!("cus_1"),
+            customer_name: Some("Lone Star LLC".to_string()),
+            status: "paid".to_string(),
+            created: 1704067200,
+            amount_due: 10825,
+            amount_paid: 10825,
+            tax: Some(825),
+            charge: Some(serde_json::json!("ch_1")),
+            request_id: Some("req_list".to_string()),
+            ..Default::default()
+        };
+        let customer = Customer {
+            id: "cus_1".to_string(),
+            address: Some(Address {
+                country: Some("US".to_string()),
+                postal_code: Some("78701".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let balance_transaction = BalanceTransaction {
+            id: "txn_1".to_string(),
+            fee: 344,
+            request_id: Some("req_txn".to_string()),
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice_with_customer(invoice.clone(), Some(&customer), None, Some(&balance_transaction)).unwrap();
+        generator.record_skip(&StripeInvoice { id: "in_2".to_string(), ..invoice }, "No state found");
+
+        let entries = audit_entries(&generator);
+        assert_eq!(entries.len(), 2);
+        let source = entries[0].source.unwrap();
+        assert_eq!(source.customer_id.as_deref(), Some("cus_1"));
+        assert_eq!(source.charge_id.as_deref(), Some("ch_1"));
+        assert_eq!(source.balance_transaction_id.as_deref(), Some("txn_1"));
+        assert_eq!((source.amount_paid, source.tax, source.fee), (10825, Some(825), Some(344)));
+        assert_eq!(source.request_ids, vec!["req_list", "req_txn"]);
+        let kinds: Vec<&str> = entries[0].decisions.iter().map(|d| d.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["state_source", "state_inferred"]);
+        assert_eq!(entries[0].decisions[0].message, "Sourced to TX from the ZIP code");
+
+        assert_eq!((entries[1].status, entries[1].reason), ("excluded", Some("No state found")));
+        assert_eq!(entries[1].source.unwrap().customer_id.as_deref(), Some("cus_1"));
+        assert_eq!(entries[1].decisions[0].kind, "invoice_skipped");
+
+        let line = serde_json::to_string(&entries[0]).unwrap();
+        assert!(line.starts_with("{\"invoice_id\":\"in_1\",\"status\":\"reported\""));
+    }
+}
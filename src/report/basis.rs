@@ -0,0 +1,190 @@
+use crate::stripe::client::{Charge, InvoicePayment, StripeInvoice};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Invoice statuses that can carry revenue: finalized and not voided
+const FINALIZED_STATUSES: [&str; 3] = ["paid", "open", "uncollectible"];
+
+/// How far before the period invoices are listed under `--basis`, since an
+/// invoice can be finalized or paid well after it was created
+pub const DEFAULT_LOOKBACK_DAYS: u32 = 92;
+
+/// Which invoices fall into the period (`--basis`). Without it, paid invoices
+/// are reported by creation date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Basis {
+    /// Invoices finalized in the period, paid or not, at their full amounts
+    Accrual,
+    /// Only amounts collected in the period, including partial payments
+    Cash,
+}
+
+impl Basis {
+    /// Statuses of the invoices to list
+    pub fn statuses(self) -> &'static [&'static str] {
+        &FINALIZED_STATUSES
+    }
+
+    /// Whether a listed invoice can belong to the period (Unix timestamps,
+    /// inclusive). Cash invoices are only candidates until their payments
+    /// are checked with `collected`.
+    pub fn includes(self, invoice: &StripeInvoice, start: i64, end: i64) -> bool {
+        match self {
+            Basis::Accrual => {
+                let finalized = invoice.finalized_at.unwrap_or(invoice.created);
+                finalized >= start && finalized <= end
+            }
+            Basis::Cash => invoice.amount_paid > 0,
+        }
+    }
+}
+
+/// Cash collected on an invoice within a period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collected {
+    /// Cents collected in the period
+    pub amount: i64,
+    /// When the last payment in the period was made
+    pub paid_at: i64,
+}
+
+/// What was collected on an invoice in a period (Unix timestamps, inclusive),
+/// or `None` if nothing was. Sources without payment records fall back to the
+/// invoice's own `amount_paid` and `paid_at`.
+pub fn collected(invoice: &StripeInvoice, payments: &[InvoicePayment], start: i64, end: i64) -> Option<Collected> {
+    let in_period = |at: i64| at >= start && at <= end;
+    if payments.is_empty() {
+        let paid_at = invoice.paid_at.filter(|&at| in_period(at))?;
+        return (invoice.amount_paid > 0).then_some(Collected { amount: invoice.amount_paid, paid_at });
+    }
+
+    let mut collected: Option<Collected> = None;
+    for payment in payments.iter().filter(|p| p.status == "paid") {
+        let Some(paid_at) = payment.status_transitions.paid_at.filter(|&at| in_period(at)) else {
+            continue;
+        };
+        let entry = collected.get_or_insert(Collected { amount: 0, paid_at });
+        entry.amount += payment.amount_paid;
+        entry.paid_at = entry.paid_at.max(paid_at);
+    }
+    collected.filter(|c| c.amount > 0)
+}
+
+/// How invoices settled partly with credit are reported (`--credit-basis`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CreditBasis {
+    /// Report the invoice's full amounts, whatever paid for them
+    #[default]
+    Invoice,
+    /// Report only the share paid in cash, leaving out what credit notes and
+    /// the customer's credit balance covered
+    Cash,
+}
+
+/// Share of an invoice paid in cash rather than with credit (0.0 to 1.0), or
+/// `None` if no credit was applied
+pub fn cash_share(invoice: &StripeInvoice) -> Option<f64> {
+    let credits = invoice.credits_applied();
+    if credits <= 0 {
+        return None;
+    }
+
+    let total = invoice.total.unwrap_or(invoice.amount_paid + credits);
+    if total <= 0 {
+        return None;
+    }
+    Some((total - credits).max(0) as f64 / total as f64)
+}
+
+/// What was refunded of a paid invoice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Refund {
+    /// Cents refunded, in the invoice's currency
+    pub amount: i64,
+    /// Share of the invoice kept (0.0 to 1.0)
+    pub share: f64,
+}
+
+/// What was refunded of an invoice after payment, or `None` if nothing was.
+/// Credit notes issued after payment say what was refunded of the invoice
+/// itself, so they're used when there are any; otherwise the amount refunded
+/// on its charge.
+pub fn refund(invoice: &StripeInvoice, charge: Option<&Charge>) -> Option<Refund> {
+    let (refunded, paid) = if invoice.post_payment_credit_notes_amount > 0 {
+        (invoice.post_payment_credit_notes_amount, invoice.total.unwrap_or(invoice.amount_paid))
+    } else {
+        let charge = charge?;
+        (charge.amount_refunded, if charge.amount > 0 { charge.amount } else { invoice.amount_paid })
+    };
+    if refunded <= 0 || paid <= 0 {
+        return None;
+    }
+    let amount = refunded.min(paid);
+    Some(Refund { amount, share: (paid - amount) as f64 / paid as f64 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refund_from_credit_notes_or_charge() {
+        let invoice = StripeInvoice { total: Some(10825), amount_paid: 10825, ..Default::default() };
+        let charge = |amount_refunded: i64| Charge { amount: 10825, amount_refunded, ..Default::default() };
+
+        assert_eq!(refund(&invoice, None), None);
+        assert_eq!(refund(&invoice, Some(&charge(0))), None);
+        assert_eq!(refund(&invoice, Some(&charge(2165))).map(|r| (r.amount, r.share)), Some((2165, 0.8)));
+        assert_eq!(refund(&invoice, Some(&charge(20000))).map(|r| r.share), Some(0.0));
+
+        // Credit notes take precedence over the charge
+        let credited = StripeInvoice { post_payment_credit_notes_amount: 5412, ..invoice };
+        assert_eq!(refund(&credited, Some(&charge(2165))).map(|r| r.amount), Some(5412));
+    }
+
+    #[test]
+    fn test_cash_share() {
+        let invoice = |total: i64, credit_notes: i64, starting: i64, ending: i64| StripeInvoice {
+            total: Some(total),
+            pre_payment_credit_notes_amount: credit_notes,
+            starting_balance: starting,
+            ending_balance: Some(ending),
+            ..Default::default()
+        };
+
+        assert_eq!(cash_share(&invoice(10000, 0, 0, 0)), None);
+        // $25 credit note and $25 of a $30 credit balance on a $100 invoice
+        assert_eq!(invoice(10000, 2500, -3000, -500).credits_applied(), 5000);
+        assert_eq!(cash_share(&invoice(10000, 2500, -3000, -500)), Some(0.5));
+        // A debit balance carried onto the invoice isn't credit
+        assert_eq!(cash_share(&invoice(10000, 0, 2000, 0)), None);
+        assert_eq!(cash_share(&invoice(10000, 0, -20000, -10000)), Some(0.0));
+    }
+
+    #[test]
+    fn test_collected_in_period() {
+        let payment = |amount: i64, status: &str, paid_at: Option<i64>| InvoicePayment {
+            amount_paid: amount,
+            status: status.to_string(),
+            status_transitions: crate::stripe::client::InvoicePaymentTransitions { paid_at },
+            ..Default::default()
+        };
+        let invoice = StripeInvoice { amount_paid: 10000, paid_at: Some(250), ..Default::default() };
+
+        // Half paid before the period, a quarter in it, the rest still open
+        let payments = vec![payment(5000, "paid", Some(50)), payment(2500, "paid", Some(150)), payment(2500, "open", None)];
+        assert_eq!(collected(&invoice, &payments, 100, 200), Some(Collected { amount: 2500, paid_at: 150 }));
+        assert_eq!(collected(&invoice, &payments, 300, 400), None);
+
+        // Without payment records the invoice's paid date decides
+        assert_eq!(collected(&invoice, &[], 200, 300), Some(Collected { amount: 10000, paid_at: 250 }));
+        assert_eq!(collected(&invoice, &[], 100, 200), None);
+
+        let finalized = StripeInvoice { created: 50, finalized_at: Some(150), ..Default::default() };
+        assert!(Basis::Accrual.includes(&finalized, 100, 200));
+        assert!(!Basis::Accrual.includes(&finalized, 0, 100));
+        assert!(!Basis::Cash.includes(&finalized, 100, 200));
+    }
+}
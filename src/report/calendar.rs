@@ -0,0 +1,144 @@
+use crate::config::FilingFrequency;
+use crate::report::compare::CompareFormat;
+use crate::report::formatter::csv_escape;
+use crate::report::quarter::{deadlines, Deadline};
+use anyhow::Result;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Upcoming sales tax return due dates across the states a profile files in
+/// (`deadlines`)
+#[derive(Debug, Clone, Serialize)]
+pub struct FilingCalendar {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+    /// By due date, then state
+    pub deadlines: Vec<Deadline>,
+}
+
+impl FilingCalendar {
+    /// Returns due between `from` and `until` inclusive in each state, at
+    /// the state's filing frequency
+    pub fn new(frequencies: &BTreeMap<String, FilingFrequency>, from: NaiveDate, until: NaiveDate) -> Self {
+        let mut all: Vec<Deadline> = frequencies
+            .iter()
+            .flat_map(|(state, frequency)| deadlines(state, *frequency, from, until))
+            .collect();
+        all.sort_by(|a, b| (a.due, &a.state).cmp(&(b.due, &b.state)));
+        FilingCalendar { from, until, deadlines: all }
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        row(&["Due", "State", "Frequency", "Period", "Start", "End"])?;
+        for deadline in &self.deadlines {
+            row(&[
+                &deadline.due.to_string(),
+                &deadline.state,
+                &deadline.frequency.to_string(),
+                &deadline.period,
+                &deadline.start.to_string(),
+                &deadline.end.to_string(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// An iCalendar file with an all-day event on each due date, and an alarm
+    /// `remind_days` before it (none if 0). `stamp` is the DTSTAMP of every
+    /// event; UIDs are stable, so re-importing updates the events in place.
+    pub fn write_ics(&self, out: &mut dyn Write, stamp: DateTime<Utc>, remind_days: u32) -> Result<()> {
+        // iCalendar lines end in CRLF
+        let mut line = |text: &str| -> Result<()> {
+            write!(out, "{}\r\n", text)?;
+            Ok(())
+        };
+        let date = |date: NaiveDate| date.format("%Y%m%d").to_string();
+
+        line("BEGIN:VCALENDAR")?;
+        line("VERSION:2.0")?;
+        line("PRODID:-//stripe-tax-reporter//deadlines//EN")?;
+        line("CALSCALE:GREGORIAN")?;
+        for deadline in &self.deadlines {
+            let summary = format!("{} sales tax return due ({})", deadline.state, deadline.period);
+            line("BEGIN:VEVENT")?;
+            line(&format!("UID:{}-{}@stripe-tax-reporter", deadline.state, deadline.period.replace('/', "-")))?;
+            line(&format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")))?;
+            line(&format!("DTSTART;VALUE=DATE:{}", date(deadline.due)))?;
+            line(&format!("DTEND;VALUE=DATE:{}", date(deadline.due + Days::new(1))))?;
+            line(&format!("SUMMARY:{}", summary))?;
+            line(&format!(
+                "DESCRIPTION:{} return for {} through {}",
+                deadline.frequency, deadline.start, deadline.end
+            ))?;
+            line("TRANSP:TRANSPARENT")?;
+            if remind_days > 0 {
+                line("BEGIN:VALARM")?;
+                line("ACTION:DISPLAY")?;
+                line(&format!("DESCRIPTION:{}", summary))?;
+                line(&format!("TRIGGER:-P{}D", remind_days))?;
+                line("END:VALARM")?;
+            }
+            line("END:VEVENT")?;
+        }
+        line("END:VCALENDAR")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_across_states() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let frequencies = BTreeMap::from([
+            ("TX".to_string(), FilingFrequency::Quarterly),
+            ("CA".to_string(), FilingFrequency::Quarterly),
+            ("WA".to_string(), FilingFrequency::Annual),
+        ]);
+        let calendar = FilingCalendar::new(&frequencies, date(2026, 1, 1), date(2026, 4, 30));
+
+        let due: Vec<(&str, &str, NaiveDate)> =
+            calendar.deadlines.iter().map(|d| (d.state.as_str(), d.period.as_str(), d.due)).collect();
+        assert_eq!(due, vec![
+            ("TX", "Q4-2025", date(2026, 1, 20)),
+            ("WA", "2025", date(2026, 1, 26)),
+            ("CA", "Q4-2025", date(2026, 2, 2)),
+            ("TX", "Q1-2026", date(2026, 4, 20)),
+            ("CA", "Q1-2026", date(2026, 4, 30)),
+        ]);
+
+        let mut ics = Vec::new();
+        let stamp = DateTime::from_timestamp(1767225600, 0).unwrap();
+        calendar.write_ics(&mut ics, stamp, 7).unwrap();
+        let ics = String::from_utf8(ics).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains(
+            "UID:TX-Q4-2025@stripe-tax-reporter\r\nDTSTAMP:20260101T000000Z\r\n\
+             DTSTART;VALUE=DATE:20260120\r\nDTEND;VALUE=DATE:20260121\r\n\
+             SUMMARY:TX sales tax return due (Q4-2025)\r\n"
+        ));
+        assert_eq!(ics.matches("TRIGGER:-P7D").count(), 5);
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    }
+}
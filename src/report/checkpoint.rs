@@ -0,0 +1,126 @@
+use crate::report::audit::SourceRefs;
+use crate::report::diagnostics::Diagnostic;
+use crate::report::generator::{ReportGenerator, ReportOptions};
+use crate::stripe::client::StripeInvoice;
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// Saved state of a report cut short by `--deadline`: everything processed so
+/// far plus the fetched invoices still to do, so a later run can finish the
+/// report without listing or enriching those invoices again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    /// Period covered (Unix timestamps, inclusive)
+    pub start: i64,
+    pub end: i64,
+    pub records: Vec<InvoiceRecord>,
+    pub skipped: Vec<SkippedInvoice>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub pending: Vec<StripeInvoice>,
+    /// Stripe references of the invoices processed, for the audit file
+    #[serde(default)]
+    pub sources: Vec<SourceRefs>,
+}
+
+impl Checkpoint {
+    pub fn capture(generator: &ReportGenerator, start: i64, end: i64) -> Self {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            start,
+            end,
+            records: generator.get_records().to_vec(),
+            skipped: generator.skipped().to_vec(),
+            diagnostics: generator.diagnostics().to_vec(),
+            pending: generator.pending().to_vec(),
+            sources: generator.sources().to_vec(),
+        }
+    }
+
+    /// Rebuild the generator with the work already done, and return the
+    /// invoices still to process
+    pub fn restore(self, options: ReportOptions) -> (ReportGenerator, Vec<StripeInvoice>) {
+        let mut generator = ReportGenerator::with_options(options);
+        for record in self.records {
+            generator.add_record(record);
+        }
+        for skip in self.skipped {
+            generator.add_skip(skip);
+        }
+        for diagnostic in self.diagnostics {
+            generator.add_diagnostic(diagnostic);
+        }
+        for source in self.sources {
+            generator.add_source(source);
+        }
+        (generator, self.pending)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string(self).context("Failed to serialize checkpoint")?;
+        std::fs::write(path, json).with_context(|| format!("Failed to write checkpoint {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+        let checkpoint: Checkpoint =
+            serde_json::from_str(&json).with_context(|| format!("Failed to parse checkpoint {}", path.display()))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            bail!(
+                "Checkpoint {} has unsupported version {} (expected {})",
+                path.display(),
+                checkpoint.version,
+                CHECKPOINT_VERSION
+            );
+        }
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, resume_report, ReportProgress};
+    use crate::source::FixtureSource;
+    use std::time::Instant;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {"id": "in_1", "customer": "cus_1", "status": "paid", "created": 1728000000,
+             "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1}]}},
+            {"id": "in_2", "customer": "cus_1", "status": "paid", "created": 1728000100,
+             "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 20000, "quantity": 1}]}}
+        ],
+        "customers": [{"id": "cus_1", "name": "Lone Star LLC", "address": {"state": "TX"}}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_expired_deadline_checkpoints_and_resumes() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+        let progress = ReportProgress::hidden();
+
+        // A deadline that has already passed leaves every invoice pending
+        let options = ReportOptions {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let partial = build_report(&source, 1727740800, 1735689599, &options, &progress).await.unwrap();
+        assert_eq!(partial.partial().map(|p| (p.processed, p.remaining)), Some((0, 2)));
+
+        // Round-trip through a file, then finish without a deadline
+        let path = std::env::temp_dir().join(format!("stripe-tax-reporter-checkpoint-{}.json", std::process::id()));
+        Checkpoint::capture(&partial, 1727740800, 1735689599).save(&path).unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let finished = resume_report(&source, checkpoint, &ReportOptions::default(), &progress).await.unwrap();
+        assert!(finished.partial().is_none());
+        let ids: Vec<&str> = finished.get_records().iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_1", "in_2"]);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::report::formatter::csv_escape;
+use crate::report::grouping::{GroupedReport, Totals};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Output formats for period comparisons (`compare --format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareFormat {
+    /// One tab-separated row per state, then the grand total
+    #[default]
+    Tsv,
+    /// Same layout as TSV, comma-separated
+    Csv,
+    /// The comparison as a JSON document
+    Json,
+}
+
+/// Whether a state is reported in both periods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateChange {
+    Both,
+    /// Only in the current period
+    New,
+    /// Only in the previous period
+    Gone,
+}
+
+/// One state's subtotals in both periods and the change between them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateComparison {
+    /// Section label, e.g. `TX` or `TX (EUR)`
+    pub state: String,
+    pub change: StateChange,
+    pub previous: Totals,
+    pub current: Totals,
+}
+
+impl StateComparison {
+    /// Change in sales (licenses), cents
+    pub fn sales_delta(&self) -> i64 {
+        self.current.licenses - self.previous.licenses
+    }
+
+    /// Change in tax, cents
+    pub fn tax_delta(&self) -> i64 {
+        self.current.tax - self.previous.tax
+    }
+
+    /// Change in fees, cents
+    pub fn fees_delta(&self) -> i64 {
+        self.current.fees - self.previous.fees
+    }
+}
+
+/// Per-state subtotals of two reports side by side, for sanity-checking a
+/// filing against the previous one before submitting it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    /// Label of the earlier period, e.g. `Q2-2025`
+    pub previous: String,
+    /// Label of the later period
+    pub current: String,
+    /// States in either period, alphabetically
+    pub states: Vec<StateComparison>,
+    pub previous_total: Totals,
+    pub current_total: Totals,
+}
+
+/// Subtotals by section label of a grouped report, with the USD grand total
+pub fn section_totals(report: &GroupedReport<'_>) -> (BTreeMap<String, Totals>, Totals) {
+    let sections = report.groups.iter().map(|g| (g.state.clone(), g.subtotal)).collect();
+    (sections, report.grand_total)
+}
+
+#[derive(Deserialize)]
+struct SavedReport {
+    states: Vec<SavedState>,
+    grand_total: Totals,
+}
+
+#[derive(Deserialize)]
+struct SavedState {
+    state: String,
+    subtotal: Totals,
+}
+
+/// Subtotals by section label and the grand total of a report saved with
+/// `generate --format json`
+pub fn load_section_totals(path: &Path) -> Result<(BTreeMap<String, Totals>, Totals)> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read report {}", path.display()))?;
+    let report: SavedReport =
+        serde_json::from_str(&json).with_context(|| format!("{} is not a JSON tax report", path.display()))?;
+    let sections = report.states.into_iter().map(|s| (s.state, s.subtotal)).collect();
+    Ok((sections, report.grand_total))
+}
+
+impl Comparison {
+    pub fn new(
+        previous_label: impl Into<String>,
+        (previous, previous_total): (BTreeMap<String, Totals>, Totals),
+        current_label: impl Into<String>,
+        (current, current_total): (BTreeMap<String, Totals>, Totals),
+    ) -> Self {
+        let mut labels: Vec<&String> = previous.keys().chain(current.keys()).collect();
+        labels.sort();
+        labels.dedup();
+
+        let states = labels
+            .into_iter()
+            .map(|state| {
+                let change = match (previous.get(state), current.get(state)) {
+                    (Some(_), Some(_)) => StateChange::Both,
+                    (None, _) => StateChange::New,
+                    (_, None) => StateChange::Gone,
+                };
+                StateComparison {
+                    state: state.clone(),
+                    change,
+                    previous: previous.get(state).copied().unwrap_or_default(),
+                    current: current.get(state).copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        Comparison {
+            previous: previous_label.into(),
+            current: current_label.into(),
+            states,
+            previous_total,
+            current_total,
+        }
+    }
+
+    /// States reported in only one of the periods
+    pub fn appeared_or_disappeared(&self) -> impl Iterator<Item = &StateComparison> {
+        self.states.iter().filter(|s| s.change != StateChange::Both)
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        let (previous, current) = (&self.previous, &self.current);
+        let mut headers = vec!["State".to_string()];
+        for column in ["Sales", "Tax", "Fees"] {
+            headers.extend([format!("{} {}", previous, column), format!("{} {}", current, column), format!("{} Change", column)]);
+        }
+        headers.push("Note".to_string());
+        let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+        row(&headers)?;
+
+        let amounts = |previous: &Totals, current: &Totals| -> Vec<String> {
+            let mut fields = Vec::new();
+            for amount in [|t: &Totals| t.licenses, |t: &Totals| t.tax, |t: &Totals| t.fees] {
+                let (before, after) = (amount(previous), amount(current));
+                fields.extend([dollars(before), dollars(after), signed_dollars(after - before)]);
+            }
+            fields
+        };
+
+        for state in &self.states {
+            let note = match state.change {
+                StateChange::Both => "",
+                StateChange::New => "NEW",
+                StateChange::Gone => "GONE",
+            };
+            let mut fields = vec![state.state.clone()];
+            fields.extend(amounts(&state.previous, &state.current));
+            fields.push(note.to_string());
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            row(&fields)?;
+        }
+
+        let mut fields = vec!["GRAND TOTAL".to_string()];
+        fields.extend(amounts(&self.previous_total, &self.current_total));
+        fields.push(String::new());
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        row(&fields)
+    }
+}
+
+pub(crate) fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// Dollars with an explicit sign for increases, e.g. `+12.50`
+pub(crate) fn signed_dollars(cents: i64) -> String {
+    if cents > 0 { format!("+{}", dollars(cents)) } else { dollars(cents) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(licenses: i64, tax: i64, fees: i64) -> Totals {
+        Totals { licenses, tax, total: licenses + tax, fees, ..Default::default() }
+    }
+
+    #[test]
+    fn test_comparison_flags_new_and_gone_states() {
+        let previous = BTreeMap::from([("CA".to_string(), totals(10000, 725, 300)), ("TX".to_string(), totals(20000, 1650, 600))]);
+        let current = BTreeMap::from([("NM".to_string(), totals(5000, 400, 150)), ("TX".to_string(), totals(25000, 2063, 750))]);
+        let comparison = Comparison::new(
+            "Q2-2025",
+            (previous, totals(30000, 2375, 900)),
+            "Q3-2025",
+            (current, totals(30000, 2463, 900)),
+        );
+
+        let changes: Vec<(&str, StateChange)> = comparison.states.iter().map(|s| (s.state.as_str(), s.change)).collect();
+        assert_eq!(changes, vec![("CA", StateChange::Gone), ("NM", StateChange::New), ("TX", StateChange::Both)]);
+        assert_eq!(comparison.states[2].tax_delta(), 413);
+        assert_eq!(comparison.appeared_or_disappeared().count(), 2);
+
+        let mut tsv = Vec::new();
+        comparison.write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("State\tQ2-2025 Sales\tQ3-2025 Sales\tSales Change\tQ2-2025 Tax\t"));
+        assert!(tsv.contains("\nCA\t100.00\t0.00\t-100.00\t7.25\t0.00\t-7.25\t3.00\t0.00\t-3.00\tGONE\n"));
+        assert!(tsv.contains("\nTX\t200.00\t250.00\t+50.00\t16.50\t20.63\t+4.13\t6.00\t7.50\t+1.50\t\n"));
+        assert!(tsv.ends_with("GRAND TOTAL\t300.00\t300.00\t0.00\t23.75\t24.63\t+0.88\t9.00\t9.00\t0.00\t\n"));
+    }
+}
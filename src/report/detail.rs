@@ -0,0 +1,130 @@
+use crate::stripe::models::{AmountColumn, InvoiceRecord};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// What each row of a state section stands for (`--detail`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Detail {
+    /// One row per invoice
+    #[default]
+    Invoice,
+    /// One row per subscription line (product, description, quantity, amount, tax)
+    LineItems,
+}
+
+impl Detail {
+    /// Apply the detail level to records already sorted for output
+    pub fn apply(self, records: &[InvoiceRecord]) -> Vec<InvoiceRecord> {
+        match self {
+            Detail::Invoice => records.to_vec(),
+            Detail::LineItems => expand_line_items(records),
+        }
+    }
+}
+
+/// Replace each record that kept its lines with one row per line, carrying the
+/// line's product, description, quantity and amounts. The first row of an
+/// invoice also takes the invoice's fees and whatever the lines don't account
+/// for (tax not broken down by line, conversion rounding), so an invoice's rows
+/// always add up to the invoice; tax moved that way is footnoted. Records
+/// without lines are passed through.
+pub fn expand_line_items(records: &[InvoiceRecord]) -> Vec<InvoiceRecord> {
+    let mut rows = Vec::with_capacity(records.len());
+
+    for record in records {
+        if record.lines.is_empty() {
+            rows.push(record.clone());
+            continue;
+        }
+
+        let mut licenses_left = record.licenses - record.lines.iter().map(|l| l.amount).sum::<i64>();
+        let mut taxable_left = record.taxable_sales - record.lines.iter().map(|l| l.taxable_sales).sum::<i64>();
+        let mut tax_left = record.tax - record.lines.iter().map(|l| l.tax).sum::<i64>();
+        let mut fees_left = record.fees;
+        let mut fee_breakdown_left = record.fee_breakdown;
+        let mut local_tax_left = record.local_tax;
+
+        for (i, line) in record.lines.iter().enumerate() {
+            let mut row = record.clone();
+            if tax_left != 0 {
+                row.add_adjustment(
+                    &[AmountColumn::Tax, AmountColumn::Total],
+                    "Includes tax Stripe didn't break down by line, shown on the invoice's first line",
+                );
+            }
+            row.lines = Vec::new();
+            row.product = line.product.clone();
+            row.description = line.description.clone();
+            row.users = line.quantity;
+            row.licenses = line.amount + std::mem::take(&mut licenses_left);
+            row.taxable_sales = line.taxable_sales + std::mem::take(&mut taxable_left);
+            row.non_taxable_sales = row.licenses - row.taxable_sales;
+            row.tax = line.tax + std::mem::take(&mut tax_left);
+            // Local tax follows each line's share of the tax; the last line takes the rest
+            row.local_tax = if i + 1 == record.lines.len() || record.tax == 0 {
+                local_tax_left
+            } else {
+                (record.local_tax as f64 * row.tax as f64 / record.tax as f64).round() as i64
+            };
+            local_tax_left -= row.local_tax;
+            row.total = row.licenses + row.tax;
+            row.fees = std::mem::take(&mut fees_left);
+            row.fee_breakdown = std::mem::take(&mut fee_breakdown_left);
+            rows.push(row);
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceLine;
+
+    #[test]
+    fn test_expand_line_items() {
+        let line = |product: &str, quantity: u32, amount: i64| InvoiceLine {
+            product: Some(product.to_string()),
+            description: Some(format!("{} × {}", quantity, product)),
+            quantity,
+            amount,
+            taxable_sales: amount,
+            ..Default::default()
+        };
+        let record = InvoiceRecord {
+            invoice_id: "in_1".to_string(),
+            customer: "Acme".to_string(),
+            state: "TX".to_string(),
+            users: 7,
+            licenses: 15000,
+            taxable_sales: 15000,
+            tax: 1238,
+            total: 16238,
+            fees: 500,
+            lines: vec![line("prod_seat", 5, 10000), line("prod_addon", 2, 5000)],
+            ..Default::default()
+        };
+        let plain = InvoiceRecord {
+            invoice_id: "in_2".to_string(),
+            licenses: 3000,
+            total: 3000,
+            ..Default::default()
+        };
+
+        let rows = Detail::LineItems.apply(&[record, plain]);
+        assert_eq!(rows.len(), 3);
+
+        // Tax not broken down by line lands on the invoice's first row
+        assert_eq!(rows[0].product.as_deref(), Some("prod_seat"));
+        assert_eq!((rows[0].users, rows[0].licenses, rows[0].tax, rows[0].total, rows[0].fees), (5, 10000, 1238, 11238, 500));
+        assert_eq!((rows[1].users, rows[1].licenses, rows[1].tax, rows[1].total, rows[1].fees), (2, 5000, 0, 5000, 0));
+        assert!(rows[1].lines.is_empty());
+        assert_eq!(rows[0].adjustments[0].columns, vec![AmountColumn::Tax, AmountColumn::Total]);
+        assert!(rows[1].adjustments.is_empty());
+
+        assert_eq!(rows[2].invoice_id, "in_2");
+        assert_eq!(rows[2].product, None);
+    }
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+/// How serious a data-quality finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Stable identifiers for findings, so pipeline consumers can match on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCode {
+    /// The invoice was excluded from the report
+    InvoiceSkipped,
+    /// No state was found; the invoice is reported under UNKNOWN
+    MissingState,
+    /// No address had a state, so it was inferred from the ZIP code
+    StateInferred,
+    /// The billing address is outside the US; reported under NON-US
+    NonUsAddress,
+    /// The invoice's charge could not be fetched, so no billing address fallback was available
+    ChargeUnavailable,
+    /// The charge's balance transaction could not be fetched, so fees are reported as 0
+    FeeUnavailable,
+    /// Conversion to USD was requested but no exchange rate was available
+    FxRateUnavailable,
+    /// A line item's product could not be fetched, so it is named by its ID
+    ProductUnavailable,
+    /// Part of the invoice was settled with credit notes or customer credit balance
+    CreditApplied,
+    /// Part or all of the invoice was refunded after payment; it is reported
+    /// net of the refund
+    Refunded,
+    /// The invoice's charge is disputed and the dispute isn't decided; it is
+    /// still reported as a sale
+    DisputeOpen,
+    /// A lost dispute couldn't be traced to an invoice, so no chargeback was reported
+    DisputeUnmatched,
+    /// The invoice names no customer, or the customer could not be fetched;
+    /// the invoice is skipped
+    CustomerUnavailable,
+    /// The customer is internal, test or demo (an exclude list or metadata
+    /// filter); the invoice is deliberately left out
+    CustomerExcluded,
+    /// Tax was collected in a state not among the registered states
+    TaxUnregisteredState,
+    /// A non-exempt sale in a registered state carries no tax
+    UntaxedRegisteredState,
+    /// The tax collected doesn't match the expected rate at the destination
+    TaxRateMismatch,
+}
+
+/// A data-quality finding about one invoice (or the report as a whole)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub invoice_id: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: DiagnosticCode, invoice_id: &str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            invoice_id: Some(invoice_id.to_string()),
+            message: message.into(),
+        }
+    }
+}
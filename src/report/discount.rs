@@ -0,0 +1,126 @@
+use crate::report::grouping::GroupedReport;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The state whose returns the filing discounts apply to
+pub const DISCOUNT_STATE: &str = "TX";
+
+/// A Texas sales tax discount taken on the return (Tax Code §151.423 and
+/// §151.424)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilingDiscount {
+    /// 0.5% of the tax due, for filing and paying on time
+    Timely,
+    /// 1.25% for prepaying a reasonable estimate of the tax due, on top of
+    /// the timely filing discount
+    Prepayment,
+}
+
+impl FilingDiscount {
+    /// Percent of the tax due taken off
+    pub fn rate(self) -> f64 {
+        match self {
+            FilingDiscount::Timely => 0.5,
+            FilingDiscount::Prepayment => 1.75,
+        }
+    }
+
+    /// Discount on `tax` (cents) due in `state`, rounded to the cent; zero
+    /// outside Texas
+    pub fn amount(self, state: &str, tax: i64) -> i64 {
+        if state != DISCOUNT_STATE || tax <= 0 {
+            return 0;
+        }
+        (tax as f64 * self.rate() / 100.0).round() as i64
+    }
+}
+
+/// Tax collected in one state and what's left to remit after the discount
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateTaxDue {
+    pub state: String,
+    /// Tax collected, cents
+    pub tax: i64,
+    /// Filing discount, cents
+    pub discount: i64,
+    /// Tax due after the discount, cents
+    pub due: i64,
+}
+
+/// Tax due per state after a filing discount (`--texas-discount`), with totals
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaxDue {
+    pub filing_discount: FilingDiscount,
+    /// Alphabetically by state
+    pub states: Vec<StateTaxDue>,
+    pub tax: i64,
+    pub discount: i64,
+    pub due: i64,
+}
+
+impl TaxDue {
+    /// Tax due per state across the report's USD sections, whatever they're
+    /// grouped by; other currencies aren't remitted from this report
+    pub fn new(report: &GroupedReport<'_>, filing_discount: FilingDiscount) -> Self {
+        let mut tax_by_state: BTreeMap<&str, i64> = BTreeMap::new();
+        for group in report.groups.iter().filter(|g| g.currency.is_none()) {
+            for record in &group.records {
+                *tax_by_state.entry(record.state.as_str()).or_default() += record.tax;
+            }
+        }
+
+        let states: Vec<StateTaxDue> = tax_by_state
+            .into_iter()
+            .map(|(state, tax)| {
+                let discount = filing_discount.amount(state, tax);
+                StateTaxDue { state: state.to_string(), tax, discount, due: tax - discount }
+            })
+            .collect();
+        TaxDue {
+            filing_discount,
+            tax: states.iter().map(|s| s.tax).sum(),
+            discount: states.iter().map(|s| s.discount).sum(),
+            due: states.iter().map(|s| s.due).sum(),
+            states,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    fn record(state: &str, tax: i64, currency: Option<&str>) -> InvoiceRecord {
+        InvoiceRecord {
+            state: state.to_string(),
+            tax,
+            currency: currency.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_discount_taken_on_texas_tax_only() {
+        let records = vec![
+            record("TX", 82500, None),
+            record("TX", 41300, None),
+            record("CA", 7250, None),
+            record("NY", 900, Some("EUR")),
+        ];
+        let report = GroupedReport::by_state(&records);
+
+        let due = TaxDue::new(&report, FilingDiscount::Timely);
+        assert_eq!(due.states, vec![
+            StateTaxDue { state: "CA".to_string(), tax: 7250, discount: 0, due: 7250 },
+            StateTaxDue { state: "TX".to_string(), tax: 123800, discount: 619, due: 123181 },
+        ]);
+        assert_eq!((due.tax, due.discount, due.due), (131050, 619, 130431));
+
+        let due = TaxDue::new(&report, FilingDiscount::Prepayment);
+        assert_eq!(due.states[1].discount, 2167);
+        assert_eq!(FilingDiscount::Prepayment.amount("CA", 10000), 0);
+    }
+}
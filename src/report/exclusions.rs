@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Customers whose invoices are left out of reports: internal, test and demo
+/// accounts, picked out by customer ID or by a metadata value
+/// (`--exclude-metadata`, `--exclude-list`, profile `exclude_customers` and
+/// `exclude_metadata`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomerExclusions {
+    /// Stripe customer IDs
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub customers: BTreeSet<String>,
+    /// Metadata values by key; a customer is excluded if any key has its value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl CustomerExclusions {
+    pub fn is_empty(&self) -> bool {
+        self.customers.is_empty() && self.metadata.is_empty()
+    }
+
+    pub fn with_customers<I, S>(mut self, customers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.customers.extend(customers.into_iter().map(|id| id.as_ref().trim().to_string()));
+        self
+    }
+
+    pub fn with_metadata<'k>(mut self, filters: impl IntoIterator<Item = (&'k String, &'k String)>) -> Self {
+        for (key, value) in filters {
+            self.metadata.insert(key.clone(), value.clone());
+        }
+        self
+    }
+
+    /// Why the customer's invoices are excluded, if they are. Customer IDs
+    /// are checked before the customer is fetched, so `metadata` is `None`
+    /// until then.
+    pub fn reason(&self, customer_id: &str, metadata: Option<&BTreeMap<String, String>>) -> Option<String> {
+        if self.customers.contains(customer_id) {
+            return Some(format!("Customer {} is on the exclude list", customer_id));
+        }
+        let metadata = metadata?;
+        self.metadata
+            .iter()
+            .find(|(key, value)| metadata.get(*key).is_some_and(|v| v.trim().eq_ignore_ascii_case(value.trim())))
+            .map(|(key, value)| format!("Customer {} has metadata {}={}", customer_id, key, value))
+    }
+}
+
+/// Customer IDs from an exclude list: one per line, with blank lines and
+/// `#` comments ignored
+pub fn load_exclude_list(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read exclude list {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Parse a `KEY=VALUE` metadata filter (`--exclude-metadata`)
+pub fn parse_metadata_filter(filter: &str) -> Result<(String, String), String> {
+    match filter.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", filter)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_customers_excluded_by_id_and_metadata() {
+        let filters = BTreeMap::from([("internal".to_string(), "true".to_string())]);
+        let exclusions = CustomerExclusions::default().with_customers(["cus_staging "]).with_metadata(&filters);
+
+        assert_eq!(exclusions.reason("cus_staging", None).unwrap(), "Customer cus_staging is on the exclude list");
+        assert!(exclusions.reason("cus_demo", None).is_none());
+        let internal = BTreeMap::from([("internal".to_string(), "TRUE".to_string())]);
+        assert_eq!(exclusions.reason("cus_demo", Some(&internal)).unwrap(), "Customer cus_demo has metadata internal=true");
+        let external = BTreeMap::from([("internal".to_string(), "false".to_string())]);
+        assert!(exclusions.reason("cus_demo", Some(&external)).is_none());
+
+        assert_eq!(parse_metadata_filter("env = demo"), Ok(("env".to_string(), "demo".to_string())));
+        assert!(parse_metadata_filter("internal").is_err());
+
+        let path = std::env::temp_dir().join(format!("stripe-tax-reporter-exclude-{}.txt", std::process::id()));
+        std::fs::write(&path, "# Our own accounts\ncus_staging\n\ncus_demo  # sales demos\n").unwrap();
+        assert_eq!(load_exclude_list(&path).unwrap(), vec!["cus_staging", "cus_demo"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
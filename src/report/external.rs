@@ -0,0 +1,154 @@
+use crate::report::addresses::split_csv_line;
+use crate::report::payments::RecordSource;
+use crate::report::states::{is_us_country, normalize_state};
+use crate::stripe::models::{AmountColumn, InvoiceRecord};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveDate;
+use std::path::Path;
+
+/// Sales made outside Stripe, entered by hand in a CSV file (`--merge-csv`),
+/// as report records for the period
+#[derive(Debug, Clone, Default)]
+pub struct ExternalSales {
+    /// The rows dated within the period
+    pub records: Vec<InvoiceRecord>,
+    /// Rows dated outside the period, left out
+    pub outside_period: usize,
+}
+
+impl ExternalSales {
+    pub fn load(path: &Path, start: NaiveDate, end: NaiveDate) -> Result<Self> {
+        let csv = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let source = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Self::parse(&csv, &source, start, end).with_context(|| format!("Failed to read sales from {}", path.display()))
+    }
+
+    /// Parse CSV with a header row naming the columns: `date` (YYYY-MM-DD or
+    /// MM/DD/YYYY), `customer`, `state`, `amount` and `tax` in dollars, and
+    /// optionally `taxable` (the part of amount taxed; all of it if there's
+    /// tax, else none), `country` (US if empty) and `reference`, used as the
+    /// row's invoice ID (else `<source>:<line>`). Rows dated between `start`
+    /// and `end` inclusive become records, footnoted as entered from `source`.
+    pub fn parse(csv: &str, source: &str, start: NaiveDate, end: NaiveDate) -> Result<Self> {
+        let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let header: Vec<String> = match lines.next() {
+            Some((_, header)) => split_csv_line(header).iter().map(|h| h.trim().to_lowercase()).collect(),
+            None => return Ok(ExternalSales::default()),
+        };
+        for name in &header {
+            if !["date", "customer", "state", "amount", "tax", "taxable", "country", "reference"].contains(&name.as_str()) {
+                bail!("Unknown column `{}`", name);
+            }
+        }
+        for required in ["date", "customer", "state", "amount", "tax"] {
+            if !header.iter().any(|h| h == required) {
+                bail!("Missing the `{}` column", required);
+            }
+        }
+
+        let note = format!("Entered from {}; not a Stripe sale", source);
+        let mut sales = ExternalSales::default();
+        for (index, line) in lines {
+            let number = index + 1;
+            let fields = split_csv_line(line);
+            let cell = |name: &str| {
+                header
+                    .iter()
+                    .position(|h| h == name)
+                    .and_then(|i| fields.get(i))
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+            };
+            let required = |name: &str| cell(name).ok_or_else(|| anyhow!("Line {}: no {}", number, name));
+
+            let date = required("date")?;
+            let date = parse_date(date).ok_or_else(|| anyhow!("Line {}: '{}' is not a date", number, date))?;
+            if date < start || date > end {
+                sales.outside_period += 1;
+                continue;
+            }
+
+            let country = cell("country").map(str::to_uppercase).unwrap_or_else(|| "US".to_string());
+            let state = required("state")?;
+            let state = if is_us_country(Some(&country)) {
+                normalize_state(state)
+                    .ok_or_else(|| anyhow!("Line {}: '{}' is not a USPS state code or name", number, state))?
+                    .to_string()
+            } else {
+                state.to_string()
+            };
+
+            let amount = parse_cents(required("amount")?).ok_or_else(|| anyhow!("Line {}: amount is not a number", number))?;
+            let tax = parse_cents(required("tax")?).ok_or_else(|| anyhow!("Line {}: tax is not a number", number))?;
+            let taxable_sales = match cell("taxable") {
+                Some(taxable) => parse_cents(taxable).ok_or_else(|| anyhow!("Line {}: taxable is not a number", number))?,
+                None if tax != 0 => amount,
+                None => 0,
+            };
+
+            let mut record = InvoiceRecord {
+                date,
+                timestamp: date.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp()).unwrap_or_default(),
+                customer: required("customer")?.to_string(),
+                state,
+                country: Some(country),
+                licenses: amount,
+                taxable_sales,
+                non_taxable_sales: amount - taxable_sales,
+                tax,
+                total: amount + tax,
+                invoice_id: cell("reference").map(str::to_string).unwrap_or_else(|| format!("{}:{}", source, number)),
+                source: RecordSource::External,
+                ..Default::default()
+            };
+            record.add_adjustment(&[AmountColumn::Total], note.clone());
+            sales.records.push(record);
+        }
+        Ok(sales)
+    }
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%m/%d/%Y"))
+        .ok()
+}
+
+/// Dollars, with an optional `$` and thousands separators, in cents
+pub(crate) fn parse_cents(dollars: &str) -> Option<i64> {
+    let dollars: String = dollars.chars().filter(|c| !matches!(c, '$' | ',')).collect();
+    dollars.parse::<f64>().ok().map(|d| (d * 100.0).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_sales_parsed_for_the_period() {
+        let csv = "date,customer,state,amount,tax,reference\n\
+                   2026-07-14,Trade Show Walk-in,texas,\"$1,200.00\",99.00,\n\
+                   08/02/2026,\"Smith, Jones\",CA,500,0,PO-1182\n\
+                   2026-10-01,Next Quarter LLC,TX,100,8.25,\n";
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let sales = ExternalSales::parse(csv, "other_sales.csv", date(2026, 7, 1), date(2026, 9, 30)).unwrap();
+
+        assert_eq!(sales.outside_period, 1);
+        let rows: Vec<(&str, &str, &str, i64, i64, i64, i64)> = sales
+            .records
+            .iter()
+            .map(|r| (r.invoice_id.as_str(), r.customer.as_str(), r.state.as_str(), r.licenses, r.taxable_sales, r.tax, r.total))
+            .collect();
+        assert_eq!(rows, vec![
+            ("other_sales.csv:2", "Trade Show Walk-in", "TX", 120000, 120000, 9900, 129900),
+            ("PO-1182", "Smith, Jones", "CA", 50000, 0, 0, 50000),
+        ]);
+        assert_eq!(sales.records[1].non_taxable_sales, 50000);
+        assert_eq!(sales.records[0].adjustments[0].note, "Entered from other_sales.csv; not a Stripe sale");
+
+        let error = ExternalSales::parse("date,customer,state,amount,tax\n2026-07-14,A,ZZ,1,0\n", "x.csv", date(2026, 7, 1), date(2026, 9, 30))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: 'ZZ' is not a USPS state code or name");
+        assert!(ExternalSales::parse("date,customer,amount\n", "x.csv", date(2026, 7, 1), date(2026, 9, 30)).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::stripe::client::{BalanceTransaction, Charge};
+use crate::stripe::models::FeeBreakdown;
+
+/// Payment method types whose processing fees are booked as ACH
+const ACH_METHODS: [&str; 3] = ["us_bank_account", "ach_debit", "ach_credit_transfer"];
+
+/// Split a balance transaction's fee by `fee_details`. Stripe fees that
+/// mention tax are Stripe Tax fees; other Stripe fees are processing fees,
+/// booked as card or ACH by the charge's payment method. Application fees,
+/// tax on Stripe's fees, and processing on other payment methods are
+/// "other". Without `fee_details`, the whole fee is taken as processing.
+pub fn fee_breakdown(balance_transaction: &BalanceTransaction, charge: Option<&Charge>) -> FeeBreakdown {
+    let method = charge
+        .and_then(|c| c.payment_method_details.as_ref())
+        .map(|details| details.method_type.as_str());
+
+    let mut breakdown = FeeBreakdown::default();
+    let processing = |breakdown: &mut FeeBreakdown, amount: i64| match method {
+        Some("card") => breakdown.card += amount,
+        Some(method) if ACH_METHODS.contains(&method) => breakdown.ach += amount,
+        _ => breakdown.other += amount,
+    };
+
+    if balance_transaction.fee_details.is_empty() {
+        processing(&mut breakdown, balance_transaction.fee);
+        return breakdown;
+    }
+
+    for detail in &balance_transaction.fee_details {
+        let description = detail.description.as_deref().unwrap_or_default().to_lowercase();
+        match detail.fee_type.as_str() {
+            "stripe_fee" if description.contains("tax") => breakdown.stripe_tax += detail.amount,
+            "stripe_fee" => processing(&mut breakdown, detail.amount),
+            _ => breakdown.other += detail.amount,
+        }
+    }
+    breakdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::client::{FeeDetail, PaymentMethodDetails};
+
+    fn detail(fee_type: &str, description: &str, amount: i64) -> FeeDetail {
+        FeeDetail { amount, description: Some(description.to_string()), fee_type: fee_type.to_string() }
+    }
+
+    fn charge(method: &str) -> Charge {
+        Charge {
+            id: "ch_1".to_string(),
+            amount: 0,
+            amount_refunded: 0,
+            balance_transaction: None,
+            billing_details: None,
+            payment_method_details: Some(PaymentMethodDetails { method_type: method.to_string() }),
+            invoice: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn test_fee_breakdown() {
+        let bt = BalanceTransaction {
+            fee: 425,
+            fee_details: vec![
+                detail("stripe_fee", "Stripe processing fees", 320),
+                detail("stripe_fee", "Automatic Taxes", 50),
+                detail("application_fee", "Platform fee", 40),
+                detail("tax", "VAT on Stripe fees", 15),
+            ],
+            ..Default::default()
+        };
+
+        let card = fee_breakdown(&bt, Some(&charge("card")));
+        assert_eq!(card, FeeBreakdown { card: 320, ach: 0, stripe_tax: 50, other: 55 });
+        let ach = fee_breakdown(&bt, Some(&charge("us_bank_account")));
+        assert_eq!((ach.card, ach.ach), (0, 320));
+        assert_eq!(fee_breakdown(&bt, None).other, 375);
+
+        let undetailed = BalanceTransaction { fee: 320, ..Default::default() };
+        assert_eq!(fee_breakdown(&undetailed, Some(&charge("card"))).card, 320);
+    }
+}
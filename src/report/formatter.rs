@@ -1,84 +1,224 @@
+use crate::report::grouping::GroupedReport;
 use crate::stripe::models::InvoiceRecord;
-use std::collections::BTreeMap;
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
 
-pub fn format_as_tsv(records: &[InvoiceRecord]) -> String {
-    let mut output = String::new();
-
-    // Group records by state (BTreeMap keeps states alphabetically sorted)
-    let mut grouped: BTreeMap<String, Vec<&InvoiceRecord>> = BTreeMap::new();
-    for record in records {
-        grouped.entry(record.state.clone())
-            .or_insert_with(Vec::new)
-            .push(record);
-    }
+mod accounting;
+mod columns;
+mod delimited;
+mod html;
+mod html_report;
+mod json;
+mod jsonl;
+mod letterhead;
+mod markdown;
+mod normalized;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod templated;
+#[cfg(feature = "parquet")]
+mod parquet;
+
+pub use accounting::{
+    AccountMapping, AvalaraFormatter, BeancountFormatter, LedgerAccounts, TaxjarFormatter, XeroFormatter,
+};
+pub use columns::{check_columns, Column};
+pub(crate) use columns::FEE_BREAKDOWN_COLUMNS;
+pub use delimited::{CsvFormatter, TsvFormatter};
+pub(crate) use delimited::{
+    csv_escape, inferred_state_note, partial_warning, unknown_state_warning, write_csv_state_detail, write_csv_state_summary,
+};
+pub use html::HtmlFragmentFormatter;
+pub use html_report::HtmlFormatter;
+pub use json::JsonFormatter;
+pub use jsonl::JsonLinesFormatter;
+pub use markdown::MarkdownFormatter;
+pub use normalized::NormalizedCsvFormatter;
+pub use letterhead::Letterhead;
+#[cfg(feature = "pdf")]
+pub use pdf::PdfFormatter;
+pub use templated::TemplateFormatter;
+#[cfg(feature = "parquet")]
+pub use parquet::ParquetFormatter;
+
+/// Renders a grouped report into an output stream.
+///
+/// Formatters only decide presentation: grouping and subtotals are computed
+/// once in `GroupedReport` so every format reports the same numbers.
+/// Implementations write as they go rather than building the whole document
+/// in memory, so large reports can be streamed to a file, gzip, or a socket.
+pub trait ReportFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()>;
+}
 
-    // Track grand totals across all states
-    let mut grand_licenses = 0i64;
-    let mut grand_tax = 0i64;
-    let mut grand_total = 0i64;
-    let mut grand_fees = 0i64;
-
-    // Output each state section
-    for (state, state_records) in &grouped {
-        // State section header
-        output.push_str(&format!("===== {} =====\n", state));
-
-        // Column headers (NO State column)
-        output.push_str("Date\tCustomer\tUsers\tLicenses\tTax\tTotal\tFees\n");
-
-        // Data rows for this state
-        for record in state_records {
-            output.push_str(&format!(
-                "{}\t{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\n",
-                record.date,
-                record.customer,
-                record.users,
-                record.licenses_dollars(),
-                record.tax_dollars(),
-                record.total_dollars(),
-                record.fees_dollars(),
-            ));
+/// Output formats selectable with `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Tab-delimited sections, ready to paste into Excel
+    Tsv,
+    /// Comma-separated sections
+    Csv,
+    /// A single JSON document
+    Json,
+    /// Inline-styled per-state summary table for embedding in email bodies
+    HtmlFragment,
+    /// Standalone HTML page with sortable, filterable tables per state
+    Html,
+    /// GitHub-flavored Markdown tables, for wikis and pull requests
+    Markdown,
+    /// PDF with the profile's letterhead and signature lines, for archiving
+    /// with the filing (builds with the `pdf` feature, on by default)
+    Pdf,
+    /// Xero sales invoice import CSV, one row per invoice
+    Xero,
+    /// Beancount transactions posting sales, sales tax and fees
+    Beancount,
+    /// TaxJar transaction import CSV, one row per invoice with its destination
+    Taxjar,
+    /// Avalara transaction import CSV, one row per invoice with its destination
+    Avalara,
+    /// Long-format CSV for BI tools, one row per invoice line
+    CsvNormalized,
+    /// One JSON record per line
+    Jsonl,
+    /// Parquet with a typed column per field (builds with the `parquet` feature)
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Conventional file extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::HtmlFragment => "html",
+            // Distinct from the fragment's name when both are written to output_dir
+            OutputFormat::Html => "report.html",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Pdf => "pdf",
+            // Distinct from the CSV report's name when both are written to output_dir
+            OutputFormat::Xero => "xero.csv",
+            OutputFormat::Beancount => "beancount",
+            OutputFormat::Taxjar => "taxjar.csv",
+            OutputFormat::Avalara => "avalara.csv",
+            OutputFormat::CsvNormalized => "normalized.csv",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Parquet => "parquet",
         }
+    }
 
-        // Calculate state subtotals
-        let mut state_licenses = 0i64;
-        let mut state_tax = 0i64;
-        let mut state_total = 0i64;
-        let mut state_fees = 0i64;
-
-        for record in state_records {
-            state_licenses += record.licenses;
-            state_tax += record.tax;
-            state_total += record.total;
-            state_fees += record.fees;
+    pub fn formatter(self) -> Box<dyn ReportFormatter> {
+        match self {
+            OutputFormat::Tsv => Box::new(TsvFormatter),
+            OutputFormat::Csv => Box::new(CsvFormatter),
+            OutputFormat::Json => Box::new(JsonFormatter),
+            OutputFormat::HtmlFragment => Box::new(HtmlFragmentFormatter),
+            OutputFormat::Html => Box::new(HtmlFormatter),
+            OutputFormat::Markdown => Box::new(MarkdownFormatter),
+            #[cfg(feature = "pdf")]
+            OutputFormat::Pdf => Box::new(PdfFormatter),
+            #[cfg(not(feature = "pdf"))]
+            OutputFormat::Pdf => Box::new(PdfUnavailable),
+            OutputFormat::Xero => Box::new(XeroFormatter),
+            OutputFormat::Beancount => Box::new(BeancountFormatter),
+            OutputFormat::Taxjar => Box::new(TaxjarFormatter),
+            OutputFormat::Avalara => Box::new(AvalaraFormatter),
+            OutputFormat::CsvNormalized => Box::new(NormalizedCsvFormatter),
+            OutputFormat::Jsonl => Box::new(JsonLinesFormatter),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => Box::new(ParquetFormatter),
+            #[cfg(not(feature = "parquet"))]
+            OutputFormat::Parquet => Box::new(ParquetUnavailable),
         }
+    }
+}
+
+/// Stands in for the PDF formatter in builds without the `pdf` feature
+#[cfg(not(feature = "pdf"))]
+struct PdfUnavailable;
+
+#[cfg(not(feature = "pdf"))]
+impl ReportFormatter for PdfUnavailable {
+    fn write_report(&self, _report: &GroupedReport<'_>, _out: &mut dyn Write) -> Result<()> {
+        anyhow::bail!("{}", PDF_UNAVAILABLE)
+    }
+}
+
+/// Why PDF output fails in builds without the `pdf` feature
+#[cfg(not(feature = "pdf"))]
+pub(crate) const PDF_UNAVAILABLE: &str =
+    "PDF output needs a build with the `pdf` feature (cargo install without --no-default-features)";
+
+/// Stands in for the Parquet formatter in builds without the `parquet` feature
+#[cfg(not(feature = "parquet"))]
+struct ParquetUnavailable;
+
+#[cfg(not(feature = "parquet"))]
+impl ReportFormatter for ParquetUnavailable {
+    fn write_report(&self, _report: &GroupedReport<'_>, _out: &mut dyn Write) -> Result<()> {
+        anyhow::bail!("Parquet output needs a build with the `parquet` feature (cargo install --features parquet)")
+    }
+}
+
+/// How invoice dates are written in TSV, CSV and HTML reports
+/// (`--date-format`). JSON dates are always ISO 8601.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateFormat {
+    /// MM/DD/YYYY
+    #[default]
+    Us,
+    /// YYYY-MM-DD (ISO 8601)
+    Iso,
+    /// DD/MM/YYYY
+    Eu,
+}
 
-        // State subtotal row
-        output.push_str(&format!(
-            "Subtotal\t\t\t{:.2}\t{:.2}\t{:.2}\t{:.2}\n\n",
-            state_licenses as f64 / 100.0,
-            state_tax as f64 / 100.0,
-            state_total as f64 / 100.0,
-            state_fees as f64 / 100.0,
-        ));
-
-        // Add to grand totals
-        grand_licenses += state_licenses;
-        grand_tax += state_tax;
-        grand_total += state_total;
-        grand_fees += state_fees;
+impl DateFormat {
+    pub fn format(self, date: NaiveDate) -> String {
+        let pattern = match self {
+            DateFormat::Us => "%m/%d/%Y",
+            DateFormat::Iso => "%Y-%m-%d",
+            DateFormat::Eu => "%d/%m/%Y",
+        };
+        date.format(pattern).to_string()
     }
+}
 
-    // Grand total section
-    output.push_str(&format!(
-        "GRAND TOTAL\t\t\t{:.2}\t{:.2}\t{:.2}\t{:.2}\n",
-        grand_licenses as f64 / 100.0,
-        grand_tax as f64 / 100.0,
-        grand_total as f64 / 100.0,
-        grand_fees as f64 / 100.0,
-    ));
+/// Stream records as the per-state TSV report
+pub fn write_tsv(records: &[InvoiceRecord], out: &mut dyn Write) -> Result<()> {
+    TsvFormatter.write_report(&GroupedReport::by_state(records), out)
+}
+
+/// Format records as the per-state TSV report into a `String`.
+/// Convenient for small reports and tests; prefer `write_tsv` for large ones.
+pub fn format_as_tsv(records: &[InvoiceRecord]) -> String {
+    let mut output = Vec::new();
+    write_tsv(records, &mut output).expect("writing to a Vec cannot fail");
+    String::from_utf8(output).expect("TSV output is valid UTF-8")
+}
 
-    output
+/// Stream a report into an async writer (e.g. a socket or async file).
+///
+/// The formatter runs on the current worker thread with blocking writes
+/// bridged onto `writer`, so this needs the multi-threaded Tokio runtime.
+pub async fn write_report_async<W>(formatter: &dyn ReportFormatter, report: &GroupedReport<'_>, writer: W) -> Result<()>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    tokio::task::block_in_place(|| {
+        let mut out = BufWriter::new(SyncIoBridge::new(writer));
+        formatter.write_report(report, &mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.shutdown()?;
+        Ok(())
+    })
 }
 
 #[cfg(test)]
@@ -97,7 +237,7 @@ mod tests {
     #[test]
     fn test_format_single_state() {
         let records = vec![InvoiceRecord {
-            date: "10/15/2025".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
             customer: "Test Company".to_string(),
             users: 5,
             state: "TX".to_string(),
@@ -105,6 +245,7 @@ mod tests {
             tax: 4000,        // $40.00
             total: 54000,     // $540.00
             fees: 1600,       // $16.00
+            ..Default::default()
         }];
 
         let output = format_as_tsv(&records);
@@ -123,7 +264,7 @@ mod tests {
     fn test_format_multiple_states() {
         let records = vec![
             InvoiceRecord {
-                date: "10/15/2025".to_string(),
+                date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
                 customer: "TX Company".to_string(),
                 users: 5,
                 state: "TX".to_string(),
@@ -131,9 +272,10 @@ mod tests {
                 tax: 4000,        // $40.00
                 total: 54000,     // $540.00
                 fees: 1600,       // $16.00
+                ..Default::default()
             },
             InvoiceRecord {
-                date: "10/20/2025".to_string(),
+                date: chrono::NaiveDate::from_ymd_opt(2025, 10, 20).unwrap(),
                 customer: "CA Company".to_string(),
                 users: 3,
                 state: "CA".to_string(),
@@ -141,6 +283,7 @@ mod tests {
                 tax: 2000,        // $20.00
                 total: 32000,     // $320.00
                 fees: 900,        // $9.00
+                ..Default::default()
             },
         ];
 
@@ -166,4 +309,37 @@ mod tests {
         // Grand total should sum both states
         assert!(output.contains("GRAND TOTAL\t\t\t800.00\t60.00\t860.00\t25.00"));
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_write_report_async_matches_sync_output() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Test Company".to_string(),
+            users: 5,
+            state: "TX".to_string(),
+            licenses: 50000,
+            tax: 4000,
+            total: 54000,
+            fees: 1600,
+            ..Default::default()
+        }];
+
+        let report = GroupedReport::by_state(&records);
+        let mut output: Vec<u8> = Vec::new();
+        write_report_async(&TsvFormatter, &report, &mut output).await.unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), format_as_tsv(&records));
+    }
+
+    #[test]
+    fn test_title_rendered_above_sections() {
+        let records: Vec<InvoiceRecord> = vec![];
+        let report = GroupedReport::by_state(&records).with_title("Acme Q3 2025 Sales Tax");
+
+        let mut output = Vec::new();
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("Acme Q3 2025 Sales Tax\n\n"));
+    }
 }
@@ -0,0 +1,470 @@
+use super::{csv_escape, ReportFormatter};
+use crate::report::formatter::DateFormat;
+use crate::report::grouping::GroupedReport;
+use crate::stripe::client::Address;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// How records map onto an accounting system's chart of accounts and tax
+/// rates, from the profile's `[accounting]` table. Used by the accounting
+/// and tax service export formats (`--format xero`, `--format beancount`,
+/// `--format avalara`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountMapping {
+    /// Revenue account code sales are booked to; 200 (Xero's "Sales") if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sales_account: Option<String>,
+    /// Name of the tax rate charged in each state, keyed by two-letter state
+    /// code; "<state> Sales Tax" if unset
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tax_types: BTreeMap<String, String>,
+    /// Tax rate name for sales without tax; "Tax Exempt" if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exempt_tax_type: Option<String>,
+    /// Accounts posted to by `--format beancount`
+    #[serde(default, skip_serializing_if = "LedgerAccounts::is_empty")]
+    pub ledger: LedgerAccounts,
+    /// Avalara company code transactions are imported under; DEFAULT if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub company_code: Option<String>,
+}
+
+/// Plain-text accounting account names (`[accounting.ledger]`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LedgerAccounts {
+    /// Where payments land, net of fees; `Assets:Stripe` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assets: Option<String>,
+    /// Revenue; `Income:Sales` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub income: Option<String>,
+    /// Sales tax owed, with the state appended (`Liabilities:SalesTax:TX`);
+    /// `Liabilities:SalesTax` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sales_tax: Option<String>,
+    /// Stripe fees; `Expenses:Stripe:Fees` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<String>,
+}
+
+impl LedgerAccounts {
+    pub fn is_empty(&self) -> bool {
+        *self == LedgerAccounts::default()
+    }
+}
+
+impl AccountMapping {
+    pub fn is_empty(&self) -> bool {
+        *self == AccountMapping::default()
+    }
+
+    pub fn sales_account(&self) -> &str {
+        self.sales_account.as_deref().unwrap_or("200")
+    }
+
+    /// The tax rate a record's sales were taxed at
+    pub fn tax_type(&self, record: &InvoiceRecord) -> String {
+        if record.tax == 0 {
+            return self.exempt_tax_type.clone().unwrap_or_else(|| "Tax Exempt".to_string());
+        }
+        match self.tax_types.get(&record.state) {
+            Some(tax_type) => tax_type.clone(),
+            None => format!("{} Sales Tax", record.state),
+        }
+    }
+}
+
+/// A record as an accounting import row, with what the columns need to render it
+struct ExportRow<'a> {
+    record: &'a InvoiceRecord,
+    mapping: &'a AccountMapping,
+    date_format: DateFormat,
+}
+
+/// A column of an accounting import: its header and how a row fills it.
+/// Each import format is a table of these, written by `write_export`.
+type ExportColumn = (&'static str, fn(&ExportRow) -> String);
+
+fn blank(_: &ExportRow) -> String {
+    String::new()
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+fn invoice_number(row: &ExportRow) -> String {
+    row.record.invoice_number.clone().unwrap_or_else(|| row.record.invoice_id.clone())
+}
+
+fn date(row: &ExportRow) -> String {
+    row.date_format.format(row.record.date)
+}
+
+fn description(row: &ExportRow) -> String {
+    let record = row.record;
+    record.description.clone().or_else(|| record.plan.clone()).unwrap_or_else(|| format!("Sales ({})", record.state))
+}
+
+fn currency(row: &ExportRow) -> String {
+    row.record.currency.as_deref().unwrap_or("usd").to_uppercase()
+}
+
+/// Refunds and chargebacks booked in the period
+fn is_refund(record: &InvoiceRecord) -> bool {
+    record.total < 0
+}
+
+/// A refund's own ID, since tax services need it distinct from the invoice's
+fn transaction_id(row: &ExportRow, id: String) -> String {
+    if is_refund(row.record) { format!("{}-refund", id) } else { id }
+}
+
+fn address<'a>(row: &ExportRow<'a>) -> Option<&'a Address> {
+    row.record.address.as_ref()
+}
+
+fn street(row: &ExportRow) -> String {
+    let lines = address(row).map(|a| [a.line1.as_deref(), a.line2.as_deref()]).unwrap_or_default();
+    lines.into_iter().flatten().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join(", ")
+}
+
+fn city(row: &ExportRow) -> String {
+    address(row).and_then(|a| a.city.clone()).unwrap_or_default()
+}
+
+fn postal_code(row: &ExportRow) -> String {
+    address(row).and_then(|a| a.postal_code.clone()).unwrap_or_default()
+}
+
+fn country(row: &ExportRow) -> String {
+    row.record.country.clone().or_else(|| address(row).and_then(|a| a.country.clone())).unwrap_or_else(|| "US".to_string())
+}
+
+/// Xero's sales invoice import template. Each row is one invoice line at its
+/// pre-tax amount with the tax charged on it; rows with the same invoice
+/// number become one invoice.
+const XERO_COLUMNS: [ExportColumn; 29] = [
+    ("*ContactName", |row| row.record.customer.clone()),
+    ("EmailAddress", blank),
+    ("POAddressLine1", blank),
+    ("POAddressLine2", blank),
+    ("POAddressLine3", blank),
+    ("POAddressLine4", blank),
+    ("POCity", blank),
+    ("PORegion", |row| row.record.state.clone()),
+    ("POPostalCode", blank),
+    ("POCountry", |row| row.record.country.clone().unwrap_or_default()),
+    ("*InvoiceNumber", invoice_number),
+    ("Reference", |row| row.record.invoice_id.clone()),
+    ("*InvoiceDate", date),
+    // Already paid, so due on the day
+    ("*DueDate", date),
+    ("Total", blank),
+    ("InventoryItemCode", blank),
+    ("*Description", description),
+    ("*Quantity", |_| "1".to_string()),
+    ("*UnitAmount", |row| dollars(row.record.licenses)),
+    ("Discount", blank),
+    ("*AccountCode", |row| row.mapping.sales_account().to_string()),
+    ("*TaxType", |row| row.mapping.tax_type(row.record)),
+    ("TaxAmount", |row| dollars(row.record.tax)),
+    ("TrackingName1", blank),
+    ("TrackingOption1", blank),
+    ("TrackingName2", blank),
+    ("TrackingOption2", blank),
+    ("Currency", currency),
+    ("BrandingTheme", blank),
+];
+
+/// TaxJar's transaction import: one row per invoice, with the destination
+/// address, the pre-tax amount and the tax collected. Refunds are their own
+/// transactions, with negative amounts, referencing the invoice.
+const TAXJAR_COLUMNS: [ExportColumn; 14] = [
+    ("provider", |_| "stripe".to_string()),
+    ("transaction_type", |row| if is_refund(row.record) { "Refund" } else { "Order" }.to_string()),
+    ("transaction_id", |row| transaction_id(row, row.record.invoice_id.clone())),
+    ("transaction_reference_id", |row| if is_refund(row.record) { row.record.invoice_id.clone() } else { String::new() }),
+    ("transaction_date", |row| row.record.date.format("%Y-%m-%d").to_string()),
+    ("to_street", street),
+    ("to_city", city),
+    ("to_state", |row| row.record.state.clone()),
+    ("to_zip", postal_code),
+    ("to_country", country),
+    ("amount", |row| dollars(row.record.licenses)),
+    ("shipping", |_| "0.00".to_string()),
+    ("sales_tax", |row| dollars(row.record.tax)),
+    ("exemption_type", |row| {
+        if row.record.tax == 0 && row.record.non_taxable_sales != 0 { "other" } else { "" }.to_string()
+    }),
+];
+
+/// Avalara's transaction import template, one single-line document per
+/// invoice. Process code 3 imports the tax as collected rather than having
+/// AvaTax calculate it; refunds are return invoices (doc type 5).
+const AVALARA_COLUMNS: [ExportColumn; 18] = [
+    ("ProcessCode", |_| "3".to_string()),
+    ("DocCode", |row| transaction_id(row, invoice_number(row))),
+    ("DocType", |row| if is_refund(row.record) { "5" } else { "1" }.to_string()),
+    ("DocDate", |row| row.record.date.format("%Y-%m-%d").to_string()),
+    ("CompanyCode", |row| row.mapping.company_code.clone().unwrap_or_else(|| "DEFAULT".to_string())),
+    ("CustomerCode", |row| row.record.customer.clone()),
+    ("LineNo", |_| "1".to_string()),
+    ("TaxCode", blank),
+    ("Description", description),
+    ("Qty", |_| "1".to_string()),
+    ("Amount", |row| dollars(row.record.licenses)),
+    ("DestAddress", street),
+    ("DestCity", city),
+    ("DestRegion", |row| row.record.state.clone()),
+    ("DestPostalCode", postal_code),
+    ("DestCountry", country),
+    ("CurrencyCode", currency),
+    ("TotalTax", |row| dollars(row.record.tax)),
+];
+
+/// Write the report's records as rows of `columns`, with a header. Unless
+/// the import takes `refunds`, records with a negative total are left out:
+/// invoice imports can't take them, and they're entered as credit notes
+/// instead.
+fn write_export(
+    columns: &[ExportColumn],
+    report: &GroupedReport<'_>,
+    refunds: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let headers: Vec<&str> = columns.iter().map(|(header, _)| *header).collect();
+    writeln!(out, "{}", headers.join(","))?;
+    for record in report.groups.iter().flat_map(|group| group.records.iter()) {
+        if is_refund(record) && !refunds {
+            continue;
+        }
+        let row = ExportRow { record, mapping: &report.accounting, date_format: report.date_format };
+        let cells: Vec<String> = columns.iter().map(|(_, cell)| csv_escape(&cell(&row))).collect();
+        writeln!(out, "{}", cells.join(","))?;
+    }
+    Ok(())
+}
+
+/// Xero sales invoice import CSV (`--format xero`)
+pub struct XeroFormatter;
+
+impl ReportFormatter for XeroFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        write_export(&XERO_COLUMNS, report, false, out)
+    }
+}
+
+/// TaxJar transaction import CSV (`--format taxjar`)
+pub struct TaxjarFormatter;
+
+impl ReportFormatter for TaxjarFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        write_export(&TAXJAR_COLUMNS, report, true, out)
+    }
+}
+
+/// Avalara transaction import CSV (`--format avalara`)
+pub struct AvalaraFormatter;
+
+impl ReportFormatter for AvalaraFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        write_export(&AVALARA_COLUMNS, report, true, out)
+    }
+}
+
+/// Beancount transactions, one per record, posting the sale to revenue, its
+/// tax to the state's sales tax liability, its fees to expenses and the rest
+/// to the Stripe balance (`--format beancount`)
+pub struct BeancountFormatter;
+
+/// A beancount string literal
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A state as an account name component, which must start with a capital
+/// letter or digit and hold only letters, digits and dashes
+fn account_component(state: &str) -> String {
+    let component: String =
+        state.trim().chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '-' }).collect();
+    match component.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => component,
+        _ => "UNKNOWN".to_string(),
+    }
+}
+
+impl ReportFormatter for BeancountFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let ledger = &report.accounting.ledger;
+        let assets = ledger.assets.as_deref().unwrap_or("Assets:Stripe");
+        let income = ledger.income.as_deref().unwrap_or("Income:Sales");
+        let sales_tax = ledger.sales_tax.as_deref().unwrap_or("Liabilities:SalesTax");
+        let fees = ledger.fees.as_deref().unwrap_or("Expenses:Stripe:Fees");
+
+        if let Some(title) = &report.title {
+            writeln!(out, "; {}\n", title)?;
+        }
+        let mut records: Vec<&InvoiceRecord> = report.groups.iter().flat_map(|group| group.records.iter().copied()).collect();
+        records.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_id.cmp(&b.invoice_id)));
+        for record in records {
+            let currency = record.currency.as_deref().unwrap_or("usd").to_uppercase();
+            let narration = match &record.invoice_number {
+                Some(number) => format!("Invoice {}", number),
+                None => format!("Invoice {}", record.invoice_id),
+            };
+            writeln!(out, "{} * {} {}", record.date.format("%Y-%m-%d"), quoted(&record.customer), quoted(&narration))?;
+            writeln!(out, "  invoice: {}", quoted(&record.invoice_id))?;
+            writeln!(out, "  state: {}", quoted(&record.state))?;
+            let mut posting = |account: &str, cents: i64| -> Result<()> {
+                writeln!(out, "  {:<40} {:>12} {}", account, dollars(cents), currency)?;
+                Ok(())
+            };
+            posting(assets, record.licenses + record.tax - record.fees)?;
+            if record.fees != 0 {
+                posting(fees, record.fees)?;
+            }
+            posting(income, -record.licenses)?;
+            if record.tax != 0 {
+                posting(&format!("{}:{}", sales_tax, account_component(&record.state)), -record.tax)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xero_rows_map_accounts_and_tax_types() {
+        let record = |id: &str, state: &str, licenses: i64, tax: i64| InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "Lone Star, LLC".to_string(),
+            state: state.to_string(),
+            country: Some("US".to_string()),
+            licenses,
+            tax,
+            total: licenses + tax,
+            invoice_id: id.to_string(),
+            ..Default::default()
+        };
+        let records = vec![
+            InvoiceRecord { invoice_number: Some("ACME-0042".to_string()), ..record("in_1", "TX", 10000, 825) },
+            record("in_2", "OR", 5000, 0),
+            record("in_3", "TX", -2000, -165),
+        ];
+        let mapping = AccountMapping {
+            sales_account: Some("4000".to_string()),
+            tax_types: BTreeMap::from([("TX".to_string(), "Texas State Tax".to_string())]),
+            ..Default::default()
+        };
+        let report = GroupedReport::by_state(&records).with_accounting(mapping);
+
+        let mut output = Vec::new();
+        XeroFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("*ContactName,EmailAddress,"));
+        assert_eq!(
+            lines[2],
+            "\"Lone Star, LLC\",,,,,,,TX,,US,ACME-0042,in_1,08/14/2025,08/14/2025,,,Sales (TX),1,100.00,,4000,Texas State Tax,8.25,,,,,USD,"
+        );
+        assert!(lines[1].contains(",OR,") && lines[1].contains(",Tax Exempt,0.00,"));
+    }
+
+    #[test]
+    fn test_tax_service_rows_carry_destination_and_refunds() {
+        let sale = InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "Lone Star LLC".to_string(),
+            state: "TX".to_string(),
+            country: Some("US".to_string()),
+            address: Some(Address {
+                line1: Some("100 Congress Ave".to_string()),
+                line2: Some("Suite 200".to_string()),
+                city: Some("Austin".to_string()),
+                state: Some("TX".to_string()),
+                postal_code: Some("78701".to_string()),
+                country: Some("US".to_string()),
+            }),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            invoice_id: "in_1".to_string(),
+            ..Default::default()
+        };
+        let refund = InvoiceRecord { licenses: -2000, tax: -165, total: -2165, ..sale.clone() };
+        let records = vec![sale, refund];
+        let mapping = AccountMapping { company_code: Some("LONESTAR".to_string()), ..Default::default() };
+        let report = GroupedReport::by_state(&records).with_accounting(mapping);
+
+        let mut output = Vec::new();
+        TaxjarFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(
+            &"stripe,Order,in_1,,2025-08-14,\"100 Congress Ave, Suite 200\",Austin,TX,78701,US,100.00,0.00,8.25,"
+        ));
+        let refund = lines.iter().find(|line| line.starts_with("stripe,Refund,in_1-refund,in_1,")).unwrap();
+        assert!(refund.ends_with(",-20.00,0.00,-1.65,"));
+
+        let mut output = Vec::new();
+        AvalaraFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("ProcessCode,DocCode,DocType,DocDate,CompanyCode,"));
+        assert!(output.contains("3,in_1,1,2025-08-14,LONESTAR,Lone Star LLC,1,,Sales (TX),1,100.00,"));
+        assert!(output.contains("3,in_1-refund,5,"));
+    }
+
+    #[test]
+    fn test_beancount_transactions_balance() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "The \"Best\" Co".to_string(),
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 344,
+            invoice_id: "in_1".to_string(),
+            ..Default::default()
+        }];
+        let mapping = AccountMapping {
+            ledger: LedgerAccounts { income: Some("Income:Subscriptions".to_string()), ..Default::default() },
+            ..Default::default()
+        };
+        let report = GroupedReport::by_state(&records).with_accounting(mapping);
+
+        let mut output = Vec::new();
+        BeancountFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("2025-08-14 * \"The \\\"Best\\\" Co\" \"Invoice in_1\""));
+        let postings: Vec<(&str, &str)> = output
+            .lines()
+            .filter(|line| line.ends_with(" USD"))
+            .map(|line| {
+                let mut parts = line.split_whitespace();
+                (parts.next().unwrap(), parts.next().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            postings,
+            vec![
+                ("Assets:Stripe", "104.81"),
+                ("Expenses:Stripe:Fees", "3.44"),
+                ("Income:Subscriptions", "-100.00"),
+                ("Liabilities:SalesTax:TX", "-8.25"),
+            ]
+        );
+    }
+}
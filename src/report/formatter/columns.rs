@@ -0,0 +1,194 @@
+use crate::report::formatter::DateFormat;
+use crate::report::rates::{effective_rate, format_rate};
+use crate::stripe::models::{AmountColumn, InvoiceRecord};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Columns splitting Fees by kind (`--fee-breakdown`)
+pub(crate) const FEE_BREAKDOWN_COLUMNS: [AmountColumn; 4] =
+    [AmountColumn::CardFees, AmountColumn::AchFees, AmountColumn::StripeTaxFees, AmountColumn::OtherFees];
+
+/// A column of the invoice rows in tabular reports, selectable and ordered
+/// with `--columns`. Each knows its header and how to read its cell from a
+/// record, so formatters lay out rows from a list of columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Column {
+    Date,
+    Customer,
+    State,
+    /// Where the state came from: an address or the ZIP code
+    StateSource,
+    /// Connected account ID
+    Account,
+    /// Plan (price nickname) the invoice's subscription bills
+    Plan,
+    InvoiceNumber,
+    InvoiceId,
+    /// Product ID, on line-item rows
+    Product,
+    /// Line description, on line-item rows
+    Description,
+    /// Total subscription quantity
+    Users,
+    /// Users under its line-item header
+    Quantity,
+    Licenses,
+    TaxableSales,
+    NonTaxableSales,
+    Tax,
+    /// Tax less local tax (`--tax-breakdown`)
+    StateTax,
+    /// Tax collected for counties, cities and districts (`--tax-breakdown`)
+    LocalTax,
+    Total,
+    Fees,
+    CardFees,
+    AchFees,
+    StripeTaxFees,
+    OtherFees,
+    /// Tax as a percentage of taxable sales, marked `(!)` outside the
+    /// expected rate
+    EffectiveRate,
+}
+
+impl Column {
+    pub fn header(self) -> &'static str {
+        if let Some(amount) = self.amount() {
+            return amount.header();
+        }
+        match self {
+            Column::Date => "Date",
+            Column::Customer => "Customer",
+            Column::State => "State",
+            Column::StateSource => "State Source",
+            Column::Account => "Account",
+            Column::Plan => "Plan",
+            Column::InvoiceNumber => "Invoice Number",
+            Column::InvoiceId => "Invoice ID",
+            Column::Product => "Product",
+            Column::Description => "Description",
+            Column::Users => "Users",
+            Column::Quantity => "Quantity",
+            Column::EffectiveRate => "Effective Rate",
+            _ => unreachable!("amount columns are handled above"),
+        }
+    }
+
+    /// The money column this is, if it's one; money cells are totaled in
+    /// subtotal rows and formatted in dollars
+    pub fn amount(self) -> Option<AmountColumn> {
+        Some(match self {
+            Column::Licenses => AmountColumn::Licenses,
+            Column::TaxableSales => AmountColumn::TaxableSales,
+            Column::NonTaxableSales => AmountColumn::NonTaxableSales,
+            Column::Tax => AmountColumn::Tax,
+            Column::StateTax => AmountColumn::StateTax,
+            Column::LocalTax => AmountColumn::LocalTax,
+            Column::Total => AmountColumn::Total,
+            Column::Fees => AmountColumn::Fees,
+            Column::CardFees => AmountColumn::CardFees,
+            Column::AchFees => AmountColumn::AchFees,
+            Column::StripeTaxFees => AmountColumn::StripeTaxFees,
+            Column::OtherFees => AmountColumn::OtherFees,
+            _ => return None,
+        })
+    }
+
+    /// The cell for `record` in a text column; empty for money columns
+    pub fn text(self, record: &InvoiceRecord, date_format: DateFormat) -> String {
+        match self {
+            Column::Date => date_format.format(record.date),
+            Column::Customer => record.customer.clone(),
+            Column::State => record.state.clone(),
+            Column::StateSource => record.state_source.map(|source| source.to_string()).unwrap_or_default(),
+            Column::Account => record.account.clone().unwrap_or_default(),
+            Column::Plan => record.plan.clone().unwrap_or_default(),
+            Column::InvoiceNumber => record.invoice_number.clone().unwrap_or_default(),
+            Column::InvoiceId => record.invoice_id.clone(),
+            Column::Product => record.product.clone().unwrap_or_default(),
+            Column::Description => record.description.clone().unwrap_or_default(),
+            Column::Users | Column::Quantity => record.users.to_string(),
+            Column::EffectiveRate => match effective_rate(record.tax, record.taxable_sales) {
+                Some(rate) if record.rate_mismatch => format!("{} (!)", format_rate(rate)),
+                Some(rate) => format_rate(rate),
+                None => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+}
+
+impl From<AmountColumn> for Column {
+    fn from(amount: AmountColumn) -> Self {
+        match amount {
+            AmountColumn::Licenses => Column::Licenses,
+            AmountColumn::TaxableSales => Column::TaxableSales,
+            AmountColumn::NonTaxableSales => Column::NonTaxableSales,
+            AmountColumn::Tax => Column::Tax,
+            AmountColumn::StateTax => Column::StateTax,
+            AmountColumn::LocalTax => Column::LocalTax,
+            AmountColumn::Total => Column::Total,
+            AmountColumn::Fees => Column::Fees,
+            AmountColumn::CardFees => Column::CardFees,
+            AmountColumn::AchFees => Column::AchFees,
+            AmountColumn::StripeTaxFees => Column::StripeTaxFees,
+            AmountColumn::OtherFees => Column::OtherFees,
+        }
+    }
+}
+
+/// Check a `--columns` selection: subtotal rows put their label in the first
+/// column, so it has to be a text column, and each column appears once
+pub fn check_columns(columns: &[Column]) -> Result<()> {
+    if let Some(first) = columns.first()
+        && first.amount().is_some()
+    {
+        bail!(
+            "--columns must start with a text column such as date or customer, not {}, to hold the subtotal labels",
+            first.header()
+        );
+    }
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i].contains(column) {
+            bail!("--columns lists {} more than once", column.header());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_parsed_and_checked() {
+        let columns: Vec<Column> = ["date", "customer", "invoice-number", "taxable-sales"]
+            .iter()
+            .map(|name| Column::from_str(name, true).unwrap())
+            .collect();
+        assert_eq!(columns, vec![Column::Date, Column::Customer, Column::InvoiceNumber, Column::TaxableSales]);
+        assert!(check_columns(&columns).is_ok());
+        assert!(check_columns(&[]).is_ok());
+
+        let error = check_columns(&[Column::Tax, Column::Customer]).unwrap_err();
+        assert!(error.to_string().contains("not Tax"));
+        assert!(check_columns(&[Column::Date, Column::Tax, Column::Tax]).is_err());
+
+        let record = InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2026, 7, 3).unwrap(),
+            invoice_number: Some("ACME-0042".to_string()),
+            users: 3,
+            ..Default::default()
+        };
+        assert_eq!(Column::Date.text(&record, DateFormat::Eu), "03/07/2026");
+        assert_eq!(Column::InvoiceNumber.text(&record, DateFormat::Us), "ACME-0042");
+        assert_eq!(Column::Quantity.text(&record, DateFormat::Us), "3");
+        assert_eq!(Column::StateSource.text(&record, DateFormat::Us), "");
+
+        let record = InvoiceRecord { taxable_sales: 10000, tax: 1000, rate_mismatch: true, ..Default::default() };
+        assert_eq!(Column::EffectiveRate.text(&record, DateFormat::Us), "10.000% (!)");
+        assert_eq!(Column::from(AmountColumn::StripeTaxFees).header(), "Stripe Tax Fees");
+    }
+}
@@ -0,0 +1,951 @@
+use super::{Column, ReportFormatter};
+use crate::report::grouping::{GroupedReport, StateGroup, Totals};
+use crate::report::rates::format_rate;
+use crate::stripe::models::AmountColumn;
+use anyhow::Result;
+use std::io::Write;
+
+/// Tab-delimited output: one section per state with a subtotal row, then a grand total
+pub struct TsvFormatter;
+
+/// Same layout as the TSV report, comma-separated with RFC 4180 quoting
+pub struct CsvFormatter;
+
+impl ReportFormatter for TsvFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        write_sections(report, out, '\t', |field| field.to_string(), report.invoice_columns.unwrap_or(false))
+    }
+}
+
+impl ReportFormatter for CsvFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        write_sections(report, out, ',', csv_escape, report.invoice_columns.unwrap_or(true))
+    }
+}
+
+/// Quote a CSV field if it contains a delimiter, quote, or line break
+pub(crate) fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// Banner for the top of the report when the time budget ran out
+pub(crate) fn partial_warning(report: &GroupedReport<'_>) -> Option<String> {
+    report.partial.map(|partial| {
+        format!(
+            "PARTIAL REPORT: time budget exhausted after {} of {} invoices; totals are incomplete",
+            partial.processed,
+            partial.processed + partial.remaining
+        )
+    })
+}
+
+/// Banner for the top of the report when revenue landed in the UNKNOWN state,
+/// since that revenue can't be filed until its state is fixed in Stripe
+pub(crate) fn unknown_state_warning(report: &GroupedReport<'_>) -> Option<String> {
+    report.unknown_state().map(|group| {
+        format!(
+            "WARNING: {} of revenue ({} invoice(s)) has no state and is reported under {}",
+            dollars(group.subtotal.total),
+            group.records.len(),
+            group.state
+        )
+    })
+}
+
+/// Note listing records whose state was inferred from a ZIP code, if any
+pub(crate) fn inferred_state_note(report: &GroupedReport<'_>) -> Option<String> {
+    let inferred: Vec<&str> = report
+        .groups
+        .iter()
+        .flat_map(|group| &group.records)
+        .filter(|record| record.state_inferred)
+        .map(|record| record.invoice_id.as_str())
+        .collect();
+
+    if inferred.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "NOTE: state inferred from ZIP code for {} invoice(s): {}",
+        inferred.len(),
+        inferred.join(", ")
+    ))
+}
+
+/// Writes delimited rows straight to the output, escaping each field
+struct RowWriter<'w> {
+    out: &'w mut dyn Write,
+    sep: char,
+    escape: fn(&str) -> String,
+    /// Columns of the invoice rows, in order
+    columns: Vec<Column>,
+    /// Money columns, in order, for subtotal and summary rows
+    amount_columns: Vec<AmountColumn>,
+}
+
+impl<'w> RowWriter<'w> {
+    fn new(
+        report: &GroupedReport<'_>,
+        out: &'w mut dyn Write,
+        sep: char,
+        escape: fn(&str) -> String,
+        invoice_columns: bool,
+    ) -> Self {
+        RowWriter {
+            out,
+            sep,
+            escape,
+            columns: report.row_columns(invoice_columns),
+            amount_columns: report.amount_columns(),
+        }
+    }
+
+    fn row(&mut self, fields: &[&str]) -> Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, "{}", self.sep)?;
+            }
+            self.out.write_all((self.escape)(field).as_bytes())?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    /// A subtotal row: the label in the first column, the totals in the
+    /// money columns and the rest blank
+    fn totals(&mut self, label: &str, totals: &Totals) -> Result<()> {
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| match column.amount() {
+                Some(amount) => dollars(totals.get(amount)),
+                None if i == 0 => label.to_string(),
+                None if *column == Column::EffectiveRate => totals.effective_rate().map(format_rate).unwrap_or_default(),
+                None => String::new(),
+            })
+            .collect();
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        self.row(&fields)
+    }
+
+    fn amount_headers(&self) -> Vec<&'static str> {
+        self.amount_columns.iter().map(|c| c.header()).collect()
+    }
+
+    /// Money columns formatted in dollars
+    fn amounts(&self, totals: &Totals) -> Vec<String> {
+        self.amount_columns.iter().map(|&c| dollars(totals.get(c))).collect()
+    }
+
+    fn blank(&mut self) -> Result<()> {
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+fn write_sections(
+    report: &GroupedReport<'_>,
+    out: &mut dyn Write,
+    sep: char,
+    escape: fn(&str) -> String,
+    invoice_columns: bool,
+) -> Result<()> {
+    let mut w = RowWriter::new(report, out, sep, escape, invoice_columns);
+
+    if let Some(title) = &report.title {
+        w.row(&[title])?;
+        w.blank()?;
+    }
+
+    write_warnings(&mut w, report)?;
+
+    // Only the subtotal lines, for filing
+    if report.summary {
+        write_summary_table(&mut w, report)?;
+        write_tax_due(&mut w, report)?;
+        write_quarter_summary(&mut w, report)?;
+        write_products(&mut w, report)?;
+        write_excluded(&mut w, report)?;
+        return write_exchange_rates(&mut w, report);
+    }
+
+    // Output each state section
+    for group in &report.groups {
+        // State section header
+        w.row(&[&format!("===== {} =====", group.state)])?;
+        write_group_table(&mut w, report, group)?;
+        if report.monthly {
+            write_month_table(&mut w, group)?;
+        }
+        w.blank()?;
+    }
+
+    // Grand total section, then one per other currency
+    w.totals("GRAND TOTAL", &report.grand_total)?;
+    for (currency, totals) in &report.currency_totals {
+        w.totals(&format!("GRAND TOTAL ({})", currency), totals)?;
+    }
+
+    write_tax_due(&mut w, report)?;
+    write_quarter_summary(&mut w, report)?;
+    write_products(&mut w, report)?;
+    write_excluded(&mut w, report)?;
+    write_exchange_rates(&mut w, report)?;
+    write_footnotes(&mut w, report)
+}
+
+/// Tax per state less the Texas filing discount (`--texas-discount`).
+/// Nothing is written without a discount.
+fn write_tax_due(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    let Some(tax_due) = report.tax_due() else {
+        return Ok(());
+    };
+
+    w.blank()?;
+    w.row(&["===== TAX DUE ====="])?;
+    w.row(&["State", "Tax", "Discount", "Tax Due After Discount"])?;
+    for state in &tax_due.states {
+        w.row(&[&state.state, &dollars(state.tax), &dollars(state.discount), &dollars(state.due)])?;
+    }
+    w.row(&["TOTAL", &dollars(tax_due.tax), &dollars(tax_due.discount), &dollars(tax_due.due)])
+}
+
+/// Sales (licenses) and tax per state with a column pair for each quarter
+/// and the year, for annual reports. Nothing is written otherwise.
+fn write_quarter_summary(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    if !report.quarterly {
+        return Ok(());
+    }
+
+    w.blank()?;
+    w.row(&["===== QUARTERLY SUMMARY ====="])?;
+    let mut headers = vec![report.group_by.header().to_string()];
+    for label in ["Q1", "Q2", "Q3", "Q4", "Year"] {
+        headers.extend([format!("{} Sales", label), format!("{} Tax", label)]);
+    }
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    w.row(&headers)?;
+
+    let mut write_quarters = |label: &str, quarters: &[Totals; 4], year: &Totals| {
+        let mut fields = vec![label.to_string()];
+        for totals in quarters.iter().chain([year]) {
+            fields.extend([dollars(totals.licenses), dollars(totals.tax)]);
+        }
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        w.row(&fields)
+    };
+
+    let mut grand_total: [Totals; 4] = Default::default();
+    for group in &report.groups {
+        let quarters = group.by_quarter(report.fiscal_start);
+        if group.currency.is_none() {
+            for (total, quarter) in grand_total.iter_mut().zip(&quarters) {
+                total.merge(quarter);
+            }
+        }
+        write_quarters(&group.state, &quarters, &group.subtotal)?;
+    }
+    write_quarters("GRAND TOTAL", &grand_total, &report.grand_total)
+}
+
+/// Revenue and tax per product (`--group-by product`). Fees are per invoice,
+/// so there are no fee columns. Nothing is written without a product breakdown.
+fn write_products(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    if report.products.is_empty() {
+        return Ok(());
+    }
+
+    let columns: Vec<AmountColumn> = w.amount_columns.iter().copied().filter(|c| !c.is_fee()).collect();
+
+    w.blank()?;
+    w.row(&["===== PRODUCTS ====="])?;
+    let mut headers = vec!["Product", "Name", "Lines"];
+    headers.extend(columns.iter().map(|c| c.header()));
+    w.row(&headers)?;
+    for group in &report.products {
+        let mut fields = vec![
+            group.product.clone().unwrap_or_default(),
+            group.label(),
+            group.lines.to_string(),
+        ];
+        fields.extend(columns.iter().map(|c| dollars(group.subtotal.get(*c))));
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        w.row(&fields)?;
+    }
+    Ok(())
+}
+
+/// Numbered explanations for cells marked [N], where a figure differs from
+/// the raw Stripe amount. Nothing is written if nothing was adjusted.
+fn write_footnotes(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    if report.footnotes.is_empty() {
+        return Ok(());
+    }
+
+    w.blank()?;
+    w.row(&["===== FOOTNOTES ====="])?;
+    for (i, note) in report.footnotes.iter().enumerate() {
+        w.row(&[&format!("[{}]", i + 1), note])?;
+    }
+    Ok(())
+}
+
+/// The rate each converted invoice was converted to USD at, so converted
+/// figures can be reproduced. Nothing is written if none were converted.
+fn write_exchange_rates(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    let mut converted = report.converted_records().peekable();
+    if converted.peek().is_none() {
+        return Ok(());
+    }
+
+    w.blank()?;
+    w.row(&["===== EXCHANGE RATES ====="])?;
+    w.row(&["Invoice", "Currency", "Rate to USD"])?;
+    for record in converted {
+        if let Some(fx) = &record.fx {
+            w.row(&[&record.invoice_id, &fx.currency, &fx.rate.to_string()])?;
+        }
+    }
+    Ok(())
+}
+
+/// Invoices left out of the report, so omitted revenue is visible in the
+/// file itself rather than only in warnings. Nothing is written if none were.
+fn write_excluded(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    if report.skipped.is_empty() {
+        return Ok(());
+    }
+
+    w.blank()?;
+    w.row(&["===== EXCLUDED INVOICES ====="])?;
+    w.row(&["Invoice", "Customer", "Amount", "Reason"])?;
+    for skip in report.skipped {
+        let amount = dollars(skip.amount);
+        w.row(&[&skip.invoice_id, &skip.customer, &amount, &skip.reason])?;
+    }
+    w.row(&["Total excluded", "", &dollars(report.skipped_amount()), ""])
+}
+
+/// Report-level warning banners, each followed by a blank line
+fn write_warnings(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    for warning in [partial_warning(report), unknown_state_warning(report), inferred_state_note(report)]
+        .into_iter()
+        .flatten()
+    {
+        w.row(&[&warning])?;
+        w.blank()?;
+    }
+    Ok(())
+}
+
+/// Column headers, one row per record, and the state's subtotal row
+fn write_group_table(w: &mut RowWriter<'_>, report: &GroupedReport<'_>, group: &StateGroup<'_>) -> Result<()> {
+    let headers: Vec<&str> = w.columns.iter().map(|c| c.header()).collect();
+    w.row(&headers)?;
+
+    // Data rows for this state
+    for record in &group.records {
+        let mut row_totals = Totals::default();
+        row_totals.add(record);
+        let fields: Vec<String> = w
+            .columns
+            .iter()
+            .map(|column| match column.amount() {
+                Some(amount) => format!("{}{}", dollars(row_totals.get(amount)), report.footnote_markers(record, amount)),
+                None => column.text(record, report.date_format),
+            })
+            .collect();
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        w.row(&fields)?;
+    }
+
+    // State subtotal row
+    w.totals("Subtotal", &group.subtotal)
+}
+
+/// Sales (licenses) and tax per month, after a blank line, for reconciling
+/// a quarterly section against monthly books
+fn write_month_table(w: &mut RowWriter<'_>, group: &StateGroup<'_>) -> Result<()> {
+    w.blank()?;
+    w.row(&["Month", "Sales", "Tax"])?;
+    for (month, totals) in group.by_month() {
+        w.row(&[&month, &dollars(totals.licenses), &dollars(totals.tax)])?;
+    }
+    Ok(())
+}
+
+/// One state's records as a standalone CSV table (no section banner or grand total)
+pub(crate) fn write_csv_state_detail(report: &GroupedReport<'_>, group: &StateGroup<'_>, out: &mut dyn Write) -> Result<()> {
+    let mut w = RowWriter::new(report, out, ',', csv_escape, report.invoice_columns.unwrap_or(true));
+    write_group_table(&mut w, report, group)?;
+    if report.monthly {
+        write_month_table(&mut w, group)?;
+    }
+    if group.records.iter().any(|r| !r.adjustments.is_empty()) {
+        write_footnotes(&mut w, report)?;
+    }
+    Ok(())
+}
+
+/// One row per state with its invoice count and subtotals, then the grand total
+pub(crate) fn write_csv_state_summary(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    let mut w = RowWriter::new(report, out, ',', csv_escape, false);
+    write_warnings(&mut w, report)?;
+    write_summary_table(&mut w, report)?;
+    write_tax_due(&mut w, report)?;
+    write_quarter_summary(&mut w, report)?;
+    write_excluded(&mut w, report)?;
+    write_exchange_rates(&mut w, report)
+}
+
+/// One row per section with its invoice count and subtotals, then the grand
+/// total and one per other currency
+fn write_summary_table(w: &mut RowWriter<'_>, report: &GroupedReport<'_>) -> Result<()> {
+    let effective_rate = w.columns.contains(&Column::EffectiveRate);
+    let mut headers = vec![report.group_by.header(), "Invoices"];
+    headers.extend(w.amount_headers());
+    if effective_rate {
+        headers.push(Column::EffectiveRate.header());
+    }
+    w.row(&headers)?;
+
+    let mut write_totals = |label: &str, count: usize, totals: &Totals| {
+        let mut fields = vec![label.to_string(), count.to_string()];
+        fields.extend(w.amounts(totals));
+        if effective_rate {
+            fields.push(totals.effective_rate().map(format_rate).unwrap_or_default());
+        }
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        w.row(&fields)
+    };
+
+    for group in &report.groups {
+        write_totals(&group.state, group.records.len(), &group.subtotal)?;
+    }
+    let invoice_count = |currency: Option<&str>| {
+        report
+            .groups
+            .iter()
+            .filter(|g| g.currency.as_deref() == currency)
+            .map(|g| g.records.len())
+            .sum()
+    };
+    write_totals("GRAND TOTAL", invoice_count(None), &report.grand_total)?;
+    for (currency, totals) in &report.currency_totals {
+        write_totals(&format!("GRAND TOTAL ({})", currency), invoice_count(Some(currency)), totals)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::formatter::DateFormat;
+    use crate::report::grouping::GroupBy;
+    use crate::report::StateSource;
+    use crate::stripe::models::{FeeBreakdown, InvoiceRecord, SkippedInvoice};
+
+    #[test]
+    fn test_csv_quotes_customer_names() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Smith, Jones & \"Partners\"".to_string(),
+            invoice_id: "in_123".to_string(),
+            invoice_number: Some("SJP-0042".to_string()),
+            users: 1,
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 320,
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        CsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("===== TX =====\nDate,Customer,Invoice Number,Invoice ID,Users,Licenses,Tax,Total,Fees\n"));
+        assert!(output.contains("10/15/2025,\"Smith, Jones & \"\"Partners\"\"\",SJP-0042,in_123,1,100.00,8.25,108.25,3.20"));
+        assert!(output.contains("GRAND TOTAL,,,,,100.00,8.25,108.25,3.20"));
+
+        // Left out of TSV unless asked for
+        let tsv = |invoice_columns| {
+            let mut output = Vec::new();
+            let report = GroupedReport::by_state(&records).with_invoice_columns(invoice_columns);
+            TsvFormatter.write_report(&report, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert!(tsv(None).contains("\nDate\tCustomer\tUsers\t"));
+        assert!(tsv(Some(true)).contains("\tSJP-0042\tin_123\t1\t100.00\t"));
+    }
+
+    #[test]
+    fn test_account_column_added_for_connected_accounts() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Test Company".to_string(),
+            users: 5,
+            state: "TX".to_string(),
+            licenses: 50000,
+            tax: 4000,
+            total: 54000,
+            fees: 1600,
+            account: Some("acct_123".to_string()),
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Date\tCustomer\tAccount\tUsers\tLicenses\tTax\tTotal\tFees\n"));
+        assert!(output.contains("10/15/2025\tTest Company\tacct_123\t5\t500.00\t40.00\t540.00\t16.00"));
+        assert!(output.contains("Subtotal\t\t\t\t500.00\t40.00\t540.00\t16.00"));
+    }
+
+    #[test]
+    fn test_unknown_state_flagged_at_top() {
+        let records = vec![InvoiceRecord {
+            customer: "No Address Co".to_string(),
+            state: crate::report::UNKNOWN_STATE.to_string(),
+            licenses: 5000,
+            total: 5000,
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with(
+            "WARNING: 50.00 of revenue (1 invoice(s)) has no state and is reported under UNKNOWN\n\n===== UNKNOWN ====="
+        ));
+    }
+
+    #[test]
+    fn test_inferred_state_noted() {
+        let records = vec![InvoiceRecord {
+            invoice_id: "in_zip".to_string(),
+            customer: "Zip Only LLC".to_string(),
+            state: "TX".to_string(),
+            state_inferred: true,
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("NOTE: state inferred from ZIP code for 1 invoice(s): in_zip\n\n===== TX ====="));
+    }
+
+    #[test]
+    fn test_monthly_breakdown_per_state() {
+        let record = |date: &str, licenses: i64, tax: i64| InvoiceRecord {
+            date: chrono::NaiveDate::parse_from_str(date, "%m/%d/%Y").unwrap(),
+            customer: "Acme".to_string(),
+            state: "TX".to_string(),
+            licenses,
+            tax,
+            total: licenses + tax,
+            ..Default::default()
+        };
+        let records = vec![
+            record("07/03/2026", 10000, 825),
+            record("08/14/2026", 20000, 1650),
+            record("07/21/2026", 5000, 413),
+        ];
+
+        let mut output = Vec::new();
+        CsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_monthly(true).with_invoice_columns(Some(false)), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "Subtotal,,,350.00,28.88,378.88,0.00\n\n\
+             Month,Sales,Tax\n\
+             07/2026,150.00,12.38\n\
+             08/2026,200.00,16.50\n\n\
+             GRAND TOTAL"
+        ));
+    }
+
+    #[test]
+    fn test_quarterly_summary_per_state() {
+        let record = |date: &str, state: &str, licenses: i64, tax: i64| InvoiceRecord {
+            date: chrono::NaiveDate::parse_from_str(date, "%m/%d/%Y").unwrap(),
+            customer: "Acme".to_string(),
+            state: state.to_string(),
+            licenses,
+            tax,
+            total: licenses + tax,
+            ..Default::default()
+        };
+        let records = vec![
+            record("02/03/2025", "TX", 10000, 825),
+            record("11/14/2025", "TX", 20000, 1650),
+            record("08/21/2025", "CA", 5000, 363),
+        ];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_quarterly(true), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "===== QUARTERLY SUMMARY =====\n\
+             State\tQ1 Sales\tQ1 Tax\tQ2 Sales\tQ2 Tax\tQ3 Sales\tQ3 Tax\tQ4 Sales\tQ4 Tax\tYear Sales\tYear Tax\n\
+             CA\t0.00\t0.00\t0.00\t0.00\t50.00\t3.63\t0.00\t0.00\t50.00\t3.63\n\
+             TX\t100.00\t8.25\t0.00\t0.00\t0.00\t0.00\t200.00\t16.50\t300.00\t24.75\n\
+             GRAND TOTAL\t100.00\t8.25\t0.00\t0.00\t50.00\t3.63\t200.00\t16.50\t350.00\t28.38\n"
+        ));
+
+        // Fiscal quarters of a year starting in July
+        let mut output = Vec::new();
+        let report = GroupedReport::by_state(&records).with_quarterly(true).with_fiscal_start(7);
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "CA\t50.00\t3.63\t0.00\t0.00\t0.00\t0.00\t0.00\t0.00\t50.00\t3.63\n\
+             TX\t0.00\t0.00\t200.00\t16.50\t100.00\t8.25\t0.00\t0.00\t300.00\t24.75\n"
+        ));
+    }
+
+    #[test]
+    fn test_summary_has_only_subtotals() {
+        let records = vec![
+            InvoiceRecord { state: "TX".to_string(), licenses: 10000, tax: 825, total: 10825, fees: 344, ..Default::default() },
+            InvoiceRecord { state: "TX".to_string(), licenses: 5000, tax: 413, total: 5413, fees: 175, ..Default::default() },
+            InvoiceRecord { state: "CA".to_string(), licenses: 2000, tax: 145, total: 2145, fees: 88, ..Default::default() },
+        ];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_summary(true), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "State\tInvoices\tLicenses\tTax\tTotal\tFees\n\
+             CA\t1\t20.00\t1.45\t21.45\t0.88\n\
+             TX\t2\t150.00\t12.38\t162.38\t5.19\n\
+             GRAND TOTAL\t3\t170.00\t13.83\t183.83\t6.07\n"
+        );
+    }
+
+    #[test]
+    fn test_customer_sections_add_state_column() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Acme".to_string(),
+            users: 1,
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by(GroupBy::Customer, &records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with(
+            "===== Acme =====\n\
+             Date\tCustomer\tState\tUsers\tLicenses\tTax\tTotal\tFees\n\
+             10/15/2025\tAcme\tTX\t1\t100.00\t8.25\t108.25\t0.00\n\
+             Subtotal\t\t\t\t100.00\t8.25\t108.25\t0.00\n"
+        ));
+    }
+
+    #[test]
+    fn test_currencies_totaled_separately() {
+        let records = vec![
+            InvoiceRecord {
+                state: "NY".to_string(),
+                licenses: 10000,
+                total: 10000,
+                ..Default::default()
+            },
+            InvoiceRecord {
+                state: "NY".to_string(),
+                currency: Some("EUR".to_string()),
+                licenses: 5000,
+                total: 5000,
+                ..Default::default()
+            },
+            InvoiceRecord {
+                invoice_id: "in_gbp".to_string(),
+                state: "NY".to_string(),
+                licenses: 12700,
+                total: 12700,
+                fx: Some(crate::stripe::models::FxConversion { currency: "GBP".to_string(), rate: 1.27 }),
+                ..Default::default()
+            },
+        ];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("===== NY (EUR) ====="));
+        assert!(output.contains(
+            "GRAND TOTAL\t\t\t227.00\t0.00\t227.00\t0.00\n\
+             GRAND TOTAL (EUR)\t\t\t50.00\t0.00\t50.00\t0.00\n"
+        ));
+        assert!(output.ends_with("===== EXCHANGE RATES =====\nInvoice\tCurrency\tRate to USD\nin_gbp\tGBP\t1.27\n"));
+    }
+
+    #[test]
+    fn test_taxable_sales_columns() {
+        let records = vec![
+            InvoiceRecord {
+                date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+                customer: "Lincoln ISD".to_string(),
+                state: "TX".to_string(),
+                licenses: 25000,
+                non_taxable_sales: 25000,
+                total: 25000,
+                ..Default::default()
+            },
+            InvoiceRecord {
+                date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+                customer: "Acme".to_string(),
+                state: "TX".to_string(),
+                licenses: 10000,
+                taxable_sales: 10000,
+                tax: 825,
+                total: 10825,
+                ..Default::default()
+            },
+        ];
+
+        let mut output = Vec::new();
+        CsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_invoice_columns(Some(false)), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "Date,Customer,Users,Licenses,Taxable Sales,Non-Taxable Sales,Tax,Total,Fees\n\
+             10/15/2025,Lincoln ISD,0,250.00,0.00,250.00,0.00,250.00,0.00\n\
+             10/15/2025,Acme,0,100.00,100.00,0.00,8.25,108.25,0.00\n\
+             Subtotal,,,350.00,100.00,250.00,8.25,358.25,0.00\n"
+        ));
+    }
+
+    #[test]
+    fn test_fee_breakdown_columns() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Acme".to_string(),
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 370,
+            fee_breakdown: FeeBreakdown { card: 320, stripe_tax: 50, ..Default::default() },
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        CsvFormatter
+            .write_report(
+                &GroupedReport::by_state(&records)
+                    .with_fee_breakdown(true)
+                    .with_date_format(DateFormat::Iso)
+                    .with_invoice_columns(Some(false)),
+                &mut output,
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "Date,Customer,Users,Licenses,Tax,Total,Fees,Card Fees,ACH Fees,Stripe Tax Fees,Other Fees\n\
+             2025-10-15,Acme,0,100.00,8.25,108.25,3.70,3.20,0.00,0.50,0.00\n\
+             Subtotal,,,100.00,8.25,108.25,3.70,3.20,0.00,0.50,0.00\n"
+        ));
+    }
+
+    #[test]
+    fn test_selected_columns() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Acme".to_string(),
+            invoice_id: "in_1".to_string(),
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 320,
+            ..Default::default()
+        }];
+        let columns = vec![Column::Customer, Column::InvoiceId, Column::Tax, Column::Date, Column::Total];
+
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_columns(columns.clone()), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "Customer\tInvoice ID\tTax\tDate\tTotal\n\
+             Acme\tin_1\t8.25\t10/15/2025\t108.25\n\
+             Subtotal\t\t8.25\t\t108.25\n"
+        ));
+        assert!(output.contains("GRAND TOTAL\t\t8.25\t\t108.25\n"));
+
+        let mut output = Vec::new();
+        let report = GroupedReport::by_state(&records).with_columns(columns).with_summary(true);
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("State\tInvoices\tTax\tTotal\nTX\t1\t8.25\t108.25\n"));
+    }
+
+    #[test]
+    fn test_effective_rate_column() {
+        let record = |invoice_id: &str, taxable_sales, tax, rate_mismatch| InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Acme".to_string(),
+            invoice_id: invoice_id.to_string(),
+            state: "TX".to_string(),
+            licenses: taxable_sales,
+            taxable_sales,
+            tax,
+            total: taxable_sales + tax,
+            rate_mismatch,
+            ..Default::default()
+        };
+        let records = vec![record("in_1", 10000, 825, false), record("in_2", 10000, 8250, true)];
+        let columns = vec![Column::InvoiceId, Column::TaxableSales, Column::Tax];
+
+        let mut output = Vec::new();
+        let report = GroupedReport::by_state(&records).with_columns(columns.clone()).with_effective_rate(true);
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "Invoice ID\tTaxable Sales\tTax\n\
+             in_1\t100.00\t8.25\n"
+        ));
+
+        let columns = vec![Column::InvoiceId, Column::TaxableSales, Column::Tax, Column::EffectiveRate];
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_columns(columns), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "Invoice ID\tTaxable Sales\tTax\tEffective Rate\n\
+             in_1\t100.00\t8.25\t8.250%\n\
+             in_2\t100.00\t82.50\t82.500% (!)\n\
+             Subtotal\t200.00\t90.75\t45.375%\n"
+        ));
+
+        let mut output = Vec::new();
+        let report = GroupedReport::by_state(&records).with_effective_rate(true).with_summary(true);
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            "State\tInvoices\tLicenses\tTax\tTotal\tFees\tEffective Rate\n\
+             TX\t2\t200.00\t90.75\t290.75\t0.00\t45.375%\n"
+        ));
+    }
+
+    #[test]
+    fn test_state_source_column() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Acme".to_string(),
+            invoice_id: "in_1".to_string(),
+            state: "TX".to_string(),
+            state_source: Some(StateSource::CardBillingAddress),
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        let report = GroupedReport::by(GroupBy::Customer, &records).with_state_source(true).with_invoice_columns(Some(false));
+        TsvFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Date\tCustomer\tState\tState Source\tUsers\t"));
+        assert!(output.contains("10/15/2025\tAcme\tTX\tcard billing address\t0\t"));
+    }
+
+    #[test]
+    fn test_adjusted_cells_footnoted() {
+        let mut converted = InvoiceRecord {
+            customer: "Euro Buyer".to_string(),
+            state: "NY".to_string(),
+            licenses: 10825,
+            tax: 866,
+            total: 11691,
+            ..Default::default()
+        };
+        converted.add_adjustment(&[AmountColumn::Licenses, AmountColumn::Tax, AmountColumn::Total], "Converted from EUR");
+        let plain = InvoiceRecord {
+            customer: "Acme".to_string(),
+            state: "NY".to_string(),
+            licenses: 5000,
+            total: 5000,
+            ..Default::default()
+        };
+
+        let records = vec![converted, plain];
+        let mut output = Vec::new();
+        TsvFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("\tEuro Buyer\t0\t108.25 [1]\t8.66 [1]\t116.91 [1]\t0.00\n"));
+        assert!(output.contains("\tAcme\t0\t50.00\t0.00\t50.00\t0.00\n"));
+        assert!(output.ends_with("===== FOOTNOTES =====\n[1]\tConverted from EUR\n"));
+    }
+
+    #[test]
+    fn test_excluded_invoices_section() {
+        let records: Vec<InvoiceRecord> = vec![];
+        let skipped = vec![SkippedInvoice {
+            invoice_id: "in_nostate".to_string(),
+            customer: "Nowhere, Inc".to_string(),
+            amount: 5000,
+            reason: "Customer state/billing address not found".to_string(),
+        }];
+
+        let mut output = Vec::new();
+        CsvFormatter
+            .write_report(&GroupedReport::by_state(&records).with_skipped(&skipped), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "===== EXCLUDED INVOICES =====\n\
+             Invoice,Customer,Amount,Reason\n\
+             in_nostate,\"Nowhere, Inc\",50.00,Customer state/billing address not found\n\
+             Total excluded,,50.00,\n"
+        ));
+    }
+}
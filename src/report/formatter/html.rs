@@ -0,0 +1,270 @@
+use super::{inferred_state_note, partial_warning, unknown_state_warning, ReportFormatter};
+use crate::report::grouping::{GroupedReport, Totals};
+use crate::stripe::models::AmountColumn;
+use anyhow::Result;
+use std::io::Write;
+
+/// Per-state summary as an inline-styled `<table>` fragment (no `<html>`/`<head>`),
+/// sized for email clients, which ignore stylesheets and limit width to ~600px
+pub struct HtmlFragmentFormatter;
+
+const TABLE_STYLE: &str =
+    "border-collapse:collapse;width:100%;max-width:600px;font-family:Arial,Helvetica,sans-serif;font-size:14px;";
+const CAPTION_STYLE: &str = "padding:6px 0;text-align:left;font-weight:bold;font-size:16px;";
+const HEADER_CELL_STYLE: &str =
+    "padding:6px 8px;border-bottom:2px solid #333333;text-align:left;background-color:#f2f2f2;";
+const NOTE_STYLE: &str = "margin:12px 0 6px;font-family:Arial,Helvetica,sans-serif;font-size:14px;color:#b00020;";
+const CELL_STYLE: &str = "padding:6px 8px;border-bottom:1px solid #dddddd;";
+const AMOUNT_CELL_STYLE: &str = "padding:6px 8px;border-bottom:1px solid #dddddd;text-align:right;";
+const TOTAL_CELL_STYLE: &str = "padding:6px 8px;border-top:2px solid #333333;font-weight:bold;";
+const TOTAL_AMOUNT_CELL_STYLE: &str = "padding:6px 8px;border-top:2px solid #333333;font-weight:bold;text-align:right;";
+
+/// Escape text for inclusion in HTML element content or attribute values
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+fn write_amounts(
+    out: &mut dyn Write,
+    totals: &Totals,
+    columns: &[AmountColumn],
+    markers: &dyn Fn(AmountColumn) -> String,
+    style: &str,
+) -> Result<()> {
+    for &column in columns {
+        writeln!(out, "    <td style=\"{}\">{}{}</td>", style, dollars(totals.get(column)), markers(column))?;
+    }
+    Ok(())
+}
+
+impl ReportFormatter for HtmlFragmentFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        for warning in [partial_warning(report), unknown_state_warning(report), inferred_state_note(report)]
+            .into_iter()
+            .flatten()
+        {
+            writeln!(out, "<p style=\"{}\">{}</p>", NOTE_STYLE, escape_html(&warning))?;
+        }
+        writeln!(out, "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" style=\"{}\">", TABLE_STYLE)?;
+        if let Some(title) = &report.title {
+            writeln!(out, "  <caption style=\"{}\">{}</caption>", CAPTION_STYLE, escape_html(title))?;
+        }
+
+        let columns = report.amount_columns();
+        let mut headers = vec![report.group_by.header(), "Invoices"];
+        headers.extend(columns.iter().map(|c| c.header()));
+
+        writeln!(out, "  <tr>")?;
+        for header in headers {
+            writeln!(out, "    <th style=\"{}\">{}</th>", HEADER_CELL_STYLE, header)?;
+        }
+        writeln!(out, "  </tr>")?;
+
+        for group in &report.groups {
+            writeln!(out, "  <tr>")?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, escape_html(&group.state))?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", AMOUNT_CELL_STYLE, group.records.len())?;
+            let markers = |column| report.subtotal_footnote_markers(group, column);
+            write_amounts(out, &group.subtotal, &columns, &markers, AMOUNT_CELL_STYLE)?;
+            writeln!(out, "  </tr>")?;
+        }
+
+        let mut grand_totals = vec![(None, "Grand Total".to_string(), &report.grand_total)];
+        for (currency, totals) in &report.currency_totals {
+            grand_totals.push((Some(currency.as_str()), format!("Grand Total ({})", currency), totals));
+        }
+        for (currency, label, totals) in grand_totals {
+            let invoice_count: usize = report
+                .groups
+                .iter()
+                .filter(|g| g.currency.as_deref() == currency)
+                .map(|g| g.records.len())
+                .sum();
+            writeln!(out, "  <tr>")?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", TOTAL_CELL_STYLE, label)?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", TOTAL_AMOUNT_CELL_STYLE, invoice_count)?;
+            write_amounts(out, totals, &columns, &|_| String::new(), TOTAL_AMOUNT_CELL_STYLE)?;
+            writeln!(out, "  </tr>")?;
+        }
+
+        writeln!(out, "</table>")?;
+
+        for (i, note) in report.footnotes.iter().enumerate() {
+            writeln!(out, "<p style=\"{}\">[{}] {}</p>", NOTE_STYLE, i + 1, escape_html(note))?;
+        }
+
+        write_tax_due(report, out)?;
+        write_months(report, out)?;
+        write_excluded(report, out)
+    }
+}
+
+/// Tax per state less the Texas filing discount (`--texas-discount`), if asked for
+fn write_tax_due(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    let Some(tax_due) = report.tax_due() else {
+        return Ok(());
+    };
+
+    writeln!(out, "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" style=\"{}\">", TABLE_STYLE)?;
+    writeln!(out, "  <caption style=\"{}\">Tax Due</caption>", CAPTION_STYLE)?;
+    writeln!(out, "  <tr>")?;
+    for header in ["State", "Tax", "Discount", "Tax Due After Discount"] {
+        writeln!(out, "    <th style=\"{}\">{}</th>", HEADER_CELL_STYLE, header)?;
+    }
+    writeln!(out, "  </tr>")?;
+    let rows = tax_due.states.iter().map(|s| (s.state.as_str(), s.tax, s.discount, s.due, CELL_STYLE, AMOUNT_CELL_STYLE));
+    let total = ("Total", tax_due.tax, tax_due.discount, tax_due.due, TOTAL_CELL_STYLE, TOTAL_AMOUNT_CELL_STYLE);
+    for (label, tax, discount, due, label_style, amount_style) in rows.chain([total]) {
+        writeln!(out, "  <tr>")?;
+        writeln!(out, "    <td style=\"{}\">{}</td>", label_style, escape_html(label))?;
+        for amount in [tax, discount, due] {
+            writeln!(out, "    <td style=\"{}\">{}</td>", amount_style, dollars(amount))?;
+        }
+        writeln!(out, "  </tr>")?;
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+/// Sales and tax per state and month (`--monthly-breakdown`), if asked for
+fn write_months(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    if !report.monthly {
+        return Ok(());
+    }
+
+    writeln!(out, "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" style=\"{}\">", TABLE_STYLE)?;
+    writeln!(out, "  <caption style=\"{}\">By Month</caption>", CAPTION_STYLE)?;
+    writeln!(out, "  <tr>")?;
+    for header in [report.group_by.header(), "Month", "Sales", "Tax"] {
+        writeln!(out, "    <th style=\"{}\">{}</th>", HEADER_CELL_STYLE, header)?;
+    }
+    writeln!(out, "  </tr>")?;
+    for group in &report.groups {
+        for (month, totals) in group.by_month() {
+            writeln!(out, "  <tr>")?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, escape_html(&group.state))?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, month)?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", AMOUNT_CELL_STYLE, dollars(totals.licenses))?;
+            writeln!(out, "    <td style=\"{}\">{}</td>", AMOUNT_CELL_STYLE, dollars(totals.tax))?;
+            writeln!(out, "  </tr>")?;
+        }
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+/// A note and table listing invoices left out of the summary, if any
+fn write_excluded(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    if report.skipped.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "<p style=\"{}\">{} invoice(s) totaling {} were excluded from this report:</p>",
+        NOTE_STYLE,
+        report.skipped.len(),
+        dollars(report.skipped_amount())
+    )?;
+    writeln!(out, "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" style=\"{}\">", TABLE_STYLE)?;
+    writeln!(out, "  <tr>")?;
+    for header in ["Invoice", "Customer", "Amount", "Reason"] {
+        writeln!(out, "    <th style=\"{}\">{}</th>", HEADER_CELL_STYLE, header)?;
+    }
+    writeln!(out, "  </tr>")?;
+    for skip in report.skipped {
+        writeln!(out, "  <tr>")?;
+        writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, escape_html(&skip.invoice_id))?;
+        writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, escape_html(&skip.customer))?;
+        writeln!(out, "    <td style=\"{}\">{}</td>", AMOUNT_CELL_STYLE, dollars(skip.amount))?;
+        writeln!(out, "    <td style=\"{}\">{}</td>", CELL_STYLE, escape_html(&skip.reason))?;
+        writeln!(out, "  </tr>")?;
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_fragment_has_summary_rows_and_no_document() {
+        let records = vec![
+            InvoiceRecord {
+                state: "TX".to_string(),
+                licenses: 50000,
+                tax: 4000,
+                total: 54000,
+                fees: 1600,
+                ..Default::default()
+            },
+            InvoiceRecord {
+                state: "TX".to_string(),
+                licenses: 10000,
+                tax: 800,
+                total: 10800,
+                fees: 300,
+                ..Default::default()
+            },
+        ];
+
+        let mut output = Vec::new();
+        HtmlFragmentFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.starts_with("<table"));
+        assert!(!html.contains("<html"));
+        assert!(!html.contains("<style"));
+        assert!(html.contains(">TX</td>"));
+        assert!(html.contains(">2</td>"));
+        assert!(html.contains(">600.00</td>"));
+        assert!(!html.contains("By Month"));
+    }
+
+    #[test]
+    fn test_monthly_breakdown_table() {
+        let record = |date: &str, licenses: i64, tax: i64| InvoiceRecord {
+            date: chrono::NaiveDate::parse_from_str(date, "%m/%d/%Y").unwrap(),
+            state: "TX".to_string(),
+            licenses,
+            tax,
+            total: licenses + tax,
+            ..Default::default()
+        };
+        let records = vec![record("07/03/2026", 10000, 825), record("08/14/2026", 20000, 1650)];
+
+        let mut output = Vec::new();
+        HtmlFragmentFormatter
+            .write_report(&GroupedReport::by_state(&records).with_monthly(true), &mut output)
+            .unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        let months = &html[html.find("By Month").unwrap()..];
+        assert!(months.contains(">07/2026</td>"));
+        assert!(months.contains(">200.00</td>\n    <td style=\"padding:6px 8px;border-bottom:1px solid #dddddd;text-align:right;\">16.50</td>"));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("Smith & <Jones>"), "Smith &amp; &lt;Jones&gt;");
+    }
+}
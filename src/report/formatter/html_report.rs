@@ -0,0 +1,260 @@
+use super::html::escape_html;
+use super::{inferred_state_note, partial_warning, unknown_state_warning, Column, ReportFormatter};
+use crate::report::grouping::{GroupedReport, StateGroup, Totals};
+use crate::report::rates::format_rate;
+use anyhow::Result;
+use std::io::Write;
+
+/// A standalone HTML page (`--format html`): the summary and a table per
+/// state, sortable by clicking a column header, with a search box that
+/// filters rows across all tables. Everything is inline, so the file can be
+/// emailed or opened from a shared drive without network access.
+pub struct HtmlFormatter;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; font-size: 14px; margin: 2em; color: #222; }
+h1 { font-size: 22px; }
+h2 { font-size: 17px; margin-top: 2em; }
+.note { color: #b00020; }
+#filter { padding: 6px 8px; width: 320px; font-size: 14px; margin-bottom: 1em; }
+table { border-collapse: collapse; margin-bottom: 0.5em; }
+th, td { padding: 5px 10px; border-bottom: 1px solid #ddd; text-align: left; white-space: nowrap; }
+th { background: #f2f2f2; border-bottom: 2px solid #333; cursor: pointer; user-select: none; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+td.num, th.num { text-align: right; }
+tfoot td { font-weight: bold; border-top: 2px solid #333; }
+tr.hidden { display: none; }
+"#;
+
+/// Sorts a table by the clicked header (numbers by their `data-sort` value)
+/// and hides rows that don't contain the search text. Footer rows keep the
+/// full totals, since that's what gets filed.
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("thead th").forEach(function (th, column) {
+    th.addEventListener("click", function () {
+      var ascending = !th.classList.contains("asc");
+      table.querySelectorAll("thead th").forEach(function (other) { other.classList.remove("asc", "desc"); });
+      th.classList.add(ascending ? "asc" : "desc");
+      var body = table.tBodies[0];
+      var rows = Array.prototype.slice.call(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[column], y = b.cells[column];
+        var order = x.dataset.sort !== undefined
+          ? parseFloat(x.dataset.sort) - parseFloat(y.dataset.sort)
+          : x.textContent.localeCompare(y.textContent);
+        return ascending ? order : -order;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+document.getElementById("filter").addEventListener("input", function (event) {
+  var text = event.target.value.toLowerCase();
+  document.querySelectorAll("table.sortable tbody tr").forEach(function (row) {
+    row.classList.toggle("hidden", text !== "" && row.textContent.toLowerCase().indexOf(text) < 0);
+  });
+});
+"#;
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// A money cell, sortable by its value in cents
+fn amount_cell(cents: i64, markers: &str) -> String {
+    format!("<td class=\"num\" data-sort=\"{}\">{}{}</td>", cents, dollars(cents), markers)
+}
+
+fn header_row(out: &mut dyn Write, headers: &[(&str, bool)]) -> Result<()> {
+    write!(out, "<thead><tr>")?;
+    for (header, numeric) in headers {
+        if *numeric {
+            write!(out, "<th class=\"num\">{}</th>", escape_html(header))?;
+        } else {
+            write!(out, "<th>{}</th>", escape_html(header))?;
+        }
+    }
+    writeln!(out, "</tr></thead>")?;
+    Ok(())
+}
+
+impl ReportFormatter for HtmlFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let title = report.title.clone().unwrap_or_else(|| match &report.period {
+            Some(period) => format!("Sales Tax Report {}", period),
+            None => "Sales Tax Report".to_string(),
+        });
+        writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{}</title>\n<style>{}</style>\n</head>\n<body>", escape_html(&title), STYLE)?;
+        writeln!(out, "<h1>{}</h1>", escape_html(&title))?;
+        for warning in [partial_warning(report), unknown_state_warning(report), inferred_state_note(report)]
+            .into_iter()
+            .flatten()
+        {
+            writeln!(out, "<p class=\"note\">{}</p>", escape_html(&warning))?;
+        }
+        writeln!(out, "<input id=\"filter\" type=\"search\" placeholder=\"Filter by customer, invoice, state...\">")?;
+
+        write_summary(report, out)?;
+        if !report.summary {
+            for group in &report.groups {
+                write_group(report, group, out)?;
+            }
+        }
+
+        if !report.footnotes.is_empty() {
+            writeln!(out, "<h2>Footnotes</h2>\n<ol>")?;
+            for note in &report.footnotes {
+                writeln!(out, "<li>{}</li>", escape_html(note))?;
+            }
+            writeln!(out, "</ol>")?;
+        }
+        write_excluded(report, out)?;
+        writeln!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT)?;
+        Ok(())
+    }
+}
+
+/// One row per section with its invoice count and subtotals; the grand
+/// totals are the footer
+fn write_summary(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    let columns = report.amount_columns();
+    let mut headers = vec![(report.group_by.header(), false), ("Invoices", true)];
+    headers.extend(columns.iter().map(|c| (c.header(), true)));
+
+    let row = |label: &str, count: usize, totals: &Totals| {
+        let mut cells = format!("<td>{}</td><td class=\"num\" data-sort=\"{1}\">{1}</td>", escape_html(label), count);
+        for &column in &columns {
+            cells.push_str(&amount_cell(totals.get(column), ""));
+        }
+        format!("<tr>{}</tr>", cells)
+    };
+
+    writeln!(out, "<h2>Summary</h2>\n<table class=\"sortable\">")?;
+    header_row(out, &headers)?;
+    writeln!(out, "<tbody>")?;
+    for group in &report.groups {
+        writeln!(out, "{}", row(&group.state, group.records.len(), &group.subtotal))?;
+    }
+    writeln!(out, "</tbody>\n<tfoot>")?;
+    let invoice_count = |currency: Option<&str>| -> usize {
+        report.groups.iter().filter(|g| g.currency.as_deref() == currency).map(|g| g.records.len()).sum()
+    };
+    writeln!(out, "{}", row("Grand Total", invoice_count(None), &report.grand_total))?;
+    for (currency, totals) in &report.currency_totals {
+        writeln!(out, "{}", row(&format!("Grand Total ({})", currency), invoice_count(Some(currency)), totals))?;
+    }
+    writeln!(out, "</tfoot>\n</table>")?;
+    Ok(())
+}
+
+/// A section's records, with the subtotal as the footer
+fn write_group(report: &GroupedReport<'_>, group: &StateGroup<'_>, out: &mut dyn Write) -> Result<()> {
+    let columns = report.row_columns(report.invoice_columns.unwrap_or(true));
+    let numeric = |column: &Column| {
+        column.amount().is_some() || matches!(column, Column::Users | Column::Quantity | Column::EffectiveRate)
+    };
+    let headers: Vec<(&str, bool)> = columns.iter().map(|c| (c.header(), numeric(c))).collect();
+
+    writeln!(out, "<h2>{}</h2>\n<table class=\"sortable\">", escape_html(&group.state))?;
+    header_row(out, &headers)?;
+    writeln!(out, "<tbody>")?;
+    for record in &group.records {
+        let mut totals = Totals::default();
+        totals.add(record);
+        write!(out, "<tr>")?;
+        for column in &columns {
+            let text = escape_html(&column.text(record, report.date_format));
+            match column.amount() {
+                Some(amount) => {
+                    write!(out, "{}", amount_cell(totals.get(amount), &report.footnote_markers(record, amount)))?
+                }
+                // Dates sort chronologically whatever --date-format shows
+                None if *column == Column::Date => {
+                    write!(out, "<td data-sort=\"{}\">{}</td>", record.date.format("%Y%m%d"), text)?
+                }
+                None if numeric(column) => {
+                    write!(out, "<td class=\"num\" data-sort=\"{}\">{}</td>", text.trim_end_matches('%'), text)?
+                }
+                None => write!(out, "<td>{}</td>", text)?,
+            }
+        }
+        writeln!(out, "</tr>")?;
+    }
+    write!(out, "</tbody>\n<tfoot><tr>")?;
+    for (i, column) in columns.iter().enumerate() {
+        match column.amount() {
+            Some(amount) => write!(out, "{}", amount_cell(group.subtotal.get(amount), ""))?,
+            None if i == 0 => write!(out, "<td>Subtotal</td>")?,
+            None if *column == Column::EffectiveRate => {
+                let rate = group.subtotal.effective_rate().map(format_rate).unwrap_or_default();
+                write!(out, "<td class=\"num\">{}</td>", rate)?
+            }
+            None => write!(out, "<td></td>")?,
+        }
+    }
+    writeln!(out, "</tr></tfoot>\n</table>")?;
+    Ok(())
+}
+
+/// Invoices left out of the report, if any
+fn write_excluded(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    if report.skipped.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "<h2>Excluded Invoices</h2>\n<table class=\"sortable\">")?;
+    header_row(out, &[("Invoice", false), ("Customer", false), ("Amount", true), ("Reason", false)])?;
+    writeln!(out, "<tbody>")?;
+    for skip in report.skipped {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td>{}<td>{}</td></tr>",
+            escape_html(&skip.invoice_id),
+            escape_html(&skip.customer),
+            amount_cell(skip.amount, ""),
+            escape_html(&skip.reason)
+        )?;
+    }
+    let total = amount_cell(report.skipped_amount(), "");
+    writeln!(out, "</tbody>\n<tfoot><tr><td>Total excluded</td><td></td>{}<td></td></tr></tfoot>", total)?;
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_standalone_page_with_sortable_tables() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "Smith & Sons".to_string(),
+            users: 4,
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 320,
+            invoice_id: "in_1".to_string(),
+            ..Default::default()
+        }];
+        let report = GroupedReport::by_state(&records).with_period("Q3-2025");
+
+        let mut output = Vec::new();
+        HtmlFormatter.write_report(&report, &mut output).unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Sales Tax Report Q3-2025</title>"));
+        assert!(html.contains("<h2>TX</h2>\n<table class=\"sortable\">"));
+        assert!(html.contains("<td data-sort=\"20250814\">08/14/2025</td><td>Smith &amp; Sons</td>"));
+        assert!(html.contains("<td class=\"num\" data-sort=\"825\">8.25</td>"));
+        assert!(html.contains("<tfoot><tr><td>Subtotal</td>"));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+}
@@ -0,0 +1,199 @@
+use super::ReportFormatter;
+use crate::report::diagnostics::Diagnostic;
+use crate::report::discount::TaxDue;
+use crate::report::generator::PartialReport;
+use crate::report::grouping::{GroupBy, GroupedReport, Totals};
+use crate::report::products::ProductGroup;
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// A single JSON document with per-state sections (amounts in cents)
+pub struct JsonFormatter;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    /// Present when the time budget ran out before every invoice was processed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial: Option<PartialReport>,
+    /// Revenue reported under UNKNOWN because the invoices had no state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unknown_state: Option<JsonUnknownState>,
+    /// What the `states` sections are: states, customers, months or countries
+    group_by: GroupBy,
+    states: Vec<JsonState<'a>>,
+    grand_total: Totals,
+    /// Totals of the sections in each non-USD currency
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    currency_totals: &'a BTreeMap<String, Totals>,
+    /// Subtotals per product (`--group-by product`)
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    products: &'a [ProductGroup],
+    /// Tax per state after the Texas filing discount (`--texas-discount`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tax_due: Option<TaxDue>,
+    /// Notes explaining records' `adjustments`, numbered from 1 in order
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    footnotes: &'a [String],
+    /// Invoices left out of the report (always present, possibly empty)
+    excluded: &'a [SkippedInvoice],
+    /// Data-quality findings (always present, possibly empty)
+    diagnostics: &'a [Diagnostic],
+}
+
+#[derive(Serialize)]
+struct JsonUnknownState {
+    invoices: usize,
+    total: i64,
+}
+
+#[derive(Serialize)]
+struct JsonState<'a> {
+    state: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+    /// Left out with `--summary`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    records: Option<&'a [&'a InvoiceRecord]>,
+    /// Number of records in the section
+    invoices: usize,
+    subtotal: Totals,
+    /// Sales and tax per month (`--monthly-breakdown`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    months: Option<Vec<JsonMonth>>,
+}
+
+#[derive(Serialize)]
+struct JsonMonth {
+    /// `MM/YYYY`
+    month: String,
+    sales: i64,
+    tax: i64,
+}
+
+impl ReportFormatter for JsonFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let document = JsonReport {
+            title: report.title.as_deref(),
+            partial: report.partial,
+            unknown_state: report.unknown_state().map(|group| JsonUnknownState {
+                invoices: group.records.len(),
+                total: group.subtotal.total,
+            }),
+            group_by: report.group_by,
+            states: report
+                .groups
+                .iter()
+                .map(|group| JsonState {
+                    state: &group.state,
+                    currency: group.currency.as_deref(),
+                    records: (!report.summary).then_some(group.records.as_slice()),
+                    invoices: group.records.len(),
+                    subtotal: group.subtotal,
+                    months: report.monthly.then(|| {
+                        group
+                            .by_month()
+                            .into_iter()
+                            .map(|(month, totals)| JsonMonth { month, sales: totals.licenses, tax: totals.tax })
+                            .collect()
+                    }),
+                })
+                .collect(),
+            grand_total: report.grand_total,
+            currency_totals: &report.currency_totals,
+            products: &report.products,
+            tax_due: report.tax_due(),
+            footnotes: &report.footnotes,
+            excluded: report.skipped,
+            diagnostics: report.diagnostics,
+        };
+
+        serde_json::to_writer_pretty(&mut *out, &document)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_report_structure() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: "Test Company".to_string(),
+            users: 5,
+            state: "TX".to_string(),
+            licenses: 50000,
+            tax: 4000,
+            total: 54000,
+            fees: 1600,
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        JsonFormatter
+            .write_report(&GroupedReport::by_state(&records), &mut output)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(value["states"][0]["state"], "TX");
+        assert_eq!(value["states"][0]["records"][0]["customer"], "Test Company");
+        assert_eq!(value["states"][0]["subtotal"]["tax"], 4000);
+        assert_eq!(value["grand_total"]["total"], 54000);
+        assert!(value["states"][0].get("months").is_none());
+
+        let mut output = Vec::new();
+        JsonFormatter
+            .write_report(&GroupedReport::by_state(&records).with_summary(true), &mut output)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert!(value["states"][0].get("records").is_none());
+        assert_eq!(value["states"][0]["invoices"], 1);
+
+        let mut output = Vec::new();
+        JsonFormatter
+            .write_report(&GroupedReport::by_state(&records).with_monthly(true), &mut output)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            value["states"][0]["months"],
+            serde_json::json!([{"month": "10/2025", "sales": 50000, "tax": 4000}])
+        );
+    }
+
+    #[test]
+    fn test_json_includes_diagnostics() {
+        use crate::report::diagnostics::{DiagnosticCode, Severity};
+
+        let records: Vec<InvoiceRecord> = vec![];
+        let diagnostics = vec![Diagnostic::new(
+            Severity::Warning,
+            DiagnosticCode::FeeUnavailable,
+            "in_123",
+            "Fees reported as 0: not found",
+        )];
+
+        let mut output = Vec::new();
+        JsonFormatter
+            .write_report(&GroupedReport::by_state(&records).with_diagnostics(&diagnostics), &mut output)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            value["diagnostics"][0],
+            serde_json::json!({
+                "severity": "warning",
+                "code": "fee_unavailable",
+                "invoice_id": "in_123",
+                "message": "Fees reported as 0: not found"
+            })
+        );
+        assert_eq!(value["excluded"], serde_json::json!([]));
+    }
+}
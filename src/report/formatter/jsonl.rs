@@ -0,0 +1,42 @@
+use super::ReportFormatter;
+use crate::report::grouping::GroupedReport;
+use anyhow::Result;
+use std::io::Write;
+
+/// One JSON object per record per line (amounts in cents), for loading into
+/// DuckDB, BigQuery and the like. Records are written in section order and
+/// carry the same fields as in the JSON report; there are no subtotals.
+pub struct JsonLinesFormatter;
+
+impl ReportFormatter for JsonLinesFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        for record in report.groups.iter().flat_map(|group| group.records.iter()) {
+            serde_json::to_writer(&mut *out, record)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_one_record_per_line() {
+        let records = vec![
+            InvoiceRecord { state: "TX".to_string(), tax: 825, invoice_id: "in_1".to_string(), ..Default::default() },
+            InvoiceRecord { state: "CA".to_string(), invoice_id: "in_2".to_string(), ..Default::default() },
+        ];
+
+        let mut output = Vec::new();
+        JsonLinesFormatter.write_report(&GroupedReport::by_state(&records), &mut output).unwrap();
+        let lines: Vec<InvoiceRecord> =
+            String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].invoice_id, "in_2");
+        assert_eq!(lines[1], records[0]);
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Company details printed at the top of PDF reports, from the profile's
+/// `[letterhead]` table
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Letterhead {
+    /// Company name, the report's first line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub company: Option<String>,
+    /// Address lines under the name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub address: Vec<String>,
+    /// Printed under the "Prepared by" signature line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prepared_by: Option<String>,
+    /// Printed under the "Reviewed by" signature line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed_by: Option<String>,
+}
+
+impl Letterhead {
+    pub fn is_empty(&self) -> bool {
+        *self == Letterhead::default()
+    }
+}
@@ -0,0 +1,199 @@
+use super::{inferred_state_note, partial_warning, unknown_state_warning, Column, ReportFormatter};
+use crate::report::grouping::{GroupedReport, Totals};
+use crate::report::rates::format_rate;
+use anyhow::Result;
+use std::io::Write;
+
+/// GitHub-flavored Markdown: a summary table of the sections, then a table per
+/// section, for pasting into wikis and pull requests
+pub struct MarkdownFormatter;
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// Make text safe inside a table cell, where a pipe ends the cell and a line
+/// break ends the row
+fn escape_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+/// A table row; `bold` rows are totals
+fn write_row(out: &mut dyn Write, cells: &[String], bold: bool) -> Result<()> {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| match (bold, cell.is_empty()) {
+            (true, false) => format!("**{}**", escape_cell(cell)),
+            _ => escape_cell(cell),
+        })
+        .collect();
+    writeln!(out, "| {} |", cells.join(" | "))?;
+    Ok(())
+}
+
+/// A header row and its delimiter row, right-aligning the `numeric` columns
+fn write_header(out: &mut dyn Write, headers: &[&str], numeric: &[bool]) -> Result<()> {
+    writeln!(out, "| {} |", headers.join(" | "))?;
+    let delimiters: Vec<&str> = numeric.iter().map(|&n| if n { "---:" } else { "---" }).collect();
+    writeln!(out, "| {} |", delimiters.join(" | "))?;
+    Ok(())
+}
+
+impl ReportFormatter for MarkdownFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        if let Some(title) = &report.title {
+            writeln!(out, "# {}\n", title)?;
+        }
+        for warning in [partial_warning(report), unknown_state_warning(report), inferred_state_note(report)]
+            .into_iter()
+            .flatten()
+        {
+            writeln!(out, "> {}\n", warning)?;
+        }
+
+        write_summary(report, out)?;
+        if !report.summary {
+            for group in &report.groups {
+                writeln!(out, "\n## {}\n", group.state)?;
+                let columns = report.row_columns(report.invoice_columns.unwrap_or(true));
+                let headers: Vec<&str> = columns.iter().map(|c| c.header()).collect();
+                let numeric: Vec<bool> = columns.iter().map(|c| is_numeric(*c)).collect();
+                write_header(out, &headers, &numeric)?;
+                for record in &group.records {
+                    let mut row_totals = Totals::default();
+                    row_totals.add(record);
+                    let cells: Vec<String> = columns
+                        .iter()
+                        .map(|column| match column.amount() {
+                            Some(amount) => {
+                                format!("{}{}", dollars(row_totals.get(amount)), report.footnote_markers(record, amount))
+                            }
+                            None => column.text(record, report.date_format),
+                        })
+                        .collect();
+                    write_row(out, &cells, false)?;
+                }
+                let subtotal: Vec<String> = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| match column.amount() {
+                        Some(amount) => dollars(group.subtotal.get(amount)),
+                        None if i == 0 => "Subtotal".to_string(),
+                        None if *column == Column::EffectiveRate => {
+                            group.subtotal.effective_rate().map(format_rate).unwrap_or_default()
+                        }
+                        None => String::new(),
+                    })
+                    .collect();
+                write_row(out, &subtotal, true)?;
+            }
+        }
+
+        write_tax_due(report, out)?;
+        if !report.footnotes.is_empty() {
+            writeln!(out, "\n## Footnotes\n")?;
+            for (i, note) in report.footnotes.iter().enumerate() {
+                writeln!(out, "{}. {}", i + 1, note)?;
+            }
+        }
+        write_excluded(report, out)
+    }
+}
+
+fn is_numeric(column: Column) -> bool {
+    column.amount().is_some() || matches!(column, Column::Users | Column::Quantity | Column::EffectiveRate)
+}
+
+/// One row per section with its invoice count and subtotals, then the grand
+/// total and one per other currency
+fn write_summary(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    let columns = report.amount_columns();
+    let mut headers = vec![report.group_by.header(), "Invoices"];
+    headers.extend(columns.iter().map(|c| c.header()));
+    let mut numeric = vec![false, true];
+    numeric.extend(columns.iter().map(|_| true));
+
+    writeln!(out, "## Summary\n")?;
+    write_header(out, &headers, &numeric)?;
+    let row = |label: &str, count: usize, totals: &Totals| {
+        let mut cells = vec![label.to_string(), count.to_string()];
+        cells.extend(columns.iter().map(|&c| dollars(totals.get(c))));
+        cells
+    };
+    for group in &report.groups {
+        write_row(out, &row(&group.state, group.records.len(), &group.subtotal), false)?;
+    }
+    let invoice_count = |currency: Option<&str>| -> usize {
+        report.groups.iter().filter(|g| g.currency.as_deref() == currency).map(|g| g.records.len()).sum()
+    };
+    write_row(out, &row("Grand Total", invoice_count(None), &report.grand_total), true)?;
+    for (currency, totals) in &report.currency_totals {
+        let label = format!("Grand Total ({})", currency);
+        write_row(out, &row(&label, invoice_count(Some(currency)), totals), true)?;
+    }
+    Ok(())
+}
+
+/// Tax per state less the Texas filing discount (`--texas-discount`), if asked for
+fn write_tax_due(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    let Some(tax_due) = report.tax_due() else {
+        return Ok(());
+    };
+
+    writeln!(out, "\n## Tax Due\n")?;
+    write_header(out, &["State", "Tax", "Discount", "Tax Due After Discount"], &[false, true, true, true])?;
+    for state in &tax_due.states {
+        write_row(out, &[state.state.clone(), dollars(state.tax), dollars(state.discount), dollars(state.due)], false)?;
+    }
+    write_row(out, &["Total".to_string(), dollars(tax_due.tax), dollars(tax_due.discount), dollars(tax_due.due)], true)
+}
+
+/// Invoices left out of the report, if any
+fn write_excluded(report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+    if report.skipped.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "\n## Excluded Invoices\n")?;
+    write_header(out, &["Invoice", "Customer", "Amount", "Reason"], &[false, false, true, false])?;
+    for skip in report.skipped {
+        let cells = [skip.invoice_id.clone(), skip.customer.clone(), dollars(skip.amount), skip.reason.clone()];
+        write_row(out, &cells, false)?;
+    }
+    write_row(out, &["Total excluded".to_string(), String::new(), dollars(report.skipped_amount()), String::new()], true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_markdown_tables() {
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "Pipe | Co".to_string(),
+            users: 4,
+            state: "TX".to_string(),
+            licenses: 10000,
+            tax: 825,
+            total: 10825,
+            fees: 320,
+            invoice_id: "in_1".to_string(),
+            ..Default::default()
+        }];
+        let report = GroupedReport::by_state(&records).with_title("Q3 2025 Sales Tax");
+
+        let mut output = Vec::new();
+        MarkdownFormatter.write_report(&report, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("# Q3 2025 Sales Tax\n\n## Summary\n\n| State | Invoices | Licenses |"));
+        assert!(output.contains("| --- | ---: | ---: |"));
+        assert!(output.contains("| TX | 1 | 100.00 | 8.25 | 108.25 | 3.20 |"));
+        assert!(output.contains("| **Grand Total** | **1** | **100.00** |"));
+        assert!(output.contains("\n## TX\n"));
+        assert!(output.contains("| 08/14/2025 | Pipe \\| Co |"));
+        assert!(output.contains("| **Subtotal** |  |"));
+    }
+}
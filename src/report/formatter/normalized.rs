@@ -0,0 +1,165 @@
+use super::{csv_escape, ReportFormatter};
+use crate::report::grouping::GroupedReport;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// Long-format CSV for BI tools (`--format csv-normalized`): one row per
+/// invoice line with its dimensions and measures, and no section headers,
+/// subtotals or totals to filter out before loading
+pub struct NormalizedCsvFormatter;
+
+const HEADERS: [&str; 13] = [
+    "date",
+    "invoice_id",
+    "state",
+    "country",
+    "customer",
+    "product",
+    "line_type",
+    "quantity",
+    "amount",
+    "taxable_amount",
+    "tax",
+    "fees",
+    "currency",
+];
+
+/// One line of a record, with its share of the record's fees
+struct LineRow {
+    product: Option<String>,
+    line_type: String,
+    quantity: u32,
+    amount: i64,
+    taxable_amount: i64,
+    tax: i64,
+    fees: i64,
+}
+
+/// The name a value serializes as, e.g. `checkout_session` for a record source
+fn serde_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+/// Split a record into its lines. The first line also takes what the lines
+/// don't account for (tax not broken down by line, conversion rounding), and
+/// fees are allocated by each line's share of the amount, the last line taking
+/// the rounding, so a record's rows add up to it. Records without lines are
+/// one row, typed by what they were built from (`invoice`, `checkout_session`, ...).
+fn line_rows(record: &InvoiceRecord) -> Vec<LineRow> {
+    if record.lines.is_empty() {
+        return vec![LineRow {
+            product: record.product.clone().or_else(|| record.plan.clone()),
+            line_type: serde_name(&record.source),
+            quantity: record.users,
+            amount: record.licenses,
+            taxable_amount: record.taxable_sales,
+            tax: record.tax,
+            fees: record.fees,
+        }];
+    }
+
+    let lines_amount: i64 = record.lines.iter().map(|line| line.amount).sum();
+    let mut licenses_left = record.licenses - lines_amount;
+    let mut taxable_left = record.taxable_sales - record.lines.iter().map(|line| line.taxable_sales).sum::<i64>();
+    let mut tax_left = record.tax - record.lines.iter().map(|line| line.tax).sum::<i64>();
+    let mut fees_left = record.fees;
+
+    let mut rows = Vec::with_capacity(record.lines.len());
+    for (i, line) in record.lines.iter().enumerate() {
+        let fees = if i + 1 == record.lines.len() {
+            fees_left
+        } else if lines_amount == 0 {
+            0
+        } else {
+            (record.fees as f64 * line.amount as f64 / lines_amount as f64).round() as i64
+        };
+        fees_left -= fees;
+        rows.push(LineRow {
+            product: line.product_name.clone().or_else(|| line.product.clone()),
+            line_type: line.line_type.clone().unwrap_or_else(|| "subscription".to_string()),
+            quantity: line.quantity,
+            amount: line.amount + std::mem::take(&mut licenses_left),
+            taxable_amount: line.taxable_sales + std::mem::take(&mut taxable_left),
+            tax: line.tax + std::mem::take(&mut tax_left),
+            fees,
+        });
+    }
+    rows
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+impl ReportFormatter for NormalizedCsvFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", HEADERS.join(","))?;
+        for record in report.groups.iter().flat_map(|group| group.records.iter()) {
+            for row in line_rows(record) {
+                let cells = [
+                    record.date.format("%Y-%m-%d").to_string(),
+                    record.invoice_id.clone(),
+                    record.state.clone(),
+                    record.country.clone().unwrap_or_default(),
+                    record.customer.clone(),
+                    row.product.unwrap_or_default(),
+                    row.line_type,
+                    row.quantity.to_string(),
+                    dollars(row.amount),
+                    dollars(row.taxable_amount),
+                    dollars(row.tax),
+                    dollars(row.fees),
+                    record.currency.as_deref().unwrap_or("usd").to_uppercase(),
+                ];
+                let cells: Vec<String> = cells.iter().map(|cell| csv_escape(cell)).collect();
+                writeln!(out, "{}", cells.join(","))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceLine;
+
+    #[test]
+    fn test_rows_per_line_add_up_to_the_invoice() {
+        let line = |product: &str, line_type: &str, amount: i64, tax: i64| InvoiceLine {
+            product: Some(product.to_string()),
+            line_type: Some(line_type.to_string()),
+            quantity: 1,
+            amount,
+            taxable_sales: amount,
+            tax,
+            ..Default::default()
+        };
+        let records = vec![InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+            customer: "Acme, Inc.".to_string(),
+            state: "TX".to_string(),
+            invoice_id: "in_1".to_string(),
+            licenses: 15000,
+            taxable_sales: 15000,
+            tax: 1238,
+            total: 16238,
+            fees: 500,
+            lines: vec![line("prod_seat", "subscription", 10000, 825), line("prod_setup", "invoiceitem", 5000, 412)],
+            ..Default::default()
+        }];
+
+        let mut output = Vec::new();
+        NormalizedCsvFormatter.write_report(&GroupedReport::by_state(&records), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], HEADERS.join(","));
+        // The cent of tax not broken down by line goes to the first line; fees split 2:1
+        assert_eq!(lines[1], "2025-08-14,in_1,TX,,\"Acme, Inc.\",prod_seat,subscription,1,100.00,100.00,8.26,3.33,USD");
+        assert_eq!(lines[2], "2025-08-14,in_1,TX,,\"Acme, Inc.\",prod_setup,invoiceitem,1,50.00,50.00,4.12,1.67,USD");
+    }
+}
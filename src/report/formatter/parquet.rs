@@ -0,0 +1,124 @@
+use super::ReportFormatter;
+use crate::report::grouping::GroupedReport;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, BooleanArray, Date32Array, Int64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Arc;
+
+/// A Parquet file with one row per record and a typed column per field
+/// (amounts as integer cents, dates as dates), for DuckDB, BigQuery and the
+/// like. Nested fields (lines, fee breakdown, adjustments) are left out;
+/// use `--format jsonl` for those.
+pub struct ParquetFormatter;
+
+/// The name a value serializes as, e.g. `checkout_session` for a record source
+fn serde_name<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_value(value).ok()?.as_str().map(str::to_string)
+}
+
+fn strings(records: &[&InvoiceRecord], field: fn(&InvoiceRecord) -> Option<String>) -> ArrayRef {
+    Arc::new(records.iter().map(|record| field(record)).collect::<StringArray>())
+}
+
+fn cents(records: &[&InvoiceRecord], field: fn(&InvoiceRecord) -> i64) -> ArrayRef {
+    Arc::new(records.iter().map(|record| field(record)).collect::<Int64Array>())
+}
+
+fn flags(records: &[&InvoiceRecord], field: fn(&InvoiceRecord) -> bool) -> ArrayRef {
+    Arc::new(records.iter().map(|record| Some(field(record))).collect::<BooleanArray>())
+}
+
+impl ReportFormatter for ParquetFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let records: Vec<&InvoiceRecord> =
+            report.groups.iter().flat_map(|group| group.records.iter().copied()).collect();
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+        let days = |record: &&InvoiceRecord| (record.date - epoch).num_days() as i32;
+
+        let columns: Vec<(&str, DataType, bool, ArrayRef)> = vec![
+            ("date", DataType::Date32, false, Arc::new(Date32Array::from(records.iter().map(days).collect::<Vec<_>>()))),
+            ("timestamp", DataType::Int64, false, cents(&records, |r| r.timestamp)),
+            ("customer", DataType::Utf8, false, strings(&records, |r| Some(r.customer.clone()))),
+            ("users", DataType::UInt32, false, Arc::new(records.iter().map(|r| r.users).collect::<UInt32Array>())),
+            ("state", DataType::Utf8, false, strings(&records, |r| Some(r.state.clone()))),
+            ("country", DataType::Utf8, true, strings(&records, |r| r.country.clone())),
+            ("licenses", DataType::Int64, false, cents(&records, |r| r.licenses)),
+            ("taxable_sales", DataType::Int64, false, cents(&records, |r| r.taxable_sales)),
+            ("non_taxable_sales", DataType::Int64, false, cents(&records, |r| r.non_taxable_sales)),
+            ("tax", DataType::Int64, false, cents(&records, |r| r.tax)),
+            ("local_tax", DataType::Int64, false, cents(&records, |r| r.local_tax)),
+            ("total", DataType::Int64, false, cents(&records, |r| r.total)),
+            ("fees", DataType::Int64, false, cents(&records, |r| r.fees)),
+            ("invoice_id", DataType::Utf8, false, strings(&records, |r| Some(r.invoice_id.clone()))),
+            ("invoice_number", DataType::Utf8, true, strings(&records, |r| r.invoice_number.clone())),
+            ("subscription", DataType::Utf8, true, strings(&records, |r| r.subscription.clone())),
+            ("plan", DataType::Utf8, true, strings(&records, |r| r.plan.clone())),
+            ("product", DataType::Utf8, true, strings(&records, |r| r.product.clone())),
+            ("description", DataType::Utf8, true, strings(&records, |r| r.description.clone())),
+            ("account", DataType::Utf8, true, strings(&records, |r| r.account.clone())),
+            ("parent", DataType::Utf8, true, strings(&records, |r| r.parent.clone())),
+            ("currency", DataType::Utf8, true, strings(&records, |r| r.currency.clone())),
+            ("source", DataType::Utf8, false, strings(&records, |r| serde_name(&r.source))),
+            ("state_source", DataType::Utf8, true, strings(&records, |r| r.state_source.as_ref().and_then(serde_name))),
+            ("state_inferred", DataType::Boolean, false, flags(&records, |r| r.state_inferred)),
+            ("refunded", DataType::Boolean, false, flags(&records, |r| r.refunded)),
+            ("rate_mismatch", DataType::Boolean, false, flags(&records, |r| r.rate_mismatch)),
+        ];
+
+        let fields: Vec<Field> =
+            columns.iter().map(|(name, data_type, nullable, _)| Field::new(*name, data_type.clone(), *nullable)).collect();
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns.into_iter().map(|(_, _, _, array)| array).collect())
+            .context("Failed to build Parquet columns")?;
+
+        // The writer needs an owned, sendable sink, so the file is built in memory
+        let mut buffer = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buffer, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        out.write_all(&buffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_parquet_has_a_typed_row_per_record() {
+        let records = vec![
+            InvoiceRecord {
+                date: NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+                customer: "Lone Star LLC".to_string(),
+                state: "TX".to_string(),
+                licenses: 10000,
+                tax: 825,
+                total: 10825,
+                invoice_id: "in_1".to_string(),
+                ..Default::default()
+            },
+            InvoiceRecord { state: "CA".to_string(), invoice_id: "in_2".to_string(), ..Default::default() },
+        ];
+
+        let mut output = Vec::new();
+        ParquetFormatter.write_report(&GroupedReport::by_state(&records), &mut output).unwrap();
+
+        let path = std::env::temp_dir().join(format!("str-output-{}.parquet", std::process::id()));
+        std::fs::write(&path, output).unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let schema = reader.metadata().file_metadata().schema_descr();
+        assert_eq!(schema.column(0).name(), "date");
+        assert_eq!(schema.column(0).logical_type(), Some(parquet::basic::LogicalType::Date));
+        assert_eq!(schema.column(9).name(), "tax");
+        assert_eq!(schema.column(9).physical_type(), parquet::basic::Type::INT64);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{partial_warning, unknown_state_warning, ReportFormatter};
+use crate::report::grouping::{GroupedReport, Totals};
+use crate::report::pdf::{fit, write_pdf, Align, Layout, FONT_SIZE, MARGIN, PAGE_WIDTH, ROW_HEIGHT};
+use anyhow::Result;
+use std::io::Write;
+
+/// The report as a PDF for archiving with the filing (`--format pdf`): the
+/// letterhead, period, a summary by state, a table per state and signature
+/// lines
+pub struct PdfFormatter;
+
+/// Right edges of the amount columns
+const COUNT_RIGHT: f32 = 260.0;
+const SALES_RIGHT: f32 = 400.0;
+const TAX_RIGHT: f32 = 480.0;
+const TOTAL_RIGHT: f32 = PAGE_WIDTH - MARGIN;
+
+/// Left edges of the invoice columns
+const CUSTOMER_LEFT: f32 = 110.0;
+const INVOICE_LEFT: f32 = 265.0;
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+fn amounts(totals: &Totals) -> [(f32, String); 3] {
+    [(SALES_RIGHT, dollars(totals.licenses)), (TAX_RIGHT, dollars(totals.tax)), (TOTAL_RIGHT, dollars(totals.total))]
+}
+
+fn summary_header(layout: &mut Layout, label: &str) {
+    layout.row(
+        true,
+        &[(MARGIN, label)],
+        &[
+            (COUNT_RIGHT, "Invoices".into()),
+            (SALES_RIGHT, "Sales".into()),
+            (TAX_RIGHT, "Tax".into()),
+            (TOTAL_RIGHT, "Total".into()),
+        ],
+    );
+    layout.rule();
+}
+
+fn invoice_header(layout: &mut Layout) {
+    layout.row(
+        true,
+        &[(MARGIN, "Date"), (CUSTOMER_LEFT, "Customer"), (INVOICE_LEFT, "Invoice")],
+        &[(SALES_RIGHT, "Sales".into()), (TAX_RIGHT, "Tax".into()), (TOTAL_RIGHT, "Total".into())],
+    );
+    layout.rule();
+}
+
+/// A signature line with the signer's name (if known) and a date line beside it
+fn signature(layout: &mut Layout, role: &str, name: Option<&str>) {
+    layout.advance(3.0 * ROW_HEIGHT, None);
+    layout.row(false, &[(MARGIN, "______________________________"), (360.0, "________________")], &[]);
+    layout.advance(ROW_HEIGHT, None);
+    let label = match name {
+        Some(name) => format!("{}: {}", role, name),
+        None => role.to_string(),
+    };
+    layout.row(false, &[(MARGIN, &label), (360.0, "Date")], &[]);
+}
+
+impl ReportFormatter for PdfFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let letterhead = &report.letterhead;
+        let title = report.title.clone().unwrap_or_else(|| "Sales Tax Report".to_string());
+        let mut layout = Layout::new(format!("{} (continued)", title));
+
+        if let Some(company) = &letterhead.company {
+            layout.text(MARGIN, true, 16.0, Align::Left, company.clone());
+            layout.advance(16.0, None);
+        }
+        for line in &letterhead.address {
+            layout.text(MARGIN, false, 10.0, Align::Left, line.clone());
+            layout.advance(13.0, None);
+        }
+        if letterhead.company.is_some() || !letterhead.address.is_empty() {
+            layout.rule();
+            layout.advance(26.0, None);
+        }
+
+        layout.text(MARGIN, true, 14.0, Align::Left, title.clone());
+        layout.advance(18.0, None);
+        if let Some(period) = &report.period {
+            layout.text(MARGIN, false, 10.0, Align::Left, format!("Period: {}", period));
+            layout.advance(14.0, None);
+        }
+        for warning in [partial_warning(report), unknown_state_warning(report)].into_iter().flatten() {
+            layout.text(MARGIN, true, FONT_SIZE, Align::Left, fit(&warning, TOTAL_RIGHT - MARGIN, true, FONT_SIZE));
+            layout.advance(ROW_HEIGHT, None);
+        }
+        layout.advance(2.0 * ROW_HEIGHT, None);
+
+        let label = report.group_by.header();
+        let header = |layout: &mut Layout| summary_header(layout, label);
+        summary_header(&mut layout, label);
+        for group in &report.groups {
+            layout.advance(ROW_HEIGHT, Some(&header));
+            let name = fit(&group.state, COUNT_RIGHT - MARGIN - 60.0, false, FONT_SIZE);
+            let mut right = vec![(COUNT_RIGHT, group.records.len().to_string())];
+            right.extend(amounts(&group.subtotal));
+            layout.row(false, &[(MARGIN, &name)], &right);
+        }
+        layout.rule();
+        let mut grand_totals = vec![("Grand Total".to_string(), &report.grand_total)];
+        for (currency, totals) in &report.currency_totals {
+            grand_totals.push((format!("Grand Total ({})", currency), totals));
+        }
+        for (label, totals) in &grand_totals {
+            layout.advance(ROW_HEIGHT, Some(&header));
+            layout.row(true, &[(MARGIN, label)], &amounts(totals));
+        }
+
+        if !report.summary {
+            for group in &report.groups {
+                layout.advance(3.0 * ROW_HEIGHT, None);
+                layout.text(MARGIN, true, 12.0, Align::Left, fit(&group.state, TOTAL_RIGHT - MARGIN, true, 12.0));
+                layout.advance(20.0, None);
+                invoice_header(&mut layout);
+                for record in &group.records {
+                    layout.advance(ROW_HEIGHT, Some(&invoice_header));
+                    let date = report.date_format.format(record.date);
+                    let customer = fit(&record.customer, INVOICE_LEFT - CUSTOMER_LEFT - 8.0, false, FONT_SIZE);
+                    let invoice = record.invoice_number.as_deref().unwrap_or(&record.invoice_id);
+                    let invoice = fit(invoice, SALES_RIGHT - INVOICE_LEFT - 50.0, false, FONT_SIZE);
+                    let mut totals = Totals::default();
+                    totals.add(record);
+                    layout.row(
+                        false,
+                        &[(MARGIN, &date), (CUSTOMER_LEFT, &customer), (INVOICE_LEFT, &invoice)],
+                        &amounts(&totals),
+                    );
+                }
+                layout.rule();
+                layout.advance(ROW_HEIGHT, Some(&invoice_header));
+                layout.row(true, &[(MARGIN, "Subtotal")], &amounts(&group.subtotal));
+            }
+        }
+
+        if !report.skipped.is_empty() {
+            layout.advance(2.0 * ROW_HEIGHT, None);
+            let note = format!(
+                "{} invoice(s) totaling {} were excluded from this report.",
+                report.skipped.len(),
+                dollars(report.skipped_amount())
+            );
+            layout.row(false, &[(MARGIN, &note)], &[]);
+        }
+
+        layout.advance(ROW_HEIGHT, None);
+        signature(&mut layout, "Prepared by", letterhead.prepared_by.as_deref());
+        signature(&mut layout, "Reviewed by", letterhead.reviewed_by.as_deref());
+
+        out.write_all(&write_pdf(&title, layout.pages))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::formatter::Letterhead;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_pdf_report_has_letterhead_and_pages() {
+        let records: Vec<InvoiceRecord> = (0..80)
+            .map(|i| InvoiceRecord {
+                date: chrono::NaiveDate::from_ymd_opt(2025, 8, 14).unwrap(),
+                customer: format!("Customer {} with a name too long for its column", i),
+                state: if i % 2 == 0 { "TX" } else { "CA" }.to_string(),
+                licenses: 10000,
+                tax: 825,
+                total: 10825,
+                invoice_id: format!("in_{}", i),
+                ..Default::default()
+            })
+            .collect();
+        let letterhead = Letterhead {
+            company: Some("Acme Software LLC".to_string()),
+            address: vec!["100 Congress Ave".to_string(), "Austin, TX 78701".to_string()],
+            prepared_by: Some("Dana Reyes".to_string()),
+            ..Default::default()
+        };
+        let report = GroupedReport::by_state(&records).with_letterhead(letterhead).with_period("Q3-2025");
+
+        let mut output = Vec::new();
+        PdfFormatter.write_report(&report, &mut output).unwrap();
+        let pdf = String::from_utf8_lossy(&output);
+
+        assert!(pdf.starts_with("%PDF-"));
+        assert!(pdf.contains("(Acme Software LLC)"));
+        assert!(pdf.contains("(Period: Q3-2025)"));
+        assert!(pdf.contains("(Prepared by: Dana Reyes)"));
+        // Names too long for their column are cut short
+        let customer = fit(&records[0].customer, INVOICE_LEFT - CUSTOMER_LEFT - 8.0, false, FONT_SIZE);
+        assert!(customer.ends_with("..."));
+        assert!(pdf.contains(&format!("({})", customer)));
+        assert!(pdf.contains("(Page 3 of "));
+    }
+}
@@ -0,0 +1,148 @@
+use super::ReportFormatter;
+use crate::report::diagnostics::Diagnostic;
+use crate::report::discount::TaxDue;
+use crate::report::grouping::{GroupBy, GroupedReport, Totals};
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use tera::Tera;
+
+/// Renders the report through a user-supplied Tera template
+/// (`--template worksheet.tera`), for filing worksheets no built-in format
+/// matches.
+///
+/// The template sees the same data as the JSON report, amounts in cents:
+/// `title`, `period`, `group_by`, `states` (each with `state`, `currency`,
+/// `records`, `invoices` and `subtotal`), `grand_total`, `currency_totals`,
+/// `tax_due`, `footnotes`, `excluded` and `diagnostics`. The `dollars` filter
+/// formats cents as dollars (`{{ state.subtotal.tax | dollars }}`).
+pub struct TemplateFormatter {
+    tera: Tera,
+}
+
+const NAME: &str = "report";
+
+#[derive(Serialize)]
+struct TemplateReport<'a> {
+    title: Option<&'a str>,
+    period: Option<&'a str>,
+    group_by: GroupBy,
+    states: Vec<TemplateState<'a>>,
+    grand_total: Totals,
+    currency_totals: &'a BTreeMap<String, Totals>,
+    tax_due: Option<TaxDue>,
+    footnotes: &'a [String],
+    excluded: &'a [SkippedInvoice],
+    diagnostics: &'a [Diagnostic],
+}
+
+#[derive(Serialize)]
+struct TemplateState<'a> {
+    state: &'a str,
+    currency: Option<&'a str>,
+    records: &'a [&'a InvoiceRecord],
+    invoices: usize,
+    subtotal: Totals,
+}
+
+/// `{{ cents | dollars }}`: an amount in cents as dollars with two decimals
+fn dollars(value: &tera::Value, _: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let cents = value.as_i64().ok_or_else(|| tera::Error::msg(format!("dollars expects cents, got {}", value)))?;
+    Ok(tera::Value::String(format!("{:.2}", cents as f64 / 100.0)))
+}
+
+impl TemplateFormatter {
+    /// Compile a template, so mistakes in it surface before any data is fetched
+    pub fn load(path: &Path) -> Result<Self> {
+        let source =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read template {}", path.display()))?;
+        Self::from_source(&source).with_context(|| format!("Invalid template {}", path.display()))
+    }
+
+    pub fn from_source(source: &str) -> Result<Self> {
+        let mut tera = Tera::default();
+        // Worksheets are usually CSV or text, so nothing is HTML-escaped
+        tera.autoescape_on(vec![]);
+        tera.register_filter("dollars", dollars);
+        tera.add_raw_template(NAME, source).map_err(|e| anyhow!(template_error(&e)))?;
+        Ok(TemplateFormatter { tera })
+    }
+}
+
+/// A Tera error with its causes, which say where in the template it went wrong
+fn template_error(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+impl ReportFormatter for TemplateFormatter {
+    fn write_report(&self, report: &GroupedReport<'_>, out: &mut dyn Write) -> Result<()> {
+        let document = TemplateReport {
+            title: report.title.as_deref(),
+            period: report.period.as_deref(),
+            group_by: report.group_by,
+            states: report
+                .groups
+                .iter()
+                .map(|group| TemplateState {
+                    state: &group.state,
+                    currency: group.currency.as_deref(),
+                    records: group.records.as_slice(),
+                    invoices: group.records.len(),
+                    subtotal: group.subtotal,
+                })
+                .collect(),
+            grand_total: report.grand_total,
+            currency_totals: &report.currency_totals,
+            tax_due: report.tax_due(),
+            footnotes: &report.footnotes,
+            excluded: report.skipped,
+            diagnostics: report.diagnostics,
+        };
+        let context = tera::Context::from_serialize(&document)?;
+        self.tera.render_to(NAME, &context, out).map_err(|e| anyhow!("Failed to render template: {}", template_error(&e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_renders_sections_and_totals() {
+        let record = |state: &str, customer: &str, tax: i64| InvoiceRecord {
+            customer: customer.to_string(),
+            state: state.to_string(),
+            licenses: 10000,
+            tax,
+            total: 10000 + tax,
+            ..Default::default()
+        };
+        let records = vec![record("TX", "Lone Star & Co", 825), record("CA", "Golden", 0)];
+        let report = GroupedReport::by_state(&records).with_period("Q3-2025");
+
+        let template = TemplateFormatter::from_source(
+            "Worksheet {{ period }}\n\
+             {% for s in states %}{{ s.state }}: {{ s.invoices }} invoice(s), tax {{ s.subtotal.tax | dollars }}\n\
+             {% for r in s.records %}  {{ r.customer }}\n{% endfor %}{% endfor %}\
+             Total tax {{ grand_total.tax | dollars }}",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        template.write_report(&report, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Worksheet Q3-2025\nCA: 1 invoice(s), tax 0.00\n  Golden\nTX: 1 invoice(s), tax 8.25\n  Lone Star & Co\nTotal tax 8.25"
+        );
+        assert!(TemplateFormatter::from_source("{% for s in states %}").is_err());
+    }
+}
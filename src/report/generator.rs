@@ -1,22 +1,140 @@
-use crate::stripe::models::InvoiceRecord;
-use crate::stripe::client::StripeInvoice;
+use crate::report::aliases::CustomerAliases;
+use crate::report::audit::SourceRefs;
+use crate::report::basis::{cash_share, refund, Basis, Collected, CreditBasis};
+use crate::report::diagnostics::{Diagnostic, DiagnosticCode, Severity};
+use crate::report::exclusions::CustomerExclusions;
+use crate::report::fees::fee_breakdown;
+use crate::report::jurisdictions::{local_share, tax_jurisdictions};
+use crate::report::payments::{OneOffPayment, RecordSource, PAYMENT_LINE_TYPE};
+use crate::report::rates::RateTable;
+use crate::report::rollup::PARENT_METADATA_KEY;
+use crate::report::states::{is_us_country, normalize_state};
+use crate::report::taxability::{Taxability, TaxabilityRule};
+use crate::report::zip::state_for_zip;
+use crate::stripe::models::{Adjustment, AmountColumn, FeeBreakdown, FxConversion, InvoiceLine, InvoiceRecord, SkippedInvoice};
+use crate::stripe::client::{Dispute, LineItem, StripeInvoice};
+use crate::stripe::ApiUsage;
 use anyhow::{anyhow, Result};
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// State code used for invoices with no state when `allow_missing_state` is set
+pub const UNKNOWN_STATE: &str = "UNKNOWN";
+
+/// Prefix of the per-country sections for invoices billed outside the US
+pub const NON_US_REGION: &str = "NON-US";
+
+/// Settings for building a report
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    /// Report invoices with no state under `UNKNOWN` instead of skipping them,
+    /// so totals reconcile against Stripe's revenue figures
+    pub allow_missing_state: bool,
+    /// Stop enriching invoices at this time and return a partial report
+    pub deadline: Option<std::time::Instant>,
+    /// Parent entity per customer ID; takes precedence over customer metadata
+    pub parent_accounts: BTreeMap<String, String>,
+    /// Customer metadata key naming the parent entity (default `parent_account`)
+    pub parent_metadata_key: Option<String>,
+    /// Convert non-USD invoices to USD at their balance transaction's exchange rate
+    pub convert_to_usd: bool,
+    /// Keep each invoice's sales lines on its record (`--detail line-items`,
+    /// `--group-by product`)
+    pub line_items: bool,
+    /// Count one-off invoice items (setup fees, overages) as sales alongside
+    /// subscription lines
+    pub include_invoiceitems: bool,
+    /// Report paid Checkout Sessions and standalone PaymentIntents that no
+    /// invoice covers alongside invoices
+    pub include_payments: bool,
+    /// Partial-taxability rules applied to taxed lines by product category
+    pub taxability: Taxability,
+    /// Report invoices settled partly with credit at their full amounts or
+    /// only the share paid in cash
+    pub credit_basis: CreditBasis,
+    /// Include invoices by finalization or by cash collected; by default paid
+    /// invoices are included by creation date
+    pub basis: Option<Basis>,
+    /// Days before the period to list invoices from under `basis`
+    /// (default `DEFAULT_LOOKBACK_DAYS`)
+    pub lookback_days: Option<u32>,
+    /// States where the business is registered to collect tax. When set, tax
+    /// collected outside them and untaxed sales inside them are flagged
+    pub registered_states: Vec<String>,
+    /// Expected tax rates; when set, invoices whose tax doesn't match the
+    /// rate at their destination are flagged
+    pub rates: Option<RateTable>,
+    /// Internal, test and demo customers whose invoices are left out
+    pub exclusions: CustomerExclusions,
+    /// Display names replacing the raw Stripe customer names
+    pub customer_aliases: CustomerAliases,
+    /// Process only the first this many invoices fetched, for a quick check
+    /// of the setup (`--limit`)
+    pub limit: Option<usize>,
+    /// Stop with an error once the Stripe API requests counted here go over
+    /// their budget (`--max-requests`), rather than skipping every invoice
+    /// left as its requests are refused
+    pub request_budget: Option<Arc<ApiUsage>>,
+    /// The profile's timezone, which dates invoices on the same calendar as
+    /// the period bounds (default UTC)
+    pub timezone: Option<Tz>,
+}
+
+/// How far a report got before its time budget ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PartialReport {
+    /// Invoices processed (reported or excluded)
+    pub processed: usize,
+    /// Invoices not yet processed
+    pub remaining: usize,
+}
 
 pub struct ReportGenerator {
     records: Vec<InvoiceRecord>,
+    skipped: Vec<SkippedInvoice>,
+    diagnostics: Vec<Diagnostic>,
+    /// Fetched invoices not processed before the deadline
+    pending: Vec<StripeInvoice>,
+    /// Product names by product ID, for naming invoice lines
+    product_names: BTreeMap<String, String>,
+    /// Cash collected in the period per invoice ID, under the cash basis
+    collected: BTreeMap<String, Collected>,
+    /// Stripe objects and raw amounts behind each processed invoice, for the audit file
+    sources: Vec<SourceRefs>,
+    options: ReportOptions,
+}
+
+impl Default for ReportGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ReportGenerator {
     pub fn new() -> Self {
+        Self::with_options(ReportOptions::default())
+    }
+
+    pub fn with_options(options: ReportOptions) -> Self {
         ReportGenerator {
             records: Vec::new(),
+            skipped: Vec::new(),
+            diagnostics: Vec::new(),
+            pending: Vec::new(),
+            product_names: BTreeMap::new(),
+            collected: BTreeMap::new(),
+            sources: Vec::new(),
+            options,
         }
     }
 
     /// Convert Stripe invoice data to an InvoiceRecord
     /// This version takes customer and charge data separately if already fetched
-    /// Uses three-level fallback for state extraction: customer address → charge billing address → invoice address
+    /// Uses three-level fallback for state extraction: customer address → charge billing address → invoice address,
+    /// then infers the state from the first US ZIP code found in those addresses
     pub fn process_invoice_with_customer(
         &mut self,
         invoice: StripeInvoice,
@@ -24,19 +142,128 @@ impl ReportGenerator {
         charge: Option<&crate::stripe::client::Charge>,
         balance_transaction: Option<&crate::stripe::client::BalanceTransaction>,
     ) -> Result<()> {
-        let date = format_invoice_date(invoice.paid_at.unwrap_or(invoice.created))?;
-        let customer_name = extract_customer_name(&invoice)?;
-        let state = extract_state_with_fallbacks(customer, charge, &invoice)?;
+        self.sources.push(SourceRefs::new(&invoice, charge, balance_transaction));
+        let collected = self.collected.get(&invoice.id).copied();
+        let date = match (self.options.basis, collected) {
+            (Some(Basis::Cash), Some(collected)) => collected.paid_at,
+            (Some(Basis::Accrual), _) => invoice.finalized_at.unwrap_or(invoice.created),
+            _ => invoice.paid_at.unwrap_or(invoice.created),
+        };
+        let timestamp = date;
+        let date = invoice_date(timestamp, self.options.timezone)?;
+        let customer_name = extract_customer_name(&invoice, &self.options.customer_aliases)?;
+        let mut state_inferred = false;
+        let (state_source, state) = match extract_state_with_fallbacks(customer, charge, &invoice) {
+            Ok((source, state)) => (Some(source), state),
+            Err(e) => match infer_state_from_zip(customer, charge, &invoice) {
+                Some((state, zip)) => {
+                    tracing::info!("Invoice {}: no state on file; inferred {} from ZIP code {}", invoice.id, state, zip);
+                    self.add_diagnostic(Diagnostic::new(
+                        Severity::Info,
+                        DiagnosticCode::StateInferred,
+                        &invoice.id,
+                        format!("No state on file; inferred {} from ZIP code {}", state, zip),
+                    ));
+                    state_inferred = true;
+                    (Some(StateSource::ZipCode), state.to_string())
+                }
+                None => (None, self.missing_state(&invoice, e)?),
+            },
+        };
+
+        let address = destination_address(state_source, customer, charge, &invoice).cloned();
+        let foreign = foreign_address(customer, charge, &invoice).filter(|_| state == NON_US_REGION);
+        let (state, country) = if let Some(address) = foreign {
+            let country = address.country.as_deref().unwrap_or_default().trim().to_uppercase();
+            let region = address.state.as_deref().unwrap_or_default().trim().to_string();
+            self.add_diagnostic(Diagnostic::new(
+                Severity::Info,
+                DiagnosticCode::NonUsAddress,
+                &invoice.id,
+                format!("Billing address is in {}; reported under {} {}", country, NON_US_REGION, country),
+            ));
+            (region, Some(country))
+        } else if state == UNKNOWN_STATE {
+            (state, None)
+        } else {
+            (state, Some("US".to_string()))
+        };
 
         // Sum subscription quantities
         let users = sum_subscription_quantities(&invoice)?;
 
-        // Sum license amounts (subscription line items only, in cents)
-        let licenses = sum_license_amounts(&invoice)?;
+        // Sum license amounts (subscription line items, plus invoice items if enabled, in cents)
+        let include_invoiceitems = self.options.include_invoiceitems;
+        let licenses = sum_license_amounts(&invoice, include_invoiceitems)?;
 
         // Extract tax
         let tax = invoice.tax.unwrap_or(0);
 
+        // Invoices settled partly with credit are reported in full unless
+        // only the share paid in cash is asked for. On the cash basis, only
+        // what was collected in the period counts, which already leaves credit out.
+        let collected_share = collected.and_then(|c| collected_share(&invoice, c));
+        let cash = match cash_share(&invoice) {
+            _ if collected.is_some() => collected_share,
+            Some(share) if self.options.credit_basis == CreditBasis::Cash => Some(share),
+            Some(_) => {
+                self.add_diagnostic(Diagnostic::new(
+                    Severity::Info,
+                    DiagnosticCode::CreditApplied,
+                    &invoice.id,
+                    format!(
+                        "{:.2} was settled with credit notes or customer credit balance; reported at the invoice amount",
+                        invoice.credits_applied() as f64 / 100.0
+                    ),
+                ));
+                None
+            }
+            None => None,
+        };
+        // Refunds after payment come off whatever share is reported
+        let refund = refund(&invoice, charge);
+        if let Some(refund) = refund {
+            self.add_diagnostic(Diagnostic::new(
+                Severity::Info,
+                DiagnosticCode::Refunded,
+                &invoice.id,
+                format!("{:.2} was refunded after payment; reported net of the refund", refund.amount as f64 / 100.0),
+            ));
+        }
+        let share = match (cash, refund) {
+            (Some(cash), Some(refund)) => Some(cash * refund.share),
+            (None, Some(refund)) => Some(refund.share),
+            (cash, None) => cash,
+        };
+
+        // Keep other currencies apart from USD, or convert them if asked to
+        let (currency, fx) = self.resolve_currency(&invoice, balance_transaction);
+        // Split gross sales into taxed and untaxed; exempt customers' sales are never
+        // taxable, and taxability rules take part of some products' sales out
+        let exempt = customer.is_some_and(|c| c.is_tax_exempt());
+        let rule_state = (country.as_deref() == Some("US")).then_some(state.as_str());
+        let taxability = &self.options.taxability;
+        let line_rule = |line: &LineItem| rule_state.and_then(|s| taxability.rule_for(s, line.product_id()));
+        let taxable_sales = if exempt { 0 } else { sum_taxable_amounts(&invoice, include_invoiceitems, line_rule) };
+        let rule_notes = if exempt { Vec::new() } else { applied_rule_notes(&invoice, include_invoiceitems, line_rule) };
+
+        let (licenses, taxable_sales, tax) = match &fx {
+            Some(fx) => (convert(licenses, fx.rate), convert(taxable_sales, fx.rate), convert(tax, fx.rate)),
+            None => (licenses, taxable_sales, tax),
+        };
+
+        // Scale invoices settled partly with credit or refunded down to the
+        // share actually paid and kept
+        let (licenses, taxable_sales, tax) = match share {
+            Some(share) => (convert(licenses, share), convert(taxable_sales, share), convert(tax, share)),
+            None => (licenses, taxable_sales, tax),
+        };
+        let non_taxable_sales = licenses - taxable_sales;
+
+        // Split tax into state and local by the jurisdictions Stripe collected for
+        let jurisdictions = tax_jurisdictions(&invoice);
+        let local_tax = local_share(&jurisdictions, tax);
+
         // Calculate total
         let total = licenses + tax;
 
@@ -46,31 +273,310 @@ impl ReportGenerator {
         } else {
             0
         };
+        let fee_breakdown = balance_transaction.map(|bt| fee_breakdown(bt, charge)).unwrap_or_default();
+
+        let parent = self.resolve_parent(&invoice, customer);
+
+        let tax_ids = customer.map(|c| c.tax_id_labels()).unwrap_or_default();
+        let lines = if self.options.line_items {
+            let mut lines = invoice_lines(&invoice, include_invoiceitems, exempt, fx.as_ref(), line_rule);
+            for line in &mut lines {
+                line.product_name = line.product.as_ref().and_then(|id| self.product_names.get(id)).cloned();
+                if let Some(share) = share {
+                    line.amount = convert(line.amount, share);
+                    line.taxable_sales = convert(line.taxable_sales, share);
+                    line.tax = convert(line.tax, share);
+                }
+            }
+            lines
+        } else {
+            Vec::new()
+        };
 
-        let record = InvoiceRecord {
+        let mut record = InvoiceRecord {
+            invoice_id: invoice.id.clone(),
+            invoice_number: invoice.number.clone(),
+            subscription: invoice.subscription_id().map(str::to_string),
+            subscription_schedule: invoice.subscription().and_then(|s| s.schedule),
+            plan: invoice.plan_name(),
+            account: None,
+            source: RecordSource::Invoice,
             date,
+            timestamp,
             customer: customer_name,
             users,
             state,
+            country,
             licenses,
+            taxable_sales,
+            non_taxable_sales,
             tax,
+            local_tax,
+            tax_jurisdictions: jurisdictions,
             total,
             fees,
+            fee_breakdown,
+            state_inferred,
+            state_source,
+            address,
+            rate_mismatch: false,
+            refunded: refund.is_some(),
+            parent,
+            currency,
+            fx,
+            tax_ids,
+            lines,
+            product: None,
+            description: None,
+            adjustments: Vec::new(),
         };
+        if let Some(fx) = &record.fx {
+            let note = format!("Converted from {} at the exchange rate Stripe applied, rounded to the cent", fx.currency);
+            record.add_adjustment(
+                &[
+                    AmountColumn::Licenses,
+                    AmountColumn::TaxableSales,
+                    AmountColumn::NonTaxableSales,
+                    AmountColumn::Tax,
+                    AmountColumn::Total,
+                ],
+                note,
+            );
+        }
+        if collected_share.is_some() {
+            record.add_adjustment(
+                &[
+                    AmountColumn::Licenses,
+                    AmountColumn::TaxableSales,
+                    AmountColumn::NonTaxableSales,
+                    AmountColumn::Tax,
+                    AmountColumn::Total,
+                ],
+                "Reduced to the share of the invoice collected in the period (cash basis)",
+            );
+        } else if cash.is_some() {
+            record.add_adjustment(
+                &[
+                    AmountColumn::Licenses,
+                    AmountColumn::TaxableSales,
+                    AmountColumn::NonTaxableSales,
+                    AmountColumn::Tax,
+                    AmountColumn::Total,
+                ],
+                "Reduced to the share paid in cash; the rest was settled with credit notes or customer credit balance",
+            );
+        }
+        if refund.is_some() {
+            record.add_adjustment(
+                &[
+                    AmountColumn::Licenses,
+                    AmountColumn::TaxableSales,
+                    AmountColumn::NonTaxableSales,
+                    AmountColumn::Tax,
+                    AmountColumn::Total,
+                ],
+                "Reduced to the share not refunded by credit notes or charge refunds after payment",
+            );
+        }
+        for note in rule_notes {
+            record.add_adjustment(&[AmountColumn::TaxableSales, AmountColumn::NonTaxableSales], note);
+        }
+        self.check_collection(&record, exempt);
+        if let Some(rates) = &self.options.rates
+            && record.country.as_deref() == Some("US")
+            && let Some(message) = rates.check(&record.state, destination_zip(customer, charge, &invoice), record.taxable_sales, record.tax)
+        {
+            tracing::warn!("Invoice {}: {}", record.invoice_id, message);
+            self.add_diagnostic(Diagnostic::new(Severity::Warning, DiagnosticCode::TaxRateMismatch, &record.invoice_id, message));
+            record.rate_mismatch = true;
+        }
 
         self.records.push(record);
         Ok(())
     }
 
+    /// Flag tax collected in a US state without a registration, and sales
+    /// to a non-exempt customer in a registered state that carry no tax
+    fn check_collection(&mut self, record: &InvoiceRecord, exempt: bool) {
+        let registered = &self.options.registered_states;
+        if registered.is_empty() || record.country.as_deref() != Some("US") {
+            return;
+        }
+        let is_registered = registered.iter().any(|s| s.eq_ignore_ascii_case(&record.state));
+        let (code, message) = if record.tax > 0 && !is_registered {
+            (
+                DiagnosticCode::TaxUnregisteredState,
+                format!("{:.2} tax collected in {}, where no registration is configured", record.tax as f64 / 100.0, record.state),
+            )
+        } else if record.tax == 0 && record.licenses > 0 && !exempt && is_registered {
+            (
+                DiagnosticCode::UntaxedRegisteredState,
+                format!("No tax collected on {:.2} of sales in {}, where the business is registered", record.licenses as f64 / 100.0, record.state),
+            )
+        } else {
+            return;
+        };
+        tracing::warn!("Invoice {}: {}", record.invoice_id, message);
+        self.add_diagnostic(Diagnostic::new(Severity::Warning, code, &record.invoice_id, message));
+    }
+
+    /// Add a lost dispute as a negative record reversing the disputed share
+    /// of its invoice's sales and tax, dated when the dispute was opened, and
+    /// carrying the dispute fee. The invoice is processed as it would be on
+    /// its own (state, exemption, conversion), then scaled to the disputed amount.
+    pub fn process_chargeback(
+        &mut self,
+        dispute: &Dispute,
+        invoice: StripeInvoice,
+        customer: Option<&crate::stripe::client::Customer>,
+        charge: Option<&crate::stripe::client::Charge>,
+        balance_transaction: Option<&crate::stripe::client::BalanceTransaction>,
+    ) -> Result<()> {
+        // The sale's own cash-basis share doesn't apply to the reversal
+        let collected = self.collected.remove(&invoice.id);
+        // What the record will represent: the amount paid if scaled to the
+        // cash share, else the full invoice
+        let scaled_to_cash = self.options.credit_basis == CreditBasis::Cash && cash_share(&invoice).is_some();
+        let base = if scaled_to_cash { invoice.amount_paid } else { invoice.total.unwrap_or(invoice.amount_paid) };
+        let invoice_id = invoice.id.clone();
+        let result = self.process_invoice_with_customer(invoice, customer, charge, balance_transaction);
+        if let Some(collected) = collected {
+            self.collected.insert(invoice_id, collected);
+        }
+        result?;
+
+        let mut record = self.records.pop().ok_or_else(|| anyhow!("No record built for chargeback"))?;
+        // The invoice stays tagged with the period that reported the sale
+        record.source = RecordSource::Chargeback;
+        let share = if base > 0 { dispute.amount.min(base) as f64 / base as f64 } else { 1.0 };
+        let reverse = |cents: i64| -convert(cents, share);
+        record.date = invoice_date(dispute.created, self.options.timezone)?;
+        record.timestamp = dispute.created;
+        record.users = 0;
+        record.licenses = reverse(record.licenses);
+        record.taxable_sales = reverse(record.taxable_sales);
+        record.tax = reverse(record.tax);
+        record.local_tax = reverse(record.local_tax);
+        record.non_taxable_sales = record.licenses - record.taxable_sales;
+        record.total = record.licenses + record.tax;
+        record.fees = dispute.fee();
+        record.fee_breakdown = FeeBreakdown { other: dispute.fee(), ..Default::default() };
+        record.lines.clear();
+        // The chargeback note replaces the sale's own scaling notes
+        record.adjustments.retain(|a| !a.note.starts_with("Reduced to the share"));
+        record.add_adjustment(
+            &[
+                AmountColumn::Licenses,
+                AmountColumn::TaxableSales,
+                AmountColumn::NonTaxableSales,
+                AmountColumn::Tax,
+                AmountColumn::Total,
+            ],
+            format!("Chargeback: dispute {} was lost, reversing {:.2} of the invoice", dispute.id, dispute.amount as f64 / 100.0),
+        );
+        if record.fees != 0 {
+            record.add_adjustment(&[AmountColumn::Fees, AmountColumn::OtherFees], "Dispute fee charged by Stripe");
+        }
+
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// The parent entity the invoice's customer files under: the configured
+    /// mapping for the customer ID, else the customer's parent metadata
+    fn resolve_parent(&self, invoice: &StripeInvoice, customer: Option<&crate::stripe::client::Customer>) -> Option<String> {
+        let customer_id = match customer {
+            Some(customer) => Some(customer.id.as_str()),
+            None => invoice.customer.as_str(),
+        };
+        if let Some(parent) = customer_id.and_then(|id| self.options.parent_accounts.get(id)) {
+            return Some(parent.clone());
+        }
+
+        let key = self.options.parent_metadata_key.as_deref().unwrap_or(PARENT_METADATA_KEY);
+        customer
+            .and_then(|c| c.metadata.get(key))
+            .map(|parent| parent.trim())
+            .filter(|parent| !parent.is_empty())
+            .map(str::to_string)
+    }
+
+    /// The record's currency (`None` for USD) and, when converting to USD,
+    /// the conversion to apply. Stripe's exchange rate is only available on
+    /// the balance transaction, so without one the invoice stays in its own
+    /// currency.
+    fn resolve_currency(
+        &mut self,
+        invoice: &StripeInvoice,
+        balance_transaction: Option<&crate::stripe::client::BalanceTransaction>,
+    ) -> (Option<String>, Option<FxConversion>) {
+        let currency = invoice.currency.as_deref().unwrap_or("usd").to_uppercase();
+        if currency == "USD" {
+            return (None, None);
+        }
+        if !self.options.convert_to_usd {
+            return (Some(currency), None);
+        }
+
+        let rate = balance_transaction
+            .filter(|bt| bt.currency.as_deref().is_some_and(|c| c.eq_ignore_ascii_case("usd")))
+            .and_then(|bt| bt.exchange_rate);
+        match rate {
+            Some(rate) => (None, Some(FxConversion { currency, rate })),
+            None => {
+                self.add_diagnostic(Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticCode::FxRateUnavailable,
+                    &invoice.id,
+                    format!("No USD exchange rate available; reported in {}", currency),
+                ));
+                (Some(currency), None)
+            }
+        }
+    }
+
+    /// No state could be found or inferred: report under UNKNOWN if allowed, else fail
+    fn missing_state(&mut self, invoice: &StripeInvoice, error: anyhow::Error) -> Result<String> {
+        if !self.options.allow_missing_state {
+            return Err(error);
+        }
+
+        tracing::warn!("{}; reporting it under {}", error, UNKNOWN_STATE);
+        self.add_diagnostic(Diagnostic::new(
+            Severity::Warning,
+            DiagnosticCode::MissingState,
+            &invoice.id,
+            format!("No state found; reported under {}", UNKNOWN_STATE),
+        ));
+        Ok(UNKNOWN_STATE.to_string())
+    }
+
+    /// Add a one-off payment (a Checkout Session or standalone PaymentIntent)
+    /// as a record, built the same way as an invoice paid when the payment was
+    pub fn process_payment(
+        &mut self,
+        payment: &OneOffPayment,
+        customer: Option<&crate::stripe::client::Customer>,
+        charge: Option<&crate::stripe::client::Charge>,
+        balance_transaction: Option<&crate::stripe::client::BalanceTransaction>,
+    ) -> Result<()> {
+        let reported = self.records.len();
+        self.process_invoice_with_customer(payment.as_invoice(), customer, charge, balance_transaction)?;
+        for record in &mut self.records[reported..] {
+            record.source = payment.source;
+        }
+        Ok(())
+    }
+
     /// Legacy method for backward compatibility
     pub fn process_invoice(&mut self, invoice: StripeInvoice) -> Result<()> {
         self.process_invoice_with_customer(invoice, None, None, None)
     }
 
     pub fn sort_records(&mut self) {
-        // Sort by state (alphabetical), then by date (ascending), then by customer name
+        // Sort by section (state, or country outside the US), then by date (ascending), then by customer name
         self.records.sort_by(|a, b| {
-            match a.state.cmp(&b.state) {
+            match a.section().cmp(&b.section()) {
                 std::cmp::Ordering::Equal => {
                     match a.date.cmp(&b.date) {
                         std::cmp::Ordering::Equal => a.customer.cmp(&b.customer),
@@ -86,6 +592,161 @@ impl ReportGenerator {
         &self.records
     }
 
+    /// Add an already-built record (e.g. from another account's report)
+    pub fn add_record(&mut self, record: InvoiceRecord) {
+        self.records.push(record);
+    }
+
+    /// Record an invoice that was excluded from the report, with the reason
+    pub fn record_skip(&mut self, invoice: &StripeInvoice, reason: impl Into<String>) {
+        let reason = reason.into();
+        self.add_diagnostic(Diagnostic::new(
+            Severity::Error,
+            DiagnosticCode::InvoiceSkipped,
+            &invoice.id,
+            format!("Excluded from the report: {}", reason),
+        ));
+        self.push_skip(invoice, reason);
+    }
+
+    /// Leave out an invoice of an internal, test or demo customer
+    /// (`options.exclusions`). It is listed with the excluded invoices, but
+    /// isn't counted by `unreportable`.
+    pub fn exclude_customer_invoice(&mut self, invoice: &StripeInvoice, reason: impl Into<String>) {
+        let reason = reason.into();
+        self.add_diagnostic(Diagnostic::new(Severity::Info, DiagnosticCode::CustomerExcluded, &invoice.id, reason.clone()));
+        self.push_skip(invoice, reason);
+    }
+
+    fn push_skip(&mut self, invoice: &StripeInvoice, reason: String) {
+        if !self.sources.iter().any(|s| s.invoice_id == invoice.id) {
+            self.sources.push(SourceRefs::new(invoice, None, None));
+        }
+        let customer = invoice.customer_name.clone().unwrap_or_else(|| match &invoice.customer {
+            serde_json::Value::String(id) => id.clone(),
+            serde_json::Value::Object(obj) => obj.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+            _ => String::new(),
+        });
+        self.skipped.push(SkippedInvoice {
+            invoice_id: invoice.id.clone(),
+            customer,
+            amount: invoice.amount_paid,
+            reason,
+        });
+    }
+
+    /// Take an invoice's rows out of the report and list it as excluded with
+    /// `reason` (e.g. excluded in review). Returns whether it was reported.
+    pub fn exclude_invoice(&mut self, invoice_id: &str, reason: impl Into<String>) -> bool {
+        let (excluded, kept): (Vec<InvoiceRecord>, Vec<InvoiceRecord>) =
+            std::mem::take(&mut self.records).into_iter().partition(|r| r.invoice_id == invoice_id);
+        self.records = kept;
+        let Some(first) = excluded.first() else {
+            return false;
+        };
+        self.skipped.push(SkippedInvoice {
+            invoice_id: invoice_id.to_string(),
+            customer: first.customer.clone(),
+            amount: excluded.iter().map(|r| r.total).sum(),
+            reason: reason.into(),
+        });
+        true
+    }
+
+    /// Attach a note to an invoice's rows, footnoted on their Total
+    pub fn annotate_invoice(&mut self, invoice_id: &str, note: &str) {
+        for record in self.records.iter_mut().filter(|r| r.invoice_id == invoice_id) {
+            record.adjustments.push(Adjustment { columns: vec![AmountColumn::Total], note: note.to_string() });
+        }
+    }
+
+    /// Add an already-recorded skip (e.g. when combining reports)
+    pub fn add_skip(&mut self, skip: SkippedInvoice) {
+        self.skipped.push(skip);
+    }
+
+    /// Name a product, for invoice lines processed from now on
+    pub fn add_product_name(&mut self, product_id: &str, name: &str) {
+        self.product_names.insert(product_id.to_string(), name.to_string());
+    }
+
+    /// Whether a product has been named (so it needn't be fetched again)
+    pub fn has_product_name(&self, product_id: &str) -> bool {
+        self.product_names.contains_key(product_id)
+    }
+
+    /// Record a data-quality finding
+    pub fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Data-quality findings, in the order they were encountered
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The Stripe objects and raw amounts behind each processed invoice
+    pub fn sources(&self) -> &[SourceRefs] {
+        &self.sources
+    }
+
+    /// Add an invoice's source references (e.g. from a checkpoint)
+    pub fn add_source(&mut self, source: SourceRefs) {
+        self.sources.push(source);
+    }
+
+    /// Invoices excluded from the report, in the order they were encountered
+    pub fn skipped(&self) -> &[SkippedInvoice] {
+        &self.skipped
+    }
+
+    /// Excluded invoices that couldn't be reported, leaving out those of
+    /// customers excluded on purpose
+    pub fn unreportable(&self) -> Vec<&SkippedInvoice> {
+        let deliberate: Vec<&str> = self
+            .diagnostics
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::CustomerExcluded)
+            .filter_map(|d| d.invoice_id.as_deref())
+            .collect();
+        self.skipped.iter().filter(|s| !deliberate.contains(&s.invoice_id.as_str())).collect()
+    }
+
+    /// Record the cash collected on an invoice in the period, for the cash basis
+    pub fn set_collected(&mut self, invoice_id: &str, collected: Collected) {
+        self.collected.insert(invoice_id.to_string(), collected);
+    }
+
+    /// Keep invoices that weren't processed in time, marking the report partial
+    pub fn set_pending(&mut self, pending: Vec<StripeInvoice>) {
+        self.pending = pending;
+    }
+
+    /// Invoices left unprocessed when the deadline was reached
+    pub fn pending(&self) -> &[StripeInvoice] {
+        &self.pending
+    }
+
+    pub fn options(&self) -> &ReportOptions {
+        &self.options
+    }
+
+    /// `Some` if the deadline cut processing short
+    pub fn partial(&self) -> Option<PartialReport> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(PartialReport {
+                processed: self.records.len() + self.skipped.len(),
+                remaining: self.pending.len(),
+            })
+        }
+    }
+
+    pub fn into_records(self) -> Vec<InvoiceRecord> {
+        self.records
+    }
+
     pub fn calculate_totals(&self) -> (i64, i64, i64, i64) {
         let mut total_licenses = 0i64;
         let mut total_tax = 0i64;
@@ -103,19 +764,39 @@ impl ReportGenerator {
     }
 }
 
-/// Format invoice date from Unix timestamp to MM/DD/YYYY
-fn format_invoice_date(timestamp: i64) -> Result<String> {
+/// Share of an invoice's total collected in the period, or `None` if all of it was
+fn collected_share(invoice: &StripeInvoice, collected: Collected) -> Option<f64> {
+    let total = invoice.total.unwrap_or(invoice.amount_due);
+    (total > 0 && collected.amount < total).then(|| collected.amount as f64 / total as f64)
+}
+
+/// Convert an amount in cents at `rate`, rounding to the nearest cent
+fn convert(cents: i64, rate: f64) -> i64 {
+    (cents as f64 * rate).round() as i64
+}
+
+/// The calendar date of a Unix timestamp in `timezone` (default UTC)
+fn invoice_date(timestamp: i64, timezone: Option<Tz>) -> Result<NaiveDate> {
     let datetime = chrono::DateTime::<Utc>::from_timestamp(timestamp, 0)
         .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp))?;
-    Ok(datetime.format("%m/%d/%Y").to_string())
+    Ok(match timezone {
+        Some(tz) => datetime.with_timezone(&tz).date_naive(),
+        None => datetime.date_naive(),
+    })
 }
 
-/// Extract customer name, with fallback
-fn extract_customer_name(invoice: &StripeInvoice) -> Result<String> {
-    if let Some(name) = &invoice.customer_name {
-        if !name.is_empty() {
-            return Ok(name.clone());
-        }
+/// Extract customer name, with fallback, under its alias if it has one
+fn extract_customer_name(invoice: &StripeInvoice, aliases: &CustomerAliases) -> Result<String> {
+    let name = raw_customer_name(invoice)?;
+    Ok(aliases.display_name(invoice.customer_id(), &name))
+}
+
+/// The customer name on the invoice, else the customer ID
+fn raw_customer_name(invoice: &StripeInvoice) -> Result<String> {
+    if let Some(name) = &invoice.customer_name
+        && !name.is_empty()
+    {
+        return Ok(name.clone());
     }
 
     // Customer can be a string ID or an expanded object
@@ -134,79 +815,56 @@ fn extract_customer_name(invoice: &StripeInvoice) -> Result<String> {
     }
 }
 
-/// Extract state from invoice, error if missing (strict validation)
-fn extract_state(invoice: &StripeInvoice) -> Result<String> {
-    if let Some(address) = &invoice.customer_address {
-        if let Some(state) = &address.state {
-            if !state.is_empty() {
-                return Ok(state.to_uppercase());
-            }
-        }
-    }
-
-    Err(anyhow!(
-        "Invoice {}: Customer state/billing address not found (strict validation required)",
-        invoice.id
-    ))
-}
-
-/// Extract state from customer object
-fn extract_state_from_customer(customer: &crate::stripe::client::Customer, invoice: &StripeInvoice) -> Result<String> {
-    if let Some(address) = &customer.address {
-        if let Some(state) = &address.state {
-            if !state.is_empty() {
-                return Ok(state.to_uppercase());
-            }
-        }
-    }
-
-    Err(anyhow!(
-        "Invoice {}: Customer {} has no state/billing address (strict validation required)",
-        invoice.id, customer.id
-    ))
-}
-
 /// Extract state with three-level fallback:
 /// 1. Customer address (if customer provided)
 /// 2. Credit card billing address (if charge provided)
 /// 3. Invoice customer address (if present)
 /// 4. Error if all three are missing
+///
+/// Returns the state and the address it came from. States are normalized to USPS codes ("Texas" → "TX"). An address whose
+/// country isn't the US yields `NON_US_REGION`; an unrecognized state moves
+/// on to the next address and is reported if no address has a valid one.
 fn extract_state_with_fallbacks(
     customer: Option<&crate::stripe::client::Customer>,
     charge: Option<&crate::stripe::client::Charge>,
     invoice: &StripeInvoice,
-) -> Result<String> {
-    // Try customer address first
-    if let Some(cust) = customer {
-        if let Some(address) = &cust.address {
-            if let Some(state) = &address.state {
-                if !state.is_empty() {
-                    return Ok(state.to_uppercase());
-                }
-            }
+) -> Result<(StateSource, String)> {
+    let addresses = [
+        (StateSource::CustomerAddress, customer.and_then(|c| c.address.as_ref())),
+        (
+            StateSource::CardBillingAddress,
+            charge.and_then(|c| c.billing_details.as_ref()).and_then(|b| b.address.as_ref()),
+        ),
+        (StateSource::InvoiceAddress, invoice.customer_address.as_ref()),
+    ];
+
+    let mut unrecognized = None;
+    for (source, address) in addresses {
+        let Some(address) = address else {
+            continue;
+        };
+        if !is_us_country(address.country.as_deref()) {
+            return Ok((source, NON_US_REGION.to_string()));
         }
-    }
 
-    // Try credit card billing address second
-    if let Some(chg) = charge {
-        if let Some(billing_details) = &chg.billing_details {
-            if let Some(address) = &billing_details.address {
-                if let Some(state) = &address.state {
-                    if !state.is_empty() {
-                        return Ok(state.to_uppercase());
-                    }
+        if let Some(state) = &address.state
+            && !state.trim().is_empty()
+        {
+            match normalize_state(state) {
+                Some(code) => return Ok((source, code.to_string())),
+                None => {
+                    unrecognized.get_or_insert(state.as_str());
                 }
             }
         }
     }
 
-    // Try invoice customer address third
-    if let Some(address) = &invoice.customer_address {
-        if let Some(state) = &address.state {
-            if !state.is_empty() {
-                return Ok(state.to_uppercase());
-            }
-        }
+    if let Some(state) = unrecognized {
+        return Err(anyhow!(
+            "Invoice {}: State '{}' is not a recognized USPS state code or name, and no other address has one",
+            invoice.id,
+            state
+        ));
     }
 
     // All three failed - error with comprehensive message
@@ -216,6 +874,114 @@ fn extract_state_with_fallbacks(
     ))
 }
 
+/// The first address (in fallback order) outside the US, if any
+fn foreign_address<'a>(
+    customer: Option<&'a crate::stripe::client::Customer>,
+    charge: Option<&'a crate::stripe::client::Charge>,
+    invoice: &'a StripeInvoice,
+) -> Option<&'a crate::stripe::client::Address> {
+    let addresses = [
+        customer.and_then(|c| c.address.as_ref()),
+        charge.and_then(|c| c.billing_details.as_ref()).and_then(|b| b.address.as_ref()),
+        invoice.customer_address.as_ref(),
+    ];
+    addresses.into_iter().flatten().find(|address| !is_us_country(address.country.as_deref()))
+}
+
+/// Infer the state from a ZIP code when no address has one, checking the
+/// addresses in the same order as `extract_state_with_fallbacks`. Addresses
+/// outside the US are ignored. Returns the state and the ZIP code used.
+fn infer_state_from_zip<'a>(
+    customer: Option<&'a crate::stripe::client::Customer>,
+    charge: Option<&'a crate::stripe::client::Charge>,
+    invoice: &'a StripeInvoice,
+) -> Option<(&'static str, &'a str)> {
+    let addresses = [
+        customer.and_then(|c| c.address.as_ref()),
+        charge.and_then(|c| c.billing_details.as_ref()).and_then(|b| b.address.as_ref()),
+        invoice.customer_address.as_ref(),
+    ];
+
+    addresses.into_iter().flatten().find_map(|address| {
+        let zip = address.postal_code.as_deref().filter(|_| is_us_country(address.country.as_deref()))?;
+        state_for_zip(zip).map(|state| (state, zip))
+    })
+}
+
+/// The address the state was taken from: the one `source` names, or for a
+/// ZIP code, the first US address (in fallback order) with one
+fn destination_address<'a>(
+    source: Option<StateSource>,
+    customer: Option<&'a crate::stripe::client::Customer>,
+    charge: Option<&'a crate::stripe::client::Charge>,
+    invoice: &'a StripeInvoice,
+) -> Option<&'a crate::stripe::client::Address> {
+    let customer_address = customer.and_then(|c| c.address.as_ref());
+    let card_address = charge.and_then(|c| c.billing_details.as_ref()).and_then(|b| b.address.as_ref());
+    match source? {
+        StateSource::CustomerAddress => customer_address,
+        StateSource::CardBillingAddress => card_address,
+        StateSource::InvoiceAddress => invoice.customer_address.as_ref(),
+        StateSource::ZipCode => [customer_address, card_address, invoice.customer_address.as_ref()]
+            .into_iter()
+            .flatten()
+            .find(|address| is_us_country(address.country.as_deref()) && address.postal_code.is_some()),
+    }
+}
+
+/// The ZIP code of the first US address (in fallback order) that has one
+fn destination_zip<'a>(
+    customer: Option<&'a crate::stripe::client::Customer>,
+    charge: Option<&'a crate::stripe::client::Charge>,
+    invoice: &'a StripeInvoice,
+) -> Option<&'a str> {
+    let addresses = [
+        customer.and_then(|c| c.address.as_ref()),
+        charge.and_then(|c| c.billing_details.as_ref()).and_then(|b| b.address.as_ref()),
+        invoice.customer_address.as_ref(),
+    ];
+    addresses
+        .into_iter()
+        .flatten()
+        .filter(|address| is_us_country(address.country.as_deref()))
+        .find_map(|address| address.postal_code.as_deref().filter(|zip| !zip.trim().is_empty()))
+}
+
+/// Where an invoice's state is taken from (`--state-source`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateSource {
+    CustomerAddress,
+    CardBillingAddress,
+    InvoiceAddress,
+    /// Inferred from the first US ZIP code in those addresses
+    ZipCode,
+}
+
+impl std::fmt::Display for StateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StateSource::CustomerAddress => "customer address",
+            StateSource::CardBillingAddress => "card billing address",
+            StateSource::InvoiceAddress => "invoice address",
+            StateSource::ZipCode => "ZIP code",
+        })
+    }
+}
+
+/// The state an invoice would be reported under and where it comes from,
+/// following the same fallbacks as `process_invoice_with_customer`;
+/// `None` when the invoice would be skipped (or reported as `UNKNOWN_STATE`).
+/// Addresses outside the US give `NON_US_REGION`.
+pub fn state_source(
+    customer: Option<&crate::stripe::client::Customer>,
+    charge: Option<&crate::stripe::client::Charge>,
+    invoice: &StripeInvoice,
+) -> Option<(StateSource, String)> {
+    extract_state_with_fallbacks(customer, charge, invoice).ok().or_else(|| {
+        infer_state_from_zip(customer, charge, invoice).map(|(state, _)| (StateSource::ZipCode, state.to_string()))
+    })
+}
 
 /// Sum all subscription line item quantities
 fn sum_subscription_quantities(invoice: &StripeInvoice) -> Result<u32> {
@@ -229,28 +995,126 @@ fn sum_subscription_quantities(invoice: &StripeInvoice) -> Result<u32> {
     Ok(total)
 }
 
-/// Sum all subscription line item amounts (in cents)
-fn sum_license_amounts(invoice: &StripeInvoice) -> Result<i64> {
+/// Whether a line's amount is reported as sales: subscription lines and
+/// one-off payments, plus one-off invoice items (setup fees, overages) when
+/// `include_invoiceitems`
+fn is_sales_line(line: &LineItem, include_invoiceitems: bool) -> bool {
+    line.line_type == "subscription"
+        || line.line_type == PAYMENT_LINE_TYPE
+        || (include_invoiceitems && line.line_type == "invoiceitem")
+}
+
+/// Sum all sales line item amounts (in cents)
+fn sum_license_amounts(invoice: &StripeInvoice, include_invoiceitems: bool) -> Result<i64> {
     let total: i64 = invoice
         .lines
         .data
         .iter()
-        .filter(|line| line.line_type == "subscription")
+        .filter(|line| is_sales_line(line, include_invoiceitems))
         .map(|line| line.amount)
         .sum();
     Ok(total)
 }
 
+/// Sum the sales line items that were taxed (cents), counting only the
+/// taxable share of lines a taxability rule covers.
+///
+/// A line counts as taxed when its `tax_amounts` are non-zero. Lines without
+/// `tax_amounts` (older invoices, manually set tax) follow the invoice: taxed
+/// if the invoice carries any tax.
+fn sum_taxable_amounts<'a>(
+    invoice: &StripeInvoice,
+    include_invoiceitems: bool,
+    line_rule: impl Fn(&LineItem) -> Option<&'a TaxabilityRule>,
+) -> i64 {
+    invoice
+        .lines
+        .data
+        .iter()
+        .filter(|line| is_sales_line(line, include_invoiceitems) && is_line_taxed(invoice, line))
+        .map(|line| line_rule(line).map_or(line.amount, |rule| rule.taxable_amount(line.amount)))
+        .sum()
+}
+
+/// Footnotes for the taxability rules that reduced any taxed line, in line order
+fn applied_rule_notes<'a>(
+    invoice: &StripeInvoice,
+    include_invoiceitems: bool,
+    line_rule: impl Fn(&LineItem) -> Option<&'a TaxabilityRule>,
+) -> Vec<String> {
+    let mut notes: Vec<String> = Vec::new();
+    for line in &invoice.lines.data {
+        if is_sales_line(line, include_invoiceitems)
+            && is_line_taxed(invoice, line)
+            && let Some(rule) = line_rule(line)
+            && !notes.contains(&rule.note())
+        {
+            notes.push(rule.note());
+        }
+    }
+    notes
+}
+
+fn is_line_taxed(invoice: &StripeInvoice, line: &LineItem) -> bool {
+    match &line.tax_amounts {
+        Some(amounts) => amounts.iter().any(|t| t.amount != 0),
+        None => invoice.tax.unwrap_or(0) != 0,
+    }
+}
+
+/// The invoice's sales lines, converted like the invoice's totals.
+/// Tax comes from each line's `tax_amounts`; tax that isn't broken down by
+/// line stays on the invoice record and is assigned when the lines are
+/// expanded into rows.
+fn invoice_lines<'a>(
+    invoice: &StripeInvoice,
+    include_invoiceitems: bool,
+    exempt: bool,
+    fx: Option<&FxConversion>,
+    line_rule: impl Fn(&LineItem) -> Option<&'a TaxabilityRule>,
+) -> Vec<InvoiceLine> {
+    let convert = |cents: i64| fx.map_or(cents, |fx| convert(cents, fx.rate));
+    invoice
+        .lines
+        .data
+        .iter()
+        .filter(|line| is_sales_line(line, include_invoiceitems))
+        .map(|line| {
+            let taxed = !exempt && is_line_taxed(invoice, line);
+            let taxable = line_rule(line).map_or(line.amount, |rule| rule.taxable_amount(line.amount));
+            let tax: i64 = line.tax_amounts.iter().flatten().map(|t| t.amount).sum();
+            InvoiceLine {
+                product: line.product_id().map(str::to_string),
+                product_name: None,
+                line_type: Some(line.line_type.clone()).filter(|line_type| !line_type.is_empty()),
+                description: line.description.clone(),
+                quantity: line.quantity.unwrap_or(0) as u32,
+                amount: convert(line.amount),
+                taxable_sales: if taxed { convert(taxable) } else { 0 },
+                tax: convert(tax),
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::stripe::client::{Address, Customer, Charge, BillingDetails};
 
     #[test]
-    fn test_format_date() {
-        // January 15, 2026 timestamp
-        let result = format_invoice_date(1768329600).unwrap();
-        assert!(result.contains("01") || result.contains("2026"));
+    fn test_invoice_date() {
+        // 2026-01-13 18:40 UTC
+        let result = invoice_date(1768329600, None).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2026, 1, 13).unwrap());
+
+        // 2024-09-30 20:00 CDT is already October 1 in UTC, but belongs to Q3
+        // in Chicago, as the period bounds do
+        let chicago = Some(chrono_tz::America::Chicago);
+        assert_eq!(invoice_date(1727744400, None).unwrap(), NaiveDate::from_ymd_opt(2024, 10, 1).unwrap());
+        assert_eq!(invoice_date(1727744400, chicago).unwrap(), NaiveDate::from_ymd_opt(2024, 9, 30).unwrap());
+        let (q3_start, q3_end) = crate::report::quarter::Period::new(2024, 3).unwrap().bounds(&chrono_tz::America::Chicago).unwrap();
+        assert!((q3_start..=q3_end).contains(&1727744400));
     }
 
     #[test]
@@ -276,6 +1140,7 @@ mod tests {
             tax: Some(4000),
             lines: crate::stripe::client::LineItems { data: vec![] },
             charge: None,
+            ..Default::default()
         };
 
         // Create a customer with address
@@ -290,9 +1155,10 @@ mod tests {
                 postal_code: Some("78701".to_string()),
                 state: Some("TX".to_string()),
             }),
+            ..Default::default()
         };
 
-        let state = extract_state_with_fallbacks(Some(&customer), None, &invoice).unwrap();
+        let (_, state) = extract_state_with_fallbacks(Some(&customer), None, &invoice).unwrap();
         assert_eq!(state, "TX");
     }
 
@@ -312,6 +1178,7 @@ mod tests {
             tax: Some(4000),
             lines: crate::stripe::client::LineItems { data: vec![] },
             charge: None,
+            ..Default::default()
         };
 
         // Create a customer with no address
@@ -319,12 +1186,18 @@ mod tests {
             id: "cus_456".to_string(),
             name: Some("Another Company".to_string()),
             address: None,
+            ..Default::default()
         };
 
         // Create a charge with billing details
         let charge = Charge {
             id: "ch_123".to_string(),
+            amount: 0,
+            amount_refunded: 0,
             balance_transaction: None,
+            payment_method_details: None,
+            invoice: None,
+            request_id: None,
             billing_details: Some(BillingDetails {
                 address: Some(Address {
                     city: Some("San Francisco".to_string()),
@@ -338,7 +1211,7 @@ mod tests {
         };
 
         let state = extract_state_with_fallbacks(Some(&customer), Some(&charge), &invoice).unwrap();
-        assert_eq!(state, "CA");
+        assert_eq!(state, (StateSource::CardBillingAddress, "CA".to_string()));
     }
 
     #[test]
@@ -364,6 +1237,7 @@ mod tests {
             tax: Some(4000),
             lines: crate::stripe::client::LineItems { data: vec![] },
             charge: None,
+            ..Default::default()
         };
 
         // Create a customer with no address
@@ -371,11 +1245,12 @@ mod tests {
             id: "cus_789".to_string(),
             name: Some("Third Company".to_string()),
             address: None,
+            ..Default::default()
         };
 
         // No charge with billing details
         let state = extract_state_with_fallbacks(Some(&customer), None, &invoice).unwrap();
-        assert_eq!(state, "NY");
+        assert_eq!(state, (StateSource::InvoiceAddress, "NY".to_string()));
     }
 
     #[test]
@@ -394,6 +1269,7 @@ mod tests {
             tax: Some(4000),
             lines: crate::stripe::client::LineItems { data: vec![] },
             charge: None,
+            ..Default::default()
         };
 
         // Customer with TX address
@@ -408,12 +1284,18 @@ mod tests {
                 postal_code: Some("77001".to_string()),
                 state: Some("TX".to_string()),
             }),
+            ..Default::default()
         };
 
         // Charge with CA billing address
         let charge = Charge {
             id: "ch_priority".to_string(),
+            amount: 0,
+            amount_refunded: 0,
             balance_transaction: None,
+            payment_method_details: None,
+            invoice: None,
+            request_id: None,
             billing_details: Some(BillingDetails {
                 address: Some(Address {
                     city: Some("Los Angeles".to_string()),
@@ -427,7 +1309,7 @@ mod tests {
         };
 
         // Should return TX (customer address) not CA (charge billing address)
-        let state = extract_state_with_fallbacks(Some(&customer), Some(&charge), &invoice).unwrap();
+        let (_, state) = extract_state_with_fallbacks(Some(&customer), Some(&charge), &invoice).unwrap();
         assert_eq!(state, "TX");
     }
 
@@ -447,6 +1329,7 @@ mod tests {
             tax: Some(4000),
             lines: crate::stripe::client::LineItems { data: vec![] },
             charge: None,
+            ..Default::default()
         };
 
         // Customer with no address
@@ -454,12 +1337,18 @@ mod tests {
             id: "cus_none".to_string(),
             name: Some("No Address Company".to_string()),
             address: None,
+            ..Default::default()
         };
 
         // Charge with no billing details
         let charge = Charge {
             id: "ch_none".to_string(),
+            amount: 0,
+            amount_refunded: 0,
             balance_transaction: None,
+            payment_method_details: None,
+            invoice: None,
+            request_id: None,
             billing_details: None,
         };
 
@@ -468,4 +1357,436 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("No state found"));
     }
+
+    #[test]
+    fn test_allow_missing_state_reports_unknown() {
+        let invoice = StripeInvoice {
+            id: "in_nostate".to_string(),
+            customer: serde_json::json!("cus_none"),
+            customer_name: Some("No Address Company".to_string()),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(0),
+            ..Default::default()
+        };
+
+        let mut strict = ReportGenerator::new();
+        assert!(strict.process_invoice(invoice.clone()).is_err());
+
+        let mut lenient = ReportGenerator::with_options(ReportOptions {
+            allow_missing_state: true,
+            ..Default::default()
+        });
+        lenient.process_invoice(invoice).unwrap();
+        assert_eq!(lenient.get_records()[0].state, UNKNOWN_STATE);
+    }
+
+    #[test]
+    fn test_state_inferred_from_zip() {
+        let invoice = StripeInvoice {
+            id: "in_zip".to_string(),
+            customer: serde_json::json!("cus_zip"),
+            customer_name: Some("Zip Only LLC".to_string()),
+            status: "paid".to_string(),
+            created: 1704067200,
+            ..Default::default()
+        };
+
+        let customer = Customer {
+            id: "cus_zip".to_string(),
+            name: Some("Zip Only LLC".to_string()),
+            address: Some(Address {
+                city: Some("Austin".to_string()),
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: Some("78701-1234".to_string()),
+                state: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice_with_customer(invoice, Some(&customer), None, None).unwrap();
+
+        let record = &generator.get_records()[0];
+        assert_eq!(record.state, "TX");
+        assert!(record.state_inferred);
+        assert_eq!(record.state_source, Some(StateSource::ZipCode));
+        assert_eq!(generator.diagnostics()[0].code, DiagnosticCode::StateInferred);
+    }
+
+    #[test]
+    fn test_non_us_address_reported_separately() {
+        let invoice = StripeInvoice {
+            id: "in_abroad".to_string(),
+            customer: serde_json::json!("cus_abroad"),
+            customer_name: Some("Abroad GmbH".to_string()),
+            customer_address: Some(Address {
+                city: Some("Berlin".to_string()),
+                country: Some("DE".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: Some("10115".to_string()),
+                state: None,
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice(invoice).unwrap();
+
+        // The German postal code must not be read as a New York ZIP code
+        let record = &generator.get_records()[0];
+        assert_eq!(record.country.as_deref(), Some("DE"));
+        assert_eq!(record.section(), "NON-US DE");
+        assert!(!record.state_inferred);
+        assert_eq!(generator.diagnostics()[0].code, DiagnosticCode::NonUsAddress);
+    }
+
+    #[test]
+    fn test_currency_kept_separate_or_converted() {
+        let invoice = StripeInvoice {
+            id: "in_eur".to_string(),
+            customer: serde_json::json!("cus_eur"),
+            customer_name: Some("Euro Buyer".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some("NY".to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(800),
+            currency: Some("eur".to_string()),
+            lines: crate::stripe::client::LineItems {
+                data: vec![crate::stripe::client::LineItem {
+                    id: "il_1".to_string(),
+                    line_type: "subscription".to_string(),
+                    amount: 10000,
+                    quantity: Some(1),
+                    tax_amounts: None,
+                    ..Default::default()
+                }],
+            },
+            ..Default::default()
+        };
+        let bt = crate::stripe::client::BalanceTransaction {
+            id: "txn_1".to_string(),
+            fee: 350,
+            currency: Some("usd".to_string()),
+            exchange_rate: Some(1.0825),
+            ..Default::default()
+        };
+
+        let mut separate = ReportGenerator::new();
+        separate.process_invoice_with_customer(invoice.clone(), None, None, Some(&bt)).unwrap();
+        let record = &separate.get_records()[0];
+        assert_eq!(record.currency.as_deref(), Some("EUR"));
+        assert_eq!((record.licenses, record.tax), (10000, 800));
+        assert_eq!(record.section(), "NY (EUR)");
+
+        let mut converted = ReportGenerator::with_options(ReportOptions {
+            convert_to_usd: true,
+            ..Default::default()
+        });
+        converted.process_invoice_with_customer(invoice.clone(), None, None, Some(&bt)).unwrap();
+        let record = &converted.get_records()[0];
+        assert_eq!(record.currency, None);
+        assert_eq!((record.licenses, record.tax, record.total, record.fees), (10825, 866, 11691, 350));
+        assert_eq!(record.fx, Some(FxConversion { currency: "EUR".to_string(), rate: 1.0825 }));
+
+        // No balance transaction: stays in EUR with a diagnostic
+        converted.process_invoice(invoice).unwrap();
+        assert_eq!(converted.get_records()[1].currency.as_deref(), Some("EUR"));
+        assert_eq!(converted.diagnostics()[0].code, DiagnosticCode::FxRateUnavailable);
+    }
+
+    #[test]
+    fn test_exempt_customer_sales() {
+        let invoice = StripeInvoice {
+            id: "in_exempt".to_string(),
+            customer: serde_json::json!("cus_school"),
+            customer_name: Some("Lincoln ISD".to_string()),
+            status: "paid".to_string(),
+            created: 1704067200,
+            lines: crate::stripe::client::LineItems {
+                data: vec![crate::stripe::client::LineItem {
+                    id: "il_1".to_string(),
+                    line_type: "subscription".to_string(),
+                    amount: 25000,
+                    quantity: Some(5),
+                    tax_amounts: None,
+                    ..Default::default()
+                }],
+            },
+            ..Default::default()
+        };
+        let customer: Customer = serde_json::from_value(serde_json::json!({
+            "id": "cus_school",
+            "address": {"state": "TX", "country": "US"},
+            "tax_exempt": "exempt",
+            "tax_ids": {"data": [{"type": "us_ein", "value": "74-1234567"}]}
+        }))
+        .unwrap();
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice_with_customer(invoice, Some(&customer), None, None).unwrap();
+
+        let record = &generator.get_records()[0];
+        assert_eq!((record.licenses, record.taxable_sales, record.non_taxable_sales), (25000, 0, 25000));
+        assert_eq!(record.tax_ids, vec!["us_ein 74-1234567"]);
+    }
+
+    #[test]
+    fn test_taxable_and_non_taxable_lines() {
+        use crate::stripe::client::{LineItem, TaxAmount};
+
+        let line = |id: &str, amount: i64, tax: Option<i64>| LineItem {
+            id: id.to_string(),
+            line_type: "subscription".to_string(),
+            amount,
+            quantity: Some(1),
+            tax_amounts: Some(tax.map(|amount| TaxAmount { amount, ..Default::default() }).into_iter().collect()),
+            ..Default::default()
+        };
+        let invoice = StripeInvoice {
+            id: "in_mixed".to_string(),
+            customer: serde_json::json!("cus_mixed"),
+            customer_name: Some("Mixed Co".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some("TX".to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(660),
+            lines: crate::stripe::client::LineItems {
+                data: vec![line("il_1", 10000, Some(660)), line("il_2", 4000, None)],
+            },
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice(invoice).unwrap();
+
+        let record = &generator.get_records()[0];
+        assert_eq!((record.licenses, record.taxable_sales, record.non_taxable_sales), (14000, 10000, 4000));
+    }
+
+    #[test]
+    fn test_texas_data_processing_rule() {
+        use crate::stripe::client::{LineItem, Price, TaxAmount};
+
+        let line = |product: &str, amount: i64, tax: i64| LineItem {
+            line_type: "subscription".to_string(),
+            amount,
+            quantity: Some(1),
+            tax_amounts: Some(vec![TaxAmount { amount: tax, ..Default::default() }]),
+            price: Some(Price { product: serde_json::json!(product), ..Default::default() }),
+            ..Default::default()
+        };
+        let invoice = StripeInvoice {
+            id: "in_hosting".to_string(),
+            customer: serde_json::json!("cus_hosting"),
+            customer_name: Some("Hosting Co".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some("TX".to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(1485),
+            lines: crate::stripe::client::LineItems {
+                data: vec![line("prod_hosting", 10000, 660), line("prod_seat", 10000, 825)],
+            },
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::with_options(ReportOptions {
+            line_items: true,
+            taxability: Taxability::new(
+                BTreeMap::from([("prod_hosting".to_string(), crate::report::taxability::DATA_PROCESSING.to_string())]),
+                &[],
+            ),
+            ..Default::default()
+        });
+        generator.process_invoice(invoice).unwrap();
+
+        let record = &generator.get_records()[0];
+        assert_eq!((record.licenses, record.taxable_sales, record.non_taxable_sales), (20000, 18000, 2000));
+        assert_eq!(record.lines[0].taxable_sales, 8000);
+        assert_eq!(record.adjustments.len(), 1);
+        assert_eq!(record.adjustments[0].columns, vec![AmountColumn::TaxableSales, AmountColumn::NonTaxableSales]);
+    }
+
+    #[test]
+    fn test_credit_basis() {
+        use crate::stripe::client::LineItem;
+
+        let invoice = StripeInvoice {
+            id: "in_credit".to_string(),
+            customer: serde_json::json!("cus_credit"),
+            customer_name: Some("Credit Co".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some("TX".to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(1650),
+            total: Some(21650),
+            amount_paid: 10825,
+            pre_payment_credit_notes_amount: 10825,
+            lines: crate::stripe::client::LineItems {
+                data: vec![LineItem {
+                    line_type: "subscription".to_string(),
+                    amount: 20000,
+                    quantity: Some(2),
+                    ..Default::default()
+                }],
+            },
+            ..Default::default()
+        };
+
+        let mut invoice_basis = ReportGenerator::new();
+        invoice_basis.process_invoice(invoice.clone()).unwrap();
+        assert_eq!(invoice_basis.get_records()[0].total, 21650);
+        assert_eq!(invoice_basis.diagnostics()[0].code, DiagnosticCode::CreditApplied);
+
+        let mut cash_basis = ReportGenerator::with_options(ReportOptions {
+            credit_basis: CreditBasis::Cash,
+            ..Default::default()
+        });
+        cash_basis.process_invoice(invoice).unwrap();
+        let record = &cash_basis.get_records()[0];
+        assert_eq!((record.licenses, record.taxable_sales, record.tax, record.total), (10000, 10000, 825, 10825));
+        assert_eq!(record.adjustments.len(), 1);
+        assert!(cash_basis.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_partial_refund_prorated() {
+        use crate::stripe::client::LineItem;
+
+        let invoice = StripeInvoice {
+            id: "in_refund".to_string(),
+            customer: serde_json::json!("cus_refund"),
+            customer_name: Some("Refund Co".to_string()),
+            customer_address: Some(Address { country: Some("US".to_string()), state: Some("TX".to_string()), ..Default::default() }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            tax: Some(1650),
+            total: Some(21650),
+            amount_paid: 21650,
+            lines: crate::stripe::client::LineItems {
+                data: vec![LineItem { line_type: "subscription".to_string(), amount: 20000, quantity: Some(2), ..Default::default() }],
+            },
+            ..Default::default()
+        };
+        let charge = Charge { id: "ch_refund".to_string(), amount: 21650, amount_refunded: 5412, ..Default::default() };
+
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice_with_customer(invoice.clone(), None, Some(&charge), None).unwrap();
+        let record = &generator.get_records()[0];
+        assert_eq!((record.licenses, record.tax, record.total), (15000, 1238, 16238));
+        assert!(record.refunded);
+        assert_eq!(generator.diagnostics()[0].code, DiagnosticCode::Refunded);
+        assert!(record.adjustments[0].note.starts_with("Reduced to the share not refunded"));
+
+        // A credit note issued after payment says what was refunded of the invoice
+        let credited = StripeInvoice { post_payment_credit_notes_amount: 10825, ..invoice };
+        let mut generator = ReportGenerator::new();
+        generator.process_invoice_with_customer(credited, None, Some(&charge), None).unwrap();
+        assert_eq!(generator.get_records()[0].total, 10825);
+    }
+
+    #[test]
+    fn test_include_invoiceitems() {
+        use crate::stripe::client::LineItem;
+
+        let line = |line_type: &str, amount: i64| LineItem {
+            line_type: line_type.to_string(),
+            amount,
+            quantity: Some(1),
+            ..Default::default()
+        };
+        let invoice = StripeInvoice {
+            id: "in_setup".to_string(),
+            customer: serde_json::json!("cus_setup"),
+            customer_name: Some("Setup Co".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some("TX".to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            lines: crate::stripe::client::LineItems {
+                data: vec![line("subscription", 10000), line("invoiceitem", 2500)],
+            },
+            ..Default::default()
+        };
+
+        let mut subscriptions_only = ReportGenerator::new();
+        subscriptions_only.process_invoice(invoice.clone()).unwrap();
+        assert_eq!(subscriptions_only.get_records()[0].licenses, 10000);
+
+        let mut with_items = ReportGenerator::with_options(ReportOptions {
+            include_invoiceitems: true,
+            ..Default::default()
+        });
+        with_items.process_invoice(invoice).unwrap();
+        let record = &with_items.get_records()[0];
+        assert_eq!((record.users, record.licenses, record.total), (1, 12500, 12500));
+    }
+
+    #[test]
+    fn test_state_names_normalized() {
+        let invoice = |id: &str, state: &str| StripeInvoice {
+            id: id.to_string(),
+            customer: serde_json::json!("cus_tx"),
+            customer_name: Some("Lone Star LLC".to_string()),
+            customer_address: Some(Address {
+                city: None,
+                country: Some("US".to_string()),
+                line1: None,
+                line2: None,
+                postal_code: None,
+                state: Some(state.to_string()),
+            }),
+            status: "paid".to_string(),
+            created: 1704067200,
+            ..Default::default()
+        };
+
+        let mut generator = ReportGenerator::new();
+        for (id, state) in [("in_1", "TX"), ("in_2", "Texas"), ("in_3", "texas "), ("in_4", "Tex.")] {
+            generator.process_invoice(invoice(id, state)).unwrap();
+        }
+        assert!(generator.get_records().iter().all(|r| r.state == "TX"));
+
+        let err = generator.process_invoice(invoice("in_5", "Narnia")).unwrap_err();
+        assert!(err.to_string().contains("'Narnia' is not a recognized USPS state"));
+    }
 }
@@ -0,0 +1,624 @@
+use crate::report::diagnostics::Diagnostic;
+use crate::report::discount::{FilingDiscount, TaxDue};
+use crate::report::formatter::{AccountMapping, Column, DateFormat, Letterhead, FEE_BREAKDOWN_COLUMNS};
+use crate::report::generator::{PartialReport, NON_US_REGION, UNKNOWN_STATE};
+use crate::report::products::ProductGroup;
+use crate::report::rates::effective_rate;
+use crate::stripe::models::{AmountColumn, FeeBreakdown, InvoiceRecord, SkippedInvoice};
+use chrono::Datelike;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Section of `--group-by plan` for records billing no subscription plan
+/// (one-off invoices and payments, merged sales)
+pub const NO_PLAN: &str = "(no plan)";
+
+/// How the report is broken down into sections (`--group-by`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupBy {
+    /// One section per state
+    #[default]
+    State,
+    /// One section per customer
+    Customer,
+    /// One section per calendar month of the record date
+    Month,
+    /// One section per billing country
+    Country,
+    /// One section per subscription plan
+    Plan,
+    /// The state sections, followed by revenue and tax subtotals per product
+    Product,
+}
+
+impl GroupBy {
+    /// Heading for the section column in summary tables
+    pub fn header(self) -> &'static str {
+        match self {
+            GroupBy::State | GroupBy::Product => "State",
+            GroupBy::Customer => "Customer",
+            GroupBy::Month => "Month",
+            GroupBy::Country => "Country",
+            GroupBy::Plan => "Plan",
+        }
+    }
+
+    /// The section a record belongs to, as (sort key, label). Amounts in
+    /// different currencies can't share a subtotal, so non-USD records get
+    /// their own section per currency.
+    fn section(self, record: &InvoiceRecord) -> (String, String) {
+        let label = match self {
+            GroupBy::State | GroupBy::Product => return (record.section(), record.section()),
+            GroupBy::Customer => record.customer.clone(),
+            GroupBy::Month => {
+                let currency = record.currency.as_deref().unwrap_or_default();
+                let key = format!("{} {}", record.date.format("%Y/%m"), currency);
+                return (key, currency_label(record.date.format("%m/%Y").to_string(), record));
+            }
+            GroupBy::Country => record.country.clone().unwrap_or_else(|| UNKNOWN_STATE.to_string()),
+            GroupBy::Plan => record.plan.clone().unwrap_or_else(|| NO_PLAN.to_string()),
+        };
+        let label = currency_label(label, record);
+        (label.clone(), label)
+    }
+}
+
+fn currency_label(label: String, record: &InvoiceRecord) -> String {
+    match &record.currency {
+        Some(currency) => format!("{} ({})", label, currency),
+        None => label,
+    }
+}
+
+/// Running totals of the money columns (all amounts in cents)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Totals {
+    pub licenses: i64,
+    pub taxable_sales: i64,
+    pub non_taxable_sales: i64,
+    pub tax: i64,
+    pub local_tax: i64,
+    pub total: i64,
+    pub fees: i64,
+    pub fee_breakdown: FeeBreakdown,
+}
+
+impl Totals {
+    pub fn add(&mut self, record: &InvoiceRecord) {
+        self.licenses += record.licenses;
+        self.taxable_sales += record.taxable_sales;
+        self.non_taxable_sales += record.non_taxable_sales;
+        self.tax += record.tax;
+        self.local_tax += record.local_tax;
+        self.total += record.total;
+        self.fees += record.fees;
+        self.fee_breakdown.add(&record.fee_breakdown);
+    }
+
+    /// Tax as a percentage of taxable sales, if anything was taxable
+    pub fn effective_rate(&self) -> Option<f64> {
+        effective_rate(self.tax, self.taxable_sales)
+    }
+
+    pub fn get(&self, column: AmountColumn) -> i64 {
+        match column {
+            AmountColumn::Licenses => self.licenses,
+            AmountColumn::TaxableSales => self.taxable_sales,
+            AmountColumn::NonTaxableSales => self.non_taxable_sales,
+            AmountColumn::Tax => self.tax,
+            AmountColumn::StateTax => self.tax - self.local_tax,
+            AmountColumn::LocalTax => self.local_tax,
+            AmountColumn::Total => self.total,
+            AmountColumn::Fees => self.fees,
+            AmountColumn::CardFees => self.fee_breakdown.card,
+            AmountColumn::AchFees => self.fee_breakdown.ach,
+            AmountColumn::StripeTaxFees => self.fee_breakdown.stripe_tax,
+            AmountColumn::OtherFees => self.fee_breakdown.other,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Totals) {
+        self.licenses += other.licenses;
+        self.taxable_sales += other.taxable_sales;
+        self.non_taxable_sales += other.non_taxable_sales;
+        self.tax += other.tax;
+        self.local_tax += other.local_tax;
+        self.total += other.total;
+        self.fees += other.fees;
+        self.fee_breakdown.add(&other.fee_breakdown);
+    }
+}
+
+/// One report section: every record for a single state (or customer, month
+/// or country, see `GroupBy`) plus its subtotal
+#[derive(Debug, Clone)]
+pub struct StateGroup<'a> {
+    /// Section label: the state, or the customer, month or country
+    pub state: String,
+    /// Currency of the section's amounts when not USD
+    pub currency: Option<String>,
+    pub records: Vec<&'a InvoiceRecord>,
+    pub subtotal: Totals,
+}
+
+impl StateGroup<'_> {
+    /// Subtotals per calendar month of the invoice date, in date order.
+    /// Months are labelled `MM/YYYY`.
+    pub fn by_month(&self) -> Vec<(String, Totals)> {
+        let mut months: BTreeMap<(i32, u32), Totals> = BTreeMap::new();
+        for record in &self.records {
+            months.entry((record.date.year(), record.date.month())).or_default().add(record);
+        }
+
+        months
+            .into_iter()
+            .map(|((year, month), totals)| (format!("{:02}/{}", month, year), totals))
+            .collect()
+    }
+
+    /// Subtotals per quarter (Q1 first) of the invoice date, for annual
+    /// reports, in fiscal years starting in month `fiscal_start` (1 for
+    /// calendar quarters)
+    pub fn by_quarter(&self, fiscal_start: u32) -> [Totals; 4] {
+        let fiscal_start = fiscal_start.clamp(1, 12);
+        let mut quarters: [Totals; 4] = Default::default();
+        for record in &self.records {
+            quarters[((record.date.month() + 12 - fiscal_start) % 12 / 3) as usize].add(record);
+        }
+        quarters
+    }
+}
+
+/// Records grouped into per-state sections (or per `GroupBy` section), with
+/// grand totals across all sections
+#[derive(Debug, Clone, Default)]
+pub struct GroupedReport<'a> {
+    /// Optional heading rendered above the report
+    pub title: Option<String>,
+    /// What the sections are
+    pub group_by: GroupBy,
+    pub groups: Vec<StateGroup<'a>>,
+    /// Totals across all USD sections
+    pub grand_total: Totals,
+    /// Totals across the sections in each other currency, which can't be
+    /// added to the USD grand total
+    pub currency_totals: BTreeMap<String, Totals>,
+    /// Invoices excluded from the report, listed after the grand total
+    pub skipped: &'a [SkippedInvoice],
+    /// Data-quality findings, included by machine-readable formats
+    pub diagnostics: &'a [Diagnostic],
+    /// Set when the time budget ran out before every invoice was processed
+    pub partial: Option<PartialReport>,
+    /// Add a month-by-month table (sales and tax) to each state section
+    pub monthly: bool,
+    /// Add a summary with each quarter's sales and tax per state (annual reports)
+    pub quarterly: bool,
+    /// Month the year starts in for the quarterly summary; calendar quarters
+    /// if 0 or 1
+    pub fiscal_start: u32,
+    /// Rows are invoice lines (`--detail line-items`) rather than invoices
+    pub line_items: bool,
+    /// Leave out the records and show only the subtotals (`--summary`)
+    pub summary: bool,
+    /// Split Fees into card, ACH, Stripe Tax and other fee columns
+    pub fee_breakdown: bool,
+    /// Follow Tax with State Tax and Local Tax columns (`--tax-breakdown`)
+    pub tax_breakdown: bool,
+    /// Distinct adjustment notes in order of first appearance; footnote N is
+    /// `footnotes[N - 1]`
+    pub footnotes: Vec<String>,
+    /// Subtotals per product (`--group-by product`), after the grand total
+    pub products: Vec<ProductGroup>,
+    /// Texas filing discount to show tax due after (`--texas-discount`)
+    pub discount: Option<FilingDiscount>,
+    /// How invoice dates are written (`--date-format`)
+    pub date_format: DateFormat,
+    /// Add Invoice Number and Invoice ID columns to the rows
+    /// (`--invoice-columns`); unset leaves it to the format, on for CSV and
+    /// off for TSV. JSON records always carry both.
+    pub invoice_columns: Option<bool>,
+    /// Row columns chosen with `--columns`, in order; empty for the default
+    /// layout
+    pub columns: Vec<Column>,
+    /// Add a State Source column after the state, naming the address (or
+    /// ZIP code) each row's state came from (`--state-source`)
+    pub state_source: bool,
+    /// Add a Plan column naming each row's subscription plan (`--plan`)
+    pub plan: bool,
+    /// Follow the money columns with the effective tax rate of each row,
+    /// subtotal and summary line (`--effective-rate`)
+    pub effective_rate: bool,
+    /// Accounts and tax rates for accounting import formats (profile `accounting`)
+    pub accounting: AccountMapping,
+    /// Company details heading PDF reports (profile `letterhead`)
+    pub letterhead: Letterhead,
+    /// The period reported on, as PDF reports print it
+    pub period: Option<String>,
+}
+
+impl<'a> GroupedReport<'a> {
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_skipped(mut self, skipped: &'a [SkippedInvoice]) -> Self {
+        self.skipped = skipped;
+        self
+    }
+
+    pub fn with_diagnostics(mut self, diagnostics: &'a [Diagnostic]) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    pub fn with_partial(mut self, partial: Option<PartialReport>) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    pub fn with_monthly(mut self, monthly: bool) -> Self {
+        self.monthly = monthly;
+        self
+    }
+
+    pub fn with_quarterly(mut self, quarterly: bool) -> Self {
+        self.quarterly = quarterly;
+        self
+    }
+
+    pub fn with_fiscal_start(mut self, fiscal_start: u32) -> Self {
+        self.fiscal_start = fiscal_start;
+        self
+    }
+
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    pub fn with_line_items(mut self, line_items: bool) -> Self {
+        self.line_items = line_items;
+        self
+    }
+
+    pub fn with_fee_breakdown(mut self, fee_breakdown: bool) -> Self {
+        self.fee_breakdown = fee_breakdown;
+        self
+    }
+
+    pub fn with_tax_breakdown(mut self, tax_breakdown: bool) -> Self {
+        self.tax_breakdown = tax_breakdown;
+        self
+    }
+
+    pub fn with_products(mut self, products: Vec<ProductGroup>) -> Self {
+        self.products = products;
+        self
+    }
+
+    pub fn with_date_format(mut self, date_format: DateFormat) -> Self {
+        self.date_format = date_format;
+        self
+    }
+
+    pub fn with_invoice_columns(mut self, invoice_columns: Option<bool>) -> Self {
+        self.invoice_columns = invoice_columns;
+        self
+    }
+
+    pub fn with_columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn with_state_source(mut self, state_source: bool) -> Self {
+        self.state_source = state_source;
+        self
+    }
+
+    pub fn with_plan(mut self, plan: bool) -> Self {
+        self.plan = plan;
+        self
+    }
+
+    pub fn with_effective_rate(mut self, effective_rate: bool) -> Self {
+        self.effective_rate = effective_rate;
+        self
+    }
+
+    pub fn with_accounting(mut self, accounting: AccountMapping) -> Self {
+        self.accounting = accounting;
+        self
+    }
+
+    pub fn with_letterhead(mut self, letterhead: Letterhead) -> Self {
+        self.letterhead = letterhead;
+        self
+    }
+
+    pub fn with_period(mut self, period: impl Into<String>) -> Self {
+        self.period = Some(period.into());
+        self
+    }
+
+    pub fn with_discount(mut self, discount: Option<FilingDiscount>) -> Self {
+        self.discount = discount;
+        self
+    }
+
+    /// Tax due per state after the filing discount, if one was asked for
+    pub fn tax_due(&self) -> Option<TaxDue> {
+        self.discount.map(|discount| TaxDue::new(self, discount))
+    }
+
+    /// The USD records with no state (`--allow-missing-state`) and their
+    /// subtotal, if any, whatever the report is grouped by
+    pub fn unknown_state(&self) -> Option<StateGroup<'a>> {
+        let records: Vec<&'a InvoiceRecord> = self
+            .groups
+            .iter()
+            .flat_map(|g| g.records.iter().copied())
+            .filter(|r| r.state == UNKNOWN_STATE && r.currency.is_none())
+            .collect();
+        if records.is_empty() {
+            return None;
+        }
+        let mut subtotal = Totals::default();
+        for record in &records {
+            subtotal.add(record);
+        }
+        Some(StateGroup { state: UNKNOWN_STATE.to_string(), currency: None, records, subtotal })
+    }
+
+    /// Whether any sales went untaxed, in which case tabular formats split
+    /// Licenses (gross sales) into Taxable Sales and Non-Taxable Sales columns
+    pub fn has_non_taxable_sales(&self) -> bool {
+        self.groups.iter().flat_map(|g| &g.records).any(|r| r.non_taxable_sales != 0)
+    }
+
+    /// Records whose amounts were converted to USD, for the exchange rate appendix
+    pub fn converted_records(&self) -> impl Iterator<Item = &'a InvoiceRecord> + '_ {
+        self.groups.iter().flat_map(|g| g.records.iter().copied()).filter(|r| r.fx.is_some())
+    }
+
+    /// Footnote markers for a record's cell, e.g. " [1][3]", or "" if the
+    /// cell wasn't adjusted
+    pub fn footnote_markers(&self, record: &InvoiceRecord, column: AmountColumn) -> String {
+        self.markers(std::slice::from_ref(&record), column)
+    }
+
+    /// Footnote markers for a section subtotal's cell: every footnote on
+    /// that column of any of its records
+    pub fn subtotal_footnote_markers(&self, group: &StateGroup<'_>, column: AmountColumn) -> String {
+        self.markers(&group.records, column)
+    }
+
+    fn markers(&self, records: &[&InvoiceRecord], column: AmountColumn) -> String {
+        let mut numbers: Vec<usize> = records
+            .iter()
+            .flat_map(|r| &r.adjustments)
+            .filter(|a| a.columns.contains(&column))
+            .filter_map(|a| self.footnotes.iter().position(|note| *note == a.note))
+            .map(|i| i + 1)
+            .collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+
+        if numbers.is_empty() {
+            return String::new();
+        }
+        let markers: String = numbers.iter().map(|n| format!("[{}]", n)).collect();
+        format!(" {}", markers)
+    }
+
+    /// Amount paid across all excluded invoices, in cents
+    pub fn skipped_amount(&self) -> i64 {
+        self.skipped.iter().map(|s| s.amount).sum()
+    }
+
+    /// Money columns in output order: those chosen with `--columns`, else
+    /// Licenses (split into taxable and non-taxable when some sales weren't
+    /// taxed), Tax, Total and Fees, followed by the fee breakdown if asked for
+    pub fn amount_columns(&self) -> Vec<AmountColumn> {
+        if !self.columns.is_empty() {
+            return self.columns.iter().filter_map(|c| c.amount()).collect();
+        }
+        let mut columns = vec![AmountColumn::Licenses];
+        if self.has_non_taxable_sales() {
+            columns.extend([AmountColumn::TaxableSales, AmountColumn::NonTaxableSales]);
+        }
+        columns.push(AmountColumn::Tax);
+        if self.tax_breakdown {
+            columns.extend([AmountColumn::StateTax, AmountColumn::LocalTax]);
+        }
+        columns.extend([AmountColumn::Total, AmountColumn::Fees]);
+        if self.fee_breakdown {
+            columns.extend(FEE_BREAKDOWN_COLUMNS);
+        }
+        columns
+    }
+
+    /// Columns of the invoice rows: those chosen with `--columns`, else Date
+    /// and Customer, then State, Account, the invoice and line-item columns
+    /// as the report calls for, Users and the money columns
+    pub fn row_columns(&self, invoice_columns: bool) -> Vec<Column> {
+        if !self.columns.is_empty() {
+            return self.columns.clone();
+        }
+        let mut columns = vec![Column::Date, Column::Customer];
+        if self.has_state_column() {
+            columns.push(Column::State);
+        }
+        if self.state_source {
+            columns.push(Column::StateSource);
+        }
+        if self.has_accounts() {
+            columns.push(Column::Account);
+        }
+        if self.plan && self.group_by != GroupBy::Plan {
+            columns.push(Column::Plan);
+        }
+        if invoice_columns {
+            columns.extend([Column::InvoiceNumber, Column::InvoiceId]);
+        }
+        if self.line_items {
+            columns.extend([Column::Product, Column::Description, Column::Quantity]);
+        } else {
+            columns.push(Column::Users);
+        }
+        columns.extend(self.amount_columns().into_iter().map(Column::from));
+        if self.effective_rate {
+            columns.push(Column::EffectiveRate);
+        }
+        columns
+    }
+
+    /// Whether rows need a State column, because the sections aren't states
+    pub fn has_state_column(&self) -> bool {
+        !matches!(self.group_by, GroupBy::State | GroupBy::Product)
+    }
+
+    /// Whether any record came from a connected account, in which case
+    /// tabular formats add an Account column
+    pub fn has_accounts(&self) -> bool {
+        self.groups
+            .iter()
+            .flat_map(|g| &g.records)
+            .any(|r| r.account.is_some())
+    }
+
+    /// Group records by state section (see `InvoiceRecord::section`)
+    pub fn by_state(records: &'a [InvoiceRecord]) -> Self {
+        Self::by(GroupBy::State, records)
+    }
+
+    /// Group records into `group_by` sections. States, customers and
+    /// countries are ordered alphabetically and months chronologically, with
+    /// the per-country NON-US sections and UNKNOWN last; records keep their
+    /// incoming order within each section.
+    pub fn by(group_by: GroupBy, records: &'a [InvoiceRecord]) -> Self {
+        // BTreeMap keeps sections sorted
+        let mut grouped: BTreeMap<(String, String), Vec<&InvoiceRecord>> = BTreeMap::new();
+        for record in records {
+            grouped.entry(group_by.section(record)).or_default().push(record);
+        }
+
+        let mut grouped: Vec<_> = grouped.into_iter().map(|((_, label), records)| (label, records)).collect();
+        grouped.sort_by_key(|(state, _)| {
+            let unknown = state.starts_with(UNKNOWN_STATE);
+            (state.starts_with(NON_US_REGION) || unknown, unknown)
+        });
+
+        let mut report = GroupedReport { group_by, ..Default::default() };
+        for (state, state_records) in grouped {
+            let mut subtotal = Totals::default();
+            for record in &state_records {
+                subtotal.add(record);
+            }
+            let currency = state_records[0].currency.clone();
+            match &currency {
+                Some(currency) => report.currency_totals.entry(currency.clone()).or_default().merge(&subtotal),
+                None => report.grand_total.merge(&subtotal),
+            }
+            report.groups.push(StateGroup {
+                state,
+                currency,
+                records: state_records,
+                subtotal,
+            });
+        }
+
+        // Number footnotes in the order they first appear in the report
+        for record in report.groups.iter().flat_map(|g| &g.records) {
+            for adjustment in &record.adjustments {
+                if !report.footnotes.contains(&adjustment.note) {
+                    report.footnotes.push(adjustment.note.clone());
+                }
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(state: &str, licenses: i64, tax: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+            customer: format!("{} Company", state),
+            users: 1,
+            state: state.to_string(),
+            licenses,
+            tax,
+            total: licenses + tax,
+            fees: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_group_by_state_subtotals() {
+        let records = vec![record("TX", 50000, 4000), record("CA", 30000, 2000), record("TX", 10000, 800)];
+        let report = GroupedReport::by_state(&records);
+
+        let states: Vec<&str> = report.groups.iter().map(|g| g.state.as_str()).collect();
+        assert_eq!(states, vec!["CA", "TX"]);
+
+        assert_eq!(report.groups[1].records.len(), 2);
+        assert_eq!(report.groups[1].subtotal, Totals { licenses: 60000, tax: 4800, total: 64800, fees: 200, ..Default::default() });
+        assert_eq!(report.grand_total, Totals { licenses: 90000, tax: 6800, total: 96800, fees: 300, ..Default::default() });
+    }
+
+    #[test]
+    fn test_country_and_unknown_sections_last() {
+        let records = vec![
+            record(UNKNOWN_STATE, 100, 0),
+            record("WY", 100, 0),
+            InvoiceRecord { country: Some("GB".to_string()), ..record("", 100, 0) },
+            // Ontario, Canada must not be confused with California
+            InvoiceRecord { country: Some("CA".to_string()), ..record("ON", 100, 0) },
+            InvoiceRecord { country: Some("US".to_string()), ..record("CA", 100, 0) },
+            record("AK", 100, 0),
+        ];
+        let report = GroupedReport::by_state(&records);
+
+        let states: Vec<&str> = report.groups.iter().map(|g| g.state.as_str()).collect();
+        assert_eq!(states, vec!["AK", "CA", "WY", "NON-US CA", "NON-US GB", UNKNOWN_STATE]);
+    }
+
+    #[test]
+    fn test_group_by_customer_month_and_country() {
+        let dated = |date: &str, state: &str, customer: &str| InvoiceRecord {
+            date: chrono::NaiveDate::parse_from_str(date, "%m/%d/%Y").unwrap(),
+            customer: customer.to_string(),
+            country: Some("US".to_string()),
+            ..record(state, 100, 8)
+        };
+        let records = vec![
+            dated("01/20/2026", "TX", "Zeta"),
+            dated("12/05/2025", "CA", "Acme"),
+            InvoiceRecord { country: Some("GB".to_string()), ..dated("12/01/2025", "", "Acme") },
+            dated("01/02/2026", UNKNOWN_STATE, "Zeta"),
+        ];
+        let labels = |group_by| -> Vec<String> {
+            GroupedReport::by(group_by, &records).groups.iter().map(|g| g.state.clone()).collect()
+        };
+
+        assert_eq!(labels(GroupBy::Customer), vec!["Acme", "Zeta"]);
+        assert_eq!(labels(GroupBy::Month), vec!["12/2025", "01/2026"]);
+        assert_eq!(labels(GroupBy::Country), vec!["GB", "US"]);
+
+        let by_customer = GroupedReport::by(GroupBy::Customer, &records);
+        assert_eq!(by_customer.groups[1].subtotal.licenses, 200);
+        assert_eq!(by_customer.grand_total.licenses, 400);
+        let unknown = by_customer.unknown_state().unwrap();
+        assert_eq!((unknown.records.len(), unknown.subtotal.total), (1, 108));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::stripe::client::StripeInvoice;
+use crate::stripe::models::JurisdictionTax;
+
+/// An invoice's tax by jurisdiction, from `total_tax_amounts` with their
+/// tax rates expanded. Amounts for the same jurisdiction are combined; tax
+/// whose rate wasn't expanded is listed under the rate ID with no level.
+pub fn tax_jurisdictions(invoice: &StripeInvoice) -> Vec<JurisdictionTax> {
+    let mut jurisdictions: Vec<JurisdictionTax> = Vec::new();
+    for tax_amount in invoice.total_tax_amounts.iter().filter(|t| t.amount != 0) {
+        let rate = tax_amount.tax_rate();
+        let jurisdiction = rate
+            .as_ref()
+            .and_then(|r| r.jurisdiction.clone().or_else(|| r.display_name.clone()))
+            .or_else(|| tax_amount.tax_rate.as_str().map(str::to_string))
+            .unwrap_or_else(|| "Unknown".to_string());
+        let level = rate.as_ref().and_then(|r| r.jurisdiction_level.clone());
+        match jurisdictions.iter_mut().find(|j| j.jurisdiction == jurisdiction && j.level == level) {
+            Some(existing) => existing.amount += tax_amount.amount,
+            None => jurisdictions.push(JurisdictionTax {
+                jurisdiction,
+                level,
+                percentage: rate.and_then(|r| r.percentage),
+                amount: tax_amount.amount,
+            }),
+        }
+    }
+    jurisdictions
+}
+
+/// The local share of `tax` (cents, after any conversion or scaling), in
+/// proportion to the local jurisdictions' part of the invoice's tax
+pub fn local_share(jurisdictions: &[JurisdictionTax], tax: i64) -> i64 {
+    let total: i64 = jurisdictions.iter().map(|j| j.amount).sum();
+    let local: i64 = jurisdictions.iter().filter(|j| j.is_local()).map(|j| j.amount).sum();
+    if total == 0 {
+        return 0;
+    }
+    (tax as f64 * local as f64 / total as f64).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::client::TaxAmount;
+
+    #[test]
+    fn test_tax_split_by_jurisdiction() {
+        let amount = |amount: i64, tax_rate: serde_json::Value| TaxAmount { amount, tax_rate };
+        let invoice = StripeInvoice {
+            total_tax_amounts: vec![
+                amount(625, serde_json::json!({"id": "txr_1", "jurisdiction": "TEXAS", "jurisdiction_level": "state", "percentage": 6.25})),
+                amount(100, serde_json::json!({"id": "txr_2", "jurisdiction": "AUSTIN", "jurisdiction_level": "city", "percentage": 1.0})),
+                amount(100, serde_json::json!({"id": "txr_3", "jurisdiction": "AUSTIN MTA", "jurisdiction_level": "district", "percentage": 1.0})),
+                amount(0, serde_json::json!({"id": "txr_4", "jurisdiction": "TRAVIS", "jurisdiction_level": "county"})),
+            ],
+            ..Default::default()
+        };
+
+        let jurisdictions = tax_jurisdictions(&invoice);
+        let names: Vec<&str> = jurisdictions.iter().map(|j| j.jurisdiction.as_str()).collect();
+        assert_eq!(names, vec!["TEXAS", "AUSTIN", "AUSTIN MTA"]);
+        assert_eq!(jurisdictions[0].percentage, Some(6.25));
+        assert_eq!(local_share(&jurisdictions, 825), 200);
+        // Scaled to a refund or currency conversion
+        assert_eq!(local_share(&jurisdictions, -413), -100);
+
+        let unexpanded = StripeInvoice { total_tax_amounts: vec![amount(825, serde_json::json!("txr_1"))], ..Default::default() };
+        let jurisdictions = tax_jurisdictions(&unexpanded);
+        assert_eq!((jurisdictions[0].jurisdiction.as_str(), jurisdictions[0].level.as_deref()), ("txr_1", None));
+        assert_eq!(local_share(&jurisdictions, 825), 0);
+        assert_eq!(local_share(&[], 825), 0);
+    }
+}
@@ -1,7 +1,91 @@
 pub mod quarter;
 pub mod generator;
+pub mod addresses;
+pub mod aliases;
+pub mod amend;
+pub mod anonymize;
+pub mod archive;
+pub mod audit;
+pub mod basis;
+pub mod calendar;
+pub mod detail;
+pub mod diagnostics;
+pub mod discount;
+pub mod exclusions;
+pub mod external;
+pub mod fees;
+pub mod grouping;
+pub mod jurisdictions;
 pub mod formatter;
+pub mod pipeline;
+pub mod products;
+pub mod rates;
+pub mod registrations;
+pub mod report_run;
+pub mod checkpoint;
+pub mod compare;
+pub mod progress;
+pub mod review;
+pub mod rollup;
+pub mod schedule;
+pub mod server;
+pub mod split;
+pub mod statement;
+pub mod taxability;
+pub mod output;
+pub mod payments;
+pub mod payouts;
+#[cfg(feature = "pdf")]
+pub(crate) mod pdf;
+pub mod states;
+pub mod template;
+pub mod validate;
+pub mod zip;
 
-pub use quarter::get_previous_quarter;
-pub use generator::ReportGenerator;
-pub use formatter::format_as_tsv;
+pub use quarter::{
+    deadlines, due_date, get_previous_fiscal_quarter_from, get_previous_quarter, get_previous_quarter_from, period_bounds,
+    period_label, Deadline, Period, PeriodLength,
+};
+pub use diagnostics::{Diagnostic, DiagnosticCode, Severity};
+pub use discount::{FilingDiscount, TaxDue};
+pub use external::ExternalSales;
+pub use exclusions::{load_exclude_list, parse_metadata_filter, CustomerExclusions};
+pub use addresses::{
+    find_address_problems, format_address, parse_corrections, prompt_corrections, AddressAudit, AddressCorrection,
+    AddressProblem,
+};
+pub use aliases::{load_aliases, CustomerAliases};
+pub use amend::{Amendment, JurisdictionAmendment};
+pub use anonymize::Anonymizer;
+pub use archive::{Archive, ArchiveManifest, Drift, InvoiceChange};
+pub use audit::{audit_entries, write_audit_file, AuditEntry, SourceRefs};
+pub use basis::{Basis, CreditBasis};
+pub use calendar::FilingCalendar;
+pub use checkpoint::Checkpoint;
+pub use compare::{load_section_totals, section_totals, CompareFormat, Comparison, StateChange};
+pub use detail::Detail;
+pub use generator::{state_source, PartialReport, ReportGenerator, ReportOptions, StateSource, NON_US_REGION, UNKNOWN_STATE};
+pub use grouping::{GroupBy, GroupedReport, Totals};
+pub use formatter::{
+    check_columns, format_as_tsv, write_report_async, write_tsv, AccountMapping, Column, DateFormat, Letterhead,
+    OutputFormat, ReportFormatter, TemplateFormatter,
+};
+pub use output::OutputSink;
+pub use payments::{one_off_payments, OneOffPayment, RecordSource};
+pub use payouts::{build_payout_report, PayoutFormat, PayoutReport};
+pub use products::{by_product, ProductGroup};
+pub use rates::RateTable;
+pub use registrations::{RegistrationCheck, RegistrationStatus};
+pub use report_run::build_report_from_run;
+pub use progress::ReportProgress;
+pub use review::{run_review, ReviewDecisions, ReviewOutcome};
+pub use rollup::{Rollup, PARENT_METADATA_KEY};
+pub use schedule::CronSchedule;
+pub use split::write_split_by_state;
+pub use statement::{Statement, StatementFormat};
+pub use states::normalize_state;
+pub use taxability::{Taxability, TaxabilityRule};
+pub use template::TemplateContext;
+pub use validate::{Check, Validation};
+pub use zip::state_for_zip;
+pub use pipeline::{build_connected_report, build_report, resume_report, tag_report_period, taggable_invoices};
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::path::Path;
+
+/// Destination for a rendered report.
+///
+/// Formatters write into the sink row by row, so memory use doesn't grow with
+/// report size. Paths ending in `.gz` are gzip-compressed on the fly.
+pub enum OutputSink {
+    Stdout(BufWriter<Stdout>),
+    File(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl OutputSink {
+    /// Open a file at `path`, or stdout when no path (or `-`) is given
+    pub fn create(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) if path != Path::new("-") => path,
+            _ => return Ok(OutputSink::Stdout(BufWriter::new(io::stdout()))),
+        };
+
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let writer = BufWriter::new(file);
+
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            Ok(OutputSink::Gzip(GzEncoder::new(writer, Compression::default())))
+        } else {
+            Ok(OutputSink::File(writer))
+        }
+    }
+
+    /// Flush buffered output (and write the gzip trailer, if compressing)
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputSink::Stdout(mut w) => w.flush()?,
+            OutputSink::File(mut w) => w.flush()?,
+            OutputSink::Gzip(w) => w.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputSink::Stdout(w) => w.write(buf),
+            OutputSink::File(w) => w.write(buf),
+            OutputSink::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputSink::Stdout(w) => w.flush(),
+            OutputSink::File(w) => w.flush(),
+            OutputSink::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_gzip_output_round_trip() {
+        let path = std::env::temp_dir().join(format!("str-output-{}.tsv.gz", std::process::id()));
+
+        let mut sink = OutputSink::create(Some(&path)).unwrap();
+        assert!(matches!(sink, OutputSink::Gzip(_)));
+        sink.write_all(b"GRAND TOTAL\t\t\t0.00\n").unwrap();
+        sink.finish().unwrap();
+
+        let mut decoded = String::new();
+        GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded, "GRAND TOTAL\t\t\t0.00\n");
+    }
+}
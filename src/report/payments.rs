@@ -0,0 +1,188 @@
+use crate::stripe::client::{Address, CheckoutSession, LineItem, LineItems, PaymentIntent, StripeInvoice, TaxAmount};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Line type given to a one-off payment's single sales line
+pub const PAYMENT_LINE_TYPE: &str = "payment";
+
+/// The Stripe object (or outside system) a record was built from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordSource {
+    #[default]
+    Invoice,
+    CheckoutSession,
+    PaymentIntent,
+    /// A sale merged from `--merge-csv`
+    External,
+    /// A row of a Stripe report run (`--report-run`)
+    ReportRun,
+    /// A lost dispute reversing an invoice's sale; it keeps the invoice's ID
+    Chargeback,
+}
+
+impl RecordSource {
+    pub fn is_invoice(&self) -> bool {
+        *self == RecordSource::Invoice
+    }
+}
+
+/// A sale paid outside invoicing: a Checkout Session in payment mode or a
+/// standalone PaymentIntent (`--include-payments`)
+#[derive(Debug, Clone)]
+pub struct OneOffPayment {
+    pub id: String,
+    pub source: RecordSource,
+    pub created: i64,
+    pub customer: Option<String>,
+    /// Name (or email) collected at checkout
+    pub customer_name: Option<String>,
+    pub address: Option<Address>,
+    /// Amount collected, including tax (cents)
+    pub amount: i64,
+    pub tax: i64,
+    pub currency: Option<String>,
+    pub charge: Option<String>,
+    pub description: Option<String>,
+}
+
+impl OneOffPayment {
+    /// The payment as a paid invoice with one sales line, so it goes
+    /// through the same sourcing, currency and taxability rules as invoices
+    pub fn as_invoice(&self) -> StripeInvoice {
+        let line = LineItem {
+            id: self.id.clone(),
+            line_type: PAYMENT_LINE_TYPE.to_string(),
+            amount: self.amount - self.tax,
+            tax_amounts: Some(vec![TaxAmount { amount: self.tax, ..Default::default() }]),
+            description: self.description.clone(),
+            ..Default::default()
+        };
+        StripeInvoice {
+            id: self.id.clone(),
+            customer: self.customer.clone().map(serde_json::Value::String).unwrap_or_default(),
+            customer_name: self.customer_name.clone(),
+            customer_address: self.address.clone(),
+            status: "paid".to_string(),
+            created: self.created,
+            paid_at: Some(self.created),
+            finalized_at: Some(self.created),
+            amount_due: self.amount,
+            amount_paid: self.amount,
+            tax: Some(self.tax),
+            total: Some(self.amount),
+            lines: LineItems { data: vec![line] },
+            charge: self.charge.clone().map(serde_json::Value::String),
+            currency: self.currency.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Paid sales in the period that no invoice covers: paid Checkout Sessions
+/// without an invoice, then succeeded PaymentIntents that neither paid an
+/// invoice nor belong to one of those sessions. Sessions take the charge of
+/// their PaymentIntent.
+pub fn one_off_payments(sessions: &[CheckoutSession], intents: &[PaymentIntent]) -> Vec<OneOffPayment> {
+    let charge_of = |intent_id: &str| intents.iter().find(|i| i.id == intent_id).and_then(|i| i.latest_charge.clone());
+    let mut claimed = BTreeSet::new();
+    let mut payments = Vec::new();
+    for session in sessions {
+        if let Some(intent) = &session.payment_intent {
+            claimed.insert(intent.as_str());
+        }
+        if session.invoice.is_some() || session.payment_status != "paid" {
+            continue;
+        }
+        let details = session.customer_details.as_ref();
+        payments.push(OneOffPayment {
+            id: session.id.clone(),
+            source: RecordSource::CheckoutSession,
+            created: session.created,
+            customer: session.customer.clone(),
+            customer_name: details.and_then(|d| d.name.clone().or_else(|| d.email.clone())),
+            address: details.and_then(|d| d.address.clone()),
+            amount: session.amount_total.unwrap_or(0),
+            tax: session.total_details.as_ref().map_or(0, |t| t.amount_tax),
+            currency: session.currency.clone(),
+            charge: session.payment_intent.as_deref().and_then(charge_of),
+            description: None,
+        });
+    }
+    for intent in intents {
+        if intent.status != "succeeded" || intent.invoice.is_some() || claimed.contains(intent.id.as_str()) {
+            continue;
+        }
+        payments.push(OneOffPayment {
+            id: intent.id.clone(),
+            source: RecordSource::PaymentIntent,
+            created: intent.created,
+            customer: intent.customer.clone(),
+            customer_name: None,
+            address: None,
+            amount: intent.amount_received,
+            tax: 0,
+            currency: intent.currency.clone(),
+            charge: intent.latest_charge.clone(),
+            description: intent.description.clone(),
+        });
+    }
+    payments.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+    payments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::client::{CustomerDetails, TotalDetails};
+
+    #[test]
+    fn test_one_off_payments_skip_invoiced_and_session_intents() {
+        let sessions = vec![
+            CheckoutSession {
+                id: "cs_1".to_string(),
+                created: 1728000000,
+                customer_details: Some(CustomerDetails {
+                    name: Some("Walk-in Buyer".to_string()),
+                    address: Some(Address { state: Some("TX".to_string()), ..Default::default() }),
+                    ..Default::default()
+                }),
+                amount_total: Some(10825),
+                total_details: Some(TotalDetails { amount_tax: 825, ..Default::default() }),
+                mode: "payment".to_string(),
+                payment_status: "paid".to_string(),
+                payment_intent: Some("pi_1".to_string()),
+                ..Default::default()
+            },
+            CheckoutSession {
+                id: "cs_sub".to_string(),
+                mode: "subscription".to_string(),
+                payment_status: "paid".to_string(),
+                invoice: Some("in_1".to_string()),
+                payment_intent: Some("pi_2".to_string()),
+                ..Default::default()
+            },
+        ];
+        let intent = |id: &str, invoice: Option<&str>| PaymentIntent {
+            id: id.to_string(),
+            created: 1728000100,
+            amount_received: 5000,
+            status: "succeeded".to_string(),
+            customer: Some("cus_1".to_string()),
+            invoice: invoice.map(str::to_string),
+            latest_charge: Some(format!("ch_{}", id)),
+            ..Default::default()
+        };
+        let intents = vec![intent("pi_1", None), intent("pi_2", Some("in_1")), intent("pi_3", None)];
+
+        let payments = one_off_payments(&sessions, &intents);
+        let ids: Vec<&str> = payments.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["cs_1", "pi_3"]);
+        assert_eq!(payments[0].charge.as_deref(), Some("ch_pi_1"));
+        assert_eq!(payments[1].source, RecordSource::PaymentIntent);
+
+        let invoice = payments[0].as_invoice();
+        assert_eq!(invoice.lines.data[0].amount, 10000);
+        assert_eq!((invoice.tax, invoice.customer_name.as_deref()), (Some(825), Some("Walk-in Buyer")));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::report::formatter::csv_escape;
+use crate::report::progress::ReportProgress;
+use crate::source::InvoiceSource;
+use crate::stripe::client::{BalanceTransaction, Payout};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Output formats for the payout reconciliation (`payouts --format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PayoutFormat {
+    /// One tab-separated section per payout, then the invoice index
+    #[default]
+    Tsv,
+    /// Same layout as TSV, comma-separated
+    Csv,
+    /// The reconciliation as a JSON document
+    Json,
+}
+
+/// One balance transaction settled by a payout
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PayoutTransaction {
+    pub id: String,
+    /// Date the transaction was created (MM/DD/YYYY)
+    pub date: String,
+    /// "charge", "refund", "adjustment", ...
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    /// Charge, refund or other object behind the transaction
+    pub source: Option<String>,
+    /// Invoice the charge paid, if any
+    pub invoice: Option<String>,
+    pub amount: i64,
+    pub fee: i64,
+    pub net: i64,
+}
+
+/// A payout and the balance transactions it deposited
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PayoutEntry {
+    pub id: String,
+    /// Date the payout reached (or is due to reach) the bank (MM/DD/YYYY)
+    pub arrival_date: String,
+    pub status: String,
+    /// Uppercase ISO currency code
+    pub currency: String,
+    pub amount: i64,
+    pub transactions: Vec<PayoutTransaction>,
+    /// Payout amount less the transactions' net; anything but 0 means the
+    /// payout doesn't tie out to the transactions listed
+    pub difference: i64,
+    /// Why transactions are missing, if they are
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// An invoice and the payout its payment was deposited in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoicePayout {
+    pub invoice: String,
+    pub payout: String,
+    pub arrival_date: String,
+}
+
+/// Payouts arriving in a period, each with the balance transactions it
+/// settled, for reconciling the tax report against bank deposits
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PayoutReport {
+    pub payouts: Vec<PayoutEntry>,
+    /// Invoices paid by charges in the payouts, in payout order
+    pub invoices: Vec<InvoicePayout>,
+}
+
+/// Fetch payouts arriving in a period (Unix timestamps, inclusive) and the
+/// balance transactions each settled, tracing charges back to their invoices.
+///
+/// Manual payouts don't record which transactions they settled, and a
+/// payout whose transactions can't be fetched is kept with a note, so every
+/// deposit still appears.
+pub async fn build_payout_report<S: InvoiceSource>(
+    source: &S,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<PayoutReport> {
+    let payouts = source.fetch_payouts(start, end).await?;
+    progress.status(&format!("Retrieved {} payouts", payouts.len()));
+
+    let mut entries = Vec::with_capacity(payouts.len());
+    for payout in payouts {
+        let (transactions, note) = if !payout.automatic {
+            (Vec::new(), Some("Manual payout; Stripe doesn't list the transactions it settled".to_string()))
+        } else {
+            match source.fetch_payout_balance_transactions(&payout.id).await {
+                Ok(transactions) => (payout_transactions(source, transactions, progress).await?, None),
+                Err(e) => {
+                    progress.warn(&format!("Payout {}: transactions unavailable: {}", payout.id, e));
+                    (Vec::new(), Some(format!("Transactions unavailable: {}", e)))
+                }
+            }
+        };
+        entries.push(payout_entry(payout, transactions, note)?);
+    }
+
+    Ok(PayoutReport::new(entries))
+}
+
+/// Rows for a payout's transactions, leaving out the payout's own transaction
+async fn payout_transactions<S: InvoiceSource>(
+    source: &S,
+    transactions: Vec<BalanceTransaction>,
+    progress: &ReportProgress,
+) -> Result<Vec<PayoutTransaction>> {
+    let mut rows = Vec::with_capacity(transactions.len());
+    for bt in transactions.into_iter().filter(|bt| bt.transaction_type != "payout") {
+        let charge_id = bt
+            .source
+            .as_deref()
+            .filter(|_| matches!(bt.transaction_type.as_str(), "charge" | "payment"));
+        let invoice = match charge_id {
+            Some(charge_id) => match source.fetch_charge(charge_id).await {
+                Ok(charge) => charge.invoice,
+                Err(e) => {
+                    progress.warn(&format!("Balance transaction {}: no invoice lookup: {}", bt.id, e));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        rows.push(PayoutTransaction {
+            date: format_date(bt.created)?,
+            transaction_type: bt.transaction_type,
+            source: bt.source,
+            invoice,
+            amount: bt.amount,
+            fee: bt.fee,
+            net: bt.net,
+            id: bt.id,
+        });
+    }
+    Ok(rows)
+}
+
+fn payout_entry(payout: Payout, transactions: Vec<PayoutTransaction>, note: Option<String>) -> Result<PayoutEntry> {
+    let net: i64 = transactions.iter().map(|t| t.net).sum();
+    Ok(PayoutEntry {
+        arrival_date: format_date(payout.arrival_date)?,
+        status: payout.status,
+        currency: payout.currency.as_deref().unwrap_or("usd").to_uppercase(),
+        difference: payout.amount - net,
+        amount: payout.amount,
+        transactions,
+        note,
+        id: payout.id,
+    })
+}
+
+impl PayoutReport {
+    pub fn new(payouts: Vec<PayoutEntry>) -> Self {
+        let invoices = payouts
+            .iter()
+            .flat_map(|payout| {
+                payout.transactions.iter().filter_map(|t| t.invoice.as_ref()).map(|invoice| InvoicePayout {
+                    invoice: invoice.clone(),
+                    payout: payout.id.clone(),
+                    arrival_date: payout.arrival_date.clone(),
+                })
+            })
+            .collect();
+        PayoutReport { payouts, invoices }
+    }
+
+    /// Payouts that don't tie out to their transactions
+    pub fn unreconciled(&self) -> impl Iterator<Item = &PayoutEntry> {
+        self.payouts.iter().filter(|p| p.difference != 0)
+    }
+
+    pub fn write(&self, format: PayoutFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            PayoutFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            PayoutFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            PayoutFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        for payout in &self.payouts {
+            row(&[&format!("===== {} =====", payout.id)])?;
+            row(&["Arrival Date", "Status", "Currency", "Amount"])?;
+            row(&[&payout.arrival_date, &payout.status, &payout.currency, &dollars(payout.amount)])?;
+            if let Some(note) = &payout.note {
+                row(&[&format!("NOTE: {}", note)])?;
+            }
+            row(&["Transaction", "Date", "Type", "Source", "Invoice", "Amount", "Fee", "Net"])?;
+            for t in &payout.transactions {
+                row(&[
+                    &t.id,
+                    &t.date,
+                    &t.transaction_type,
+                    t.source.as_deref().unwrap_or(""),
+                    t.invoice.as_deref().unwrap_or(""),
+                    &dollars(t.amount),
+                    &dollars(t.fee),
+                    &dollars(t.net),
+                ])?;
+            }
+            let sum = |f: fn(&PayoutTransaction) -> i64| dollars(payout.transactions.iter().map(f).sum());
+            row(&["Total", "", "", "", "", &sum(|t| t.amount), &sum(|t| t.fee), &sum(|t| t.net)])?;
+            if payout.difference != 0 {
+                row(&[&format!(
+                    "UNRECONCILED: payout differs from its transactions' net by {}",
+                    dollars(payout.difference)
+                )])?;
+            }
+            row(&[])?;
+        }
+
+        row(&["===== INVOICES ====="])?;
+        row(&["Invoice", "Payout", "Arrival Date"])?;
+        for invoice in &self.invoices {
+            row(&[&invoice.invoice, &invoice.payout, &invoice.arrival_date])?;
+        }
+        Ok(())
+    }
+}
+
+fn format_date(timestamp: i64) -> Result<String> {
+    let datetime =
+        chrono::DateTime::from_timestamp(timestamp, 0).ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp))?;
+    Ok(datetime.format("%m/%d/%Y").to_string())
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FixtureSource;
+
+    const FIXTURE: &str = r#"{
+        "payouts": [
+            {"id": "po_1", "amount": 15206, "currency": "usd", "arrival_date": 1728172800, "status": "paid", "automatic": true},
+            {"id": "po_manual", "amount": 5000, "arrival_date": 1728259200, "status": "paid", "automatic": false},
+            {"id": "po_q3", "amount": 100, "arrival_date": 1719792000, "status": "paid", "automatic": true}
+        ],
+        "payout_balance_transactions": {"po_1": ["txn_1", "txn_2", "txn_re", "txn_po"]},
+        "balance_transactions": [
+            {"id": "txn_1", "type": "charge", "source": "ch_1", "amount": 10825, "fee": 344, "net": 10481, "created": 1728000000},
+            {"id": "txn_2", "type": "charge", "source": "ch_2", "amount": 5000, "fee": 175, "net": 4825, "created": 1728000000},
+            {"id": "txn_re", "type": "refund", "source": "re_1", "amount": -100, "fee": 0, "net": -100, "created": 1728086400},
+            {"id": "txn_po", "type": "payout", "source": "po_1", "amount": -15206, "net": -15206, "created": 1728086400}
+        ],
+        "charges": [{"id": "ch_1", "invoice": "in_1"}, {"id": "ch_2"}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_payout_report_ties_charges_to_invoices() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+        // Q4 2024
+        let report = build_payout_report(&source, 1727740800, 1735689599, &ReportProgress::hidden()).await.unwrap();
+
+        let ids: Vec<&str> = report.payouts.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["po_1", "po_manual"]);
+
+        let payout = &report.payouts[0];
+        assert_eq!(payout.transactions.len(), 3);
+        assert_eq!(payout.difference, 0);
+        assert_eq!(report.invoices, vec![InvoicePayout {
+            invoice: "in_1".to_string(),
+            payout: "po_1".to_string(),
+            arrival_date: "10/06/2024".to_string(),
+        }]);
+        assert!(report.payouts[1].note.is_some());
+        let unreconciled: Vec<&str> = report.unreconciled().map(|p| p.id.as_str()).collect();
+        assert_eq!(unreconciled, vec!["po_manual"]);
+
+        let mut tsv = Vec::new();
+        report.write(PayoutFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("txn_1\t10/04/2024\tcharge\tch_1\tin_1\t108.25\t3.44\t104.81\n"));
+        assert!(tsv.contains("Total\t\t\t\t\t157.25\t5.19\t152.06\n\n===== po_manual"));
+        assert!(tsv.ends_with("===== INVOICES =====\nInvoice\tPayout\tArrival Date\nin_1\tpo_1\t10/06/2024\n"));
+    }
+}
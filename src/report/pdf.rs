@@ -0,0 +1,211 @@
+//! Minimal PDF rendering shared by customer statements and the PDF report:
+//! US Letter pages set in the standard Helvetica fonts, so no font data has
+//! to be embedded.
+
+use pdf_writer::{Content, Name, Pdf, Rect, Ref, Str, TextStr};
+
+pub(crate) const PAGE_WIDTH: f32 = 612.0;
+pub(crate) const PAGE_HEIGHT: f32 = 792.0;
+pub(crate) const MARGIN: f32 = 54.0;
+/// Lowest baseline for body text; the page number sits below it
+pub(crate) const BOTTOM: f32 = 72.0;
+pub(crate) const ROW_HEIGHT: f32 = 13.0;
+pub(crate) const FONT_SIZE: f32 = 9.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+/// Helvetica advance widths (1/1000 em) for ASCII 32..=126
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 222, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 222, 556, 556, 500, 556,
+    556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334,
+    260, 334, 584,
+];
+
+/// Helvetica-Bold advance widths (1/1000 em) for ASCII 32..=126
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 278, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 278, 556, 611, 556, 611,
+    556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389,
+    280, 389, 584,
+];
+
+pub(crate) enum Align {
+    Left,
+    Right,
+}
+
+pub(crate) struct Text {
+    x: f32,
+    y: f32,
+    bold: bool,
+    size: f32,
+    align: Align,
+    text: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Page {
+    texts: Vec<Text>,
+    /// Heights of full-width horizontal rules
+    rules: Vec<f32>,
+}
+
+/// Lays text out top to bottom, starting a new page when one fills up
+pub(crate) struct Layout {
+    /// Heading repeated at the top of continuation pages
+    continued: String,
+    pub(crate) pages: Vec<Page>,
+    pub(crate) y: f32,
+}
+
+impl Layout {
+    pub(crate) fn new(continued: impl Into<String>) -> Self {
+        Layout { continued: continued.into(), pages: vec![Page::default()], y: PAGE_HEIGHT - MARGIN }
+    }
+
+    pub(crate) fn text(&mut self, x: f32, bold: bool, size: f32, align: Align, text: impl Into<String>) {
+        let y = self.y;
+        self.page().texts.push(Text { x, y, bold, size, align, text: text.into() });
+    }
+
+    /// A horizontal rule just below the current line
+    pub(crate) fn rule(&mut self) {
+        let y = self.y - 3.0;
+        self.page().rules.push(y);
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("layout always has a page")
+    }
+
+    /// Move down a line, continuing on a new page (under `header`) if needed
+    pub(crate) fn advance(&mut self, height: f32, header: Option<&dyn Fn(&mut Layout)>) {
+        self.y -= height;
+        if self.y < BOTTOM {
+            self.pages.push(Page::default());
+            self.y = PAGE_HEIGHT - MARGIN;
+            let continued = self.continued.clone();
+            self.text(MARGIN, true, 10.0, Align::Left, continued);
+            self.y -= 2.0 * ROW_HEIGHT;
+            if let Some(header) = header {
+                header(self);
+                self.y -= ROW_HEIGHT;
+            }
+        }
+    }
+
+    /// One table row: left-aligned cells then right-aligned amounts
+    pub(crate) fn row(&mut self, bold: bool, left: &[(f32, &str)], right: &[(f32, String)]) {
+        for (x, text) in left {
+            self.text(*x, bold, FONT_SIZE, Align::Left, *text);
+        }
+        for (x, text) in right {
+            self.text(*x, bold, FONT_SIZE, Align::Right, text.clone());
+        }
+    }
+}
+
+/// Cut text down to `width` points, ending it with "..." if anything was cut
+pub(crate) fn fit(text: &str, width: f32, bold: bool, size: f32) -> String {
+    if text_width(text, bold, size) <= width {
+        return text.to_string();
+    }
+    let mut fitted: String = text.to_string();
+    while !fitted.is_empty() && text_width(&format!("{}...", fitted), bold, size) > width {
+        fitted.pop();
+    }
+    format!("{}...", fitted.trim_end())
+}
+
+/// Write laid-out pages as a PDF document titled `title`, numbering the pages
+pub(crate) fn write_pdf(title: &str, pages: Vec<Page>) -> Vec<u8> {
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let first_page = 6;
+    let page_ids: Vec<Ref> = (0..pages.len()).map(|i| Ref::new(first_page + 2 * i as i32)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    for (id, font) in [(regular_id, "Helvetica"), (bold_id, "Helvetica-Bold")] {
+        pdf.type1_font(id)
+            .base_font(Name(font.as_bytes()))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+    pdf.document_info(info_id)
+        .title(TextStr(title))
+        .producer(TextStr(concat!("stripe-tax-reporter ", env!("CARGO_PKG_VERSION"))));
+
+    let count = pages.len();
+    for (index, page) in pages.into_iter().enumerate() {
+        let page_id = page_ids[index];
+        let content_id = Ref::new(page_id.get() + 1);
+
+        let mut content = Content::new();
+        content.set_line_width(0.5);
+        for y in page.rules {
+            content.move_to(MARGIN, y).line_to(PAGE_WIDTH - MARGIN, y).stroke();
+        }
+        let footer = Text {
+            x: PAGE_WIDTH - MARGIN,
+            y: MARGIN - 18.0,
+            bold: false,
+            size: 8.0,
+            align: Align::Right,
+            text: format!("Page {} of {}", index + 1, count),
+        };
+        for text in page.texts.iter().chain(std::iter::once(&footer)) {
+            let x = match text.align {
+                Align::Left => text.x,
+                Align::Right => text.x - text_width(&text.text, text.bold, text.size),
+            };
+            content
+                .begin_text()
+                .set_font(if text.bold { BOLD } else { REGULAR }, text.size)
+                .next_line(x, text.y)
+                .show(Str(&win_ansi(&text.text)))
+                .end_text();
+        }
+
+        let mut writer = pdf.page(page_id);
+        writer.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .parent(page_tree_id)
+            .contents(content_id);
+        writer.resources().fonts().pair(REGULAR, regular_id).pair(BOLD, bold_id);
+        drop(writer);
+        pdf.stream(content_id, &content.finish());
+    }
+
+    pdf.finish()
+}
+
+/// Width of text in points; characters outside ASCII are taken as wide as a digit
+pub(crate) fn text_width(text: &str, bold: bool, size: f32) -> f32 {
+    let widths = if bold { &HELVETICA_BOLD_WIDTHS } else { &HELVETICA_WIDTHS };
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 32..=126 => widths[(code - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
+
+/// Encode text for the fonts' WinAnsiEncoding: ASCII and Latin-1 pass
+/// through, anything else becomes `?`
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            code @ (32..=126 | 160..=255) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
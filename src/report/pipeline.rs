@@ -0,0 +1,812 @@
+use crate::report::basis::{collected, Basis, DEFAULT_LOOKBACK_DAYS};
+use crate::report::diagnostics::{Diagnostic, DiagnosticCode, Severity};
+use crate::report::checkpoint::Checkpoint;
+use crate::report::generator::{ReportGenerator, ReportOptions};
+use crate::report::payments::{one_off_payments, OneOffPayment};
+use crate::source::InvoiceSource;
+use crate::report::progress::ReportProgress;
+use crate::stripe::client::{Dispute, StripeClient, StripeInvoice};
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use anyhow::{anyhow, bail, Result};
+use std::time::Instant;
+use tracing::Instrument;
+
+/// Metadata key written onto each reported invoice by `tag_report_period`
+pub const REPORT_PERIOD_METADATA_KEY: &str = "tax_report_period";
+
+/// Fetch paid invoices for a date range (Unix timestamps) from `source`,
+/// enrich each with its customer, charge and balance transaction, and feed
+/// them through a `ReportGenerator`. With a `basis` in the options, finalized
+/// invoices are listed from before the period and kept by finalization date
+/// (accrual) or by payments received in the period (cash).
+///
+/// Invoices that cannot be processed are skipped with a warning, so one bad
+/// customer record doesn't abort the whole report. One-off payments are added
+/// next when asked for, then disputes opened in the period are checked; lost
+/// ones are reported as chargebacks.
+pub async fn build_report<S: InvoiceSource>(
+    source: &S,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    progress.start_fetching();
+    let mut invoices = match options.basis {
+        Some(basis) => fetch_for_basis(source, basis, start, end, options.lookback_days, progress).await?,
+        None => {
+            source
+                .fetch_paid_invoices_with_progress(start, end, &|retrieved| progress.page_fetched(retrieved))
+                .await?
+        }
+    };
+    progress.status(&format!("Retrieved {} invoices", invoices.len()));
+    if let Some(limit) = options.limit
+        && invoices.len() > limit
+    {
+        progress.warn(&format!("Processing only the first {} of {} invoices (--limit)", limit, invoices.len()));
+        invoices.truncate(limit);
+    }
+
+    let generator = ReportGenerator::with_options(options.clone());
+    let generator = process_invoices(source, generator, invoices, start, end, progress).await?;
+    let generator = process_payments(source, generator, start, end, progress).await?;
+    let generator = process_disputes(source, generator, start, end, progress).await?;
+    if let Some(budget) = &generator.options().request_budget {
+        budget.check()?;
+    }
+    Ok(generator)
+}
+
+/// Invoices in every status that can carry revenue, created from the
+/// lookback window up to the end of the period, that may belong to the
+/// period under `basis`
+async fn fetch_for_basis<S: InvoiceSource>(
+    source: &S,
+    basis: Basis,
+    start: i64,
+    end: i64,
+    lookback_days: Option<u32>,
+    progress: &ReportProgress,
+) -> Result<Vec<StripeInvoice>> {
+    let from = start - i64::from(lookback_days.unwrap_or(DEFAULT_LOOKBACK_DAYS)) * 86_400;
+    let mut invoices = Vec::new();
+    for status in basis.statuses() {
+        let before = invoices.len();
+        let fetched = source
+            .fetch_invoices_with_progress(status, from, end, &|retrieved| progress.page_fetched(before + retrieved))
+            .await?;
+        invoices.extend(fetched.into_iter().filter(|invoice| basis.includes(invoice, start, end)));
+    }
+    invoices.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+    Ok(invoices)
+}
+
+/// Continue a report from a checkpoint written when an earlier run hit its
+/// deadline, processing only the invoices that run didn't get to
+pub async fn resume_report<S: InvoiceSource>(
+    source: &S,
+    checkpoint: Checkpoint,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    let (start, end) = (checkpoint.start, checkpoint.end);
+    let (generator, pending) = checkpoint.restore(options.clone());
+    progress.status(&format!(
+        "Resuming from checkpoint: {} invoices already processed, {} remaining",
+        generator.get_records().len() + generator.skipped().len(),
+        pending.len()
+    ));
+    let generator = process_invoices(source, generator, pending, start, end, progress).await?;
+    let generator = process_payments(source, generator, start, end, progress).await?;
+    process_disputes(source, generator, start, end, progress).await
+}
+
+/// Enrich and add each invoice, stopping early if the deadline passes. Any
+/// invoices not reached are kept on the generator as pending.
+async fn process_invoices<S: InvoiceSource>(
+    source: &S,
+    mut generator: ReportGenerator,
+    invoices: Vec<StripeInvoice>,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    let deadline = generator.options().deadline;
+
+    progress.start_enrichment(invoices.len());
+    let mut processed = 0;
+    let mut skipped = 0;
+    let mut invoices = invoices.into_iter();
+    while let Some(invoice) = invoices.next() {
+        if let Some(budget) = &generator.options().request_budget {
+            budget.check()?;
+        }
+        if let Some(deadline) = deadline
+            && Instant::now() >= deadline
+        {
+            let mut pending = vec![invoice];
+            pending.extend(invoices);
+            progress.warn(&format!("Time budget exhausted with {} invoices still to process", pending.len()));
+            generator.set_pending(pending);
+            break;
+        }
+
+        let span = tracing::info_span!("invoice", id = %invoice.id);
+        match enrich_invoice(source, &mut generator, &invoice, start, end, progress).instrument(span).await {
+            Ok(()) => processed += 1,
+            Err(e) => {
+                progress.warn(&format!("Skipping invoice {}: {}", invoice.id, e));
+                generator.record_skip(&invoice, e.to_string());
+                progress.invoice_skipped();
+                skipped += 1;
+            }
+        }
+        progress.invoice_done();
+    }
+    progress.finish();
+
+    progress.status(&format!("Processed {} invoices, skipped {}", processed, skipped));
+
+    Ok(generator)
+}
+
+/// Add a record for each Checkout Session and standalone PaymentIntent paid
+/// in the period that no invoice covers, under `include_payments`. Left for
+/// the resumed run if invoices are still pending.
+async fn process_payments<S: InvoiceSource>(
+    source: &S,
+    mut generator: ReportGenerator,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    if !generator.options().include_payments || !generator.pending().is_empty() {
+        return Ok(generator);
+    }
+
+    let sessions = source
+        .fetch_checkout_sessions(start, end)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch checkout sessions: {}", e))?;
+    let intents = source
+        .fetch_payment_intents(start, end)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch payment intents: {}", e))?;
+    let mut reported = 0;
+    for payment in one_off_payments(&sessions, &intents) {
+        let span = tracing::info_span!("payment", id = %payment.id);
+        match enrich_payment(source, &mut generator, &payment).instrument(span).await {
+            Ok(()) => reported += 1,
+            Err(e) => {
+                progress.warn(&format!("Skipping payment {}: {}", payment.id, e));
+                generator.record_skip(&payment.as_invoice(), e.to_string());
+            }
+        }
+    }
+    if reported > 0 {
+        progress.status(&format!("Reported {} one-off payments", reported));
+    }
+
+    Ok(generator)
+}
+
+/// Look up a one-off payment's customer (if it has one), charge and fee and
+/// add it to the report
+async fn enrich_payment<S: InvoiceSource>(
+    source: &S,
+    generator: &mut ReportGenerator,
+    payment: &OneOffPayment,
+) -> Result<()> {
+    let customer = match &payment.customer {
+        Some(id) => {
+            if let Some(reason) = generator.options().exclusions.reason(id, None) {
+                generator.exclude_customer_invoice(&payment.as_invoice(), reason);
+                return Ok(());
+            }
+            let customer = source.fetch_customer(id).await.map_err(|e| anyhow!("Failed to fetch customer: {}", e))?;
+            if let Some(reason) = generator.options().exclusions.reason(id, Some(&customer.metadata)) {
+                generator.exclude_customer_invoice(&payment.as_invoice(), reason);
+                return Ok(());
+            }
+            Some(customer)
+        }
+        // Guest checkout: the name and address collected at checkout stand in
+        None => None,
+    };
+
+    let mut charge = None;
+    let mut balance_transaction = None;
+    if let Some(charge_id) = &payment.charge {
+        match source.fetch_charge(charge_id).await {
+            Ok(fetched) => {
+                if let Some(balance_tx_id) = &fetched.balance_transaction {
+                    match source.fetch_balance_transaction(balance_tx_id).await {
+                        Ok(bt) => balance_transaction = Some(bt),
+                        Err(e) => generator.add_diagnostic(Diagnostic::new(
+                            Severity::Warning,
+                            DiagnosticCode::FeeUnavailable,
+                            &payment.id,
+                            format!("Fees reported as 0: {}", e),
+                        )),
+                    }
+                }
+                charge = Some(fetched);
+            }
+            Err(e) => generator.add_diagnostic(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::ChargeUnavailable,
+                &payment.id,
+                format!("Fees reported as 0 and no card billing address fallback: {}", e),
+            )),
+        }
+    }
+
+    generator.process_payment(payment, customer.as_ref(), charge.as_ref(), balance_transaction.as_ref())
+}
+
+/// Add a chargeback record for each dispute opened in the period and lost,
+/// and flag invoices whose disputes are still open. Left for the resumed run
+/// if invoices are still pending.
+async fn process_disputes<S: InvoiceSource>(
+    source: &S,
+    mut generator: ReportGenerator,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    if !generator.pending().is_empty() {
+        return Ok(generator);
+    }
+
+    let disputes = source
+        .fetch_disputes(start, end)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch disputes: {}", e))?;
+    let mut chargebacks = 0;
+    for dispute in disputes.iter().filter(|d| d.is_lost() || d.is_open()) {
+        let span = tracing::info_span!("dispute", id = %dispute.id);
+        let invoice = match disputed_invoice(source, dispute).instrument(span.clone()).await {
+            Ok(Some(invoice)) => invoice,
+            // Not an invoice payment, or another clock's or customer's invoice
+            Ok(None) => continue,
+            Err(e) if dispute.is_lost() => {
+                generator.add_diagnostic(Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticCode::DisputeUnmatched,
+                    &dispute.id,
+                    format!("No chargeback reported: {}", e),
+                ));
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        if dispute.is_open() {
+            generator.add_diagnostic(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::DisputeOpen,
+                &invoice.id,
+                format!(
+                    "Dispute {} for {:.2} is {}; the invoice is still reported as a sale",
+                    dispute.id,
+                    dispute.amount as f64 / 100.0,
+                    dispute.status
+                ),
+            ));
+            continue;
+        }
+
+        match add_chargeback(source, &mut generator, dispute, invoice).instrument(span).await {
+            Ok(()) => chargebacks += 1,
+            Err(e) => generator.add_diagnostic(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::DisputeUnmatched,
+                &dispute.id,
+                format!("No chargeback reported: {}", e),
+            )),
+        }
+    }
+    if chargebacks > 0 {
+        progress.status(&format!("Reported {} chargebacks", chargebacks));
+    }
+
+    Ok(generator)
+}
+
+/// The invoice a dispute's charge paid, if it paid one this source reports on
+async fn disputed_invoice<S: InvoiceSource>(source: &S, dispute: &Dispute) -> Result<Option<StripeInvoice>> {
+    let charge = source
+        .fetch_charge(&dispute.charge)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch charge {}: {}", dispute.charge, e))?;
+    let Some(invoice_id) = charge.invoice else {
+        return Ok(None);
+    };
+    let invoice = source
+        .fetch_invoice(&invoice_id)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch invoice {}: {}", invoice_id, e))?;
+    Ok(source.in_scope(&invoice).then_some(invoice))
+}
+
+/// Look up a disputed invoice's customer and charge and add its chargeback
+async fn add_chargeback<S: InvoiceSource>(
+    source: &S,
+    generator: &mut ReportGenerator,
+    dispute: &Dispute,
+    invoice: StripeInvoice,
+) -> Result<()> {
+    let customer = source
+        .fetch_customer(&customer_id(&invoice)?)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch customer: {}", e))?;
+    let charge = source.fetch_charge(&dispute.charge).await?;
+    // The charge's balance transaction carries the exchange rate for converting to USD
+    let balance_transaction = match &charge.balance_transaction {
+        Some(id) => source.fetch_balance_transaction(id).await.ok(),
+        None => None,
+    };
+    generator.process_chargeback(dispute, invoice, Some(&customer), Some(&charge), balance_transaction.as_ref())
+}
+
+pub(crate) fn customer_id(invoice: &StripeInvoice) -> Result<String> {
+    match &invoice.customer {
+        serde_json::Value::String(s) if !s.is_empty() => Ok(s.clone()),
+        serde_json::Value::Object(obj) => match obj.get("id").and_then(|v| v.as_str()) {
+            Some(id) => Ok(id.to_string()),
+            None => bail!("No customer ID found"),
+        },
+        _ => bail!("No customer ID found"),
+    }
+}
+
+/// Look up an invoice's customer, charge and fee and add it to the report.
+/// On the cash basis the invoice's payments are checked first, and it is left
+/// out if nothing was collected in the period.
+async fn enrich_invoice<S: InvoiceSource>(
+    source: &S,
+    generator: &mut ReportGenerator,
+    invoice: &StripeInvoice,
+    start: i64,
+    end: i64,
+    progress: &ReportProgress,
+) -> Result<()> {
+    if generator.options().basis == Some(Basis::Cash) {
+        let payments = source
+            .fetch_invoice_payments(&invoice.id)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch invoice payments: {}", e))?;
+        match collected(invoice, &payments, start, end) {
+            Some(collected) => generator.set_collected(&invoice.id, collected),
+            None => return Ok(()),
+        }
+    }
+
+    // Fetch customer details, unless the customer is excluded by ID
+    let id = customer_id(invoice);
+    if let Ok(id) = &id
+        && let Some(reason) = generator.options().exclusions.reason(id, None)
+    {
+        generator.exclude_customer_invoice(invoice, reason);
+        return Ok(());
+    }
+    let customer = match &id {
+        Ok(id) => source.fetch_customer(id).await.map_err(|e| anyhow!("Failed to fetch customer: {}", e)),
+        Err(e) => Err(anyhow!("{}", e)),
+    };
+    let customer = match customer {
+        Ok(customer) => customer,
+        Err(e) => {
+            generator.add_diagnostic(Diagnostic::new(
+                Severity::Error,
+                DiagnosticCode::CustomerUnavailable,
+                &invoice.id,
+                e.to_string(),
+            ));
+            return Err(e);
+        }
+    };
+    progress.customer_fetched();
+    if let Ok(id) = &id
+        && let Some(reason) = generator.options().exclusions.reason(id, Some(&customer.metadata))
+    {
+        generator.exclude_customer_invoice(invoice, reason);
+        return Ok(());
+    }
+
+    // Name the products of the invoice's lines, once per product
+    if generator.options().line_items {
+        let product_ids: Vec<String> = invoice.lines.data.iter().filter_map(|l| l.product_id()).map(str::to_string).collect();
+        for product_id in product_ids {
+            if generator.has_product_name(&product_id) {
+                continue;
+            }
+            match source.fetch_product(&product_id).await {
+                Ok(product) => generator.add_product_name(&product_id, product.name.as_deref().unwrap_or(&product_id)),
+                Err(e) => {
+                    generator.add_diagnostic(Diagnostic::new(
+                        Severity::Warning,
+                        DiagnosticCode::ProductUnavailable,
+                        &invoice.id,
+                        format!("Product {} named by its ID: {}", product_id, e),
+                    ));
+                    generator.add_product_name(&product_id, &product_id);
+                }
+            }
+        }
+    }
+
+    let mut charge_data = None;
+    let mut balance_transaction = None;
+
+    // Fetch the charge to get its balance_transaction ID and billing address
+    if let Some(serde_json::Value::String(charge_id)) = &invoice.charge {
+        match source.fetch_charge(charge_id).await {
+            Ok(charge) => {
+                // Extract balance_transaction for fees
+                if let Some(balance_tx_id) = &charge.balance_transaction {
+                    match source.fetch_balance_transaction(balance_tx_id).await {
+                        Ok(bt) => balance_transaction = Some(bt),
+                        Err(e) => generator.add_diagnostic(Diagnostic::new(
+                            Severity::Warning,
+                            DiagnosticCode::FeeUnavailable,
+                            &invoice.id,
+                            format!("Fees reported as 0: {}", e),
+                        )),
+                    }
+                }
+                // Store charge for state fallback
+                charge_data = Some(charge);
+            }
+            Err(e) => generator.add_diagnostic(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::ChargeUnavailable,
+                &invoice.id,
+                format!("Fees reported as 0 and no card billing address fallback: {}", e),
+            )),
+        }
+    }
+
+    generator.process_invoice_with_customer(
+        invoice.clone(),
+        Some(&customer),
+        charge_data.as_ref(),
+        balance_transaction.as_ref(),
+    )
+}
+
+/// Build one report across every account connected to the platform.
+///
+/// Each connected account is fetched with its own `Stripe-Account` header and
+/// its records are tagged with the account ID so formatters can show an
+/// Account column.
+pub async fn build_connected_report(
+    client: &StripeClient,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    let accounts = client.fetch_connected_accounts().await?;
+    progress.status(&format!("Found {} connected accounts", accounts.len()));
+
+    let mut combined = ReportGenerator::with_options(options.clone());
+    for account in accounts {
+        let name = account
+            .business_profile
+            .as_ref()
+            .and_then(|p| p.name.as_deref())
+            .unwrap_or("(unnamed)");
+        progress.status(&format!("Account {} ({})", account.id, name));
+
+        let generator = build_report(&client.for_account(&account.id), start, end, options, progress).await?;
+        for diagnostic in generator.diagnostics() {
+            combined.add_diagnostic(diagnostic.clone());
+        }
+        for source in generator.sources() {
+            combined.add_source(source.clone());
+        }
+        for skip in generator.skipped() {
+            combined.add_skip(SkippedInvoice {
+                reason: format!("{} (account {})", skip.reason, account.id),
+                ..skip.clone()
+            });
+        }
+        for mut record in generator.into_records() {
+            record.account = Some(account.id.clone());
+            combined.add_record(record);
+        }
+    }
+
+    Ok(combined)
+}
+
+/// The records of Stripe invoices in a report, each invoice once. Sales from
+/// Checkout Sessions, PaymentIntents, report runs or other systems have no
+/// invoice to tag.
+pub fn taggable_invoices(records: &[InvoiceRecord]) -> Vec<&InvoiceRecord> {
+    let mut seen = std::collections::HashSet::new();
+    records
+        .iter()
+        .filter(|record| record.source.is_invoice())
+        .filter(|record| seen.insert((record.account.as_deref(), record.invoice_id.as_str())))
+        .collect()
+}
+
+/// Write `tax_report_period=<period>` onto every invoice in the report, so the
+/// Stripe Dashboard shows which filing covered each invoice. Pass the records
+/// from `taggable_invoices`.
+///
+/// Every invoice is attempted even if some updates fail; the call errors at the
+/// end if any invoice could not be tagged. Records from connected accounts are
+/// updated on behalf of their account. Returns the number of invoices tagged.
+pub async fn tag_report_period(client: &StripeClient, records: &[&InvoiceRecord], period: &str) -> Result<usize> {
+    let mut tagged = 0;
+    let mut failed = 0;
+
+    for record in records {
+        let account_client;
+        let client = match &record.account {
+            Some(account) => {
+                account_client = client.for_account(account);
+                &account_client
+            }
+            None => client,
+        };
+
+        match client
+            .update_invoice_metadata(&record.invoice_id, REPORT_PERIOD_METADATA_KEY, period)
+            .await
+        {
+            Ok(()) => tagged += 1,
+            Err(e) => {
+                tracing::warn!(invoice = %record.invoice_id, "Failed to tag invoice: {:#}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!(
+            "Failed to tag {} of {} invoices with {}={}",
+            failed,
+            records.len(),
+            REPORT_PERIOD_METADATA_KEY,
+            period
+        );
+    }
+
+    Ok(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::exclusions::CustomerExclusions;
+    use crate::report::payments::RecordSource;
+    use crate::source::{FixtureSource, RecordingSource};
+    use std::collections::BTreeMap;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {
+                "id": "in_tx", "customer": "cus_tx", "customer_name": "Lone Star LLC",
+                "status": "paid", "created": 1728000000, "paid_at": 1728000000, "tax": 825,
+                "charge": "ch_tx",
+                "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 2}]}
+            },
+            {
+                "id": "in_nostate", "customer": "cus_nostate", "customer_name": "Nowhere Inc",
+                "status": "paid", "created": 1728000000, "tax": 0,
+                "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 5000, "quantity": 1}]}
+            }
+        ],
+        "customers": [
+            {"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "tx"}},
+            {"id": "cus_nostate", "name": "Nowhere Inc"}
+        ],
+        "charges": [{"id": "ch_tx", "balance_transaction": "txn_tx"}],
+        "balance_transactions": [{"id": "txn_tx", "fee": 320}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_build_report_from_fixture() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+        let generator = build_report(&source, 1727740800, 1735689599, &ReportOptions::default(), &ReportProgress::hidden()).await.unwrap();
+
+        // The invoice without any state is skipped, and the skip is recorded
+        let records = generator.get_records();
+        assert_eq!(records.len(), 1);
+        let skipped = generator.skipped();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].invoice_id, "in_nostate");
+        assert_eq!(skipped[0].customer, "Nowhere Inc");
+
+        let record = &records[0];
+        assert_eq!(record.customer, "Lone Star LLC");
+        assert_eq!(record.state, "TX");
+        assert_eq!(record.users, 2);
+        assert_eq!(record.licenses, 10000);
+        assert_eq!(record.tax, 825);
+        assert_eq!(record.total, 10825);
+        assert_eq!(record.fees, 320);
+        assert_eq!(record.invoice_id, "in_tx");
+    }
+
+    #[tokio::test]
+    async fn test_only_invoices_are_tagged() {
+        let fixture = r#"{
+            "invoices": [
+                {"id": "in_tx", "customer": "cus_tx", "customer_name": "Lone Star LLC", "status": "paid",
+                 "created": 1728000000, "tax": 825,
+                 "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1}]}}
+            ],
+            "customers": [{"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "TX"}}],
+            "checkout_sessions": [
+                {"id": "cs_1", "created": 1728000100, "mode": "payment", "payment_status": "paid", "payment_intent": "pi_1",
+                 "amount_total": 5413, "total_details": {"amount_tax": 413},
+                 "customer_details": {"name": "Walk-in Buyer", "address": {"state": "TX"}}}
+            ],
+            "payment_intents": [
+                {"id": "pi_1", "created": 1728000100, "amount_received": 5413, "status": "succeeded"},
+                {"id": "pi_2", "created": 1728000200, "amount_received": 2000, "status": "succeeded", "customer": "cus_tx"}
+            ]
+        }"#;
+        let source = FixtureSource::from_json(fixture).unwrap();
+        let options = ReportOptions { include_payments: true, ..Default::default() };
+        let generator = build_report(&source, 1727740800, 1735689599, &options, &ReportProgress::hidden()).await.unwrap();
+
+        let mut records = generator.get_records().to_vec();
+        let ids: Vec<&str> = records.iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_tx", "cs_1", "pi_2"]);
+
+        // An invoice listed twice (e.g. once per account merge) is tagged once
+        records.push(records[0].clone());
+        let tagged: Vec<&str> = taggable_invoices(&records).iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(tagged, vec!["in_tx"]);
+    }
+
+    #[tokio::test]
+    async fn test_limit_processes_only_the_first_invoices() {
+        let fixture = FixtureSource::from_json(FIXTURE).unwrap();
+        let source = RecordingSource::new(&fixture);
+        let options = ReportOptions { limit: Some(1), ..Default::default() };
+        let generator = build_report(&source, 1727740800, 1735689599, &options, &ReportProgress::hidden()).await.unwrap();
+
+        let records: Vec<&str> = generator.get_records().iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(records, vec!["in_tx"]);
+        assert!(generator.skipped().is_empty());
+        assert_eq!(generator.sources().len(), 1);
+
+        // The invoice past the limit is listed but its customer isn't fetched
+        let fetched = source.into_fixture();
+        let customers: Vec<&str> = fetched.customers.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(customers, vec!["cus_tx"]);
+        assert_eq!(fetched.charges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_customers_left_out() {
+        let fixture = FIXTURE.replace(
+            r#"{"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "tx"}}"#,
+            r#"{"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "tx"}, "metadata": {"internal": "true"}}"#,
+        );
+        let source = FixtureSource::from_json(&fixture).unwrap();
+        let filters = BTreeMap::from([("internal".to_string(), "true".to_string())]);
+        let options = ReportOptions {
+            exclusions: CustomerExclusions::default().with_metadata(&filters),
+            ..Default::default()
+        };
+        let generator = build_report(&source, 1727740800, 1735689599, &options, &ReportProgress::hidden()).await.unwrap();
+
+        assert!(generator.get_records().is_empty());
+        let skipped: Vec<(&str, &str)> =
+            generator.skipped().iter().map(|s| (s.invoice_id.as_str(), s.reason.as_str())).collect();
+        assert_eq!(skipped[0], ("in_tx", "Customer cus_tx has metadata internal=true"));
+        // Only the invoice without a state counts against --fail-on-skip
+        let unreportable: Vec<&str> = generator.unreportable().iter().map(|s| s.invoice_id.as_str()).collect();
+        assert_eq!(unreportable, vec!["in_nostate"]);
+    }
+
+    // Q4 2024. in_late was created in September, finalized in October and paid
+    // half in October, half in January; in_open was finalized in November and
+    // never paid.
+    const BASIS_FIXTURE: &str = r#"{
+        "invoices": [
+            {
+                "id": "in_late", "customer": "cus_tx", "status": "paid", "created": 1726000000,
+                "finalized_at": 1728000000, "paid_at": 1736500000, "amount_paid": 20000, "total": 20000, "tax": 1650,
+                "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 18350, "quantity": 2}]}
+            },
+            {
+                "id": "in_open", "customer": "cus_tx", "status": "open", "created": 1731000000,
+                "finalized_at": 1731000000, "amount_paid": 0, "total": 10825, "tax": 825,
+                "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            }
+        ],
+        "invoice_payments": [
+            {"id": "inpay_1", "invoice": "in_late", "amount_paid": 10000, "status": "paid", "status_transitions": {"paid_at": 1728100000}},
+            {"id": "inpay_2", "invoice": "in_late", "amount_paid": 10000, "status": "paid", "status_transitions": {"paid_at": 1736500000}}
+        ],
+        "customers": [{"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "TX"}}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_accrual_and_cash_basis() {
+        let source = &FixtureSource::from_json(BASIS_FIXTURE).unwrap();
+        let report = |basis| async move {
+            let options = ReportOptions { basis, ..Default::default() };
+            build_report(source, 1727740800, 1735689599, &options, &ReportProgress::hidden()).await.unwrap()
+        };
+
+        // Paid by creation date: in_late was created in Q3, so nothing
+        assert!(report(None).await.get_records().is_empty());
+
+        let accrual = report(Some(Basis::Accrual)).await;
+        let ids: Vec<&str> = accrual.get_records().iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_late", "in_open"]);
+        assert_eq!(accrual.get_records()[0].date, chrono::NaiveDate::from_ymd_opt(2024, 10, 4).unwrap());
+        assert_eq!(accrual.get_records()[0].total, 20000);
+
+        // Only the October half of in_late was collected in Q4
+        let cash = report(Some(Basis::Cash)).await;
+        let records = cash.get_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].date, chrono::NaiveDate::from_ymd_opt(2024, 10, 5).unwrap());
+        assert_eq!((records[0].licenses, records[0].tax, records[0].total), (9175, 825, 10000));
+        assert_eq!(records[0].adjustments.len(), 1);
+    }
+
+    // in_sep was paid in Q3 and charged back for half in Q4; in_oct's
+    // dispute is still under review, and dp_other's charge paid no invoice
+    const DISPUTE_FIXTURE: &str = r#"{
+        "invoices": [
+            {
+                "id": "in_sep", "customer": "cus_tx", "status": "paid", "created": 1726000000, "paid_at": 1726000000,
+                "amount_paid": 21650, "total": 21650, "tax": 1650, "charge": "ch_sep",
+                "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 20000, "quantity": 2}]}
+            },
+            {
+                "id": "in_oct", "customer": "cus_tx", "status": "paid", "created": 1728000000, "paid_at": 1728000000,
+                "amount_paid": 10825, "total": 10825, "tax": 825, "charge": "ch_oct",
+                "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            }
+        ],
+        "disputes": [
+            {
+                "id": "dp_sep", "charge": "ch_sep", "amount": 10825, "status": "lost", "created": 1729000000,
+                "balance_transactions": [{"id": "txn_dp", "amount": -10825, "fee": 1500}]
+            },
+            {"id": "dp_oct", "charge": "ch_oct", "amount": 10825, "status": "under_review", "created": 1729000000},
+            {"id": "dp_other", "charge": "ch_other", "amount": 500, "status": "lost", "created": 1729000000}
+        ],
+        "customers": [{"id": "cus_tx", "name": "Lone Star LLC", "address": {"state": "TX"}}],
+        "charges": [{"id": "ch_sep", "invoice": "in_sep"}, {"id": "ch_oct", "invoice": "in_oct"}, {"id": "ch_other"}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_lost_dispute_reported_as_chargeback() {
+        let source = FixtureSource::from_json(DISPUTE_FIXTURE).unwrap();
+        let generator = build_report(&source, 1727740800, 1735689599, &ReportOptions::default(), &ReportProgress::hidden()).await.unwrap();
+
+        let records = generator.get_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].invoice_id, "in_oct");
+        let chargeback = &records[1];
+        assert_eq!(chargeback.invoice_id, "in_sep");
+        assert_eq!(chargeback.date, chrono::NaiveDate::from_ymd_opt(2024, 10, 15).unwrap());
+        assert_eq!((chargeback.users, chargeback.licenses, chargeback.tax, chargeback.total), (0, -10000, -825, -10825));
+        assert_eq!(chargeback.fees, 1500);
+        assert_eq!(chargeback.fee_breakdown.other, 1500);
+
+        // Tagging leaves the charged-back invoice with the period of its sale
+        assert_eq!(chargeback.source, RecordSource::Chargeback);
+        let tagged: Vec<&str> = taggable_invoices(records).iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(tagged, vec!["in_oct"]);
+
+        let codes: Vec<(DiagnosticCode, Option<&str>)> =
+            generator.diagnostics().iter().map(|d| (d.code, d.invoice_id.as_deref())).collect();
+        assert_eq!(codes, vec![(DiagnosticCode::DisputeOpen, Some("in_oct"))]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::report::grouping::Totals;
+use crate::stripe::models::InvoiceRecord;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Label for amounts that can't be attributed to a product: tax Stripe didn't
+/// break down by line, and conversion rounding
+pub const NOT_BY_LINE: &str = "(not broken down by line)";
+
+/// Label for lines without a price/product
+pub const NO_PRODUCT: &str = "(no product)";
+
+/// Ordering key: unattributed amounts last, then name, product ID and currency
+type GroupKey = (bool, String, Option<String>, Option<String>);
+
+/// Revenue and tax for one product across the whole report (`--group-by product`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProductGroup {
+    /// Product ID; `None` for the unattributed rows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    pub name: String,
+    /// ISO currency code when the amounts aren't USD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Invoice lines counted
+    pub lines: usize,
+    /// Fees are charged per invoice, so they're always 0 here
+    pub subtotal: Totals,
+}
+
+impl ProductGroup {
+    /// Name with the currency appended when it isn't USD, e.g. "Pro Plan (EUR)"
+    pub fn label(&self) -> String {
+        match &self.currency {
+            Some(currency) => format!("{} ({})", self.name, currency),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Subtotal records' lines by product (and currency), ordered by name with the
+/// unattributed amounts last. Records need their lines kept (see
+/// `ReportOptions::line_items`); amounts not covered by any line are reported
+/// under `NOT_BY_LINE`, so the products add up to the report's totals.
+pub fn by_product(records: &[InvoiceRecord]) -> Vec<ProductGroup> {
+    let mut groups: BTreeMap<GroupKey, (usize, Totals)> = BTreeMap::new();
+
+    for record in records {
+        let mut covered = Totals::default();
+        for line in &record.lines {
+            let totals = Totals {
+                licenses: line.amount,
+                taxable_sales: line.taxable_sales,
+                non_taxable_sales: line.amount - line.taxable_sales,
+                tax: line.tax,
+                total: line.amount + line.tax,
+                ..Default::default()
+            };
+            covered.merge(&totals);
+
+            let name = line
+                .product_name
+                .clone()
+                .or_else(|| line.product.clone())
+                .unwrap_or_else(|| NO_PRODUCT.to_string());
+            let entry = groups
+                .entry((false, name, line.product.clone(), record.currency.clone()))
+                .or_default();
+            entry.0 += 1;
+            entry.1.merge(&totals);
+        }
+
+        let rest = Totals {
+            licenses: record.licenses - covered.licenses,
+            taxable_sales: record.taxable_sales - covered.taxable_sales,
+            non_taxable_sales: record.non_taxable_sales - covered.non_taxable_sales,
+            tax: record.tax - covered.tax,
+            total: record.total - covered.total,
+            ..Default::default()
+        };
+        if rest != Totals::default() {
+            groups
+                .entry((true, NOT_BY_LINE.to_string(), None, record.currency.clone()))
+                .or_default()
+                .1
+                .merge(&rest);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((_, name, product, currency), (lines, subtotal))| ProductGroup {
+            product,
+            name,
+            currency,
+            lines,
+            subtotal,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceLine;
+
+    #[test]
+    fn test_by_product() {
+        let line = |product: &str, name: &str, amount: i64, tax: i64| InvoiceLine {
+            product: Some(product.to_string()),
+            product_name: Some(name.to_string()),
+            quantity: 1,
+            amount,
+            taxable_sales: amount,
+            tax,
+            ..Default::default()
+        };
+        let records = vec![
+            InvoiceRecord {
+                licenses: 15000,
+                taxable_sales: 15000,
+                tax: 1238,
+                total: 16238,
+                lines: vec![line("prod_seat", "Seats", 10000, 825), line("prod_addon", "Add-on", 5000, 0)],
+                ..Default::default()
+            },
+            InvoiceRecord {
+                licenses: 10000,
+                taxable_sales: 10000,
+                tax: 825,
+                total: 10825,
+                lines: vec![line("prod_seat", "Seats", 10000, 825)],
+                ..Default::default()
+            },
+        ];
+
+        let products = by_product(&records);
+        let names: Vec<&str> = products.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["Add-on", "Seats", NOT_BY_LINE]);
+
+        assert_eq!(products[1].lines, 2);
+        assert_eq!((products[1].subtotal.licenses, products[1].subtotal.tax), (20000, 1650));
+        // The first invoice's tax that no line carries
+        assert_eq!((products[2].subtotal.licenses, products[2].subtotal.tax), (0, 413));
+    }
+}
@@ -0,0 +1,112 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Progress display for report generation, drawn on stderr.
+///
+/// Shows a spinner while invoice pages are retrieved, then a bar while each
+/// invoice is enriched with its customer, charge and fee. Status messages and
+/// warnings are logged through `tracing` with the bar suspended so they don't
+/// garble it. A quiet progress draws nothing and drops status messages, but
+/// still logs warnings.
+pub struct ReportProgress {
+    bar: ProgressBar,
+    quiet: bool,
+    customers: AtomicUsize,
+    skipped: AtomicUsize,
+}
+
+impl ReportProgress {
+    pub fn new(quiet: bool) -> Self {
+        let bar = if quiet {
+            ProgressBar::hidden()
+        } else {
+            // indicatif skips drawing when stderr is not a terminal
+            ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+        };
+
+        ReportProgress {
+            bar,
+            quiet,
+            customers: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+        }
+    }
+
+    /// A progress that draws nothing, for library callers and tests
+    pub fn hidden() -> Self {
+        Self::new(true)
+    }
+
+    /// Log an informational message (suppressed when quiet)
+    pub fn status(&self, message: &str) {
+        if !self.quiet {
+            self.bar.suspend(|| tracing::info!("{}", message));
+        }
+    }
+
+    /// Log a warning above the bar
+    pub fn warn(&self, message: &str) {
+        self.bar.suspend(|| tracing::warn!("{}", message));
+    }
+
+    /// Start the pagination spinner
+    pub fn start_fetching(&self) {
+        self.bar.reset();
+        self.bar.set_length(0);
+        self.bar
+            .set_style(ProgressStyle::with_template("{spinner} Fetching invoices: {msg}").expect("valid template"));
+        self.bar.set_message("0 retrieved");
+        self.bar.enable_steady_tick(Duration::from_millis(120));
+    }
+
+    /// A page of invoices arrived; `retrieved` is the running total
+    pub fn page_fetched(&self, retrieved: usize) {
+        self.bar.set_message(format!("{} retrieved", retrieved));
+    }
+
+    /// Switch to the enrichment bar for `total` invoices
+    pub fn start_enrichment(&self, total: usize) {
+        self.customers.store(0, Ordering::Relaxed);
+        self.skipped.store(0, Ordering::Relaxed);
+        self.bar.disable_steady_tick();
+        self.bar.reset();
+        self.bar.set_length(total as u64);
+        self.bar.set_style(
+            ProgressStyle::with_template("Enriching {bar:30} {pos}/{len} invoices ({msg})")
+                .expect("valid template")
+                .progress_chars("=> "),
+        );
+        self.update_message();
+    }
+
+    /// A customer record was fetched
+    pub fn customer_fetched(&self) {
+        self.customers.fetch_add(1, Ordering::Relaxed);
+        self.update_message();
+    }
+
+    /// An invoice was skipped
+    pub fn invoice_skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        self.update_message();
+    }
+
+    /// An invoice finished processing (added or skipped)
+    pub fn invoice_done(&self) {
+        self.bar.inc(1);
+    }
+
+    /// Remove the bar from the terminal
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn update_message(&self) {
+        self.bar.set_message(format!(
+            "{} customers fetched, {} skipped",
+            self.customers.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed)
+        ));
+    }
+}
@@ -1,59 +1,514 @@
-use chrono::{Datelike, Local, NaiveDate};
-
-/// Calculate start and end dates of the previous fiscal quarter
-/// Returns (start_date, end_date, quarter_num, year)
-pub fn get_previous_quarter() -> (NaiveDate, NaiveDate, u32, i32) {
-    let today = Local::now().date_naive();
-    let current_month = today.month();
-    let current_year = today.year();
-
-    // Determine current quarter (1-4)
-    let current_quarter = (current_month - 1) / 3 + 1;
-
-    // Calculate previous quarter
-    let (prev_quarter, prev_year) = if current_quarter == 1 {
-        (4, current_year - 1)
-    } else {
-        (current_quarter - 1, current_year)
+use anyhow::{anyhow, bail, Result};
+use crate::clock::{Clock, SystemClock};
+use crate::config::FilingFrequency;
+use chrono::{Datelike, Days, NaiveDate, TimeZone, Weekday};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A quarter, the unit sales tax is usually reported in, or a whole year for
+/// annual filers. Calendar quarters unless the period is placed in a fiscal
+/// year starting in another month (`in_fiscal_year`).
+///
+/// Orders chronologically, a year before its first quarter. Displays and
+/// parses as `Q3-2024` or `2024`, the labels used in Stripe metadata and file
+/// names; `2024-Q3` and `Q3 2024` also parse. A fiscal year is labeled with
+/// the calendar year it ends in, so with a July start `Q1-2025` is July to
+/// September 2024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Period {
+    year: i32,
+    /// `None` for the whole year
+    quarter: Option<u32>,
+    /// Month (1-12) the fiscal year starts in; 1 for calendar years
+    #[serde(default = "calendar_year_start")]
+    fiscal_start: u32,
+}
+
+fn calendar_year_start() -> u32 {
+    1
+}
+
+/// How long a reporting period is (`generate --period`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PeriodLength {
+    /// A calendar quarter
+    #[default]
+    Quarter,
+    /// A calendar year, with each quarter broken out in the summary
+    Year,
+}
+
+impl PeriodLength {
+    /// The last complete period of this length before `today`
+    pub fn previous_from(self, today: NaiveDate) -> Period {
+        self.previous_in_fiscal_year(today, 1)
+    }
+
+    /// The last complete period of this length before `today`, in fiscal
+    /// years starting in month `fiscal_start` (1-12)
+    pub fn previous_in_fiscal_year(self, today: NaiveDate, fiscal_start: u32) -> Period {
+        let current = Period::containing_in_fiscal_year(today, fiscal_start);
+        match self {
+            PeriodLength::Quarter => current.previous(),
+            PeriodLength::Year => Period { quarter: None, ..current }.previous(),
+        }
+    }
+}
+
+impl Period {
+    /// The given quarter (1-4) of `year`
+    pub fn new(year: i32, quarter: u32) -> Result<Self> {
+        if !(1..=4).contains(&quarter) {
+            bail!("Quarter must be 1-4, got {}", quarter);
+        }
+        Ok(Period { year, quarter: Some(quarter), fiscal_start: 1 })
+    }
+
+    /// The whole of `year`
+    pub fn annual(year: i32) -> Self {
+        Period { year, quarter: None, fiscal_start: 1 }
+    }
+
+    /// Parse a label such as `Q3-2024`, `2024-Q3`, `q3 2024` or `2024`
+    pub fn parse(label: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid period '{}' (expected e.g. Q3-2024 or 2024)", label);
+
+        let normalized = label.trim().to_uppercase().replace(' ', "-");
+        if let Ok(year) = normalized.parse::<i32>() {
+            return Ok(Self::annual(year));
+        }
+        let (first, second) = normalized.split_once('-').ok_or_else(invalid)?;
+        let (quarter, year) = match (first.strip_prefix('Q'), second.strip_prefix('Q')) {
+            (Some(quarter), None) => (quarter, second),
+            (None, Some(quarter)) => (quarter, first),
+            _ => return Err(invalid()),
+        };
+
+        let quarter: u32 = quarter.parse().map_err(|_| invalid())?;
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+        Self::new(year, quarter).map_err(|_| invalid())
+    }
+
+    /// The calendar quarter `date` falls in
+    pub fn containing(date: NaiveDate) -> Self {
+        Self::containing_in_fiscal_year(date, 1)
+    }
+
+    /// The quarter `date` falls in, in fiscal years starting in month
+    /// `fiscal_start` (1-12)
+    pub fn containing_in_fiscal_year(date: NaiveDate, fiscal_start: u32) -> Self {
+        let months_in = (date.month() + 12 - fiscal_start) % 12;
+        let year = if fiscal_start > 1 && date.month() >= fiscal_start {
+            date.year() + 1
+        } else {
+            date.year()
+        };
+        Period { year, quarter: Some(months_in / 3 + 1), fiscal_start }
+    }
+
+    /// The same quarter or year of a fiscal year starting in month
+    /// `fiscal_start` (1-12) instead, e.g. a `Q1-2025` parsed from the
+    /// command line in a company's July-June fiscal year
+    pub fn in_fiscal_year(self, fiscal_start: u32) -> Result<Self> {
+        if !(1..=12).contains(&fiscal_start) {
+            bail!("Fiscal year start month must be 1-12, got {}", fiscal_start);
+        }
+        Ok(Period { fiscal_start, ..self })
+    }
+
+    /// Month (1-12) the period's fiscal year starts in
+    pub fn fiscal_start(self) -> u32 {
+        self.fiscal_start
+    }
+
+    pub fn year(self) -> i32 {
+        self.year
+    }
+
+    /// The quarter (1-4), or `None` for a whole year
+    pub fn quarter(self) -> Option<u32> {
+        self.quarter
+    }
+
+    pub fn is_annual(self) -> bool {
+        self.quarter.is_none()
+    }
+
+    /// The period of the same length just before this one
+    pub fn previous(self) -> Self {
+        match self.quarter {
+            None => Period { year: self.year - 1, ..self },
+            Some(1) => Period { year: self.year - 1, quarter: Some(4), ..self },
+            Some(q) => Period { quarter: Some(q - 1), ..self },
+        }
+    }
+
+    /// The period of the same length just after this one
+    pub fn next(self) -> Self {
+        match self.quarter {
+            None => Period { year: self.year + 1, ..self },
+            Some(4) => Period { year: self.year + 1, quarter: Some(1), ..self },
+            Some(q) => Period { quarter: Some(q + 1), ..self },
+        }
+    }
+
+    /// Every period from `first` through `last` inclusive, each as long as
+    /// `first` (empty if `last` is earlier)
+    pub fn iter_between(first: Period, last: Period) -> impl Iterator<Item = Period> {
+        std::iter::successors(Some(first), |period| Some(period.next())).take_while(move |period| *period <= last)
+    }
+
+    /// The quarters the period covers: itself, or a year's four quarters
+    pub fn quarters(self) -> Vec<Period> {
+        match self.quarter {
+            Some(_) => vec![self],
+            None => (1..=4).map(|quarter| Period { quarter: Some(quarter), ..self }).collect(),
+        }
+    }
+
+    /// First day of the period
+    pub fn start_date(self) -> NaiveDate {
+        // A fiscal year starting after January began the calendar year before
+        let year = if self.fiscal_start > 1 { self.year - 1 } else { self.year };
+        let year_start = NaiveDate::from_ymd_opt(year, self.fiscal_start, 1).expect("valid fiscal year start");
+        let months = self.quarter.map_or(0, |quarter| (quarter - 1) * 3);
+        year_start.checked_add_months(chrono::Months::new(months)).expect("valid period start")
+    }
+
+    /// Last day of the period
+    pub fn end_date(self) -> NaiveDate {
+        self.next().start_date().pred_opt().expect("valid period end")
+    }
+
+    /// First day of each month the period covers, in order
+    pub fn months(self) -> impl Iterator<Item = NaiveDate> {
+        let end = self.end_date();
+        std::iter::successors(Some(self.start_date()), |month| month.checked_add_months(chrono::Months::new(1)))
+            .take_while(move |month| *month <= end)
+    }
+
+    /// Unix timestamps covering the whole period, with day boundaries in `tz`
+    pub fn bounds<Tz: TimeZone>(self, tz: &Tz) -> Result<(i64, i64)> {
+        period_bounds(self.start_date(), self.end_date(), tz)
+    }
+
+    /// Like `bounds`, in a profile's timezone if it has one, else UTC
+    pub fn bounds_in(self, timezone: Option<chrono_tz::Tz>) -> Result<(i64, i64)> {
+        match timezone {
+            Some(tz) => self.bounds(&tz),
+            None => self.bounds(&chrono::Utc),
+        }
+    }
+
+    /// Label as used in Stripe metadata and file names, e.g. "Q2-2025" or "2025"
+    pub fn label(self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.quarter {
+            Some(quarter) => write!(f, "{}", period_label(quarter, self.year)),
+            None => write!(f, "{}", self.year),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Period::parse(s)
+    }
+}
+
+/// The calendar quarter before the current one
+pub fn get_previous_quarter() -> Period {
+    get_previous_quarter_from(SystemClock.today(None))
+}
+
+/// The calendar quarter before the one `today` (e.g. a test clock's frozen
+/// time) falls in
+pub fn get_previous_quarter_from(today: NaiveDate) -> Period {
+    get_previous_fiscal_quarter_from(today, 1)
+}
+
+/// The quarter before the one `today` falls in, in fiscal years starting in
+/// month `fiscal_start` (1-12)
+pub fn get_previous_fiscal_quarter_from(today: NaiveDate, fiscal_start: u32) -> Period {
+    Period::containing_in_fiscal_year(today, fiscal_start).previous()
+}
+
+/// Unix timestamps covering `start` 00:00:00 through `end` 23:59:59 inclusive,
+/// with day boundaries taken in the given timezone
+pub fn period_bounds<Tz: TimeZone>(start: NaiveDate, end: NaiveDate, tz: &Tz) -> Result<(i64, i64)> {
+    let local_midnight = |date: NaiveDate| -> Result<i64> {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(|| anyhow!("Invalid date {}", date))?;
+        tz.from_local_datetime(&midnight)
+            .earliest()
+            .map(|dt| dt.timestamp())
+            .ok_or_else(|| anyhow!("Midnight on {} does not exist in the configured timezone", date))
+    };
+
+    let next_day = end.succ_opt().ok_or_else(|| anyhow!("Invalid end date {}", end))?;
+    Ok((local_midnight(start)?, local_midnight(next_day)? - 1))
+}
+
+/// Label for a quarter as used in Stripe metadata, e.g. "Q2-2025"
+pub fn period_label(quarter: u32, year: i32) -> String {
+    format!("Q{}-{}", quarter, year)
+}
+
+/// Day of the month after a filing period ends that states other than those
+/// in `DUE_DAYS` want the return by
+pub const DEFAULT_DUE_DAY: u32 = 20;
+
+/// States whose returns aren't due on the 20th, with the day of the month
+/// after the period they are due; 0 for the last day of that month.
+/// Approximate: some states vary the day by filing frequency or size.
+const DUE_DAYS: &[(&str, u32)] = &[("CA", 0), ("IA", 0), ("OH", 23), ("WA", 25)];
+
+/// One sales tax return to file: the state, the period it covers and when
+/// it's due
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Deadline {
+    pub state: String,
+    pub frequency: FilingFrequency,
+    /// `Q3-2025` or `2025`, or `MM/YYYY` for monthly filers
+    pub period: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub due: NaiveDate,
+}
+
+/// When the return for a period ending on `period_end` is due in `state`:
+/// its due day in the following month, moved to the Monday when that falls
+/// on a weekend. Holidays aren't accounted for.
+pub fn due_date(state: &str, period_end: NaiveDate) -> NaiveDate {
+    let day = DUE_DAYS
+        .iter()
+        .find(|(code, _)| *code == state)
+        .map_or(DEFAULT_DUE_DAY, |&(_, day)| day);
+    let month_start = period_end.succ_opt().expect("valid period end");
+    let month_end = next_month(month_start).pred_opt().expect("valid month end");
+    let due = match day {
+        0 => month_end,
+        day => month_start.with_day(day).unwrap_or(month_end),
     };
+    match due.weekday() {
+        Weekday::Sat => due + Days::new(2),
+        Weekday::Sun => due + Days::new(1),
+        _ => due,
+    }
+}
 
-    // Calculate start and end months for the quarter
-    let start_month = (prev_quarter - 1) * 3 + 1;
-    let end_month = prev_quarter * 3;
+/// Returns due in `state` between `from` and `until` inclusive, for a
+/// `frequency` filer, in due-date order
+pub fn deadlines(state: &str, frequency: FilingFrequency, from: NaiveDate, until: NaiveDate) -> Vec<Deadline> {
+    // Start one period back: its return is due after it ends
+    let (start, _, _) = filing_period(frequency, from);
+    let (mut start, _, _) = filing_period(frequency, start.pred_opt().expect("valid date"));
 
-    let start_date = NaiveDate::from_ymd_opt(prev_year, start_month, 1).unwrap();
+    let mut deadlines = Vec::new();
+    loop {
+        let (_, end, period) = filing_period(frequency, start);
+        let due = due_date(state, end);
+        if due > until {
+            return deadlines;
+        }
+        if due >= from {
+            deadlines.push(Deadline { state: state.to_string(), frequency, period, start, end, due });
+        }
+        start = end.succ_opt().expect("valid date");
+    }
+}
 
-    // Get last day of the end month
-    let end_day = if end_month == 12 {
-        NaiveDate::from_ymd_opt(prev_year + 1, 1, 1).unwrap() - chrono::Duration::days(1)
-    } else {
-        NaiveDate::from_ymd_opt(prev_year, end_month + 1, 1).unwrap() - chrono::Duration::days(1)
+/// The filing period of `frequency` containing `date`: its first and last
+/// day and label
+fn filing_period(frequency: FilingFrequency, date: NaiveDate) -> (NaiveDate, NaiveDate, String) {
+    let period = match frequency {
+        FilingFrequency::Monthly => {
+            let start = date.with_day(1).expect("valid month start");
+            let end = next_month(start).pred_opt().expect("valid month end");
+            return (start, end, format!("{:02}/{}", start.month(), start.year()));
+        }
+        FilingFrequency::Quarterly => Period::containing(date),
+        FilingFrequency::Annual => Period::annual(date.year()),
     };
+    (period.start_date(), period.end_date(), period.label())
+}
 
-    (start_date, end_day, prev_quarter, prev_year)
+/// First day of the month after the one starting on `month_start`
+fn next_month(month_start: NaiveDate) -> NaiveDate {
+    month_start.checked_add_months(chrono::Months::new(1)).expect("valid month")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Month;
 
     #[test]
     fn test_q4_2025_from_jan_2026() {
-        // Simulate running in January 2026, should return Q4 2025
-        let (start, end, quarter, year) = get_previous_quarter();
+        // Running in January 2026 should return Q4 2025
+        let today = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+        let period = get_previous_quarter_from(today);
 
-        assert_eq!(quarter, 4);
-        assert_eq!(year, 2025);
-        assert_eq!(start.month() as u32, 10);
-        assert_eq!(start.day(), 1);
-        assert_eq!(end.month() as u32, 12);
-        assert_eq!(end.day(), 31);
+        assert_eq!(period.quarter(), Some(4));
+        assert_eq!(period.year(), 2025);
+        assert_eq!(period.start_date().month(), 10);
+        assert_eq!(period.start_date().day(), 1);
+        assert_eq!(period.end_date().month(), 12);
+        assert_eq!(period.end_date().day(), 31);
     }
 
     #[test]
     fn test_quarter_calculation() {
-        let (start, end, _quarter, _year) = get_previous_quarter();
-        assert!(start < end || start == end); // Date sanity check
+        let period = get_previous_quarter();
+        assert!(period.start_date() <= period.end_date()); // Date sanity check
+    }
+
+    #[test]
+    fn test_period_label() {
+        assert_eq!(period_label(2, 2025), "Q2-2025");
+    }
+
+    #[test]
+    fn test_period_parse_and_display() {
+        let q3 = Period::new(2024, 3).unwrap();
+        assert_eq!(Period::parse("Q3-2024").unwrap(), q3);
+        assert_eq!("2024-Q3".parse::<Period>().unwrap(), q3);
+        assert_eq!(Period::parse(" q3 2024 ").unwrap(), q3);
+        assert_eq!(q3.to_string(), "Q3-2024");
+        assert_eq!(q3.label(), "Q3-2024");
+
+        assert!(Period::parse("Q5-2024").is_err());
+        assert!(Period::parse("Q-2024").is_err());
+        assert!(Period::new(2024, 0).is_err());
+
+        let year = Period::parse("2024").unwrap();
+        assert_eq!(year, Period::annual(2024));
+        assert_eq!(year.label(), "2024");
+        assert!(year < q3 && year.quarter().is_none());
+    }
+
+    #[test]
+    fn test_period_navigation() {
+        let jan = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+        let q1 = Period::containing(jan);
+        assert_eq!(q1, Period::new(2026, 1).unwrap());
+        assert_eq!(q1.previous(), Period::new(2025, 4).unwrap());
+        assert_eq!(q1.previous().next(), q1);
+
+        let q4 = q1.previous();
+        assert_eq!(q4.start_date(), NaiveDate::from_ymd_opt(2025, 10, 1).unwrap());
+        assert_eq!(q4.end_date(), NaiveDate::from_ymd_opt(2025, 12, 31).unwrap());
+        assert_eq!(q4.bounds(&chrono::Utc).unwrap(), (1759276800, 1767225599));
+
+        let labels: Vec<String> = Period::iter_between(Period::parse("Q3-2025").unwrap(), Period::parse("Q2-2026").unwrap())
+            .map(|p| p.label())
+            .collect();
+        assert_eq!(labels, vec!["Q3-2025", "Q4-2025", "Q1-2026", "Q2-2026"]);
+        assert_eq!(Period::iter_between(q1, q4).count(), 0);
+    }
+
+    #[test]
+    fn test_annual_period() {
+        let today = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+        assert_eq!(PeriodLength::Quarter.previous_from(today), Period::new(2025, 4).unwrap());
+        let year = PeriodLength::Year.previous_from(today);
+        assert_eq!(year, Period::annual(2025));
+        assert_eq!(year.previous(), Period::annual(2024));
+        assert_eq!(year.start_date(), NaiveDate::from_ymd_opt(2025, 1, 1).unwrap());
+        assert_eq!(year.end_date(), NaiveDate::from_ymd_opt(2025, 12, 31).unwrap());
+        assert_eq!(year.bounds(&chrono::Utc).unwrap(), (1735689600, 1767225599));
+
+        let labels: Vec<String> = year.quarters().into_iter().map(|p| p.label()).collect();
+        assert_eq!(labels, vec!["Q1-2025", "Q2-2025", "Q3-2025", "Q4-2025"]);
+        assert_eq!(year.months().count(), 12);
+
+        let months: Vec<NaiveDate> = Period::new(2025, 4).unwrap().months().collect();
+        assert_eq!(months, vec![
+            NaiveDate::from_ymd_opt(2025, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 11, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 12, 1).unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn test_fiscal_year_quarters() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // Fiscal years starting in July, named for the year they end in
+        let q1 = Period::containing_in_fiscal_year(date(2025, 8, 15), 7);
+        assert_eq!((q1.label(), q1.start_date(), q1.end_date()), ("Q1-2026".to_string(), date(2025, 7, 1), date(2025, 9, 30)));
+        let q3 = Period::containing_in_fiscal_year(date(2026, 1, 5), 7);
+        assert_eq!((q3.label(), q3.start_date()), ("Q3-2026".to_string(), date(2026, 1, 1)));
+        assert_eq!(q3.previous().end_date(), date(2025, 12, 31));
+        assert_eq!(q1.previous().label(), "Q4-2025");
+        assert_eq!(q1.previous().start_date(), date(2025, 4, 1));
+
+        let previous = get_previous_fiscal_quarter_from(date(2025, 10, 2), 7);
+        assert_eq!((previous.label(), previous.start_date(), previous.end_date()), ("Q1-2026".to_string(), date(2025, 7, 1), date(2025, 9, 30)));
+        assert_eq!(get_previous_fiscal_quarter_from(date(2025, 10, 2), 1), get_previous_quarter_from(date(2025, 10, 2)));
+
+        let year = PeriodLength::Year.previous_in_fiscal_year(date(2025, 8, 1), 7);
+        assert_eq!((year.label(), year.start_date(), year.end_date()), ("2025".to_string(), date(2024, 7, 1), date(2025, 6, 30)));
+        assert_eq!(year.quarters()[3].end_date(), date(2025, 6, 30));
+
+        // A parsed label placed in the fiscal year
+        let parsed = Period::parse("Q2-2026").unwrap().in_fiscal_year(10).unwrap();
+        assert_eq!((parsed.start_date(), parsed.end_date()), (date(2026, 1, 1), date(2026, 3, 31)));
+        assert!(Period::parse("Q2-2026").unwrap().in_fiscal_year(13).is_err());
+    }
+
+    #[test]
+    fn test_filing_deadlines() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // The 20th, or the Monday after when it's a weekend
+        assert_eq!(due_date("TX", date(2025, 12, 31)), date(2026, 1, 20));
+        assert_eq!(due_date("TX", date(2025, 9, 30)), date(2025, 10, 20));
+        assert_eq!(due_date("TX", date(2025, 8, 31)), date(2025, 9, 22));
+        assert_eq!(due_date("CA", date(2026, 3, 31)), date(2026, 4, 30));
+        assert_eq!(due_date("CA", date(2025, 6, 30)), date(2025, 7, 31));
+
+        let quarterly = deadlines("TX", FilingFrequency::Quarterly, date(2026, 1, 10), date(2026, 7, 31));
+        let periods: Vec<(&str, NaiveDate)> = quarterly.iter().map(|d| (d.period.as_str(), d.due)).collect();
+        assert_eq!(periods, vec![
+            ("Q4-2025", date(2026, 1, 20)),
+            ("Q1-2026", date(2026, 4, 20)),
+            ("Q2-2026", date(2026, 7, 20)),
+        ]);
+        assert_eq!(quarterly[0].start, date(2025, 10, 1));
+
+        // December's return is due Sunday the 25th, so Monday the 26th
+        let monthly = deadlines("WA", FilingFrequency::Monthly, date(2026, 1, 26), date(2026, 3, 31));
+        let periods: Vec<(&str, NaiveDate)> = monthly.iter().map(|d| (d.period.as_str(), d.due)).collect();
+        assert_eq!(periods, vec![
+            ("12/2025", date(2026, 1, 26)),
+            ("01/2026", date(2026, 2, 25)),
+            ("02/2026", date(2026, 3, 25)),
+        ]);
+
+        let annual = deadlines("TX", FilingFrequency::Annual, date(2026, 1, 1), date(2026, 12, 31));
+        assert_eq!(annual.len(), 1);
+        assert_eq!((annual[0].period.as_str(), annual[0].due), ("2025", date(2026, 1, 20)));
+    }
+
+    #[test]
+    fn test_period_bounds_in_timezone() {
+        let start = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2025, 12, 31).unwrap();
+
+        let (utc_start, utc_end) = period_bounds(start, end, &chrono::Utc).unwrap();
+        assert_eq!(utc_start, 1759276800); // 2025-10-01T00:00:00Z
+        assert_eq!(utc_end, 1767225599); // 2025-12-31T23:59:59Z
+
+        // Chicago is UTC-5 in October (CDT) and UTC-6 in December (CST)
+        let (chi_start, chi_end) = period_bounds(start, end, &chrono_tz::America::Chicago).unwrap();
+        assert_eq!(chi_start, utc_start + 5 * 3600);
+        assert_eq!(chi_end, utc_end + 6 * 3600);
     }
 }
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+/// Combined state and local sales tax rates (percent) per state: the state
+/// rate, including any statewide mandatory local rate, and the highest
+/// combined rate any locality charges. Approximate as of 2025; states
+/// without a sales tax have a range of zero.
+const STATE_RATES: &[(&str, f64, f64)] = &[
+    ("AK", 0.0, 7.85),
+    ("AL", 4.0, 11.5),
+    ("AR", 6.5, 12.625),
+    ("AZ", 5.6, 11.2),
+    ("CA", 7.25, 11.25),
+    ("CO", 2.9, 11.2),
+    ("CT", 6.35, 6.35),
+    ("DC", 6.0, 6.0),
+    ("DE", 0.0, 0.0),
+    ("FL", 6.0, 8.0),
+    ("GA", 4.0, 8.9),
+    ("HI", 4.0, 4.712),
+    ("IA", 6.0, 7.0),
+    ("ID", 6.0, 9.0),
+    ("IL", 6.25, 11.0),
+    ("IN", 7.0, 7.0),
+    ("KS", 6.5, 11.6),
+    ("KY", 6.0, 6.0),
+    ("LA", 5.0, 11.95),
+    ("MA", 6.25, 6.25),
+    ("MD", 6.0, 6.0),
+    ("ME", 5.5, 5.5),
+    ("MI", 6.0, 6.0),
+    ("MN", 6.875, 9.875),
+    ("MO", 4.225, 12.0),
+    ("MS", 7.0, 8.0),
+    ("MT", 0.0, 0.0),
+    ("NC", 4.75, 7.5),
+    ("ND", 5.0, 8.5),
+    ("NE", 5.5, 8.0),
+    ("NH", 0.0, 0.0),
+    ("NJ", 6.625, 6.625),
+    ("NM", 4.875, 9.4375),
+    ("NV", 6.85, 8.375),
+    ("NY", 4.0, 8.875),
+    ("OH", 5.75, 8.0),
+    ("OK", 4.5, 11.5),
+    ("OR", 0.0, 0.0),
+    ("PA", 6.0, 8.0),
+    ("RI", 7.0, 7.0),
+    ("SC", 6.0, 9.0),
+    ("SD", 4.2, 6.2),
+    ("TN", 7.0, 9.75),
+    ("TX", 6.25, 8.25),
+    ("UT", 6.1, 9.05),
+    ("VA", 5.3, 7.0),
+    ("VT", 6.0, 7.0),
+    ("WA", 6.5, 10.6),
+    ("WI", 5.0, 7.9),
+    ("WV", 6.0, 7.0),
+    ("WY", 4.0, 6.0),
+];
+
+/// Default allowance for rounding, in percentage points of the taxable amount
+pub const DEFAULT_RATE_TOLERANCE: f64 = 0.05;
+
+/// The rates (percent) a sale can be taxed at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl RateRange {
+    fn exact(rate: f64) -> Self {
+        RateRange { min: rate, max: rate }
+    }
+}
+
+/// Expected sales tax rates by destination, to check the tax collected on
+/// each invoice. The bundled state ranges can be narrowed to an exact rate
+/// per state or per 5-digit ZIP code (profile `tax_rates`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateTable {
+    states: BTreeMap<String, RateRange>,
+    /// Exact rates by state code or ZIP code
+    overrides: BTreeMap<String, f64>,
+    /// Percentage points of the taxable amount allowed for rounding
+    pub tolerance: f64,
+}
+
+impl Default for RateTable {
+    fn default() -> Self {
+        RateTable {
+            states: STATE_RATES
+                .iter()
+                .map(|&(state, min, max)| (state.to_string(), RateRange { min, max }))
+                .collect(),
+            overrides: BTreeMap::new(),
+            tolerance: DEFAULT_RATE_TOLERANCE,
+        }
+    }
+}
+
+impl RateTable {
+    /// The bundled rates with exact rates keyed by state code (`TX`) or
+    /// ZIP code (`78701`) taking precedence
+    pub fn with_overrides(mut self, overrides: &BTreeMap<String, f64>) -> Self {
+        for (key, rate) in overrides {
+            self.overrides.insert(key.trim().to_uppercase(), *rate);
+        }
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// The rates expected for a sale to `state`, at `zip` if known: a ZIP
+    /// override, else a state override, else the bundled state range
+    pub fn expected(&self, state: &str, zip: Option<&str>) -> Option<RateRange> {
+        let zip = zip.map(|z| z.trim().get(..5).unwrap_or(z.trim()));
+        zip.and_then(|z| self.overrides.get(z))
+            .or_else(|| self.overrides.get(state))
+            .map(|&rate| RateRange::exact(rate))
+            .or_else(|| self.states.get(state).copied())
+    }
+
+    /// Why `tax` (cents) collected on `taxable` (cents) doesn't match the
+    /// expected rate, if it doesn't. States without a known rate pass.
+    pub fn check(&self, state: &str, zip: Option<&str>, taxable: i64, tax: i64) -> Option<String> {
+        if taxable <= 0 || tax <= 0 {
+            return None;
+        }
+        let range = self.expected(state, zip)?;
+        let amount = |rate: f64| (taxable as f64 * rate / 100.0).round() as i64;
+        let allowance = amount(self.tolerance).max(1);
+        if tax >= amount(range.min) - allowance && tax <= amount(range.max) + allowance {
+            return None;
+        }
+
+        let collected = tax as f64 * 100.0 / taxable as f64;
+        let expected = if range.min == range.max {
+            format!("{}%", range.min)
+        } else {
+            format!("{}% to {}%", range.min, range.max)
+        };
+        Some(format!(
+            "Collected {:.2} on {:.2} taxable ({:.3}%); expected {} in {}{}",
+            tax as f64 / 100.0,
+            taxable as f64 / 100.0,
+            collected,
+            expected,
+            state,
+            zip.map(|z| format!(" {}", z)).unwrap_or_default()
+        ))
+    }
+}
+
+/// Tax as a percentage of the taxable amount (`--effective-rate`); `None`
+/// with nothing taxable
+pub fn effective_rate(tax: i64, taxable: i64) -> Option<f64> {
+    (taxable > 0).then(|| tax as f64 * 100.0 / taxable as f64)
+}
+
+/// A rate in percent to three decimals, e.g. `8.250%`
+pub fn format_rate(rate: f64) -> String {
+    format!("{:.3}%", rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tax_checked_against_rate_ranges_and_overrides() {
+        let table = RateTable::default();
+        // Anywhere from the Texas state rate to the local maximum
+        assert!(table.check("TX", None, 10000, 625).is_none());
+        assert!(table.check("TX", None, 10000, 825).is_none());
+        assert!(table.check("TX", None, 1, 0).is_none());
+        let message = table.check("TX", Some("78701"), 10000, 1000).unwrap();
+        assert_eq!(message, "Collected 10.00 on 100.00 taxable (10.000%); expected 6.25% to 8.25% in TX 78701");
+        assert!(table.check("OR", None, 10000, 100).is_some());
+        // Rounding to the cent on a small sale
+        assert!(table.check("TX", None, 99, 8).is_none());
+
+        let overrides = BTreeMap::from([("tx".to_string(), 8.25), ("78613".to_string(), 8.0)]);
+        let table = RateTable::default().with_overrides(&overrides);
+        assert!(table.check("TX", None, 10000, 625).is_some());
+        assert!(table.check("TX", Some("78701-1234"), 10000, 825).is_none());
+        assert!(table.check("TX", Some("78613"), 10000, 800).is_none());
+        assert_eq!(table.expected("CA", None), Some(RateRange { min: 7.25, max: 11.25 }));
+
+        assert_eq!(effective_rate(825, 10000).map(format_rate).as_deref(), Some("8.250%"));
+        assert_eq!(effective_rate(0, 0), None);
+    }
+}
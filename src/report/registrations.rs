@@ -0,0 +1,214 @@
+use crate::report::compare::{dollars, CompareFormat};
+use crate::report::formatter::csv_escape;
+use crate::report::generator::UNKNOWN_STATE;
+use crate::stripe::client::TaxRegistration;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+/// How a state's sales line up with the Stripe Tax registrations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationStatus {
+    /// Registered, with sales in the period
+    Registered,
+    /// Sales in the period but no registration in effect
+    Unregistered,
+    /// Registered, but nothing was sold there in the period
+    NoSales,
+}
+
+impl fmt::Display for RegistrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegistrationStatus::Registered => "registered",
+            RegistrationStatus::Unregistered => "unregistered",
+            RegistrationStatus::NoSales => "no sales",
+        })
+    }
+}
+
+/// One US state's registrations and reported sales
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateRegistration {
+    pub state: String,
+    pub status: RegistrationStatus,
+    /// IDs of the registrations in effect during the period
+    pub registrations: Vec<String>,
+    pub invoices: usize,
+    /// Gross sales, cents
+    pub sales: i64,
+    /// Tax collected, cents
+    pub tax: i64,
+}
+
+/// The states reported in a period checked against the Stripe Tax
+/// registrations in effect during it (`registrations`)
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistrationCheck {
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    /// Alphabetically by state
+    pub states: Vec<StateRegistration>,
+}
+
+impl RegistrationCheck {
+    /// Compare the US states of `records` with the `registrations` in effect
+    /// between `start` and `end` (Unix timestamps, inclusive). Sales outside
+    /// the US and without a state aren't checked.
+    pub fn new(
+        period: impl Into<String>,
+        registrations: &[TaxRegistration],
+        records: &[InvoiceRecord],
+        start: i64,
+        end: i64,
+    ) -> Self {
+        let mut states: BTreeMap<String, StateRegistration> = BTreeMap::new();
+        for registration in registrations.iter().filter(|r| r.active_between(start, end)) {
+            if let Some(state) = registration.us_state() {
+                let state = state.to_uppercase();
+                states
+                    .entry(state.clone())
+                    .or_insert_with(|| empty(&state))
+                    .registrations
+                    .push(registration.id.clone());
+            }
+        }
+        for record in records {
+            if record.country.as_deref() != Some("US") || record.state == UNKNOWN_STATE {
+                continue;
+            }
+            let row = states.entry(record.state.clone()).or_insert_with(|| empty(&record.state));
+            row.invoices += 1;
+            row.sales += record.licenses;
+            row.tax += record.tax;
+        }
+
+        let states = states
+            .into_values()
+            .map(|mut row| {
+                row.status = match (row.registrations.is_empty(), row.invoices == 0) {
+                    (true, _) => RegistrationStatus::Unregistered,
+                    (false, true) => RegistrationStatus::NoSales,
+                    (false, false) => RegistrationStatus::Registered,
+                };
+                row
+            })
+            .collect();
+        RegistrationCheck { period: period.into(), states }
+    }
+
+    /// States with sales but no registration
+    pub fn unregistered(&self) -> impl Iterator<Item = &StateRegistration> {
+        self.states.iter().filter(|s| s.status == RegistrationStatus::Unregistered)
+    }
+
+    /// Registered states without sales
+    pub fn idle(&self) -> impl Iterator<Item = &StateRegistration> {
+        self.states.iter().filter(|s| s.status == RegistrationStatus::NoSales)
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        row(&["State", "Status", "Registrations", "Invoices", "Sales", "Tax"])?;
+        for state in &self.states {
+            row(&[
+                &state.state,
+                &state.status.to_string(),
+                &state.registrations.join(" "),
+                &state.invoices.to_string(),
+                &dollars(state.sales),
+                &dollars(state.tax),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+/// A state with neither registrations nor sales yet
+fn empty(state: &str) -> StateRegistration {
+    StateRegistration {
+        state: state.to_string(),
+        status: RegistrationStatus::NoSales,
+        registrations: Vec::new(),
+        invoices: 0,
+        sales: 0,
+        tax: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration(id: &str, state: &str, active_from: i64, expires_at: Option<i64>) -> TaxRegistration {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "country": "US",
+            "country_options": {"us": {"state": state, "type": "state_sales_tax"}},
+            "status": "active",
+            "active_from": active_from,
+            "expires_at": expires_at,
+        }))
+        .unwrap()
+    }
+
+    fn record(state: &str, licenses: i64, tax: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            state: state.to_string(),
+            country: Some("US".to_string()),
+            licenses,
+            tax,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sales_checked_against_registrations() {
+        let (start, end) = (1727740800, 1735689599);
+        let registrations = [
+            registration("taxreg_tx", "TX", 1700000000, None),
+            registration("taxreg_wa", "WA", 1700000000, None),
+            // Expired before the period, and not yet in effect
+            registration("taxreg_ny", "NY", 1600000000, Some(1700000000)),
+            registration("taxreg_fl", "FL", 1740000000, None),
+        ];
+        let records = [record("TX", 10000, 825), record("TX", 5000, 413), record("NY", 2000, 178), record(UNKNOWN_STATE, 700, 0)];
+        let check = RegistrationCheck::new("Q4-2024", &registrations, &records, start, end);
+
+        let states: Vec<(&str, RegistrationStatus, usize, i64)> =
+            check.states.iter().map(|s| (s.state.as_str(), s.status, s.invoices, s.sales)).collect();
+        assert_eq!(states, vec![
+            ("NY", RegistrationStatus::Unregistered, 1, 2000),
+            ("TX", RegistrationStatus::Registered, 2, 15000),
+            ("WA", RegistrationStatus::NoSales, 0, 0),
+        ]);
+        assert_eq!(check.unregistered().count(), 1);
+        assert_eq!(check.idle().next().unwrap().registrations, vec!["taxreg_wa"]);
+
+        let mut tsv = Vec::new();
+        check.write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("\nTX\tregistered\ttaxreg_tx\t2\t150.00\t12.38\n"));
+    }
+}
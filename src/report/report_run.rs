@@ -0,0 +1,211 @@
+use crate::report::addresses::split_csv_line;
+use crate::report::external::parse_cents;
+use crate::report::generator::{ReportGenerator, ReportOptions};
+use crate::report::payments::{OneOffPayment, RecordSource};
+use crate::report::progress::ReportProgress;
+use crate::stripe::client::{Address, BalanceTransaction, ReportRun, StripeClient};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveDateTime;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the report run has finished
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for a report run without a `--deadline`
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// A sale or refund read from a report run's CSV, with its Stripe fee (cents)
+#[derive(Debug, Clone)]
+pub struct ReportRow {
+    pub payment: OneOffPayment,
+    pub fee: i64,
+}
+
+/// Build a report from a Stripe Report Run instead of listing invoices
+/// (`--report-run`): create a run of `report_type` for the period, wait for
+/// it, download its CSV and turn each charge and refund row into a record.
+/// For large accounts this takes a handful of API calls rather than several
+/// per invoice. The report must have a `tax` column (see `report_rows`).
+pub async fn build_report_from_run(
+    client: &StripeClient,
+    report_type: &str,
+    start: i64,
+    end: i64,
+    options: &ReportOptions,
+    progress: &ReportProgress,
+) -> Result<ReportGenerator> {
+    // Report intervals end exclusively; the period's end is its last second
+    let run = client.create_report_run(report_type, start, end + 1, &[]).await?;
+    progress.status(&format!("Created report run {} ({})", run.id, report_type));
+    let run = wait_for_run(client, run, options.deadline.unwrap_or_else(|| Instant::now() + MAX_WAIT)).await?;
+    let url = run
+        .result
+        .as_ref()
+        .and_then(|file| file.url.as_deref())
+        .ok_or_else(|| anyhow!("Report run {} succeeded without a downloadable file", run.id))?;
+    let csv = client.download_file(url).await?;
+
+    let rows = report_rows(&csv).with_context(|| format!("Can't read report run {} ({})", run.id, report_type))?;
+    progress.status(&format!("Downloaded {} sales and refunds from report run {}", rows.len(), run.id));
+
+    let mut generator = ReportGenerator::with_options(options.clone());
+    for row in rows {
+        let payment = &row.payment;
+        if let Some(customer_id) = &payment.customer
+            && let Some(reason) = options.exclusions.reason(customer_id, None)
+        {
+            generator.exclude_customer_invoice(&payment.as_invoice(), reason);
+            continue;
+        }
+        let balance_transaction = BalanceTransaction { fee: row.fee, ..Default::default() };
+        if let Err(e) = generator.process_payment(payment, None, None, Some(&balance_transaction)) {
+            progress.warn(&format!("Skipping {}: {}", payment.id, e));
+            generator.record_skip(&payment.as_invoice(), e.to_string());
+        }
+    }
+    Ok(generator)
+}
+
+/// Poll a report run until it succeeds, failing if it fails or `deadline` passes
+async fn wait_for_run(client: &StripeClient, mut run: ReportRun, deadline: Instant) -> Result<ReportRun> {
+    loop {
+        match run.status.as_str() {
+            "succeeded" => return Ok(run),
+            "failed" => bail!("Report run {} failed: {}", run.id, run.error.as_deref().unwrap_or("no reason given")),
+            _ if Instant::now() >= deadline => bail!("Report run {} was still {} at the deadline", run.id, run.status),
+            _ => {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                run = client.fetch_report_run(&run.id).await?;
+            }
+        }
+    }
+}
+
+/// Parse a report run's CSV into the sales (`charge` rows) and refunds
+/// (`refund` rows) it lists, skipping fees, payouts and other balance
+/// changes; reports without `reporting_category` are read as all sales.
+/// Amounts are in major units.
+///
+/// The report needs a `tax` column: without one, each sale's tax would be
+/// counted as a taxable sale and no tax as collected. Stripe's balance
+/// reports (`balance_change_from_activity.*`) have none.
+///
+/// Each row is keyed by its invoice ID, else its charge or balance
+/// transaction ID, and sourced to the `shipping_address_*` (or
+/// `customer_address_*`) state, postal code and country.
+pub fn report_rows(csv: &str) -> Result<Vec<ReportRow>> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some((_, header)) => split_csv_line(header).iter().map(|h| h.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+    if !header.iter().any(|h| h == "gross") {
+        bail!("Report has no `gross` column");
+    }
+    if !header.iter().any(|h| h == "created_utc" || h == "created") {
+        bail!("Report has no `created_utc` column");
+    }
+    if !header.iter().any(|h| h == "tax") {
+        bail!("Report has no `tax` column, so tax can't be told apart from sales; pass a --report-type with one");
+    }
+
+    let mut rows = Vec::new();
+    for (index, line) in lines {
+        let number = index + 1;
+        let fields = split_csv_line(line);
+        let cell = |name: &str| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .and_then(|i| fields.get(i))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let first = |names: &[&str]| names.iter().find_map(|name| cell(name));
+
+        if let Some(category) = cell("reporting_category")
+            && category != "charge"
+            && category != "refund"
+        {
+            continue;
+        }
+        let id = first(&["invoice_id", "charge_id", "balance_transaction_id"])
+            .ok_or_else(|| anyhow!("Line {}: no invoice, charge or balance transaction ID", number))?;
+        let created = first(&["created_utc", "created"]).ok_or_else(|| anyhow!("Line {}: no created time", number))?;
+        let created = NaiveDateTime::parse_from_str(created, "%Y-%m-%d %H:%M:%S")
+            .map_err(|_| anyhow!("Line {}: '{}' is not a time", number, created))?
+            .and_utc()
+            .timestamp();
+        let amount = |name: &str| match cell(name) {
+            Some(value) => parse_cents(value).ok_or_else(|| anyhow!("Line {}: {} is not a number", number, name)),
+            None => Ok(0),
+        };
+        let address = Address {
+            state: first(&["shipping_address_state", "customer_address_state"]).map(str::to_string),
+            postal_code: first(&["shipping_address_postal_code", "customer_address_postal_code"]).map(str::to_string),
+            country: first(&["shipping_address_country", "customer_address_country"]).map(str::to_string),
+            ..Default::default()
+        };
+
+        rows.push(ReportRow {
+            payment: OneOffPayment {
+                id: id.to_string(),
+                source: RecordSource::ReportRun,
+                created,
+                customer: cell("customer_id").map(str::to_string),
+                customer_name: first(&["customer_name", "customer_email"]).map(str::to_string),
+                address: Some(address),
+                amount: amount("gross")?,
+                tax: amount("tax")?,
+                currency: cell("currency").map(str::to_lowercase),
+                charge: None,
+                description: cell("description").map(str::to_string),
+            },
+            fee: amount("fee")?,
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_rows_keep_sales_and_refunds() {
+        let csv = "\
+balance_transaction_id,created_utc,reporting_category,currency,gross,fee,customer_id,customer_name,invoice_id,charge_id,shipping_address_state,shipping_address_country,tax
+txn_1,2025-07-03 14:22:10,charge,usd,108.25,3.44,cus_1,\"Lone Star, LLC\",in_1,ch_1,TX,US,8.25
+txn_2,2025-07-04 09:00:00,fee,usd,-2.00,0.00,,,,,,,
+txn_3,2025-07-05 10:00:00,refund,usd,-50.00,0.00,cus_2,,,ch_2,CA,US,0
+";
+        let rows = report_rows(csv).unwrap();
+        let ids: Vec<&str> = rows.iter().map(|r| r.payment.id.as_str()).collect();
+        assert_eq!(ids, vec!["in_1", "ch_2"]);
+
+        let sale = &rows[0];
+        assert_eq!((sale.payment.amount, sale.payment.tax, sale.fee), (10825, 825, 344));
+        assert_eq!(sale.payment.customer_name.as_deref(), Some("Lone Star, LLC"));
+        assert_eq!(sale.payment.address.as_ref().unwrap().state.as_deref(), Some("TX"));
+        assert_eq!(sale.payment.created, 1751552530);
+        assert_eq!(rows[1].payment.amount, -5000);
+
+        let mut generator = ReportGenerator::new();
+        let bt = BalanceTransaction { fee: sale.fee, ..Default::default() };
+        generator.process_payment(&sale.payment, None, None, Some(&bt)).unwrap();
+        let record = &generator.get_records()[0];
+        assert_eq!((record.state.as_str(), record.licenses, record.tax, record.fees), ("TX", 10000, 825, 344));
+        assert_eq!(record.source, RecordSource::ReportRun);
+    }
+
+    #[test]
+    fn test_balance_report_without_tax_is_refused() {
+        // The columns of `balance_change_from_activity.itemized.3`
+        let csv = "\
+balance_transaction_id,created_utc,reporting_category,currency,gross,fee,customer_id,customer_name,customer_email,invoice_id,charge_id,shipping_address_state,shipping_address_postal_code,shipping_address_country
+txn_1,2025-07-03 14:22:10,charge,usd,108.25,3.44,cus_1,Lone Star LLC,,in_1,ch_1,TX,78701,US
+";
+        let error = report_rows(csv).unwrap_err().to_string();
+        assert!(error.contains("no `tax` column"), "{}", error);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::report::generator::ReportGenerator;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+mod tui;
+
+pub use tui::{run as run_review, ReviewOutcome};
+
+/// What was decided about one invoice in review
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvoiceDecision {
+    /// Leave the invoice out of the report
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub excluded: bool,
+    /// Reviewer's note, footnoted on the invoice's Total
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Inclusion and notes decided in `review`, saved so they survive
+/// regenerating the report (`generate --review`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewDecisions {
+    /// By invoice ID; invoices without a decision are reported as usual
+    #[serde(default)]
+    pub invoices: BTreeMap<String, InvoiceDecision>,
+}
+
+impl ReviewDecisions {
+    /// Load decisions from `path`; a missing file means nothing was decided yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse review {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ReviewDecisions::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read review {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize review")?;
+        std::fs::write(path, json).with_context(|| format!("Failed to write review {}", path.display()))
+    }
+
+    pub fn is_excluded(&self, invoice_id: &str) -> bool {
+        self.invoices.get(invoice_id).is_some_and(|d| d.excluded)
+    }
+
+    pub fn note(&self, invoice_id: &str) -> Option<&str> {
+        self.invoices.get(invoice_id).and_then(|d| d.note.as_deref())
+    }
+
+    /// Flip whether an invoice is left out of the report
+    pub fn toggle(&mut self, invoice_id: &str) {
+        let decision = self.invoices.entry(invoice_id.to_string()).or_default();
+        decision.excluded = !decision.excluded;
+        self.prune(invoice_id);
+    }
+
+    /// Set an invoice's note; an empty note removes it
+    pub fn annotate(&mut self, invoice_id: &str, note: &str) {
+        let note = note.trim();
+        self.invoices.entry(invoice_id.to_string()).or_default().note = (!note.is_empty()).then(|| note.to_string());
+        self.prune(invoice_id);
+    }
+
+    /// Forget an invoice that no longer has anything decided
+    fn prune(&mut self, invoice_id: &str) {
+        if self.invoices.get(invoice_id).is_some_and(|d| *d == InvoiceDecision::default()) {
+            self.invoices.remove(invoice_id);
+        }
+    }
+
+    /// Exclude and annotate the generator's records as decided. Decisions
+    /// about invoices that aren't in the report are ignored.
+    pub fn apply(&self, generator: &mut ReportGenerator) {
+        for (invoice_id, decision) in &self.invoices {
+            if decision.excluded {
+                let reason = match &decision.note {
+                    Some(note) => format!("Excluded in review: {}", note),
+                    None => "Excluded in review".to_string(),
+                };
+                generator.exclude_invoice(invoice_id, reason);
+            } else if let Some(note) = &decision.note {
+                generator.annotate_invoice(invoice_id, note);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    fn record(invoice_id: &str, total: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            customer: "Lone Star LLC".to_string(),
+            state: "TX".to_string(),
+            total,
+            invoice_id: invoice_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decisions_exclude_and_annotate_records() {
+        let mut decisions = ReviewDecisions::default();
+        decisions.toggle("in_1");
+        decisions.annotate("in_1", "Duplicate of in_2");
+        decisions.annotate("in_2", "Reseller; exemption certificate on file");
+        decisions.toggle("in_3");
+        decisions.toggle("in_3");
+        assert!(decisions.is_excluded("in_1"));
+        assert!(!decisions.invoices.contains_key("in_3"));
+
+        let mut generator = ReportGenerator::new();
+        for r in [record("in_1", 10825), record("in_2", 5000), record("in_3", 700)] {
+            generator.add_record(r);
+        }
+        decisions.apply(&mut generator);
+
+        let ids: Vec<&str> = generator.get_records().iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_2", "in_3"]);
+        assert_eq!(generator.get_records()[0].adjustments[0].note, "Reseller; exemption certificate on file");
+        let skipped = &generator.skipped()[0];
+        assert_eq!((skipped.invoice_id.as_str(), skipped.amount), ("in_1", 10825));
+        assert_eq!(skipped.reason, "Excluded in review: Duplicate of in_2");
+    }
+}
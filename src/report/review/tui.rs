@@ -0,0 +1,392 @@
+use super::ReviewDecisions;
+use crate::report::formatter::DateFormat;
+use crate::stripe::models::{InvoiceRecord, SkippedInvoice};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+
+/// How the reviewer left the review screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewOutcome {
+    /// Keep the decisions and export
+    Save,
+    /// Throw away the changes made in this session
+    Discard,
+}
+
+/// The pane keys act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Sections,
+    Rows,
+}
+
+/// A state's records, by index into the report's records
+struct Section {
+    label: String,
+    rows: Vec<usize>,
+}
+
+struct App<'a> {
+    period: &'a str,
+    records: &'a [InvoiceRecord],
+    skipped: &'a [SkippedInvoice],
+    decisions: &'a mut ReviewDecisions,
+    /// One per state, then the excluded invoices
+    sections: Vec<Section>,
+    section_state: ListState,
+    row_state: TableState,
+    focus: Focus,
+    /// The note being typed, while editing one
+    note: Option<String>,
+    dirty: bool,
+    /// The last key was a quit with unsaved changes
+    confirm_discard: bool,
+    /// Shown in the footer until the next key
+    message: Option<String>,
+    outcome: Option<ReviewOutcome>,
+}
+
+impl<'a> App<'a> {
+    fn new(period: &'a str, records: &'a [InvoiceRecord], skipped: &'a [SkippedInvoice], decisions: &'a mut ReviewDecisions) -> Self {
+        let mut by_section: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, record) in records.iter().enumerate() {
+            by_section.entry(record.section()).or_default().push(index);
+        }
+        let sections = by_section.into_iter().map(|(label, rows)| Section { label, rows }).collect();
+        App {
+            period,
+            records,
+            skipped,
+            decisions,
+            sections,
+            section_state: ListState::default().with_selected(Some(0)),
+            row_state: TableState::default().with_selected(Some(0)),
+            focus: Focus::Sections,
+            note: None,
+            dirty: false,
+            confirm_discard: false,
+            message: None,
+            outcome: None,
+        }
+    }
+
+    fn selected_section(&self) -> usize {
+        self.section_state.selected().unwrap_or(0)
+    }
+
+    /// The list after the states: invoices the report left out
+    fn on_skipped(&self) -> bool {
+        self.selected_section() == self.sections.len()
+    }
+
+    fn row_count(&self) -> usize {
+        match self.sections.get(self.selected_section()) {
+            Some(section) => section.rows.len(),
+            None => self.skipped.len(),
+        }
+    }
+
+    /// The record under the cursor, if the cursor is on a record
+    fn selected_record(&self) -> Option<&'a InvoiceRecord> {
+        let section = self.sections.get(self.selected_section())?;
+        let records = self.records;
+        section.rows.get(self.row_state.selected()?).map(|&index| &records[index])
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        self.message = None;
+        let confirming = std::mem::take(&mut self.confirm_discard);
+        if let Some(note) = &mut self.note {
+            match key.code {
+                KeyCode::Enter => {
+                    let note = self.note.take().unwrap_or_default();
+                    if let Some(record) = self.selected_record() {
+                        self.decisions.annotate(&record.invoice_id, &note);
+                        self.dirty = true;
+                    }
+                }
+                KeyCode::Esc => self.note = None,
+                KeyCode::Backspace => {
+                    note.pop();
+                }
+                KeyCode::Char(c) => note.push(c),
+                _ => {}
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc if self.dirty && !confirming => {
+                self.confirm_discard = true;
+                self.message = Some("Unsaved changes: press q again to discard them, or w to save".to_string());
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.outcome = Some(ReviewOutcome::Discard),
+            KeyCode::Char('w') => self.outcome = Some(ReviewOutcome::Save),
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+                self.focus = match self.focus {
+                    Focus::Sections => Focus::Rows,
+                    Focus::Rows => Focus::Sections,
+                };
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.step(1),
+            KeyCode::Char(' ') => match self.selected_record() {
+                Some(record) => {
+                    self.decisions.toggle(&record.invoice_id);
+                    self.dirty = true;
+                }
+                None if self.on_skipped() => {
+                    self.message = Some("Invoices the report couldn't include can only be inspected".to_string());
+                }
+                None => {}
+            },
+            KeyCode::Char('n') => {
+                if let Some(record) = self.selected_record() {
+                    self.note = Some(self.decisions.note(&record.invoice_id).unwrap_or_default().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Move the cursor in the focused pane
+    fn step(&mut self, delta: isize) {
+        let (state_len, selected) = match self.focus {
+            Focus::Sections => (self.sections.len() + 1, self.section_state.selected()),
+            Focus::Rows => (self.row_count(), self.row_state.selected()),
+        };
+        if state_len == 0 {
+            return;
+        }
+        let next = (selected.unwrap_or(0) as isize + delta).clamp(0, state_len as isize - 1) as usize;
+        match self.focus {
+            Focus::Sections => {
+                self.section_state.select(Some(next));
+                self.row_state.select(Some(0));
+            }
+            Focus::Rows => self.row_state.select(Some(next)),
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let [header, body, details, footer] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(5), Constraint::Length(5), Constraint::Length(1)])
+                .areas(frame.area());
+        let [sections_area, rows_area] = Layout::horizontal([Constraint::Length(24), Constraint::Min(20)]).areas(body);
+
+        let excluded = self.decisions.invoices.values().filter(|d| d.excluded).count();
+        frame.render_widget(
+            Line::from(format!(
+                "Review {}: {} rows, {} excluded in review{}",
+                self.period,
+                self.records.len(),
+                excluded,
+                if self.dirty { " (unsaved)" } else { "" }
+            ))
+            .bold(),
+            header,
+        );
+
+        let focused = |focus: Focus| if self.focus == focus { Style::new().bold() } else { Style::new() };
+        let mut items: Vec<ListItem> = self
+            .sections
+            .iter()
+            .map(|s| ListItem::new(format!("{} ({})", s.label, s.rows.len())))
+            .collect();
+        items.push(ListItem::new(format!("Not reported ({})", self.skipped.len())));
+        let list = List::new(items)
+            .block(Block::bordered().title("States").title_style(focused(Focus::Sections)))
+            .highlight_symbol("> ")
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, sections_area, &mut self.section_state);
+
+        let row_highlight = Style::new().add_modifier(Modifier::REVERSED);
+        let rows_block = Block::bordered().title("Invoices").title_style(focused(Focus::Rows));
+        match self.sections.get(self.selected_section()) {
+            Some(section) => {
+                let rows = section.rows.iter().map(|&index| {
+                    let record = &self.records[index];
+                    let mark = if self.decisions.is_excluded(&record.invoice_id) { "x" } else { "" };
+                    let note = if self.decisions.note(&record.invoice_id).is_some() { "*" } else { "" };
+                    let row = Row::new(vec![
+                        mark.to_string(),
+                        DateFormat::Us.format(record.date),
+                        record.customer.clone(),
+                        record.invoice_id.clone(),
+                        dollars(record.total),
+                        dollars(record.tax),
+                        note.to_string(),
+                    ]);
+                    if mark.is_empty() { row } else { row.style(Style::new().add_modifier(Modifier::CROSSED_OUT)) }
+                });
+                let widths = [
+                    Constraint::Length(1),
+                    Constraint::Length(10),
+                    Constraint::Min(12),
+                    Constraint::Length(28),
+                    Constraint::Length(12),
+                    Constraint::Length(10),
+                    Constraint::Length(1),
+                ];
+                let table = Table::new(rows, widths)
+                    .header(Row::new(vec!["", "Date", "Customer", "Invoice", "Total", "Tax", ""]).bold())
+                    .block(rows_block)
+                    .row_highlight_style(row_highlight);
+                frame.render_stateful_widget(table, rows_area, &mut self.row_state);
+            }
+            None => {
+                let rows = self.skipped.iter().map(|skip| {
+                    Row::new(vec![skip.invoice_id.clone(), skip.customer.clone(), dollars(skip.amount), skip.reason.clone()])
+                });
+                let widths = [Constraint::Length(28), Constraint::Min(12), Constraint::Length(12), Constraint::Min(20)];
+                let table = Table::new(rows, widths)
+                    .header(Row::new(vec!["Invoice", "Customer", "Amount", "Reason"]).bold())
+                    .block(rows_block)
+                    .row_highlight_style(row_highlight);
+                frame.render_stateful_widget(table, rows_area, &mut self.row_state);
+            }
+        }
+
+        frame.render_widget(
+            Paragraph::new(self.details()).wrap(Wrap { trim: true }).block(Block::bordered().title("Details")),
+            details,
+        );
+
+        let footer_text = match (&self.note, &self.message) {
+            (Some(note), _) => format!("Note: {}_   (Enter to save, Esc to cancel)", note),
+            (None, Some(message)) => message.clone(),
+            (None, None) => "Tab switch pane · ↑/↓ move · Space include/exclude · n note · w save and export · q quit".to_string(),
+        };
+        frame.render_widget(Line::from(footer_text), footer);
+    }
+
+    /// Lines describing the selected invoice
+    fn details(&self) -> Vec<Line<'static>> {
+        if self.on_skipped() {
+            return match self.row_state.selected().and_then(|i| self.skipped.get(i)) {
+                Some(skip) => vec![
+                    Line::from(format!("{}  {}  {}", skip.invoice_id, skip.customer, dollars(skip.amount))),
+                    Line::from(format!("Not reported: {}", skip.reason)),
+                ],
+                None => Vec::new(),
+            };
+        }
+        let Some(record) = self.selected_record() else {
+            return Vec::new();
+        };
+        let mut lines = vec![Line::from(format!(
+            "{}  {}  {}  sales {}  tax {}  fees {}",
+            record.invoice_id,
+            record.customer,
+            record.section(),
+            dollars(record.licenses),
+            dollars(record.tax),
+            dollars(record.fees)
+        ))];
+        lines.extend(record.adjustments.iter().map(|a| Line::from(format!("Adjusted: {}", a.note))));
+        if self.decisions.is_excluded(&record.invoice_id) {
+            lines.push(Line::from("Excluded from the report in review"));
+        }
+        if let Some(note) = self.decisions.note(&record.invoice_id) {
+            lines.push(Line::from(format!("Note: {}", note)));
+        }
+        lines
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// Show the records by state and the invoices left out, letting the reviewer
+/// exclude invoices and annotate them until they save (`w`) or quit (`q`).
+/// `decisions` is updated in place either way; discard it on
+/// `ReviewOutcome::Discard`.
+pub fn run(
+    period: &str,
+    records: &[InvoiceRecord],
+    skipped: &[SkippedInvoice],
+    decisions: &mut ReviewDecisions,
+) -> Result<ReviewOutcome> {
+    if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        anyhow::bail!("Review needs an interactive terminal");
+    }
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, App::new(period, records, skipped, decisions));
+    ratatui::try_restore()?;
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, mut app: App<'_>) -> Result<ReviewOutcome> {
+    loop {
+        terminal.draw(|frame| app.render(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.handle_key(key);
+        }
+        if let Some(outcome) = app.outcome {
+            return Ok(outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn record(invoice_id: &str, state: &str) -> InvoiceRecord {
+        InvoiceRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 10, 4).unwrap(),
+            customer: "Lone Star LLC".to_string(),
+            state: state.to_string(),
+            total: 10825,
+            invoice_id: invoice_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn press(app: &mut App<'_>, code: KeyCode) {
+        app.handle_key(KeyEvent::from(code));
+    }
+
+    #[test]
+    fn test_review_keys_exclude_and_annotate() {
+        let records = vec![record("in_ca", "CA"), record("in_tx1", "TX"), record("in_tx2", "TX")];
+        let skipped = vec![SkippedInvoice { invoice_id: "in_x".to_string(), reason: "No state".to_string(), ..Default::default() }];
+        let mut decisions = ReviewDecisions::default();
+        let mut app = App::new("Q4-2024", &records, &skipped, &mut decisions);
+
+        // TX, second invoice: exclude it and note why
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Char('n'));
+        for c in "Test order".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| app.render(frame)).unwrap();
+
+        // Quitting with unsaved changes asks first
+        press(&mut app, KeyCode::Char('q'));
+        assert_eq!(app.outcome, None);
+        press(&mut app, KeyCode::Char('w'));
+        assert_eq!(app.outcome, Some(ReviewOutcome::Save));
+
+        assert!(decisions.is_excluded("in_tx2"));
+        assert_eq!(decisions.note("in_tx2"), Some("Test order"));
+        assert_eq!(decisions.invoices.len(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::stripe::models::InvoiceRecord;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Customer metadata key naming the parent entity a customer files under,
+/// used when the profile doesn't set `parent_metadata_key`
+pub const PARENT_METADATA_KEY: &str = "parent_account";
+
+/// How rows are aggregated within each state section (`--rollup`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rollup {
+    /// One row per invoice
+    #[default]
+    Invoice,
+    /// Combine invoices of customers with a parent account into one row per parent
+    Parent,
+}
+
+impl Rollup {
+    /// Apply the rollup to records already sorted for output
+    pub fn apply(self, records: &[InvoiceRecord]) -> Vec<InvoiceRecord> {
+        match self {
+            Rollup::Invoice => records.to_vec(),
+            Rollup::Parent => rollup_by_parent(records),
+        }
+    }
+}
+
+/// Consolidate records of customers that have a parent account into one row
+/// per parent, section and connected account, named after the parent. The row
+/// takes the place of the parent's first record, so ordering is preserved;
+/// its invoice ID lists every invoice it covers. Records without a parent
+/// are passed through unchanged.
+pub fn rollup_by_parent(records: &[InvoiceRecord]) -> Vec<InvoiceRecord> {
+    let mut rows: Vec<InvoiceRecord> = Vec::with_capacity(records.len());
+    let mut parent_rows: HashMap<(String, Option<&str>, &str), usize> = HashMap::new();
+
+    for record in records {
+        let Some(parent) = record.parent.as_deref() else {
+            rows.push(record.clone());
+            continue;
+        };
+
+        let key = (record.section(), record.account.as_deref(), parent);
+        match parent_rows.get(&key) {
+            Some(&index) => {
+                let row = &mut rows[index];
+                row.users += record.users;
+                row.licenses += record.licenses;
+                row.taxable_sales += record.taxable_sales;
+                row.non_taxable_sales += record.non_taxable_sales;
+                row.tax += record.tax;
+                row.local_tax += record.local_tax;
+                for jurisdiction in &record.tax_jurisdictions {
+                    match row
+                        .tax_jurisdictions
+                        .iter_mut()
+                        .find(|j| j.jurisdiction == jurisdiction.jurisdiction && j.level == jurisdiction.level)
+                    {
+                        Some(existing) => existing.amount += jurisdiction.amount,
+                        None => row.tax_jurisdictions.push(jurisdiction.clone()),
+                    }
+                }
+                row.total += record.total;
+                row.fees += record.fees;
+                row.fee_breakdown.add(&record.fee_breakdown);
+                row.state_inferred |= record.state_inferred;
+                row.rate_mismatch |= record.rate_mismatch;
+                row.refunded |= record.refunded;
+                if row.state_source != record.state_source {
+                    row.state_source = None;
+                }
+                if row.address != record.address {
+                    row.address = None;
+                }
+                if row.plan != record.plan {
+                    row.plan = None;
+                }
+                if row.subscription != record.subscription {
+                    row.subscription = None;
+                    row.subscription_schedule = None;
+                }
+                for adjustment in &record.adjustments {
+                    row.add_adjustment(&adjustment.columns, adjustment.note.clone());
+                }
+                row.invoice_id = format!("{},{}", row.invoice_id, record.invoice_id);
+                if let Some(number) = &record.invoice_number {
+                    row.invoice_number = Some(match row.invoice_number.take() {
+                        Some(numbers) => format!("{},{}", numbers, number),
+                        None => number.clone(),
+                    });
+                }
+            }
+            None => {
+                let mut row = record.clone();
+                row.customer = parent.to_string();
+                parent_rows.insert(key, rows.len());
+                rows.push(row);
+            }
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, customer: &str, parent: Option<&str>, state: &str, licenses: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            invoice_id: id.to_string(),
+            invoice_number: Some(id.replace("in_", "INV-")),
+            date: chrono::NaiveDate::from_ymd_opt(2026, 7, 1).unwrap(),
+            customer: customer.to_string(),
+            parent: parent.map(str::to_string),
+            state: state.to_string(),
+            users: 1,
+            licenses,
+            total: licenses,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rollup_by_parent() {
+        let records = vec![
+            record("in_1", "Acme East", Some("Acme Holdings"), "TX", 10000),
+            record("in_2", "Solo LLC", None, "TX", 5000),
+            record("in_3", "Acme West", Some("Acme Holdings"), "TX", 20000),
+            record("in_4", "Acme West", Some("Acme Holdings"), "CA", 7000),
+        ];
+
+        let rows = Rollup::Parent.apply(&records);
+        assert_eq!(rows.len(), 3);
+
+        assert_eq!(rows[0].customer, "Acme Holdings");
+        assert_eq!(rows[0].invoice_id, "in_1,in_3");
+        assert_eq!(rows[0].invoice_number.as_deref(), Some("INV-1,INV-3"));
+        assert_eq!((rows[0].users, rows[0].licenses, rows[0].total), (2, 30000, 30000));
+
+        assert_eq!(rows[1].customer, "Solo LLC");
+        assert_eq!((rows[2].customer.as_str(), rows[2].state.as_str()), ("Acme Holdings", "CA"));
+
+        assert_eq!(Rollup::Invoice.apply(&records).len(), 4);
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use std::str::FromStr;
+
+/// When `schedule` regenerates the report: a five-field cron expression
+/// (minute, hour, day of month, month, day of week), evaluated in the
+/// profile's timezone, else UTC
+pub struct CronSchedule {
+    cron: Cron,
+    expression: String,
+    timezone: Option<Tz>,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str, timezone: Option<Tz>) -> Result<Self> {
+        let cron = Cron::from_str(expression).map_err(|e| anyhow!("Invalid cron expression '{}': {}", expression, e))?;
+        Ok(CronSchedule { cron, expression: expression.to_string(), timezone })
+    }
+
+    /// The first scheduled time after `now`
+    pub fn next_after(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let next = match self.timezone {
+            Some(tz) => self.cron.find_next_occurrence(&now.with_timezone(&tz), false).map(|t| t.with_timezone(&Utc)),
+            None => self.cron.find_next_occurrence(&now, false),
+        };
+        next.map_err(|e| anyhow!("No time after {} matches '{}': {}", now, self.expression, e))
+    }
+}
+
+/// Post a message to a chat webhook (`--notify-webhook`) as `{"text": ...}`,
+/// which Slack, Mattermost and Google Chat incoming webhooks all accept
+pub async fn notify(url: &str, text: &str) -> Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "text": text }))
+        .send()
+        .await
+        .context("Failed to send notification")?;
+    if !response.status().is_success() {
+        anyhow::bail!("Notification webhook returned {}", response.status());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_after_in_profile_timezone() {
+        let now = "2026-10-17T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        // 06:00 on the 5th of the month after each quarter ends
+        let utc = CronSchedule::parse("0 6 5 1,4,7,10 *", None).unwrap();
+        assert_eq!(utc.next_after(now).unwrap().to_rfc3339(), "2027-01-05T06:00:00+00:00");
+
+        let chicago = CronSchedule::parse("0 6 5 1,4,7,10 *", Some(chrono_tz::America::Chicago)).unwrap();
+        assert_eq!(chicago.next_after(now).unwrap().to_rfc3339(), "2027-01-05T12:00:00+00:00");
+
+        assert!(CronSchedule::parse("every quarter", None).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::config::TokenScope;
+use crate::health::{self, Health};
+use crate::http::{self, ServeOptions};
+use crate::report::generator::ReportOptions;
+use crate::report::grouping::GroupedReport;
+use crate::report::pipeline::build_report;
+use crate::report::progress::ReportProgress;
+use crate::report::quarter::Period;
+use crate::report::OutputFormat;
+use crate::source::events::{replay_events, ReplaySummary, EVENT_TYPES};
+use crate::source::{FixtureSource, InvoiceSource, SelectedSource, Store, StoreSource};
+use crate::stripe::StripeClient;
+use anyhow::{anyhow, bail, Context, Result};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+
+/// A key clients present to the report API, and what it lets them do
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    /// Who holds it, for the logs
+    pub name: String,
+    pub key: String,
+    pub scope: TokenScope,
+}
+
+/// What the report API needs to build reports for a request
+pub struct ReportServer<S> {
+    pub source: S,
+    /// Report settings, including the timezone periods are bounded in
+    pub options: ReportOptions,
+    /// Keys accepted from clients; every request must present one
+    pub tokens: Vec<ApiToken>,
+    /// Client admin tokens' syncs fetch events with; none disables syncing
+    pub sync_client: Option<StripeClient>,
+}
+
+/// Sources admin tokens can bring up to date (`POST /sync/{year}/{quarter}`)
+pub trait SyncSource: InvoiceSource {
+    /// Store the Stripe events from a range, as `backfill-events` does.
+    /// `None` for sources that have nothing to sync.
+    fn sync(&self, _client: &StripeClient, _start: i64, _end: i64) -> impl Future<Output = Result<Option<ReplaySummary>>> + Send {
+        async { Ok(None) }
+    }
+}
+
+/// Reports from the API are always current
+impl SyncSource for StripeClient {}
+
+impl SyncSource for FixtureSource {}
+
+impl SyncSource for SelectedSource {
+    async fn sync(&self, client: &StripeClient, start: i64, end: i64) -> Result<Option<ReplaySummary>> {
+        match self {
+            SelectedSource::Store(store) => store.sync(client, start, end).await,
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<S: Store> SyncSource for StoreSource<S> {
+    async fn sync(&self, client: &StripeClient, start: i64, end: i64) -> Result<Option<ReplaySummary>> {
+        let events = client.fetch_events(&EVENT_TYPES, start, end).await?;
+        replay_events(self, &events, Some(client)).await.map(Some)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    format: Option<String>,
+}
+
+impl<S: InvoiceSource> ReportServer<S> {
+    /// Build a quarter's report and render it by state in `format`, returning
+    /// its content type and body
+    pub async fn render(&self, period: Period, format: OutputFormat) -> Result<(&'static str, Vec<u8>)> {
+        let (start, end) = period.bounds_in(self.options.timezone)?;
+        let mut generator = build_report(&self.source, start, end, &self.options, &ReportProgress::new(true)).await?;
+        generator.sort_records();
+        let skipped = generator.skipped();
+        let report = GroupedReport::by_state(generator.get_records())
+            .with_skipped(skipped)
+            .with_diagnostics(generator.diagnostics())
+            .with_partial(generator.partial())
+            .with_title(format!("Sales Tax Report {}", period))
+            .with_period(period.to_string());
+
+        let mut body = Vec::new();
+        format.formatter().write_report(&report, &mut body)?;
+        let content_type = match format {
+            OutputFormat::Json => "application/json",
+            OutputFormat::Csv
+            | OutputFormat::Xero
+            | OutputFormat::Taxjar
+            | OutputFormat::Avalara
+            | OutputFormat::CsvNormalized => "text/csv; charset=utf-8",
+            OutputFormat::Tsv => "text/tab-separated-values; charset=utf-8",
+            OutputFormat::HtmlFragment | OutputFormat::Html => "text/html; charset=utf-8",
+            OutputFormat::Markdown => "text/markdown; charset=utf-8",
+            OutputFormat::Beancount => "text/plain; charset=utf-8",
+            OutputFormat::Jsonl => "application/x-ndjson",
+            OutputFormat::Parquet => "application/vnd.apache.parquet",
+            OutputFormat::Pdf => "application/pdf",
+        };
+        Ok((content_type, body))
+    }
+
+    /// The token the request carries, as `Authorization: Bearer <key>` or
+    /// `X-API-Key: <key>`, if it's one of ours
+    pub fn token(&self, headers: &HeaderMap) -> Option<&ApiToken> {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let key = headers.get("x-api-key").and_then(|value| value.to_str().ok());
+        let given = bearer.or(key)?.trim();
+        // Every token is compared, so response times don't reveal which one
+        // matched; an empty key would let in any request sending an empty header.
+        // Both sides are trimmed, so a key read from a file with a trailing
+        // newline still matches.
+        self.tokens.iter().fold(None, |found, token| {
+            let key = token.key.trim();
+            let matches = !key.is_empty() && constant_time_eq(given.as_bytes(), key.as_bytes());
+            found.or(matches.then_some(token))
+        })
+    }
+
+    /// The request's token if its scope allows `needed`, else the status and
+    /// message to refuse it with
+    fn authorize(&self, headers: &HeaderMap, needed: TokenScope) -> std::result::Result<&ApiToken, (StatusCode, String)> {
+        match self.token(headers) {
+            None => Err((StatusCode::UNAUTHORIZED, "Missing or invalid API key".to_string())),
+            Some(token) if !token.scope.allows(needed) => {
+                tracing::warn!("Refused {} token '{}' an {} request", token.scope, token.name, needed);
+                Err((StatusCode::FORBIDDEN, format!("This token's scope doesn't allow {} requests", needed)))
+            }
+            Some(token) => Ok(token),
+        }
+    }
+
+}
+
+/// Compare without returning early, so response times don't leak how much of a key matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Parse `/reports/{year}/{quarter}`, where the quarter is `3` or `Q3`
+fn parse_period(year: &str, quarter: &str) -> Result<Period> {
+    let year: i32 = year.parse().map_err(|_| anyhow!("'{}' is not a year", year))?;
+    let quarter = quarter.trim_start_matches(['Q', 'q']);
+    let quarter: u32 = quarter.parse().map_err(|_| anyhow!("'{}' is not a quarter", quarter))?;
+    Period::new(year, quarter)
+}
+
+/// The report routes, plus the unauthenticated health endpoints
+fn router<S: SyncSource + Send + 'static>(server: ReportServer<S>, health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/reports/{year}/{quarter}", get(handle_report::<S>))
+        .route("/sync/{year}/{quarter}", post(handle_sync::<S>))
+        .with_state(Arc::new(server))
+        .merge(health::routes(health))
+}
+
+/// Serve `GET /reports/{year}/{quarter}?format=json|csv|tsv` (any token)
+/// and `POST /sync/{year}/{quarter}` (admin tokens) until Ctrl-C or
+/// SIGTERM, finishing requests already being handled
+pub async fn serve<S: SyncSource + Send + 'static>(options: &ServeOptions, server: ReportServer<S>) -> Result<()> {
+    if server.tokens.is_empty() {
+        bail!("No report API tokens; pass --api-key, set TAX_REPORT_API_KEY or add report_tokens to the profile");
+    }
+    if let Some(token) = server.tokens.iter().find(|token| token.key.trim().is_empty()) {
+        bail!("The report API token '{}' is empty; give it a secret", token.name);
+    }
+    let health = Health::new();
+    let app = router(server, health.clone());
+    tracing::info!("Serving reports on {}/reports/{{year}}/{{quarter}}", options.base_url());
+    http::run(app, options, health).await.context("Report server failed")
+}
+
+async fn handle_report<S: SyncSource + Send + 'static>(
+    State(server): State<Arc<ReportServer<S>>>,
+    Path((year, quarter)): Path<(String, String)>,
+    Query(query): Query<ReportQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(refused) = server.authorize(&headers, TokenScope::Read) {
+        return refused.into_response();
+    }
+    let period = match parse_period(&year, &quarter) {
+        Ok(period) => period,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let format = match OutputFormat::from_str(query.format.as_deref().unwrap_or("json"), true) {
+        Ok(format) => format,
+        Err(_) => return (StatusCode::BAD_REQUEST, "unknown format").into_response(),
+    };
+    match server.render(period, format).await {
+        Ok((content_type, body)) => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+        Err(e) => {
+            // The error may quote Stripe or the local store; clients only learn it failed
+            tracing::error!("Failed to build {} report: {:#}", period, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build the report").into_response()
+        }
+    }
+}
+
+async fn handle_sync<S: SyncSource + Send + 'static>(
+    State(server): State<Arc<ReportServer<S>>>,
+    Path((year, quarter)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let token = match server.authorize(&headers, TokenScope::Admin) {
+        Ok(token) => token,
+        Err(refused) => return refused.into_response(),
+    };
+    let period = match parse_period(&year, &quarter) {
+        Ok(period) => period,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let Some(client) = &server.sync_client else {
+        return (StatusCode::CONFLICT, "Syncing needs a Stripe API key in the profile").into_response();
+    };
+    let synced = match period.bounds_in(server.options.timezone) {
+        Ok((start, end)) => server.source.sync(client, start, end).await,
+        Err(e) => Err(e),
+    };
+    match synced {
+        Ok(Some(summary)) => {
+            tracing::info!("'{}' synced {}: {} events stored, {} already stored", token.name, period, summary.stored, summary.duplicates);
+            Json(serde_json::json!({"period": period.to_string(), "stored": summary.stored, "duplicates": summary.duplicates}))
+                .into_response()
+        }
+        Ok(None) => (StatusCode::CONFLICT, "Reports aren't served from a store, so there's nothing to sync").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to sync {}: {:#}", period, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to sync the store").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {"id": "in_1", "customer": "cus_1", "customer_name": "Lone Star LLC", "status": "paid", "created": 1721000000,
+             "amount_paid": 10825, "tax": 825, "total": 10825,
+             "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 4}]}}
+        ],
+        "customers": [{"id": "cus_1", "name": "Lone Star LLC", "address": {"state": "TX", "country": "US"}}]
+    }"#;
+
+    fn token(name: &str, key: &str, scope: TokenScope) -> ApiToken {
+        ApiToken { name: name.to_string(), key: key.to_string(), scope }
+    }
+
+    fn fixture_server(tokens: Vec<ApiToken>) -> ReportServer<FixtureSource> {
+        ReportServer {
+            source: FixtureSource::from_json(FIXTURE).unwrap(),
+            options: ReportOptions::default(),
+            tokens,
+            sync_client: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_and_authorize() {
+        let server = fixture_server(vec![token("finance", "secret", TokenScope::Read)]);
+
+        let (content_type, body) = server.render(parse_period("2024", "Q3").unwrap(), OutputFormat::Csv).await.unwrap();
+        assert!(content_type.starts_with("text/csv"));
+        assert!(String::from_utf8(body).unwrap().contains("Lone Star LLC"));
+        assert!(parse_period("2024", "5").is_err());
+
+        let mut headers = HeaderMap::new();
+        assert!(server.token(&headers).is_none());
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(server.token(&headers).is_none());
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert_eq!(server.token(&headers).unwrap().name, "finance");
+
+        // Whitespace around either key is ignored
+        let padded = fixture_server(vec![token("ops", "secret\n", TokenScope::Admin)]);
+        assert_eq!(padded.token(&headers).unwrap().name, "ops");
+        headers.insert("x-api-key", " secret ".parse().unwrap());
+        headers.remove(header::AUTHORIZATION);
+        assert_eq!(padded.token(&headers).unwrap().name, "ops");
+
+        let open = ReportServer { tokens: vec![token("blank", " ", TokenScope::Admin)], ..server };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "".parse().unwrap());
+        assert!(open.token(&headers).is_none());
+        headers.insert("x-api-key", " ".parse().unwrap());
+        assert!(open.token(&headers).is_none());
+        let options = ServeOptions { bind: "127.0.0.1:0".parse().unwrap(), tls: None, shutdown_grace: Duration::ZERO };
+        assert!(serve(&options, open).await.is_err());
+        assert!(serve(&options, fixture_server(Vec::new())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_scopes() {
+        let mut server = fixture_server(vec![token("finance", "fin", TokenScope::Read), token("ops", "ops", TokenScope::Admin)]);
+        server.sync_client = Some(StripeClient::new("sk_test_unused".to_string()));
+        let base = health::spawn_test_server(router(server, Health::new())).await;
+        let client = reqwest::Client::new();
+
+        // Both scopes read reports; only admin may sync
+        for key in ["fin", "ops"] {
+            let response = client.get(format!("{}/reports/2024/Q3", base)).bearer_auth(key).send().await.unwrap();
+            assert_eq!(response.status(), 200);
+        }
+        let response = client.post(format!("{}/sync/2024/Q3", base)).bearer_auth("fin").send().await.unwrap();
+        assert_eq!(response.status(), 403);
+        let response = client.post(format!("{}/sync/2024/Q3", base)).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        // A fixture has nothing to sync, so the request is admitted but refused
+        let response = client.post(format!("{}/sync/2024/Q3", base)).bearer_auth("ops").send().await.unwrap();
+        assert_eq!(response.status(), 409);
+    }
+
+    #[tokio::test]
+    async fn test_health_needs_no_key() {
+        let server = fixture_server(vec![token("finance", "secret", TokenScope::Read)]);
+        let base = health::spawn_test_server(router(server, Health::new())).await;
+        let client = reqwest::Client::new();
+
+        assert_eq!(client.get(format!("{}/healthz", base)).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/readyz", base)).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/reports/2024/Q3", base)).send().await.unwrap().status(), 401);
+        let response = client.get(format!("{}/reports/2024/Q3", base)).bearer_auth("secret").send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::report::formatter::{write_csv_state_detail, write_csv_state_summary};
+use crate::report::grouping::GroupedReport;
+use crate::report::output::OutputSink;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Name of the per-state summary written alongside the state files
+pub const SUMMARY_FILE_NAME: &str = "summary.csv";
+
+/// Write one CSV per state (`TX.csv`, `CA.csv`, ...) plus `summary.csv` into
+/// `dir`, creating it if needed, for workflows that upload each state's detail
+/// separately. Returns the paths written, summary last.
+pub fn write_split_by_state(report: &GroupedReport<'_>, dir: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+
+    let mut written = Vec::with_capacity(report.groups.len() + 1);
+
+    for group in &report.groups {
+        let path = dir.join(format!("{}.csv", file_stem(&group.state)));
+        let mut sink = OutputSink::create(Some(&path))?;
+        write_csv_state_detail(report, group, &mut sink)?;
+        sink.finish()?;
+        written.push(path);
+    }
+
+    let path = dir.join(SUMMARY_FILE_NAME);
+    let mut sink = OutputSink::create(Some(&path))?;
+    write_csv_state_summary(report, &mut sink)?;
+    sink.finish()?;
+    written.push(path);
+
+    Ok(written)
+}
+
+/// State codes come from customer data, so keep them from escaping the directory
+fn file_stem(state: &str) -> String {
+    state
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stripe::models::InvoiceRecord;
+
+    #[test]
+    fn test_split_writes_state_files_and_summary() {
+        let records = vec![
+            InvoiceRecord {
+                date: chrono::NaiveDate::from_ymd_opt(2025, 10, 15).unwrap(),
+                customer: "Lone Star LLC".to_string(),
+                invoice_id: "in_tx".to_string(),
+                invoice_number: Some("LS-0007".to_string()),
+                users: 2,
+                state: "TX".to_string(),
+                licenses: 10000,
+                tax: 825,
+                total: 10825,
+                fees: 320,
+                ..Default::default()
+            },
+            InvoiceRecord {
+                date: chrono::NaiveDate::from_ymd_opt(2025, 11, 1).unwrap(),
+                customer: "Golden Gate Inc".to_string(),
+                users: 1,
+                state: "CA".to_string(),
+                licenses: 5000,
+                tax: 363,
+                total: 5363,
+                fees: 180,
+                ..Default::default()
+            },
+        ];
+
+        let dir = std::env::temp_dir().join(format!("stripe-tax-reporter-split-{}", std::process::id()));
+        let written = write_split_by_state(&GroupedReport::by_state(&records), &dir).unwrap();
+
+        let names: Vec<_> = written.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, vec!["CA.csv", "TX.csv", "summary.csv"]);
+
+        let texas = std::fs::read_to_string(dir.join("TX.csv")).unwrap();
+        assert_eq!(
+            texas,
+            "Date,Customer,Invoice Number,Invoice ID,Users,Licenses,Tax,Total,Fees\n\
+             10/15/2025,Lone Star LLC,LS-0007,in_tx,2,100.00,8.25,108.25,3.20\n\
+             Subtotal,,,,,100.00,8.25,108.25,3.20\n"
+        );
+
+        let summary = std::fs::read_to_string(dir.join("summary.csv")).unwrap();
+        assert!(summary.starts_with("State,Invoices,Licenses,Tax,Total,Fees\nCA,1,50.00,3.63,53.63,1.80\n"));
+        assert!(summary.ends_with("GRAND TOTAL,2,150.00,11.88,161.88,5.00\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
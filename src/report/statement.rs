@@ -0,0 +1,234 @@
+use crate::report::formatter::DateFormat;
+use crate::stripe::models::InvoiceRecord;
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+#[cfg(feature = "pdf")]
+mod pdf;
+
+/// Output formats for customer statements (`statement --format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatementFormat {
+    /// Printable statement to send to the customer (builds with the `pdf`
+    /// feature, on by default)
+    #[default]
+    Pdf,
+    /// Invoice table then the per-state summary, comma-separated
+    Csv,
+    /// The statement as a JSON document
+    Json,
+}
+
+impl StatementFormat {
+    /// Conventional file extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            StatementFormat::Pdf => "pdf",
+            StatementFormat::Csv => "csv",
+            StatementFormat::Json => "json",
+        }
+    }
+}
+
+/// One invoice on a statement
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub invoice_id: String,
+    /// Report section: the state, or `NON-US <country>`, with the currency
+    /// appended when it isn't USD
+    pub state: String,
+    pub amount: i64,
+    pub tax: i64,
+    pub total: i64,
+}
+
+/// Invoice count and amounts for part of a statement
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct StatementTotals {
+    pub invoices: usize,
+    pub amount: i64,
+    pub tax: i64,
+    pub total: i64,
+}
+
+impl StatementTotals {
+    fn add(&mut self, record: &InvoiceRecord) {
+        self.invoices += 1;
+        self.amount += record.licenses;
+        self.tax += record.tax;
+        self.total += record.total;
+    }
+}
+
+/// Amounts and tax collected in one state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementState {
+    pub state: String,
+    #[serde(flatten)]
+    pub totals: StatementTotals,
+}
+
+/// A customer-facing statement of one customer's paid invoices for a
+/// calendar year, with the tax collected in each state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub customer_id: String,
+    /// Customer name as reported, or the ID if there are no invoices
+    pub customer: String,
+    pub year: i32,
+    /// Invoices in date order
+    pub invoices: Vec<StatementLine>,
+    /// Per-state summary, ordered by state
+    pub states: Vec<StatementState>,
+    /// Totals per ISO currency code (USD unless invoices were in other currencies)
+    pub totals: BTreeMap<String, StatementTotals>,
+}
+
+impl Statement {
+    /// Build a statement from the customer's report records
+    pub fn new(customer_id: &str, year: i32, records: &[InvoiceRecord]) -> Self {
+        let mut sorted: Vec<&InvoiceRecord> = records.iter().collect();
+        sorted.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_id.cmp(&b.invoice_id)));
+
+        let mut states: BTreeMap<String, StatementTotals> = BTreeMap::new();
+        let mut totals: BTreeMap<String, StatementTotals> = BTreeMap::new();
+        for record in &sorted {
+            states.entry(record.section()).or_default().add(record);
+            let currency = record.currency.clone().unwrap_or_else(|| "USD".to_string());
+            totals.entry(currency).or_default().add(record);
+        }
+
+        Statement {
+            customer_id: customer_id.to_string(),
+            customer: sorted.first().map_or_else(|| customer_id.to_string(), |r| r.customer.clone()),
+            year,
+            invoices: sorted
+                .iter()
+                .map(|record| StatementLine {
+                    date: record.date,
+                    invoice_id: record.invoice_id.clone(),
+                    state: record.section(),
+                    amount: record.licenses,
+                    tax: record.tax,
+                    total: record.total,
+                })
+                .collect(),
+            states: states.into_iter().map(|(state, totals)| StatementState { state, totals }).collect(),
+            totals,
+        }
+    }
+
+    pub fn write(&self, format: StatementFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            #[cfg(feature = "pdf")]
+            StatementFormat::Pdf => out.write_all(&pdf::render(self))?,
+            #[cfg(not(feature = "pdf"))]
+            StatementFormat::Pdf => anyhow::bail!("{}", crate::report::formatter::PDF_UNAVAILABLE),
+            StatementFormat::Csv => self.write_csv(out)?,
+            StatementFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_csv(&self, out: &mut dyn Write) -> Result<()> {
+        let row = |out: &mut dyn Write, fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| crate::report::formatter::csv_escape(f)).collect();
+            writeln!(out, "{}", fields.join(","))?;
+            Ok(())
+        };
+
+        row(out, &["Statement", &format!("{} ({})", self.customer, self.customer_id), &self.year.to_string()])?;
+        row(out, &["Date", "Invoice", "State", "Amount", "Tax", "Total"])?;
+        for line in &self.invoices {
+            row(out, &[&DateFormat::Us.format(line.date), &line.invoice_id, &line.state, &dollars(line.amount), &dollars(line.tax), &dollars(line.total)])?;
+        }
+        for (currency, total) in &self.totals {
+            let label = self.total_label(currency);
+            row(out, &[&label, "", "", &dollars(total.amount), &dollars(total.tax), &dollars(total.total)])?;
+        }
+
+        writeln!(out)?;
+        row(out, &["State", "Invoices", "Amount", "Tax", "Total"])?;
+        for state in &self.states {
+            let totals = &state.totals;
+            row(out, &[&state.state, &totals.invoices.to_string(), &dollars(totals.amount), &dollars(totals.tax), &dollars(totals.total)])?;
+        }
+        Ok(())
+    }
+
+    /// "Total", or "Total (EUR)" when the statement has several currencies
+    /// or none of them is USD
+    fn total_label(&self, currency: &str) -> String {
+        if self.totals.len() == 1 && currency == "USD" {
+            "Total".to_string()
+        } else {
+            format!("Total ({})", currency)
+        }
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, date: &str, state: &str, licenses: i64, tax: i64) -> InvoiceRecord {
+        InvoiceRecord {
+            invoice_id: id.to_string(),
+            date: NaiveDate::parse_from_str(date, "%m/%d/%Y").unwrap(),
+            customer: "Acme Corp".to_string(),
+            state: state.to_string(),
+            country: Some("US".to_string()),
+            licenses,
+            tax,
+            total: licenses + tax,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_statement_by_date_and_state() {
+        let records = vec![
+            record("in_3", "11/02/2024", "TX", 10000, 825),
+            record("in_1", "02/15/2024", "CA", 5000, 0),
+            record("in_2", "06/30/2024", "TX", 10000, 825),
+        ];
+
+        let statement = Statement::new("cus_acme", 2024, &records);
+        assert_eq!(statement.customer, "Acme Corp");
+        let ids: Vec<&str> = statement.invoices.iter().map(|l| l.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_1", "in_2", "in_3"]);
+
+        assert_eq!(statement.states.len(), 2);
+        assert_eq!((statement.states[1].state.as_str(), statement.states[1].totals.invoices, statement.states[1].totals.tax), ("TX", 2, 1650));
+        assert_eq!(statement.totals["USD"].total, 26650);
+
+        let mut csv = Vec::new();
+        statement.write(StatementFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("Statement,Acme Corp (cus_acme),2024\n"));
+        assert!(csv.contains("Total,,,250.00,16.50,266.50\n"));
+        assert!(csv.contains("TX,2,200.00,16.50,216.50\n"));
+
+        let mut pdf = Vec::new();
+        #[cfg(feature = "pdf")]
+        {
+            statement.write(StatementFormat::Pdf, &mut pdf).unwrap();
+            assert!(pdf.starts_with(b"%PDF-"));
+        }
+        #[cfg(not(feature = "pdf"))]
+        assert!(statement.write(StatementFormat::Pdf, &mut pdf).is_err());
+    }
+}
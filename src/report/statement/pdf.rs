@@ -0,0 +1,97 @@
+//! Customer statements as PDF, laid out with the shared report PDF helpers.
+
+use super::{dollars, Statement};
+use crate::report::formatter::DateFormat;
+use crate::report::pdf::{write_pdf, Align, Layout, MARGIN, PAGE_WIDTH, ROW_HEIGHT};
+
+/// Right edges of the amount columns
+const AMOUNT_RIGHT: f32 = 430.0;
+const TAX_RIGHT: f32 = 495.0;
+const TOTAL_RIGHT: f32 = PAGE_WIDTH - MARGIN;
+
+fn invoice_header(layout: &mut Layout) {
+    layout.row(
+        true,
+        &[(MARGIN, "Date"), (120.0, "Invoice"), (300.0, "State")],
+        &[(AMOUNT_RIGHT, "Amount".into()), (TAX_RIGHT, "Tax".into()), (TOTAL_RIGHT, "Total".into())],
+    );
+    layout.rule();
+}
+
+fn state_header(layout: &mut Layout) {
+    layout.row(
+        true,
+        &[(MARGIN, "State")],
+        &[
+            (300.0, "Invoices".into()),
+            (AMOUNT_RIGHT, "Amount".into()),
+            (TAX_RIGHT, "Tax Collected".into()),
+            (TOTAL_RIGHT, "Total".into()),
+        ],
+    );
+    layout.rule();
+}
+
+/// Render the statement as a PDF document
+pub fn render(statement: &Statement) -> Vec<u8> {
+    let mut layout = Layout::new(format!("{} - {} (continued)", statement.customer, statement.year));
+
+    layout.text(MARGIN, true, 16.0, Align::Left, "Statement of Invoices and Sales Tax");
+    layout.advance(24.0, None);
+    layout.text(MARGIN, false, 10.0, Align::Left, format!("Customer: {} ({})", statement.customer, statement.customer_id));
+    layout.advance(14.0, None);
+    layout.text(
+        MARGIN,
+        false,
+        10.0,
+        Align::Left,
+        format!("Period: January 1, {0} to December 31, {0} (paid invoices)", statement.year),
+    );
+    layout.advance(30.0, None);
+
+    invoice_header(&mut layout);
+    for line in &statement.invoices {
+        layout.advance(ROW_HEIGHT, Some(&invoice_header));
+        layout.row(
+            false,
+            &[(MARGIN, &DateFormat::Us.format(line.date)), (120.0, &line.invoice_id), (300.0, &line.state)],
+            &[(AMOUNT_RIGHT, dollars(line.amount)), (TAX_RIGHT, dollars(line.tax)), (TOTAL_RIGHT, dollars(line.total))],
+        );
+    }
+    if statement.invoices.is_empty() {
+        layout.advance(ROW_HEIGHT, Some(&invoice_header));
+        layout.row(false, &[(MARGIN, "No paid invoices in this period.")], &[]);
+    }
+    layout.rule();
+    for (currency, total) in &statement.totals {
+        let label = statement.total_label(currency);
+        layout.advance(ROW_HEIGHT, Some(&invoice_header));
+        layout.row(
+            true,
+            &[(MARGIN, &label)],
+            &[(AMOUNT_RIGHT, dollars(total.amount)), (TAX_RIGHT, dollars(total.tax)), (TOTAL_RIGHT, dollars(total.total))],
+        );
+    }
+
+    layout.advance(3.0 * ROW_HEIGHT, None);
+    layout.text(MARGIN, true, 12.0, Align::Left, "Sales Tax by State");
+    layout.advance(20.0, None);
+    state_header(&mut layout);
+    for state in &statement.states {
+        let totals = &state.totals;
+        layout.advance(ROW_HEIGHT, Some(&state_header));
+        layout.row(
+            false,
+            &[(MARGIN, &state.state)],
+            &[
+                (300.0, totals.invoices.to_string()),
+                (AMOUNT_RIGHT, dollars(totals.amount)),
+                (TAX_RIGHT, dollars(totals.tax)),
+                (TOTAL_RIGHT, dollars(totals.total)),
+            ],
+        );
+    }
+
+    write_pdf(&format!("Statement for {}, {}", statement.customer, statement.year), layout.pages)
+}
+
@@ -0,0 +1,175 @@
+/// USPS two-letter codes with the state, district, territory or military
+/// region each stands for
+const USPS_STATES: &[(&str, &str)] = &[
+    ("AL", "Alabama"),
+    ("AK", "Alaska"),
+    ("AZ", "Arizona"),
+    ("AR", "Arkansas"),
+    ("CA", "California"),
+    ("CO", "Colorado"),
+    ("CT", "Connecticut"),
+    ("DE", "Delaware"),
+    ("DC", "District of Columbia"),
+    ("FL", "Florida"),
+    ("GA", "Georgia"),
+    ("HI", "Hawaii"),
+    ("ID", "Idaho"),
+    ("IL", "Illinois"),
+    ("IN", "Indiana"),
+    ("IA", "Iowa"),
+    ("KS", "Kansas"),
+    ("KY", "Kentucky"),
+    ("LA", "Louisiana"),
+    ("ME", "Maine"),
+    ("MD", "Maryland"),
+    ("MA", "Massachusetts"),
+    ("MI", "Michigan"),
+    ("MN", "Minnesota"),
+    ("MS", "Mississippi"),
+    ("MO", "Missouri"),
+    ("MT", "Montana"),
+    ("NE", "Nebraska"),
+    ("NV", "Nevada"),
+    ("NH", "New Hampshire"),
+    ("NJ", "New Jersey"),
+    ("NM", "New Mexico"),
+    ("NY", "New York"),
+    ("NC", "North Carolina"),
+    ("ND", "North Dakota"),
+    ("OH", "Ohio"),
+    ("OK", "Oklahoma"),
+    ("OR", "Oregon"),
+    ("PA", "Pennsylvania"),
+    ("RI", "Rhode Island"),
+    ("SC", "South Carolina"),
+    ("SD", "South Dakota"),
+    ("TN", "Tennessee"),
+    ("TX", "Texas"),
+    ("UT", "Utah"),
+    ("VT", "Vermont"),
+    ("VA", "Virginia"),
+    ("WA", "Washington"),
+    ("WV", "West Virginia"),
+    ("WI", "Wisconsin"),
+    ("WY", "Wyoming"),
+    ("AS", "American Samoa"),
+    ("GU", "Guam"),
+    ("MP", "Northern Mariana Islands"),
+    ("PR", "Puerto Rico"),
+    ("VI", "Virgin Islands"),
+    ("AA", "Armed Forces Americas"),
+    ("AE", "Armed Forces Europe"),
+    ("AP", "Armed Forces Pacific"),
+];
+
+/// Common abbreviations, alternate spellings and typos seen in customer-entered
+/// addresses (compared after trimming, dropping periods and uppercasing)
+const ALIASES: &[(&str, &str)] = &[
+    ("ALA", "AL"),
+    ("ARIZ", "AZ"),
+    ("ARK", "AR"),
+    ("CALIF", "CA"),
+    ("CALIFORINA", "CA"),
+    ("CALFORNIA", "CA"),
+    ("COLO", "CO"),
+    ("CONN", "CT"),
+    ("WASHINGTON DC", "DC"),
+    ("WASHINGTON D C", "DC"),
+    ("FLA", "FL"),
+    ("FLORDIA", "FL"),
+    ("ILL", "IL"),
+    ("ILLINIOS", "IL"),
+    ("IND", "IN"),
+    ("KAN", "KS"),
+    ("KANS", "KS"),
+    ("KENTUCKEY", "KY"),
+    ("LOUISANA", "LA"),
+    ("MASS", "MA"),
+    ("MASSACHUSETES", "MA"),
+    ("MASSACHUSSETTS", "MA"),
+    ("MICH", "MI"),
+    ("MINN", "MN"),
+    ("MISS", "MS"),
+    ("MISSISIPPI", "MS"),
+    ("MISSOURRI", "MO"),
+    ("MONT", "MT"),
+    ("NEB", "NE"),
+    ("NEBR", "NE"),
+    ("NEV", "NV"),
+    ("NEW YORK STATE", "NY"),
+    ("OKLA", "OK"),
+    ("ORE", "OR"),
+    ("PENN", "PA"),
+    ("PENNA", "PA"),
+    ("PENSYLVANIA", "PA"),
+    ("PENNSYLVANNIA", "PA"),
+    ("TENN", "TN"),
+    ("TENNESSE", "TN"),
+    ("TEX", "TX"),
+    ("TEXS", "TX"),
+    ("TX TEXAS", "TX"),
+    ("VIRGINA", "VA"),
+    ("WASH", "WA"),
+    ("WISC", "WI"),
+    ("WIS", "WI"),
+    ("WYO", "WY"),
+];
+
+/// Map a state as entered in an address to its USPS two-letter code.
+///
+/// Accepts codes and full names in any case ("TX", "texas ", "Texas"), common
+/// abbreviations ("Calif.") and typos ("Pensylvania"). Returns `None` for
+/// anything that isn't a USPS state, district, territory or military code.
+pub fn normalize_state(state: &str) -> Option<&'static str> {
+    let key = state
+        .replace('.', "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase();
+
+    USPS_STATES
+        .iter()
+        .find(|(code, name)| *code == key || name.to_uppercase() == key)
+        .map(|(code, _)| *code)
+        .or_else(|| ALIASES.iter().find(|(alias, _)| *alias == key).map(|(_, code)| *code))
+}
+
+/// Whether an address country (ISO 3166 code as Stripe stores it) is the US.
+/// A missing country is assumed to be US.
+pub fn is_us_country(country: Option<&str>) -> bool {
+    country
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .is_none_or(|c| c.eq_ignore_ascii_case("US"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_state() {
+        assert_eq!(normalize_state("TX"), Some("TX"));
+        assert_eq!(normalize_state("tx"), Some("TX"));
+        assert_eq!(normalize_state("Texas"), Some("TX"));
+        assert_eq!(normalize_state("texas "), Some("TX"));
+        assert_eq!(normalize_state("new  york"), Some("NY"));
+        assert_eq!(normalize_state("Calif."), Some("CA"));
+        assert_eq!(normalize_state("Pensylvania"), Some("PA"));
+        assert_eq!(normalize_state("Washington, D.C."), None);
+        assert_eq!(normalize_state("Washington D.C."), Some("DC"));
+
+        assert_eq!(normalize_state("Ontario"), None);
+        assert_eq!(normalize_state("XX"), None);
+    }
+
+    #[test]
+    fn test_is_us_country() {
+        assert!(is_us_country(Some("US")));
+        assert!(is_us_country(Some("us")));
+        assert!(is_us_country(None));
+        assert!(is_us_country(Some("")));
+        assert!(!is_us_country(Some("CA")));
+    }
+}
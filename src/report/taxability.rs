@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Product category for data processing services
+pub const DATA_PROCESSING: &str = "data_processing";
+
+/// A state rule taxing only part of the sale price of a product category
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxabilityRule {
+    /// Two-letter state code
+    pub state: String,
+    /// Product category the rule covers (see `product_categories` in the profile)
+    pub category: String,
+    /// Share of the sale price that is taxable, in percent
+    pub taxable_percent: u32,
+}
+
+impl TaxabilityRule {
+    /// The taxable part of an amount (cents), rounded to the cent
+    pub fn taxable_amount(&self, amount: i64) -> i64 {
+        (amount as f64 * self.taxable_percent as f64 / 100.0).round() as i64
+    }
+
+    /// Footnote for figures the rule adjusted
+    pub fn note(&self) -> String {
+        format!(
+            "{} taxes {}% of {} charges; the other {}% is reported as non-taxable",
+            self.state,
+            self.taxable_percent,
+            self.category.replace('_', " "),
+            100u32.saturating_sub(self.taxable_percent)
+        )
+    }
+}
+
+/// Rules that apply without configuration: Texas taxes 80% of the charge for
+/// data processing services (Tax Code §151.351)
+pub fn builtin_rules() -> Vec<TaxabilityRule> {
+    vec![TaxabilityRule {
+        state: "TX".to_string(),
+        category: DATA_PROCESSING.to_string(),
+        taxable_percent: 80,
+    }]
+}
+
+/// Which taxability rule, if any, covers a product sold into a state
+#[derive(Debug, Clone, Default)]
+pub struct Taxability {
+    /// Category per Stripe product ID
+    categories: BTreeMap<String, String>,
+    rules: Vec<TaxabilityRule>,
+}
+
+impl Taxability {
+    /// The built-in rules plus `rules`, which replace a built-in rule for the
+    /// same state and category
+    pub fn new(categories: BTreeMap<String, String>, rules: &[TaxabilityRule]) -> Self {
+        let mut all = rules.to_vec();
+        for builtin in builtin_rules() {
+            if !rules.iter().any(|r| r.state.eq_ignore_ascii_case(&builtin.state) && r.category == builtin.category) {
+                all.push(builtin);
+            }
+        }
+        Taxability { categories, rules: all }
+    }
+
+    /// The rule for a product's category in a state. Products without a
+    /// category are fully taxable.
+    pub fn rule_for(&self, state: &str, product: Option<&str>) -> Option<&TaxabilityRule> {
+        let category = self.categories.get(product?)?;
+        self.rules
+            .iter()
+            .find(|rule| rule.state.eq_ignore_ascii_case(state) && &rule.category == category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_for_product_category() {
+        let categories = BTreeMap::from([
+            ("prod_hosting".to_string(), DATA_PROCESSING.to_string()),
+            ("prod_training".to_string(), "training".to_string()),
+        ]);
+        let custom = TaxabilityRule {
+            state: "CA".to_string(),
+            category: "training".to_string(),
+            taxable_percent: 0,
+        };
+        let taxability = Taxability::new(categories, &[custom]);
+
+        let texas = taxability.rule_for("TX", Some("prod_hosting")).unwrap();
+        assert_eq!(texas.taxable_amount(10000), 8000);
+        assert_eq!(texas.taxable_amount(333), 266);
+        assert_eq!(
+            texas.note(),
+            "TX taxes 80% of data processing charges; the other 20% is reported as non-taxable"
+        );
+
+        assert!(taxability.rule_for("CA", Some("prod_hosting")).is_none());
+        assert_eq!(taxability.rule_for("CA", Some("prod_training")).unwrap().taxable_percent, 0);
+        assert!(taxability.rule_for("TX", Some("prod_seat")).is_none());
+        assert!(taxability.rule_for("TX", None).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::clock::Clock;
+use anyhow::{anyhow, bail, Result};
+
+/// Values available to `{placeholder}` templates in output paths and titles
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    pub year: i32,
+    /// Not set for annual reports
+    pub quarter: Option<u32>,
+    /// Only set when rendering a per-state artifact
+    pub state: Option<String>,
+    pub profile: Option<String>,
+    pub run_id: String,
+}
+
+impl TemplateContext {
+    /// Context for a run of `clock`'s current time
+    pub fn new(year: i32, quarter: Option<u32>, profile: Option<&str>, clock: &dyn Clock) -> Self {
+        TemplateContext {
+            year,
+            quarter,
+            state: None,
+            profile: profile.map(str::to_string),
+            run_id: new_run_id(clock),
+        }
+    }
+
+    /// The same context for one state's artifact
+    pub fn for_state(&self, state: &str) -> Self {
+        TemplateContext {
+            state: Some(state.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Identifier for one invocation: the UTC start time, e.g. `20251005T060000Z`
+pub fn new_run_id(clock: &dyn Clock) -> String {
+    clock.now().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Render a template, replacing `{year}`, `{quarter}`, `{state}`, `{profile}`
+/// and `{run_id}`. Literal braces are written `{{` and `}}`. Unknown or
+/// unavailable placeholders are errors rather than being left in place, so a
+/// typo can't silently produce a misnamed filing artifact.
+pub fn render(template: &str, ctx: &TemplateContext) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!("Unclosed '{{' in template \"{}\"", template),
+                    }
+                }
+                output.push_str(&resolve(&name, ctx, template)?);
+            }
+            '}' => bail!("Unmatched '}}' in template \"{}\"", template),
+            _ => output.push(c),
+        }
+    }
+
+    Ok(output)
+}
+
+fn resolve(name: &str, ctx: &TemplateContext, template: &str) -> Result<String> {
+    match name {
+        "year" => Ok(ctx.year.to_string()),
+        "quarter" => ctx
+            .quarter
+            .map(|quarter| quarter.to_string())
+            .ok_or_else(|| anyhow!("{{quarter}} isn't available for annual reports (in \"{}\")", template)),
+        "state" => ctx
+            .state
+            .clone()
+            .ok_or_else(|| anyhow!("{{state}} is only available for per-state outputs (in \"{}\")", template)),
+        "profile" => ctx
+            .profile
+            .clone()
+            .ok_or_else(|| anyhow!("{{profile}} used in \"{}\" but no profile is selected", template)),
+        "run_id" => Ok(ctx.run_id.clone()),
+        other => bail!(
+            "Unknown placeholder {{{}}} in \"{}\" (expected year, quarter, state, profile or run_id)",
+            other,
+            template
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            year: 2025,
+            quarter: Some(3),
+            state: None,
+            profile: Some("live".to_string()),
+            run_id: "20251005T060000Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let ctx = context();
+        assert_eq!(
+            render("reports/{profile}/{year}-Q{quarter}-{run_id}.tsv", &ctx).unwrap(),
+            "reports/live/2025-Q3-20251005T060000Z.tsv"
+        );
+        assert_eq!(render("{state}.csv", &ctx.for_state("TX")).unwrap(), "TX.csv");
+        assert_eq!(render("{{literal}}", &ctx).unwrap(), "{literal}");
+    }
+
+    #[test]
+    fn test_render_rejects_unknown_and_unavailable_placeholders() {
+        let ctx = context();
+        assert!(render("{yeer}.tsv", &ctx).unwrap_err().to_string().contains("Unknown placeholder {yeer}"));
+        assert!(render("{state}.tsv", &ctx).is_err());
+        assert!(render("{year", &ctx).is_err());
+    }
+}
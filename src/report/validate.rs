@@ -0,0 +1,300 @@
+use crate::report::compare::{dollars, CompareFormat};
+use crate::report::diagnostics::DiagnosticCode;
+use crate::report::formatter::csv_escape;
+use crate::report::generator::{ReportGenerator, UNKNOWN_STATE};
+use crate::report::states::is_us_country;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Write;
+
+/// A data-quality check run before filing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// No address gives a state, so the invoice can't be assigned to a return
+    MissingState,
+    /// The invoice names no customer, or the customer couldn't be fetched
+    UnparsableCustomer,
+    /// Sales in a US state in a currency other than USD
+    CurrencyMismatch,
+    /// Left out of the report for another reason (e.g. a fetch error)
+    Skipped,
+    /// Tax collected in a state where the business isn't registered
+    OverCollected,
+    /// Tax collected at a rate that doesn't match the destination
+    RateMismatch,
+    /// No tax collected on a non-exempt sale in a state where the business
+    /// is registered
+    ZeroTax,
+}
+
+impl Check {
+    /// Whether the check failing should stop the period being filed. Untaxed
+    /// sales may be legitimately non-taxable products, and the bundled rates
+    /// are approximate, so those only warn.
+    pub fn is_blocker(self) -> bool {
+        !matches!(self, Check::ZeroTax | Check::RateMismatch)
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Check::MissingState => "Missing state",
+            Check::UnparsableCustomer => "Unparsable customer",
+            Check::CurrencyMismatch => "Currency mismatch",
+            Check::Skipped => "Skipped invoice",
+            Check::OverCollected => "Tax in unregistered state",
+            Check::RateMismatch => "Unexpected tax rate",
+            Check::ZeroTax => "Zero tax in registered state",
+        })
+    }
+}
+
+/// One invoice failing a check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub check: Check,
+    pub invoice_id: String,
+    pub customer: String,
+    /// Reported state, if the invoice made it into the report
+    pub state: Option<String>,
+    /// Invoice total (skipped invoices: amount paid), cents in `currency`
+    pub amount: i64,
+    /// ISO currency code when not USD
+    pub currency: Option<String>,
+    pub detail: String,
+}
+
+/// How many invoices fail a check and the revenue they carry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckSummary {
+    pub check: Check,
+    pub blocker: bool,
+    pub invoices: usize,
+    /// Cents; amounts in other currencies are added as they are
+    pub revenue: i64,
+}
+
+/// Data-quality findings for a period, from a report built but not written
+/// (`validate`)
+#[derive(Debug, Clone, Serialize)]
+pub struct Validation {
+    /// Period label, e.g. `Q3-2025`
+    pub period: String,
+    /// Checks that found something, blockers first
+    pub checks: Vec<CheckSummary>,
+    pub problems: Vec<Problem>,
+}
+
+impl Validation {
+    /// Check a report built with `allow_missing_state`, so invoices without a
+    /// state are reported under `UNKNOWN_STATE` rather than skipped, and with
+    /// `registered_states` and `rates`, so tax collection is checked
+    pub fn new(period: impl Into<String>, generator: &ReportGenerator) -> Self {
+        let mut problems = Vec::new();
+        let flagged = |code: DiagnosticCode| -> BTreeSet<&str> {
+            generator
+                .diagnostics()
+                .iter()
+                .filter(|d| d.code == code)
+                .filter_map(|d| d.invoice_id.as_deref())
+                .collect()
+        };
+        let (over_collected, untaxed) =
+            (flagged(DiagnosticCode::TaxUnregisteredState), flagged(DiagnosticCode::UntaxedRegisteredState));
+        let rate_mismatches: BTreeMap<&str, &str> = generator
+            .diagnostics()
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::TaxRateMismatch)
+            .filter_map(|d| Some((d.invoice_id.as_deref()?, d.message.as_str())))
+            .collect();
+
+        for record in generator.get_records() {
+            let problem = |check: Check, detail: String| Problem {
+                check,
+                invoice_id: record.invoice_id.clone(),
+                customer: record.customer.clone(),
+                state: Some(record.state.clone()),
+                amount: record.total,
+                currency: record.currency.clone(),
+                detail,
+            };
+            let us = is_us_country(record.country.as_deref());
+            if record.state == UNKNOWN_STATE {
+                problems.push(problem(Check::MissingState, "No address or ZIP code gives a state".to_string()));
+            }
+            if let Some(currency) = &record.currency
+                && us
+            {
+                problems.push(problem(Check::CurrencyMismatch, format!("Billed in {} in a US state", currency)));
+            }
+            if over_collected.contains(record.invoice_id.as_str()) {
+                problems.push(problem(Check::OverCollected, format!("Tax collected in {}, where no registration is configured", record.state)));
+            }
+            if let Some(message) = rate_mismatches.get(record.invoice_id.as_str()) {
+                problems.push(problem(Check::RateMismatch, message.to_string()));
+            }
+            if untaxed.contains(record.invoice_id.as_str()) {
+                problems.push(problem(Check::ZeroTax, format!("No tax collected in {}", record.state)));
+            }
+        }
+
+        let no_customer = flagged(DiagnosticCode::CustomerUnavailable);
+        for skipped in generator.skipped() {
+            let check = match no_customer.contains(skipped.invoice_id.as_str()) {
+                true => Check::UnparsableCustomer,
+                false => Check::Skipped,
+            };
+            problems.push(Problem {
+                check,
+                invoice_id: skipped.invoice_id.clone(),
+                customer: skipped.customer.clone(),
+                state: None,
+                amount: skipped.amount,
+                currency: None,
+                detail: skipped.reason.clone(),
+            });
+        }
+        problems.sort_by(|a, b| (a.check, &a.invoice_id).cmp(&(b.check, &b.invoice_id)));
+
+        let mut checks: Vec<CheckSummary> = Vec::new();
+        for problem in &problems {
+            match checks.last_mut() {
+                Some(summary) if summary.check == problem.check => {
+                    summary.invoices += 1;
+                    summary.revenue += problem.amount;
+                }
+                _ => checks.push(CheckSummary {
+                    check: problem.check,
+                    blocker: problem.check.is_blocker(),
+                    invoices: 1,
+                    revenue: problem.amount,
+                }),
+            }
+        }
+
+        Validation { period: period.into(), checks, problems }
+    }
+
+    /// Invoices failing a blocking check
+    pub fn blockers(&self) -> usize {
+        self.checks.iter().filter(|c| c.blocker).map(|c| c.invoices).sum()
+    }
+
+    pub fn write(&self, format: CompareFormat, out: &mut dyn Write) -> Result<()> {
+        match format {
+            CompareFormat::Tsv => self.write_delimited(out, '\t', |field| field.to_string()),
+            CompareFormat::Csv => self.write_delimited(out, ',', csv_escape),
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_delimited(&self, out: &mut dyn Write, sep: char, escape: fn(&str) -> String) -> Result<()> {
+        let mut row = |fields: &[&str]| -> Result<()> {
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(out, "{}", fields.join(&sep.to_string()))?;
+            Ok(())
+        };
+
+        row(&["VALIDATION", &self.period])?;
+        row(&["Check", "Severity", "Invoices", "Revenue"])?;
+        for summary in &self.checks {
+            row(&[
+                &summary.check.to_string(),
+                if summary.blocker { "blocker" } else { "warning" },
+                &summary.invoices.to_string(),
+                &dollars(summary.revenue),
+            ])?;
+        }
+        row(&[])?;
+
+        row(&["===== INVOICES ====="])?;
+        row(&["Check", "Invoice ID", "Customer", "State", "Amount", "Currency", "Detail"])?;
+        for problem in &self.problems {
+            row(&[
+                &problem.check.to_string(),
+                &problem.invoice_id,
+                &problem.customer,
+                problem.state.as_deref().unwrap_or(""),
+                &dollars(problem.amount),
+                problem.currency.as_deref().unwrap_or("USD"),
+                &problem.detail,
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::build_report;
+    use crate::report::generator::ReportOptions;
+    use crate::report::progress::ReportProgress;
+    use crate::report::rates::RateTable;
+    use crate::source::FixtureSource;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {"id": "in_ok", "customer": "cus_tx", "customer_name": "Lone Star LLC", "status": "paid", "created": 1728000000,
+             "amount_paid": 10825, "tax": 825, "lines": {"data": [{"type": "subscription", "amount": 10000, "quantity": 1}]}},
+            {"id": "in_untaxed", "customer": "cus_tx", "customer_name": "Lone Star LLC", "status": "paid", "created": 1728000000,
+             "amount_paid": 5000, "lines": {"data": [{"type": "subscription", "amount": 5000, "quantity": 1}]}},
+            {"id": "in_eur", "customer": "cus_ca", "customer_name": "Golden LLC", "status": "paid", "created": 1728000000,
+             "amount_paid": 2000, "currency": "eur", "lines": {"data": [{"type": "subscription", "amount": 2000, "quantity": 1}]}},
+            {"id": "in_nostate", "customer": "cus_none", "customer_name": "Nowhere Inc", "status": "paid", "created": 1728000000,
+             "amount_paid": 700, "lines": {"data": [{"type": "subscription", "amount": 700, "quantity": 1}]}},
+            {"id": "in_high", "customer": "cus_tx", "customer_name": "Lone Star LLC", "status": "paid", "created": 1728000000,
+             "amount_paid": 12000, "tax": 2000, "lines": {"data": [{"type": "subscription", "amount": 10000, "quantity": 1}]}},
+            {"id": "in_ny", "customer": "cus_ny", "customer_name": "Empire LLC", "status": "paid", "created": 1728000000,
+             "amount_paid": 5400, "tax": 400, "lines": {"data": [{"type": "subscription", "amount": 5000, "quantity": 1}]}},
+            {"id": "in_nocustomer", "customer": null, "customer_name": "Ghost", "status": "paid", "created": 1728000000,
+             "amount_paid": 300}
+        ],
+        "customers": [
+            {"id": "cus_tx", "address": {"state": "TX", "country": "US"}},
+            {"id": "cus_ca", "address": {"state": "CA", "country": "US"}},
+            {"id": "cus_ny", "address": {"state": "NY", "country": "US"}},
+            {"id": "cus_none"}
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn test_validation_finds_blockers_and_warnings() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+        let options = ReportOptions {
+            allow_missing_state: true,
+            registered_states: vec!["TX".to_string()],
+            rates: Some(RateTable::default()),
+            ..Default::default()
+        };
+        let generator = build_report(&source, 1727740800, 1735689599, &options, &ReportProgress::hidden()).await.unwrap();
+        let validation = Validation::new("Q4-2024", &generator);
+
+        let summary: Vec<(Check, bool, usize, i64)> =
+            validation.checks.iter().map(|c| (c.check, c.blocker, c.invoices, c.revenue)).collect();
+        assert_eq!(summary, vec![
+            (Check::MissingState, true, 1, 700),
+            (Check::UnparsableCustomer, true, 1, 300),
+            (Check::CurrencyMismatch, true, 1, 2000),
+            (Check::OverCollected, true, 1, 5400),
+            (Check::RateMismatch, false, 1, 12000),
+            (Check::ZeroTax, false, 1, 5000),
+        ]);
+        assert_eq!(validation.blockers(), 4);
+
+        let mut tsv = Vec::new();
+        validation.write(CompareFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("VALIDATION\tQ4-2024\nCheck\tSeverity\tInvoices\tRevenue\nMissing state\tblocker\t1\t7.00\n"));
+        assert!(tsv.contains("\nZero tax in registered state\twarning\t1\t50.00\n\n===== INVOICES =====\n"));
+        assert!(tsv.contains("\nCurrency mismatch\tin_eur\tGolden LLC\tCA\t20.00\tEUR\tBilled in EUR in a US state\n"));
+    }
+}
@@ -0,0 +1,112 @@
+/// US ZIP code prefix (first three digits) ranges and the state each is
+/// assigned to, per the USPS three-digit ZIP prefix list. Territories and
+/// military (APO/FPO) prefixes are left out, so they infer nothing.
+const ZIP3_RANGES: &[(u16, u16, &str)] = &[
+    (5, 5, "NY"),
+    (10, 27, "MA"),
+    (28, 29, "RI"),
+    (30, 38, "NH"),
+    (39, 49, "ME"),
+    (50, 54, "VT"),
+    (55, 55, "MA"),
+    (56, 59, "VT"),
+    (60, 69, "CT"),
+    (70, 89, "NJ"),
+    (100, 149, "NY"),
+    (150, 196, "PA"),
+    (197, 199, "DE"),
+    (200, 200, "DC"),
+    (201, 201, "VA"),
+    (202, 205, "DC"),
+    (206, 219, "MD"),
+    (220, 246, "VA"),
+    (247, 268, "WV"),
+    (270, 289, "NC"),
+    (290, 299, "SC"),
+    (300, 319, "GA"),
+    (320, 339, "FL"),
+    (341, 349, "FL"),
+    (350, 369, "AL"),
+    (370, 385, "TN"),
+    (386, 397, "MS"),
+    (398, 399, "GA"),
+    (400, 427, "KY"),
+    (430, 459, "OH"),
+    (460, 479, "IN"),
+    (480, 499, "MI"),
+    (500, 528, "IA"),
+    (530, 549, "WI"),
+    (550, 567, "MN"),
+    (569, 569, "DC"),
+    (570, 577, "SD"),
+    (580, 588, "ND"),
+    (590, 599, "MT"),
+    (600, 629, "IL"),
+    (630, 658, "MO"),
+    (660, 679, "KS"),
+    (680, 693, "NE"),
+    (700, 715, "LA"),
+    (716, 729, "AR"),
+    (730, 732, "OK"),
+    (733, 733, "TX"),
+    (734, 749, "OK"),
+    (750, 799, "TX"),
+    (800, 816, "CO"),
+    (820, 831, "WY"),
+    (832, 838, "ID"),
+    (840, 847, "UT"),
+    (850, 865, "AZ"),
+    (870, 884, "NM"),
+    (885, 885, "TX"),
+    (889, 898, "NV"),
+    (900, 961, "CA"),
+    (967, 968, "HI"),
+    (970, 979, "OR"),
+    (980, 994, "WA"),
+    (995, 999, "AK"),
+];
+
+/// Infer the two-letter state for a US ZIP or ZIP+4 code (`78701`, `78701-1234`).
+/// Returns `None` for anything that isn't a five-digit ZIP or whose prefix
+/// isn't assigned to a state.
+pub fn state_for_zip(postal_code: &str) -> Option<&'static str> {
+    let postal_code = postal_code.trim();
+    let (zip, plus4) = match postal_code.split_once('-') {
+        Some((zip, plus4)) => (zip, Some(plus4)),
+        None => (postal_code, None),
+    };
+
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if zip.len() != 5 || !all_digits(zip) || plus4.is_some_and(|p| p.len() != 4 || !all_digits(p)) {
+        return None;
+    }
+
+    let prefix: u16 = zip[..3].parse().ok()?;
+    ZIP3_RANGES
+        .iter()
+        .find(|(low, high, _)| (*low..=*high).contains(&prefix))
+        .map(|(_, _, state)| *state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_for_zip() {
+        assert_eq!(state_for_zip("78701"), Some("TX"));
+        assert_eq!(state_for_zip("73301-0001"), Some("TX"));
+        assert_eq!(state_for_zip("73102"), Some("OK"));
+        assert_eq!(state_for_zip("94105"), Some("CA"));
+        assert_eq!(state_for_zip("02108"), Some("MA"));
+        assert_eq!(state_for_zip("00501"), Some("NY"));
+        assert_eq!(state_for_zip("20500"), Some("DC"));
+
+        // Military, territories, and non-US formats infer nothing
+        assert_eq!(state_for_zip("09012"), None);
+        assert_eq!(state_for_zip("00901"), None);
+        assert_eq!(state_for_zip("SW1A 1AA"), None);
+        assert_eq!(state_for_zip("1234"), None);
+        assert_eq!(state_for_zip("787011"), None);
+    }
+}
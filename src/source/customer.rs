@@ -0,0 +1,97 @@
+use super::InvoiceSource;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeInvoice, TestClock,
+};
+use anyhow::Result;
+
+/// Restricts a source to one customer's invoices (`statement --customer`)
+pub struct CustomerScope<'a, S> {
+    inner: &'a S,
+    customer_id: &'a str,
+}
+
+impl<'a, S> CustomerScope<'a, S> {
+    pub fn new(inner: &'a S, customer_id: &'a str) -> Self {
+        CustomerScope { inner, customer_id }
+    }
+
+    fn retain_customer(&self, mut invoices: Vec<StripeInvoice>) -> Vec<StripeInvoice> {
+        invoices.retain(|invoice| invoice.customer_id() == Some(self.customer_id));
+        invoices
+    }
+}
+
+impl<S: InvoiceSource> InvoiceSource for CustomerScope<'_, S> {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        let invoices = self.inner.fetch_invoices(status, start, end).await?;
+        Ok(self.retain_customer(invoices))
+    }
+
+    async fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<StripeInvoice>> {
+        let invoices = self.inner.fetch_invoices_with_progress(status, start, end, on_page).await?;
+        Ok(self.retain_customer(invoices))
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        self.inner.fetch_invoice(invoice_id).await
+    }
+
+    fn in_scope(&self, invoice: &StripeInvoice) -> bool {
+        invoice.customer_id() == Some(self.customer_id) && self.inner.in_scope(invoice)
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        self.inner.fetch_disputes(start, end).await
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        let mut sessions = self.inner.fetch_checkout_sessions(start, end).await?;
+        sessions.retain(|session| session.customer.as_deref() == Some(self.customer_id));
+        Ok(sessions)
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        let mut intents = self.inner.fetch_payment_intents(start, end).await?;
+        intents.retain(|intent| intent.customer.as_deref() == Some(self.customer_id));
+        Ok(intents)
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        self.inner.fetch_invoice_payments(invoice_id).await
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        self.inner.fetch_customer(customer_id).await
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        self.inner.fetch_charge(charge_id).await
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        self.inner.fetch_balance_transaction(balance_tx_id).await
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        self.inner.fetch_test_clock(test_clock_id).await
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        self.inner.fetch_product(product_id).await
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        self.inner.fetch_payouts(start, end).await
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        self.inner.fetch_payout_balance_transactions(payout_id).await
+    }
+}
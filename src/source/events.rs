@@ -0,0 +1,214 @@
+use super::store::{Store, StoreSource, StoredObject};
+use super::InvoiceSource;
+use crate::stripe::client::{Charge, CreditNote, Customer, StripeClient, StripeEvent, StripeInvoice};
+use anyhow::{Context, Result};
+
+/// Event types applied to the store; other events are acknowledged and ignored
+pub const EVENT_TYPES: [&str; 3] = ["invoice.paid", "charge.refunded", "credit_note.created"];
+
+/// How long Stripe keeps events listable from `/v1/events`
+pub const EVENT_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// What applying an event did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applied {
+    /// The event's objects were stored
+    Stored,
+    /// The event was applied before (a redelivery)
+    Duplicate,
+    /// Not one of `EVENT_TYPES`
+    Ignored,
+}
+
+/// Apply a Stripe event to the store, so reports can be built from it
+/// without listing invoices:
+///
+/// - `invoice.paid` stores the invoice
+/// - `charge.refunded` stores the charge with its refunded amount
+/// - `credit_note.created` stores the credit note, which counts toward its
+///   invoice's credit note amounts whenever the invoice is read
+///
+/// With a `client`, the invoice is fetched again with the expansions reports
+/// use (tax rates, subscription), along with its customer, charge and
+/// balance transaction, so the store has everything `generate --sqlite`
+/// reads; without one, the customer is taken from the invoice's name and
+/// address. The event is recorded in the same transaction as its objects,
+/// so a redelivery, even one arriving concurrently, is skipped.
+pub async fn apply_event<S: Store>(
+    store: &StoreSource<S>,
+    event: &StripeEvent,
+    client: Option<&StripeClient>,
+) -> Result<Applied> {
+    if !EVENT_TYPES.contains(&event.event_type.as_str()) {
+        return Ok(Applied::Ignored);
+    }
+    if store.has_event(&event.id).await? {
+        return Ok(Applied::Duplicate);
+    }
+
+    let object = event.data.object.clone();
+    let objects = match event.event_type.as_str() {
+        "invoice.paid" => {
+            let invoice: StripeInvoice = serde_json::from_value(object).context("Failed to parse invoice")?;
+            invoice_objects(store, invoice, client).await?
+        }
+        "charge.refunded" => {
+            let charge: Charge = serde_json::from_value(object).context("Failed to parse charge")?;
+            vec![StoredObject::charge(&charge)?]
+        }
+        "credit_note.created" => {
+            let credit_note: CreditNote = serde_json::from_value(object).context("Failed to parse credit note")?;
+            let mut objects = vec![StoredObject::credit_note(&credit_note)?];
+            if let Some(client) = client {
+                objects.push(StoredObject::invoice(&client.fetch_invoice(&credit_note.invoice).await?)?);
+            }
+            objects
+        }
+        _ => unreachable!("event types are checked above"),
+    };
+
+    // Another delivery of the same event may have been applied meanwhile
+    match store.insert_event(event, &objects).await? {
+        true => Ok(Applied::Stored),
+        false => Ok(Applied::Duplicate),
+    }
+}
+
+/// How many events a replay applied and how many were already in the store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub stored: usize,
+    pub duplicates: usize,
+}
+
+/// Apply `events` in order, as `backfill-events` does with the events listed
+/// from the API, counting those stored and those already applied (by the
+/// listener or an earlier backfill)
+pub async fn replay_events<S: Store>(
+    store: &StoreSource<S>,
+    events: &[StripeEvent],
+    client: Option<&StripeClient>,
+) -> Result<ReplaySummary> {
+    let mut summary = ReplaySummary::default();
+    for event in events {
+        match apply_event(store, event, client).await.with_context(|| format!("Failed to apply {}", event.id))? {
+            Applied::Stored => summary.stored += 1,
+            Applied::Duplicate => summary.duplicates += 1,
+            Applied::Ignored => {}
+        }
+    }
+    Ok(summary)
+}
+
+/// The objects to store for a paid invoice, refetched with its related
+/// objects when there's a client
+async fn invoice_objects<S: Store>(
+    store: &StoreSource<S>,
+    invoice: StripeInvoice,
+    client: Option<&StripeClient>,
+) -> Result<Vec<StoredObject>> {
+    let mut objects = Vec::new();
+    let Some(client) = client else {
+        // The customer as the invoice recorded them, unless already stored
+        if let Some(customer_id) = invoice.customer_id()
+            && store.fetch_customer(customer_id).await.is_err()
+        {
+            let customer = Customer {
+                id: customer_id.to_string(),
+                name: invoice.customer_name.clone(),
+                address: invoice.customer_address.clone(),
+                ..Default::default()
+            };
+            objects.push(StoredObject::customer(&customer)?);
+        }
+        objects.push(StoredObject::invoice(&invoice)?);
+        return Ok(objects);
+    };
+    let invoice = client.fetch_invoice(&invoice.id).await?;
+    if let Some(customer_id) = invoice.customer_id() {
+        objects.push(StoredObject::customer(&client.fetch_customer(customer_id).await?)?);
+    }
+    if let Some(charge_id) = invoice.charge_id() {
+        let charge = client.fetch_charge(charge_id).await?;
+        if let Some(balance_tx_id) = &charge.balance_transaction {
+            objects.push(StoredObject::balance_transaction(&client.fetch_balance_transaction(balance_tx_id).await?)?);
+        }
+        objects.push(StoredObject::charge(&charge)?);
+    }
+    objects.push(StoredObject::invoice(&invoice)?);
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SqliteStore;
+
+    fn event(id: &str, event_type: &str, object: serde_json::Value) -> StripeEvent {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "type": event_type, "created": 1728000000, "data": {"object": object}
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_events_are_applied_once() {
+        let store = StoreSource::new(SqliteStore::open_in_memory().unwrap());
+        let paid = event(
+            "evt_1",
+            "invoice.paid",
+            serde_json::json!({"id": "in_1", "customer": "cus_1", "status": "paid", "created": 1728000000, "amount_paid": 10825}),
+        );
+        let credited = event(
+            "evt_2",
+            "credit_note.created",
+            serde_json::json!({"id": "cn_1", "invoice": "in_1", "amount": 2000, "type": "post_payment"}),
+        );
+
+        assert_eq!(apply_event(&store, &paid, None).await.unwrap(), Applied::Stored);
+        assert_eq!(apply_event(&store, &credited, None).await.unwrap(), Applied::Stored);
+        assert_eq!(apply_event(&store, &credited, None).await.unwrap(), Applied::Duplicate);
+        let refunded = event("evt_4", "charge.refunded", serde_json::json!({"id": "ch_1", "amount_refunded": 500}));
+        let summary = replay_events(&store, &[paid.clone(), refunded], None).await.unwrap();
+        assert_eq!(summary, ReplaySummary { stored: 1, duplicates: 1 });
+        let ignored = event("evt_3", "customer.created", serde_json::json!({"id": "cus_1"}));
+        assert_eq!(apply_event(&store, &ignored, None).await.unwrap(), Applied::Ignored);
+
+        let invoices = store.fetch_paid_invoices(1727000000, 1729000000).await.unwrap();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].post_payment_credit_notes_amount, 2000);
+    }
+
+    #[tokio::test]
+    async fn test_credit_notes_survive_invoice_updates() {
+        let store = StoreSource::new(SqliteStore::open_in_memory().unwrap());
+        let invoice = serde_json::json!({"id": "in_1", "status": "paid", "created": 1728000000, "amount_paid": 10825});
+        let credited = event(
+            "evt_2",
+            "credit_note.created",
+            serde_json::json!({"id": "cn_1", "invoice": "in_1", "amount": 2000, "type": "post_payment"}),
+        );
+        apply_event(&store, &event("evt_1", "invoice.paid", invoice.clone()), None).await.unwrap();
+        apply_event(&store, &credited, None).await.unwrap();
+
+        // A later copy of the invoice doesn't drop the credit note
+        apply_event(&store, &event("evt_3", "invoice.paid", invoice), None).await.unwrap();
+        assert_eq!(store.fetch_invoice("in_1").await.unwrap().post_payment_credit_notes_amount, 2000);
+
+        // Nor is it counted twice once Stripe's copy includes it
+        let refetched: StripeInvoice = serde_json::from_value(serde_json::json!({
+            "id": "in_1", "status": "paid", "created": 1728000000, "amount_paid": 10825,
+            "post_payment_credit_notes_amount": 2000
+        }))
+        .unwrap();
+        store.insert_invoice(&refetched).await.unwrap();
+        assert_eq!(store.fetch_invoice("in_1").await.unwrap().post_payment_credit_notes_amount, 2000);
+
+        // A delivery that lost the race to record the event stores nothing
+        let late: CreditNote =
+            serde_json::from_value(serde_json::json!({"id": "cn_2", "invoice": "in_1", "amount": 500, "type": "post_payment"}))
+                .unwrap();
+        assert!(!store.insert_event(&credited, &[StoredObject::credit_note(&late).unwrap()]).await.unwrap());
+        assert_eq!(store.fetch_invoice("in_1").await.unwrap().post_payment_credit_notes_amount, 2000);
+    }
+}
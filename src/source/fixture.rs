@@ -0,0 +1,243 @@
+use super::InvoiceSource;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeInvoice, TaxRegistration, TestClock,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Raw Stripe objects as they would be returned by the API
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub invoices: Vec<StripeInvoice>,
+    #[serde(default)]
+    pub customers: Vec<Customer>,
+    #[serde(default)]
+    pub charges: Vec<Charge>,
+    #[serde(default)]
+    pub balance_transactions: Vec<BalanceTransaction>,
+    /// Test clocks referenced by invoices' `test_clock`, for `--test-clock` runs
+    #[serde(default)]
+    pub test_clocks: Vec<TestClock>,
+    /// Products referenced by line items' prices, for `--group-by product` names
+    #[serde(default)]
+    pub products: Vec<Product>,
+    /// Payments toward invoices, for `--basis cash` runs; invoices without
+    /// any count as paid in full at `paid_at`
+    #[serde(default)]
+    pub invoice_payments: Vec<InvoicePayment>,
+    /// Disputes (chargebacks) against invoices' charges
+    #[serde(default)]
+    pub disputes: Vec<Dispute>,
+    /// Checkout Sessions, for `--include-payments` runs
+    #[serde(default)]
+    pub checkout_sessions: Vec<CheckoutSession>,
+    /// PaymentIntents, for `--include-payments` runs
+    #[serde(default)]
+    pub payment_intents: Vec<PaymentIntent>,
+    /// Payouts, for the `payouts` command
+    #[serde(default)]
+    pub payouts: Vec<Payout>,
+    /// IDs of the `balance_transactions` each payout settled, by payout ID
+    #[serde(default)]
+    pub payout_balance_transactions: BTreeMap<String, Vec<String>>,
+    /// Stripe Tax registrations, for the `registrations` command
+    #[serde(default)]
+    pub tax_registrations: Vec<TaxRegistration>,
+}
+
+/// Invoice source backed by a JSON fixture file
+///
+/// Invoices are filtered the same way the Stripe list endpoint filters them
+/// (by status, created within the range), so a fixture can hold several
+/// quarters of data.
+pub struct FixtureSource {
+    fixture: Fixture,
+}
+
+impl FixtureSource {
+    pub fn new(fixture: Fixture) -> Self {
+        FixtureSource { fixture }
+    }
+
+    /// Parse a fixture from a JSON string
+    pub fn from_json(json: &str) -> Result<Self> {
+        let fixture = serde_json::from_str(json).context("Failed to parse fixture JSON")?;
+        Ok(Self::new(fixture))
+    }
+
+    /// Load a fixture from a JSON file
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+        Self::from_json(&json)
+    }
+
+    /// The fixture's Stripe Tax registrations (not part of `InvoiceSource`,
+    /// which only reads what reports are built from)
+    pub fn tax_registrations(&self) -> &[TaxRegistration] {
+        &self.fixture.tax_registrations
+    }
+}
+
+impl InvoiceSource for FixtureSource {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        Ok(self
+            .fixture
+            .invoices
+            .iter()
+            .filter(|invoice| invoice.status == status)
+            .filter(|invoice| invoice.created >= start && invoice.created <= end)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        self.fixture
+            .invoices
+            .iter()
+            .find(|invoice| invoice.id == invoice_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Invoice {} not found in fixture", invoice_id))
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        Ok(self
+            .fixture
+            .disputes
+            .iter()
+            .filter(|dispute| dispute.created >= start && dispute.created <= end)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        Ok(self
+            .fixture
+            .checkout_sessions
+            .iter()
+            .filter(|session| session.created >= start && session.created <= end)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        Ok(self
+            .fixture
+            .payment_intents
+            .iter()
+            .filter(|intent| intent.created >= start && intent.created <= end)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        Ok(self
+            .fixture
+            .invoice_payments
+            .iter()
+            .filter(|payment| payment.invoice == invoice_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        self.fixture
+            .customers
+            .iter()
+            .find(|customer| customer.id == customer_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Customer {} not found in fixture", customer_id))
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        self.fixture
+            .charges
+            .iter()
+            .find(|charge| charge.id == charge_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Charge {} not found in fixture", charge_id))
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        self.fixture
+            .balance_transactions
+            .iter()
+            .find(|bt| bt.id == balance_tx_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Balance transaction {} not found in fixture", balance_tx_id))
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        self.fixture
+            .test_clocks
+            .iter()
+            .find(|clock| clock.id == test_clock_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Test clock {} not found in fixture", test_clock_id))
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        self.fixture
+            .products
+            .iter()
+            .find(|product| product.id == product_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Product {} not found in fixture", product_id))
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        Ok(self
+            .fixture
+            .payouts
+            .iter()
+            .filter(|payout| payout.arrival_date >= start && payout.arrival_date <= end)
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        let ids = self.fixture.payout_balance_transactions.get(payout_id).map(Vec::as_slice).unwrap_or_default();
+        let mut transactions = Vec::with_capacity(ids.len());
+        for id in ids {
+            transactions.push(self.fetch_balance_transaction(id).await?);
+        }
+        Ok(transactions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {"id": "in_q3", "status": "paid", "created": 1719792000},
+            {"id": "in_q4", "status": "paid", "created": 1728000000},
+            {"id": "in_open", "status": "open", "created": 1728000000}
+        ],
+        "customers": [{"id": "cus_1", "name": "Fixture Co"}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_fetch_paid_invoices_filters_status_and_range() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+
+        // Q4 2024
+        let invoices = source.fetch_paid_invoices(1727740800, 1735689599).await.unwrap();
+        let ids: Vec<&str> = invoices.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec!["in_q4"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_missing_customer_errors() {
+        let source = FixtureSource::from_json(FIXTURE).unwrap();
+
+        assert_eq!(source.fetch_customer("cus_1").await.unwrap().name.as_deref(), Some("Fixture Co"));
+        assert!(source.fetch_customer("cus_missing").await.is_err());
+    }
+}
@@ -0,0 +1,197 @@
+pub mod customer;
+pub mod events;
+pub mod fixture;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod recording;
+pub mod selected;
+pub mod sqlite;
+pub mod store;
+pub mod test_clock;
+pub mod webhook;
+
+pub use customer::CustomerScope;
+pub use fixture::FixtureSource;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use recording::RecordingSource;
+pub use selected::SelectedSource;
+pub use sqlite::{SqliteSource, SqliteStore};
+pub use store::{Store, StoreBackend, StoreSource, StoredObject};
+pub use test_clock::TestClockScope;
+
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeClient, StripeInvoice, TestClock,
+};
+use anyhow::Result;
+use std::future::Future;
+
+/// Where the report pipeline gets its Stripe objects from.
+///
+/// `StripeClient` talks to the live API; `FixtureSource` and `StoreSource`
+/// (SQLite or Postgres) serve the same objects from local data so the
+/// pipeline can be exercised end to end without an API key.
+pub trait InvoiceSource: Sync {
+    /// Fetch invoices with a status ("paid", "open", ...) created in a date
+    /// range (Unix timestamps, inclusive)
+    fn fetch_invoices(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+    ) -> impl Future<Output = Result<Vec<StripeInvoice>>> + Send;
+
+    /// Like `fetch_invoices`, reporting the running invoice count via
+    /// `on_page` as results arrive. Sources that don't paginate report once.
+    fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> impl Future<Output = Result<Vec<StripeInvoice>>> + Send {
+        async move {
+            let invoices = self.fetch_invoices(status, start, end).await?;
+            on_page(invoices.len());
+            Ok(invoices)
+        }
+    }
+
+    /// Fetch paid invoices for a date range (Unix timestamps, inclusive)
+    fn fetch_paid_invoices(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> impl Future<Output = Result<Vec<StripeInvoice>>> + Send {
+        self.fetch_invoices("paid", start, end)
+    }
+
+    /// Like `fetch_paid_invoices`, reporting the running invoice count via
+    /// `on_page` as results arrive
+    fn fetch_paid_invoices_with_progress(
+        &self,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> impl Future<Output = Result<Vec<StripeInvoice>>> + Send {
+        self.fetch_invoices_with_progress("paid", start, end, on_page)
+    }
+
+    /// Fetch an invoice by ID, whatever its status or date
+    fn fetch_invoice(&self, invoice_id: &str) -> impl Future<Output = Result<StripeInvoice>> + Send;
+
+    /// Whether an invoice fetched by ID belongs to this source's invoices.
+    /// Scopes narrow it to their clock or customer.
+    fn in_scope(&self, _invoice: &StripeInvoice) -> bool {
+        true
+    }
+
+    /// Fetch disputes opened in a date range (Unix timestamps, inclusive)
+    fn fetch_disputes(&self, start: i64, end: i64) -> impl Future<Output = Result<Vec<Dispute>>> + Send;
+
+    /// Fetch completed Checkout Sessions created in a date range (Unix
+    /// timestamps, inclusive)
+    fn fetch_checkout_sessions(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> impl Future<Output = Result<Vec<CheckoutSession>>> + Send;
+
+    /// Fetch PaymentIntents created in a date range (Unix timestamps, inclusive)
+    fn fetch_payment_intents(&self, start: i64, end: i64) -> impl Future<Output = Result<Vec<PaymentIntent>>> + Send;
+
+    /// Fetch the payments made toward an invoice
+    fn fetch_invoice_payments(&self, invoice_id: &str) -> impl Future<Output = Result<Vec<InvoicePayment>>> + Send;
+
+    /// Fetch a customer by ID
+    fn fetch_customer(&self, customer_id: &str) -> impl Future<Output = Result<Customer>> + Send;
+
+    /// Fetch a charge by ID
+    fn fetch_charge(&self, charge_id: &str) -> impl Future<Output = Result<Charge>> + Send;
+
+    /// Fetch a balance transaction by ID
+    fn fetch_balance_transaction(
+        &self,
+        balance_tx_id: &str,
+    ) -> impl Future<Output = Result<BalanceTransaction>> + Send;
+
+    /// Fetch a test clock by ID
+    fn fetch_test_clock(&self, test_clock_id: &str) -> impl Future<Output = Result<TestClock>> + Send;
+
+    /// Fetch a product by ID
+    fn fetch_product(&self, product_id: &str) -> impl Future<Output = Result<Product>> + Send;
+
+    /// Fetch payouts arriving in a date range (Unix timestamps, inclusive)
+    fn fetch_payouts(&self, start: i64, end: i64) -> impl Future<Output = Result<Vec<Payout>>> + Send;
+
+    /// Fetch the balance transactions a payout settled
+    fn fetch_payout_balance_transactions(
+        &self,
+        payout_id: &str,
+    ) -> impl Future<Output = Result<Vec<BalanceTransaction>>> + Send;
+}
+
+impl InvoiceSource for StripeClient {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        StripeClient::fetch_invoices_paged(self, status, start, end, |_| {}).await
+    }
+
+    async fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<StripeInvoice>> {
+        StripeClient::fetch_invoices_paged(self, status, start, end, on_page).await
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        StripeClient::fetch_invoice(self, invoice_id).await
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        StripeClient::fetch_disputes(self, start, end).await
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        StripeClient::fetch_checkout_sessions(self, start, end).await
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        StripeClient::fetch_payment_intents(self, start, end).await
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        StripeClient::fetch_invoice_payments(self, invoice_id).await
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        StripeClient::fetch_customer(self, customer_id).await
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        StripeClient::fetch_charge(self, charge_id).await
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        StripeClient::fetch_balance_transaction(self, balance_tx_id).await
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        StripeClient::fetch_test_clock(self, test_clock_id).await
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        StripeClient::fetch_product(self, product_id).await
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        StripeClient::fetch_payouts(self, start, end).await
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        StripeClient::fetch_payout_balance_transactions(self, payout_id).await
+    }
+}
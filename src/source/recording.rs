@@ -0,0 +1,179 @@
+use super::fixture::Fixture;
+use super::InvoiceSource;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeInvoice, TestClock,
+};
+use anyhow::Result;
+use std::sync::Mutex;
+
+/// Passes every call through to another source and keeps a copy of each
+/// object returned, so a run's raw data can be saved as a fixture and the
+/// report reproduced offline with `--fixture`.
+pub struct RecordingSource<'a, S> {
+    inner: &'a S,
+    recorded: Mutex<Fixture>,
+}
+
+/// Add `items` to `list`, skipping IDs already recorded
+fn record<T: Clone>(list: &mut Vec<T>, items: &[T], id: fn(&T) -> &str) {
+    for item in items {
+        if !list.iter().any(|existing| id(existing) == id(item)) {
+            list.push(item.clone());
+        }
+    }
+}
+
+impl<'a, S> RecordingSource<'a, S> {
+    pub fn new(inner: &'a S) -> Self {
+        RecordingSource { inner, recorded: Mutex::new(Fixture::default()) }
+    }
+
+    /// Everything fetched so far, as a fixture
+    pub fn into_fixture(self) -> Fixture {
+        self.recorded.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn with<T>(&self, result: Result<T>, add: impl FnOnce(&mut Fixture, &T)) -> Result<T> {
+        if let Ok(value) = &result {
+            let mut recorded = self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            add(&mut recorded, value);
+        }
+        result
+    }
+}
+
+impl<S: InvoiceSource> InvoiceSource for RecordingSource<'_, S> {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        let result = self.inner.fetch_invoices(status, start, end).await;
+        self.with(result, |f, invoices| record(&mut f.invoices, invoices, |i| &i.id))
+    }
+
+    async fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<StripeInvoice>> {
+        let result = self.inner.fetch_invoices_with_progress(status, start, end, on_page).await;
+        self.with(result, |f, invoices| record(&mut f.invoices, invoices, |i| &i.id))
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        let result = self.inner.fetch_invoice(invoice_id).await;
+        self.with(result, |f, invoice| record(&mut f.invoices, std::slice::from_ref(invoice), |i| &i.id))
+    }
+
+    fn in_scope(&self, invoice: &StripeInvoice) -> bool {
+        self.inner.in_scope(invoice)
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        let result = self.inner.fetch_disputes(start, end).await;
+        self.with(result, |f, disputes| record(&mut f.disputes, disputes, |d| &d.id))
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        let result = self.inner.fetch_checkout_sessions(start, end).await;
+        self.with(result, |f, sessions| record(&mut f.checkout_sessions, sessions, |s| &s.id))
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        let result = self.inner.fetch_payment_intents(start, end).await;
+        self.with(result, |f, intents| record(&mut f.payment_intents, intents, |p| &p.id))
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        let result = self.inner.fetch_invoice_payments(invoice_id).await;
+        self.with(result, |f, payments| record(&mut f.invoice_payments, payments, |p| &p.id))
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        let result = self.inner.fetch_customer(customer_id).await;
+        self.with(result, |f, customer| record(&mut f.customers, std::slice::from_ref(customer), |c| &c.id))
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        let result = self.inner.fetch_charge(charge_id).await;
+        self.with(result, |f, charge| record(&mut f.charges, std::slice::from_ref(charge), |c| &c.id))
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        let result = self.inner.fetch_balance_transaction(balance_tx_id).await;
+        self.with(result, |f, bt| record(&mut f.balance_transactions, std::slice::from_ref(bt), |b| &b.id))
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        let result = self.inner.fetch_test_clock(test_clock_id).await;
+        self.with(result, |f, clock| record(&mut f.test_clocks, std::slice::from_ref(clock), |c| &c.id))
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        let result = self.inner.fetch_product(product_id).await;
+        self.with(result, |f, product| record(&mut f.products, std::slice::from_ref(product), |p| &p.id))
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        let result = self.inner.fetch_payouts(start, end).await;
+        self.with(result, |f, payouts| record(&mut f.payouts, payouts, |p| &p.id))
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        let result = self.inner.fetch_payout_balance_transactions(payout_id).await;
+        self.with(result, |f, transactions| {
+            record(&mut f.balance_transactions, transactions, |b| &b.id);
+            f.payout_balance_transactions
+                .insert(payout_id.to_string(), transactions.iter().map(|bt| bt.id.clone()).collect());
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, ReportOptions, ReportProgress};
+    use crate::source::FixtureSource;
+
+    const FIXTURE: &str = r#"{
+        "invoices": [
+            {
+                "id": "in_1", "customer": "cus_1", "status": "paid", "created": 1728000000, "tax": 825, "charge": "ch_1",
+                "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            },
+            {
+                "id": "in_2", "customer": "cus_1", "status": "paid", "created": 1728000000, "tax": 825,
+                "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            },
+            {"id": "in_q3", "customer": "cus_2", "status": "paid", "created": 1720000000, "tax": 0}
+        ],
+        "customers": [
+            {"id": "cus_1", "name": "Lone Star LLC", "address": {"state": "TX"}},
+            {"id": "cus_2", "name": "Elsewhere Inc", "address": {"state": "CA"}}
+        ],
+        "charges": [{"id": "ch_1", "balance_transaction": "txn_1"}],
+        "balance_transactions": [{"id": "txn_1", "fee": 320}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_snapshot_reproduces_the_report() {
+        let fixture = FixtureSource::from_json(FIXTURE).unwrap();
+        let recording = RecordingSource::new(&fixture);
+        let (start, end) = (1727740800, 1735689599);
+        let original = build_report(&recording, start, end, &ReportOptions::default(), &ReportProgress::hidden()).await.unwrap();
+
+        // Only what the run fetched, once each
+        let snapshot = recording.into_fixture();
+        let ids: Vec<&str> = snapshot.invoices.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec!["in_1", "in_2"]);
+        assert_eq!(snapshot.customers.len(), 1);
+        assert_eq!(snapshot.balance_transactions.len(), 1);
+
+        let replay = FixtureSource::new(snapshot);
+        let replayed = build_report(&replay, start, end, &ReportOptions::default(), &ReportProgress::hidden()).await.unwrap();
+        let totals = |records: &[crate::stripe::models::InvoiceRecord]| -> Vec<(String, i64, i64)> {
+            records.iter().map(|r| (r.invoice_id.clone(), r.total, r.fees)).collect()
+        };
+        assert_eq!(totals(replayed.get_records()), totals(original.get_records()));
+    }
+}
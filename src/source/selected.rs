@@ -0,0 +1,105 @@
+use super::fixture::FixtureSource;
+use super::store::{StoreBackend, StoreSource};
+use super::InvoiceSource;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeClient, StripeInvoice, TestClock,
+};
+use anyhow::Result;
+
+/// The invoice source selected at runtime: `--fixture`, `--sqlite` or
+/// `--postgres`, else the Stripe API
+pub enum SelectedSource {
+    Fixture(FixtureSource),
+    Store(StoreSource<StoreBackend>),
+    Stripe(StripeClient),
+}
+
+impl SelectedSource {
+    /// The API client, when reading from Stripe
+    pub fn stripe(&self) -> Option<&StripeClient> {
+        match self {
+            SelectedSource::Stripe(client) => Some(client),
+            _ => None,
+        }
+    }
+}
+
+/// Call the same method on whichever source is selected
+macro_rules! dispatch {
+    ($self:ident, $source:ident => $call:expr) => {
+        match $self {
+            SelectedSource::Fixture($source) => $call,
+            SelectedSource::Store($source) => $call,
+            SelectedSource::Stripe($source) => $call,
+        }
+    };
+}
+
+impl InvoiceSource for SelectedSource {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        dispatch!(self, source => InvoiceSource::fetch_invoices(source, status, start, end).await)
+    }
+
+    async fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<StripeInvoice>> {
+        dispatch!(self, source => InvoiceSource::fetch_invoices_with_progress(source, status, start, end, on_page).await)
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        dispatch!(self, source => InvoiceSource::fetch_invoice(source, invoice_id).await)
+    }
+
+    fn in_scope(&self, invoice: &StripeInvoice) -> bool {
+        dispatch!(self, source => InvoiceSource::in_scope(source, invoice))
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        dispatch!(self, source => InvoiceSource::fetch_disputes(source, start, end).await)
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        dispatch!(self, source => InvoiceSource::fetch_checkout_sessions(source, start, end).await)
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        dispatch!(self, source => InvoiceSource::fetch_payment_intents(source, start, end).await)
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        dispatch!(self, source => InvoiceSource::fetch_invoice_payments(source, invoice_id).await)
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        dispatch!(self, source => InvoiceSource::fetch_customer(source, customer_id).await)
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        dispatch!(self, source => InvoiceSource::fetch_charge(source, charge_id).await)
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        dispatch!(self, source => InvoiceSource::fetch_balance_transaction(source, balance_tx_id).await)
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        dispatch!(self, source => InvoiceSource::fetch_test_clock(source, test_clock_id).await)
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        dispatch!(self, source => InvoiceSource::fetch_product(source, product_id).await)
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        dispatch!(self, source => InvoiceSource::fetch_payouts(source, start, end).await)
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        dispatch!(self, source => InvoiceSource::fetch_payout_balance_transactions(source, payout_id).await)
+    }
+}
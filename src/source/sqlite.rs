@@ -0,0 +1,178 @@
+use super::store::{Store, StoreSource, StoredObject};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Raw Stripe objects are stored as JSON, keyed by object type and ID.
+/// `created` and `status` are copied out so invoices can be filtered in SQL.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS stripe_objects (
+    object  TEXT NOT NULL,
+    id      TEXT NOT NULL,
+    created INTEGER,
+    status  TEXT,
+    data    TEXT NOT NULL,
+    PRIMARY KEY (object, id)
+);
+";
+
+/// Store of Stripe objects in a local SQLite database
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+/// Invoice source backed by a local SQLite database
+pub type SqliteSource = StoreSource<SqliteStore>;
+
+impl SqliteStore {
+    /// Open (or create) a SQLite database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+        Self::with_connection(conn)
+    }
+
+    /// Open a throwaway in-memory database
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA).context("Failed to initialize SQLite schema")?;
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow!("SQLite connection poisoned"))
+    }
+}
+
+impl SqliteSource {
+    /// Open (or create) a SQLite store at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(StoreSource::new(SqliteStore::open(path)?))
+    }
+
+    /// Open a throwaway in-memory store
+    pub fn open_in_memory() -> Result<Self> {
+        Ok(StoreSource::new(SqliteStore::open_in_memory()?))
+    }
+}
+
+impl Store for SqliteStore {
+    async fn put(&self, object: &str, id: &str, created: Option<i64>, status: Option<&str>, data: &str) -> Result<()> {
+        self.lock()?.execute(
+            "INSERT OR REPLACE INTO stripe_objects (object, id, created, status, data)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![object, id, created, status, data],
+        )?;
+        Ok(())
+    }
+
+    async fn get(&self, object: &str, id: &str) -> Result<Option<String>> {
+        let data = self
+            .lock()?
+            .query_row(
+                "SELECT data FROM stripe_objects WHERE object = ?1 AND id = ?2",
+                params![object, id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data)
+    }
+
+    async fn invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<String>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT data FROM stripe_objects
+             WHERE object = 'invoice' AND status = ?1 AND created >= ?2 AND created <= ?3
+             ORDER BY created, id",
+        )?;
+
+        let rows = stmt.query_map(params![status, start, end], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn created_between(&self, object: &str, start: i64, end: i64) -> Result<Vec<String>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT data FROM stripe_objects
+             WHERE object = ?1 AND created >= ?2 AND created <= ?3
+             ORDER BY created, id",
+        )?;
+
+        let rows = stmt.query_map(params![object, start, end], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn credit_notes(&self, invoice_id: &str) -> Result<Vec<String>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT data FROM stripe_objects
+             WHERE object = 'credit_note' AND json_extract(data, '$.invoice') = ?1
+             ORDER BY created, id",
+        )?;
+
+        let rows = stmt.query_map(params![invoice_id], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_once(&self, marker: &StoredObject, objects: &[StoredObject]) -> Result<bool> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO stripe_objects (object, id, created, status, data)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![marker.object, marker.id, marker.created, marker.status, marker.data],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        for row in objects {
+            tx.execute(
+                "INSERT OR REPLACE INTO stripe_objects (object, id, created, status, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![row.object, row.id, row.created, row.status, row.data],
+            )?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::InvoiceSource;
+    use crate::stripe::client::{Customer, StripeInvoice};
+
+    #[tokio::test]
+    async fn test_round_trip_invoices_and_customers() {
+        let source = SqliteSource::open_in_memory().unwrap();
+
+        let invoice: StripeInvoice = serde_json::from_value(serde_json::json!({
+            "id": "in_1", "status": "paid", "created": 1728000000, "customer": "cus_1"
+        }))
+        .unwrap();
+        let draft: StripeInvoice = serde_json::from_value(serde_json::json!({
+            "id": "in_2", "status": "draft", "created": 1728000000
+        }))
+        .unwrap();
+        source.insert_invoice(&invoice).await.unwrap();
+        source.insert_invoice(&draft).await.unwrap();
+        source
+            .insert_customer(&Customer { id: "cus_1".to_string(), name: Some("Stored Co".to_string()), ..Default::default() })
+            .await
+            .unwrap();
+
+        let invoices = source.fetch_paid_invoices(1727740800, 1735689599).await.unwrap();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].id, "in_1");
+
+        let customer = source.fetch_customer("cus_1").await.unwrap();
+        assert_eq!(customer.name.as_deref(), Some("Stored Co"));
+        assert!(source.fetch_charge("ch_missing").await.is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use super::InvoiceSource;
+use super::sqlite::SqliteStore;
+#[cfg(feature = "postgres")]
+use super::postgres::PostgresStore;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, CreditNote, Customer, Dispute, InvoicePayment, PaymentIntent,
+    Payout, Product, StripeEvent, StripeInvoice, TestClock,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+
+/// Persistence for raw Stripe objects, stored as JSON keyed by object type
+/// and ID. `created` and `status` are kept alongside so invoices can be
+/// filtered by the backend.
+///
+/// SQLite is the default backend; Postgres (the `postgres` feature) lets a
+/// team share one central store.
+pub trait Store: Sync {
+    /// Insert or replace an object
+    fn put(
+        &self,
+        object: &str,
+        id: &str,
+        created: Option<i64>,
+        status: Option<&str>,
+        data: &str,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// An object's JSON, if stored
+    fn get(&self, object: &str, id: &str) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    /// JSON of invoices with a status created in a range (Unix timestamps,
+    /// inclusive), ordered by creation time then ID
+    fn invoices(&self, status: &str, start: i64, end: i64) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// JSON of objects of a type whose `created` falls in a range, ordered
+    /// by `created` then ID
+    fn created_between(&self, object: &str, start: i64, end: i64) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// JSON of the credit notes against an invoice
+    fn credit_notes(&self, invoice_id: &str) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Insert `marker` and insert or replace `objects` in one transaction,
+    /// unless `marker` is already stored. Returns whether anything was written.
+    fn put_once(&self, marker: &StoredObject, objects: &[StoredObject]) -> impl Future<Output = Result<bool>> + Send;
+}
+
+/// One row of the store: a Stripe object's JSON with its type, ID, and the
+/// `created` and `status` copied out for filtering
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub object: String,
+    pub id: String,
+    pub created: Option<i64>,
+    pub status: Option<String>,
+    pub data: String,
+}
+
+impl StoredObject {
+    fn new<T: Serialize>(object: &str, id: &str, created: Option<i64>, status: Option<&str>, value: &T) -> Result<Self> {
+        Ok(StoredObject {
+            object: object.to_string(),
+            id: id.to_string(),
+            created,
+            status: status.map(str::to_string),
+            data: serde_json::to_string(value)?,
+        })
+    }
+
+    pub fn invoice(invoice: &StripeInvoice) -> Result<Self> {
+        Self::new("invoice", &invoice.id, Some(invoice.created), Some(&invoice.status), invoice)
+    }
+
+    pub fn credit_note(credit_note: &CreditNote) -> Result<Self> {
+        Self::new("credit_note", &credit_note.id, Some(credit_note.created), Some(&credit_note.note_type), credit_note)
+    }
+
+    pub fn customer(customer: &Customer) -> Result<Self> {
+        Self::new("customer", &customer.id, None, None, customer)
+    }
+
+    pub fn charge(charge: &Charge) -> Result<Self> {
+        Self::new("charge", &charge.id, None, None, charge)
+    }
+
+    pub fn balance_transaction(bt: &BalanceTransaction) -> Result<Self> {
+        Self::new("balance_transaction", &bt.id, None, None, bt)
+    }
+
+    fn event(event: &StripeEvent) -> Result<Self> {
+        Self::new("event", &event.id, Some(event.created), Some(&event.event_type), event)
+    }
+}
+
+/// Invoice source backed by a `Store` of Stripe objects
+pub struct StoreSource<S> {
+    store: S,
+}
+
+impl<S: Store> StoreSource<S> {
+    pub fn new(store: S) -> Self {
+        StoreSource { store }
+    }
+
+    pub async fn insert_invoice(&self, invoice: &StripeInvoice) -> Result<()> {
+        self.put(StoredObject::invoice(invoice)?).await
+    }
+
+    /// Store the payments toward an invoice, replacing any stored before
+    pub async fn insert_invoice_payments(&self, invoice_id: &str, payments: &[InvoicePayment]) -> Result<()> {
+        self.insert("invoice_payments", invoice_id, None, None, &payments).await
+    }
+
+    pub async fn insert_dispute(&self, dispute: &Dispute) -> Result<()> {
+        self.insert("dispute", &dispute.id, Some(dispute.created), Some(&dispute.status), dispute)
+            .await
+    }
+
+    pub async fn insert_checkout_session(&self, session: &CheckoutSession) -> Result<()> {
+        self.insert("checkout_session", &session.id, Some(session.created), session.status.as_deref(), session)
+            .await
+    }
+
+    pub async fn insert_payment_intent(&self, intent: &PaymentIntent) -> Result<()> {
+        self.insert("payment_intent", &intent.id, Some(intent.created), Some(&intent.status), intent)
+            .await
+    }
+
+    /// Store a payout, filed by its arrival date
+    pub async fn insert_payout(&self, payout: &Payout) -> Result<()> {
+        self.insert("payout", &payout.id, Some(payout.arrival_date), Some(&payout.status), payout)
+            .await
+    }
+
+    /// Store the balance transactions a payout settled, replacing any stored before
+    pub async fn insert_payout_balance_transactions(&self, payout_id: &str, transactions: &[BalanceTransaction]) -> Result<()> {
+        self.insert("payout_balance_transactions", payout_id, None, None, &transactions).await
+    }
+
+    pub async fn insert_credit_note(&self, credit_note: &CreditNote) -> Result<()> {
+        self.put(StoredObject::credit_note(credit_note)?).await
+    }
+
+    /// Record a webhook event as applied and store the objects it brought in
+    /// one transaction, so a redelivery isn't applied twice. Returns false,
+    /// storing nothing, if the event was already applied.
+    pub async fn insert_event(&self, event: &StripeEvent, objects: &[StoredObject]) -> Result<bool> {
+        self.store
+            .put_once(&StoredObject::event(event)?, objects)
+            .await
+            .with_context(|| format!("Failed to store event {}", event.id))
+    }
+
+    /// Whether an event has already been applied
+    pub async fn has_event(&self, event_id: &str) -> Result<bool> {
+        Ok(self.store.get("event", event_id).await?.is_some())
+    }
+
+    pub async fn insert_customer(&self, customer: &Customer) -> Result<()> {
+        self.put(StoredObject::customer(customer)?).await
+    }
+
+    pub async fn insert_charge(&self, charge: &Charge) -> Result<()> {
+        self.put(StoredObject::charge(charge)?).await
+    }
+
+    pub async fn insert_balance_transaction(&self, bt: &BalanceTransaction) -> Result<()> {
+        self.put(StoredObject::balance_transaction(bt)?).await
+    }
+
+    pub async fn insert_test_clock(&self, clock: &TestClock) -> Result<()> {
+        self.insert("test_clock", &clock.id, None, None, clock).await
+    }
+
+    pub async fn insert_product(&self, product: &Product) -> Result<()> {
+        self.insert("product", &product.id, None, None, product).await
+    }
+
+    async fn insert<T: Serialize>(
+        &self,
+        object: &str,
+        id: &str,
+        created: Option<i64>,
+        status: Option<&str>,
+        value: &T,
+    ) -> Result<()> {
+        self.put(StoredObject::new(object, id, created, status, value)?).await
+    }
+
+    async fn put(&self, row: StoredObject) -> Result<()> {
+        self.store
+            .put(&row.object, &row.id, row.created, row.status.as_deref(), &row.data)
+            .await
+            .with_context(|| format!("Failed to store {} {}", row.object, row.id))
+    }
+
+    /// An invoice with the credit notes stored against it counted in its
+    /// pre- and post-payment credit note amounts. Stripe's own amounts are
+    /// kept where larger, e.g. for notes issued before events were stored.
+    async fn with_credit_notes(&self, mut invoice: StripeInvoice) -> Result<StripeInvoice> {
+        let (mut pre_payment, mut post_payment) = (0, 0);
+        for data in self.store.credit_notes(&invoice.id).await? {
+            let credit_note: CreditNote = serde_json::from_str(&data).context("Failed to parse stored credit note")?;
+            match credit_note.note_type.as_str() {
+                "pre_payment" => pre_payment += credit_note.amount,
+                _ => post_payment += credit_note.amount,
+            }
+        }
+        invoice.pre_payment_credit_notes_amount = invoice.pre_payment_credit_notes_amount.max(pre_payment);
+        invoice.post_payment_credit_notes_amount = invoice.post_payment_credit_notes_amount.max(post_payment);
+        Ok(invoice)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, object: &str, id: &str) -> Result<T> {
+        let data = self
+            .store
+            .get(object, id)
+            .await?
+            .ok_or_else(|| anyhow!("{} {} not found in local store", object, id))?;
+        serde_json::from_str(&data).with_context(|| format!("Failed to parse stored {} {}", object, id))
+    }
+}
+
+impl<S: Store> InvoiceSource for StoreSource<S> {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        let mut invoices = Vec::new();
+        for data in self.store.invoices(status, start, end).await? {
+            let invoice = serde_json::from_str(&data).context("Failed to parse stored invoice")?;
+            invoices.push(self.with_credit_notes(invoice).await?);
+        }
+        Ok(invoices)
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        self.with_credit_notes(self.get("invoice", invoice_id).await?).await
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        let mut disputes = Vec::new();
+        for data in self.store.created_between("dispute", start, end).await? {
+            disputes.push(serde_json::from_str(&data).context("Failed to parse stored dispute")?);
+        }
+        Ok(disputes)
+    }
+
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        let mut sessions = Vec::new();
+        for data in self.store.created_between("checkout_session", start, end).await? {
+            sessions.push(serde_json::from_str(&data).context("Failed to parse stored checkout session")?);
+        }
+        Ok(sessions)
+    }
+
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        let mut intents = Vec::new();
+        for data in self.store.created_between("payment_intent", start, end).await? {
+            intents.push(serde_json::from_str(&data).context("Failed to parse stored payment intent")?);
+        }
+        Ok(intents)
+    }
+
+    /// Payments stored with `insert_invoice_payments`; none if never stored
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        match self.store.get("invoice_payments", invoice_id).await? {
+            Some(data) => serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse stored payments for invoice {}", invoice_id)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        self.get("customer", customer_id).await
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        self.get("charge", charge_id).await
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        self.get("balance_transaction", balance_tx_id).await
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        self.get("test_clock", test_clock_id).await
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        self.get("product", product_id).await
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        let mut payouts = Vec::new();
+        for data in self.store.created_between("payout", start, end).await? {
+            payouts.push(serde_json::from_str(&data).context("Failed to parse stored payout")?);
+        }
+        Ok(payouts)
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        self.get("payout_balance_transactions", payout_id).await
+    }
+}
+
+/// The store selected at runtime (`--sqlite` or `--postgres`)
+pub enum StoreBackend {
+    Sqlite(SqliteStore),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresStore),
+}
+
+impl StoreBackend {
+    /// Connect to a Postgres store; fails unless built with the `postgres` feature
+    #[cfg(feature = "postgres")]
+    pub async fn connect_postgres(url: &str) -> Result<Self> {
+        Ok(StoreBackend::Postgres(PostgresStore::connect(url).await?))
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    pub async fn connect_postgres(_url: &str) -> Result<Self> {
+        anyhow::bail!("Postgres stores need a build with the `postgres` feature (cargo install --features postgres)")
+    }
+}
+
+impl Store for StoreBackend {
+    async fn put(&self, object: &str, id: &str, created: Option<i64>, status: Option<&str>, data: &str) -> Result<()> {
+        match self {
+            StoreBackend::Sqlite(store) => store.put(object, id, created, status, data).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.put(object, id, created, status, data).await,
+        }
+    }
+
+    async fn get(&self, object: &str, id: &str) -> Result<Option<String>> {
+        match self {
+            StoreBackend::Sqlite(store) => store.get(object, id).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.get(object, id).await,
+        }
+    }
+
+    async fn invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<String>> {
+        match self {
+            StoreBackend::Sqlite(store) => store.invoices(status, start, end).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.invoices(status, start, end).await,
+        }
+    }
+
+    async fn created_between(&self, object: &str, start: i64, end: i64) -> Result<Vec<String>> {
+        match self {
+            StoreBackend::Sqlite(store) => store.created_between(object, start, end).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.created_between(object, start, end).await,
+        }
+    }
+
+    async fn credit_notes(&self, invoice_id: &str) -> Result<Vec<String>> {
+        match self {
+            StoreBackend::Sqlite(store) => store.credit_notes(invoice_id).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.credit_notes(invoice_id).await,
+        }
+    }
+
+    async fn put_once(&self, marker: &StoredObject, objects: &[StoredObject]) -> Result<bool> {
+        match self {
+            StoreBackend::Sqlite(store) => store.put_once(marker, objects).await,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres(store) => store.put_once(marker, objects).await,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use super::InvoiceSource;
+use crate::stripe::client::{
+    BalanceTransaction, Charge, CheckoutSession, Customer, Dispute, InvoicePayment, PaymentIntent, Payout, Product,
+    StripeInvoice, TestClock,
+};
+use anyhow::Result;
+
+/// Restricts a source to invoices generated under one Stripe test clock.
+///
+/// A test-mode account usually mixes clock-driven invoices with ones created
+/// in real time; scoping to the clock keeps a `--test-clock` run to the
+/// simulated invoices only. With no clock the source is passed through as is.
+pub struct TestClockScope<'a, S> {
+    inner: &'a S,
+    test_clock: Option<&'a str>,
+}
+
+impl<'a, S> TestClockScope<'a, S> {
+    pub fn new(inner: &'a S, test_clock: Option<&'a str>) -> Self {
+        TestClockScope { inner, test_clock }
+    }
+
+    fn retain_clock(&self, mut invoices: Vec<StripeInvoice>) -> Vec<StripeInvoice> {
+        if let Some(clock) = self.test_clock {
+            invoices.retain(|invoice| invoice.test_clock.as_deref() == Some(clock));
+        }
+        invoices
+    }
+}
+
+impl<S: InvoiceSource> InvoiceSource for TestClockScope<'_, S> {
+    async fn fetch_invoices(&self, status: &str, start: i64, end: i64) -> Result<Vec<StripeInvoice>> {
+        let invoices = self.inner.fetch_invoices(status, start, end).await?;
+        Ok(self.retain_clock(invoices))
+    }
+
+    async fn fetch_invoices_with_progress(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<StripeInvoice>> {
+        let invoices = self.inner.fetch_invoices_with_progress(status, start, end, on_page).await?;
+        Ok(self.retain_clock(invoices))
+    }
+
+    async fn fetch_invoice(&self, invoice_id: &str) -> Result<StripeInvoice> {
+        self.inner.fetch_invoice(invoice_id).await
+    }
+
+    fn in_scope(&self, invoice: &StripeInvoice) -> bool {
+        self.test_clock.is_none_or(|clock| invoice.test_clock.as_deref() == Some(clock)) && self.inner.in_scope(invoice)
+    }
+
+    async fn fetch_disputes(&self, start: i64, end: i64) -> Result<Vec<Dispute>> {
+        self.inner.fetch_disputes(start, end).await
+    }
+
+    /// None under a clock: clocks only advance subscriptions and invoices
+    async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> Result<Vec<CheckoutSession>> {
+        match self.test_clock {
+            Some(_) => Ok(Vec::new()),
+            None => self.inner.fetch_checkout_sessions(start, end).await,
+        }
+    }
+
+    /// None under a clock: clocks only advance subscriptions and invoices
+    async fn fetch_payment_intents(&self, start: i64, end: i64) -> Result<Vec<PaymentIntent>> {
+        match self.test_clock {
+            Some(_) => Ok(Vec::new()),
+            None => self.inner.fetch_payment_intents(start, end).await,
+        }
+    }
+
+    async fn fetch_invoice_payments(&self, invoice_id: &str) -> Result<Vec<InvoicePayment>> {
+        self.inner.fetch_invoice_payments(invoice_id).await
+    }
+
+    async fn fetch_customer(&self, customer_id: &str) -> Result<Customer> {
+        self.inner.fetch_customer(customer_id).await
+    }
+
+    async fn fetch_charge(&self, charge_id: &str) -> Result<Charge> {
+        self.inner.fetch_charge(charge_id).await
+    }
+
+    async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> Result<BalanceTransaction> {
+        self.inner.fetch_balance_transaction(balance_tx_id).await
+    }
+
+    async fn fetch_test_clock(&self, test_clock_id: &str) -> Result<TestClock> {
+        self.inner.fetch_test_clock(test_clock_id).await
+    }
+
+    async fn fetch_product(&self, product_id: &str) -> Result<Product> {
+        self.inner.fetch_product(product_id).await
+    }
+
+    async fn fetch_payouts(&self, start: i64, end: i64) -> Result<Vec<Payout>> {
+        self.inner.fetch_payouts(start, end).await
+    }
+
+    async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> Result<Vec<BalanceTransaction>> {
+        self.inner.fetch_payout_balance_transactions(payout_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, get_previous_quarter_from, ReportOptions, ReportProgress};
+    use crate::source::FixtureSource;
+    use crate::clock::{Clock, FixedClock};
+
+    // The clock is frozen at 2025-01-15, so the reported quarter is Q4 2024.
+    // Only in_clock_q4 is both on the clock and inside that quarter.
+    const FIXTURE: &str = r#"{
+        "test_clocks": [{"id": "tc_123", "name": "Quarter rollover", "frozen_time": 1736899200}],
+        "invoices": [
+            {
+                "id": "in_clock_q4", "customer": "cus_1", "status": "paid", "created": 1733011200,
+                "test_clock": "tc_123", "tax": 825,
+                "lines": {"data": [{"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            },
+            {
+                "id": "in_clock_q1", "customer": "cus_1", "status": "paid", "created": 1736294400,
+                "test_clock": "tc_123", "tax": 825,
+                "lines": {"data": [{"id": "il_2", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            },
+            {
+                "id": "in_realtime_q4", "customer": "cus_1", "status": "paid", "created": 1733011200, "tax": 825,
+                "lines": {"data": [{"id": "il_3", "type": "subscription", "amount": 10000, "quantity": 1}]}
+            }
+        ],
+        "customers": [{"id": "cus_1", "name": "Clockwork LLC", "address": {"state": "TX"}}]
+    }"#;
+
+    #[tokio::test]
+    async fn test_test_clock_drives_quarter_and_scopes_invoices() {
+        let fixture = FixtureSource::from_json(FIXTURE).unwrap();
+        let source = TestClockScope::new(&fixture, Some("tc_123"));
+
+        let clock = source.fetch_test_clock("tc_123").await.unwrap();
+        let today = FixedClock::from_timestamp(clock.frozen_time).unwrap().today(None);
+        let period = get_previous_quarter_from(today);
+        assert_eq!(period.label(), "Q4-2024");
+
+        let (start, end) = period.bounds(&chrono::Utc).unwrap();
+        let generator = build_report(&source, start, end, &ReportOptions::default(), &ReportProgress::hidden()).await.unwrap();
+
+        let ids: Vec<&str> = generator.get_records().iter().map(|r| r.invoice_id.as_str()).collect();
+        assert_eq!(ids, vec!["in_clock_q4"]);
+    }
+
+    #[tokio::test]
+    async fn test_no_clock_passes_everything_through() {
+        let fixture = FixtureSource::from_json(FIXTURE).unwrap();
+        let source = TestClockScope::new(&fixture, None);
+
+        let invoices = source.fetch_paid_invoices(0, i64::MAX).await.unwrap();
+        assert_eq!(invoices.len(), 3);
+    }
+}
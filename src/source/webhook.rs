@@ -0,0 +1,154 @@
+use super::events::{apply_event, Applied};
+use super::store::{Store, StoreSource};
+use crate::health::{self, Health};
+use crate::http::{self, ServeOptions};
+use crate::stripe::client::{StripeClient, StripeEvent};
+use anyhow::{anyhow, bail, Context, Result};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+/// How old a signed timestamp may be before the event is rejected as a replay
+const TOLERANCE_SECS: i64 = 300;
+
+/// What the webhook handler needs to verify and store events
+struct Listener<S: Store> {
+    store: StoreSource<S>,
+    secret: String,
+    client: Option<StripeClient>,
+}
+
+/// Verify a `Stripe-Signature` header (`t=...,v1=...`) against the raw
+/// payload: one of its `v1` signatures must be the HMAC-SHA256 of
+/// `{t}.{payload}` under the endpoint's signing secret, and `t` must be
+/// within five minutes of `now`.
+pub fn verify_signature(payload: &[u8], header: &str, secret: &str, now: i64) -> Result<()> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(decode_hex(value)),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or_else(|| anyhow!("Signature header has no timestamp"))?;
+    if signatures.is_empty() {
+        bail!("Signature header has no v1 signature");
+    }
+    if (now - timestamp).abs() > TOLERANCE_SECS {
+        bail!("Signature timestamp is outside the {}s tolerance", TOLERANCE_SECS);
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).context("Invalid webhook secret")?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    if signatures.iter().any(|signature| mac.clone().verify_slice(signature).is_ok()) {
+        Ok(())
+    } else {
+        bail!("No signature matches the payload")
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len()).step_by(2).map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// The webhook route, plus the health endpoints
+fn router<S: Store + Send + 'static>(listener: Listener<S>, health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/webhook", post(handle_webhook::<S>))
+        .with_state(Arc::new(listener))
+        .merge(health::routes(health))
+}
+
+/// Listen for Stripe webhooks on `POST /webhook` until Ctrl-C or SIGTERM,
+/// verifying each against `secret` and applying it to `store`. Events
+/// already being applied are finished before it exits.
+pub async fn listen<S: Store + Send + 'static>(
+    options: &ServeOptions,
+    store: StoreSource<S>,
+    secret: String,
+    client: Option<StripeClient>,
+) -> Result<()> {
+    let health = Health::new();
+    let app = router(Listener { store, secret, client }, health.clone());
+    tracing::info!("Listening for Stripe webhooks on {}/webhook", options.base_url());
+    http::run(app, options, health).await.context("Webhook listener failed")
+}
+
+async fn handle_webhook<S: Store + Send + 'static>(
+    State(listener): State<Arc<Listener<S>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let header = headers.get("stripe-signature").and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if let Err(e) = verify_signature(&body, header, &listener.secret, chrono::Utc::now().timestamp()) {
+        tracing::warn!("Rejected webhook: {}", e);
+        return (StatusCode::BAD_REQUEST, e.to_string());
+    }
+    let event: StripeEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid event: {}", e)),
+    };
+    match apply_event(&listener.store, &event, listener.client.as_ref()).await {
+        Ok(applied) => {
+            if applied == Applied::Stored {
+                tracing::info!("Stored {} ({})", event.id, event.event_type);
+            }
+            (StatusCode::OK, format!("{:?}", applied).to_lowercase())
+        }
+        Err(e) => {
+            // Stripe retries failed deliveries, so the event isn't lost
+            tracing::error!("Failed to apply {}: {:#}", event.id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(payload: &[u8], timestamp: i64, secret: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(payload);
+        let signature: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("t={},v1={}", timestamp, signature)
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let payload = br#"{"id":"evt_1","type":"invoice.paid"}"#;
+        let header = sign(payload, 1728000000, "whsec_test");
+
+        assert!(verify_signature(payload, &header, "whsec_test", 1728000060).is_ok());
+        assert!(verify_signature(payload, &header, "whsec_other", 1728000060).is_err());
+        assert!(verify_signature(b"{}", &header, "whsec_test", 1728000060).is_err());
+        assert!(verify_signature(payload, &header, "whsec_test", 1728001000).is_err());
+        assert!(verify_signature(payload, "t=1728000000", "whsec_test", 1728000000).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_alongside_webhook() {
+        let store = crate::source::SqliteSource::open_in_memory().unwrap();
+        let listener = Listener { store, secret: "whsec_test".to_string(), client: None };
+        let base = health::spawn_test_server(router(listener, Health::new())).await;
+        let client = reqwest::Client::new();
+
+        assert_eq!(client.get(format!("{}/readyz", base)).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/version", base)).send().await.unwrap().status(), 200);
+        let response = client.post(format!("{}/webhook", base)).body("{}").send().await.unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -1,9 +1,15 @@
+use super::schema::{self, Schema};
+use super::usage::{self, ApiUsage};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StripeInvoice {
     pub id: String,
+    /// Customer-facing invoice number (e.g. `ACME-0042`), assigned when finalized
+    #[serde(default)]
+    pub number: Option<String>,
     #[serde(default)]
     pub customer: serde_json::Value,
     #[serde(default)]
@@ -16,16 +22,114 @@ pub struct StripeInvoice {
     pub created: i64,
     #[serde(default)]
     pub paid_at: Option<i64>,
+    /// When the invoice was finalized (moved out of draft)
+    #[serde(default)]
+    pub finalized_at: Option<i64>,
     #[serde(default)]
     pub amount_due: i64,
     #[serde(default)]
     pub amount_paid: i64,
     #[serde(default)]
     pub tax: Option<i64>,
+    /// Amount after discounts, including tax
+    #[serde(default)]
+    pub total: Option<i64>,
+    /// Credit notes issued before the invoice was paid, reducing what was due
+    #[serde(default)]
+    pub pre_payment_credit_notes_amount: i64,
+    /// Credit notes issued after the invoice was paid (refunds or credit)
+    #[serde(default)]
+    pub post_payment_credit_notes_amount: i64,
+    /// Customer balance before the invoice was finalized; negative is credit
+    #[serde(default)]
+    pub starting_balance: i64,
+    /// Customer balance after the invoice was finalized
+    #[serde(default)]
+    pub ending_balance: Option<i64>,
     #[serde(default)]
     pub lines: LineItems,
+    /// Tax per tax rate; the rates say which jurisdiction it was collected for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub total_tax_amounts: Vec<TaxAmount>,
     #[serde(default)]
     pub charge: Option<serde_json::Value>,
+    /// Three-letter ISO currency code, lowercase (e.g. "usd"); USD if absent
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Test clock the invoice was generated under (test mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+    /// Subscription the invoice bills, as an ID or expanded
+    /// (`expand[]=data.subscription`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<serde_json::Value>,
+    /// Stripe's ID for the request it was fetched in, for the audit file
+    #[serde(skip)]
+    pub request_id: Option<String>,
+}
+
+impl StripeInvoice {
+    /// The invoice's customer ID, whether `customer` is an ID or expanded
+    pub fn customer_id(&self) -> Option<&str> {
+        self.customer.as_str().or_else(|| self.customer.get("id")?.as_str()).filter(|id| !id.is_empty())
+    }
+
+    /// The ID of the charge that paid the invoice, whether `charge` is an ID or expanded
+    pub fn charge_id(&self) -> Option<&str> {
+        let charge = self.charge.as_ref()?;
+        charge.as_str().or_else(|| charge.get("id")?.as_str()).filter(|id| !id.is_empty())
+    }
+
+    /// The subscription the invoice bills, if it was expanded
+    pub fn subscription(&self) -> Option<Subscription> {
+        serde_json::from_value(self.subscription.clone()?).ok()
+    }
+
+    /// The ID of the subscription the invoice bills, whether `subscription` is an ID or expanded
+    pub fn subscription_id(&self) -> Option<&str> {
+        let subscription = self.subscription.as_ref()?;
+        subscription.as_str().or_else(|| subscription.get("id")?.as_str()).filter(|id| !id.is_empty())
+    }
+
+    /// The plans billed: the nicknames (else price IDs) of the subscription's
+    /// items, or of the invoice's subscription lines when the subscription
+    /// isn't expanded. Several plans are joined with ` + `.
+    pub fn plan_name(&self) -> Option<String> {
+        let prices: Vec<Price> = match self.subscription() {
+            Some(subscription) if !subscription.items.data.is_empty() => {
+                subscription.items.data.into_iter().filter_map(|item| item.price).collect()
+            }
+            _ => self
+                .lines
+                .data
+                .iter()
+                .filter(|line| line.line_type == "subscription")
+                .filter_map(|line| line.price.clone())
+                .collect(),
+        };
+        let mut names: Vec<String> = Vec::new();
+        for price in prices {
+            let name = price.nickname.filter(|n| !n.is_empty()).unwrap_or(price.id);
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        (!names.is_empty()).then(|| names.join(" + "))
+    }
+
+    /// Customer credit balance applied to the invoice (cents)
+    pub fn applied_balance(&self) -> i64 {
+        match self.ending_balance {
+            Some(ending) if self.starting_balance < 0 => (ending.min(0) - self.starting_balance).max(0),
+            _ => 0,
+        }
+    }
+
+    /// What was settled with credit rather than paid: credit notes issued
+    /// before payment plus applied customer credit balance (cents)
+    pub fn credits_applied(&self) -> i64 {
+        self.pre_payment_credit_notes_amount + self.applied_balance()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -34,7 +138,7 @@ pub struct LineItems {
     pub data: Vec<LineItem>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LineItem {
     #[serde(default)]
     pub id: String,
@@ -46,12 +150,109 @@ pub struct LineItem {
     pub quantity: Option<i32>,
     #[serde(default)]
     pub tax_amounts: Option<Vec<TaxAmount>>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub price: Option<Price>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Price {
+    #[serde(default)]
+    pub id: String,
+    /// Product ID, or the product object when expanded
+    #[serde(default)]
+    pub product: serde_json::Value,
+    /// Name of the price as set in the Dashboard (the plan name)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+/// An expanded subscription, as far as reports need it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Subscription {
+    #[serde(default)]
+    pub id: String,
+    /// Subscription schedule managing the subscription's phases, if any
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub items: SubscriptionItems,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubscriptionItems {
+    #[serde(default)]
+    pub data: Vec<SubscriptionItem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubscriptionItem {
+    #[serde(default)]
+    pub price: Option<Price>,
+}
+
+impl LineItem {
+    /// The line's product ID, if it has a price
+    pub fn product_id(&self) -> Option<&str> {
+        let product = &self.price.as_ref()?.product;
+        product.as_str().or_else(|| product.get("id")?.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TaxAmount {
     #[serde(default)]
     pub amount: i64,
+    /// Tax rate ID, or the tax rate object when expanded
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub tax_rate: serde_json::Value,
+}
+
+impl TaxAmount {
+    /// The tax rate the amount was charged at, if it was expanded
+    pub fn tax_rate(&self) -> Option<TaxRate> {
+        self.tax_rate.is_object().then(|| serde_json::from_value(self.tax_rate.clone()).ok()).flatten()
+    }
+}
+
+/// A tax rate, naming the jurisdiction tax was collected for
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaxRate {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Jurisdiction name, e.g. `TEXAS` or `AUSTIN`
+    #[serde(default)]
+    pub jurisdiction: Option<String>,
+    /// `state`, `county`, `city`, `district`, `country` or `multiple`
+    #[serde(default)]
+    pub jurisdiction_level: Option<String>,
+    #[serde(default)]
+    pub percentage: Option<f64>,
+}
+
+/// One payment toward an invoice; an invoice paid in installments has several
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InvoicePayment {
+    pub id: String,
+    /// Invoice ID
+    #[serde(default)]
+    pub invoice: String,
+    #[serde(default)]
+    pub amount_paid: i64,
+    /// "paid", "open" or "canceled"
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub status_transitions: InvoicePaymentTransitions,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InvoicePaymentTransitions {
+    #[serde(default)]
+    pub paid_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,7 +267,7 @@ pub struct InvoiceListResponse {
     pub url: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Customer {
     #[serde(default)]
     pub id: String,
@@ -74,9 +275,48 @@ pub struct Customer {
     pub name: Option<String>,
     #[serde(default)]
     pub address: Option<Address>,
+    #[serde(default)]
+    pub metadata: std::collections::BTreeMap<String, String>,
+    /// "none", "exempt" or "reverse"
+    #[serde(default)]
+    pub tax_exempt: Option<String>,
+    /// Only present when expanded (`expand[]=tax_ids`)
+    #[serde(default)]
+    pub tax_ids: Option<TaxIdList>,
+}
+
+impl Customer {
+    /// Whether the customer's sales are exempt from tax (tax exempt, or
+    /// reverse charge where the buyer accounts for the tax)
+    pub fn is_tax_exempt(&self) -> bool {
+        matches!(self.tax_exempt.as_deref(), Some("exempt") | Some("reverse"))
+    }
+
+    /// Tax IDs as "type value", e.g. "us_ein 12-3456789"
+    pub fn tax_id_labels(&self) -> Vec<String> {
+        self.tax_ids
+            .iter()
+            .flat_map(|ids| &ids.data)
+            .map(|id| format!("{} {}", id.id_type, id.value))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TaxIdList {
+    #[serde(default)]
+    pub data: Vec<TaxId>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TaxId {
+    #[serde(rename = "type", default)]
+    pub id_type: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct Address {
     #[serde(default)]
     pub city: Option<String>,
@@ -98,155 +338,1183 @@ pub struct BillingDetails {
     pub address: Option<Address>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Charge {
     #[serde(default)]
     pub id: String,
+    /// Amount charged (cents)
+    #[serde(default)]
+    pub amount: i64,
+    /// Amount refunded so far (cents)
+    #[serde(default)]
+    pub amount_refunded: i64,
     #[serde(default)]
     pub balance_transaction: Option<String>,
     #[serde(default)]
     pub billing_details: Option<BillingDetails>,
+    #[serde(default)]
+    pub payment_method_details: Option<PaymentMethodDetails>,
+    /// Invoice the charge paid, if any
+    #[serde(default)]
+    pub invoice: Option<String>,
+    /// Stripe's ID for the request it was fetched in, for the audit file
+    #[serde(skip)]
+    pub request_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PaymentMethodDetails {
+    /// Payment method type: "card", "us_bank_account", ...
+    #[serde(rename = "type", default)]
+    pub method_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BalanceTransaction {
     #[serde(default)]
     pub id: String,
+    /// Fee in the settlement currency
     #[serde(default)]
     pub fee: i64,
+    /// Settlement currency (lowercase ISO code)
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Rate from the charge's currency to the settlement currency, when they differ
+    #[serde(default)]
+    pub exchange_rate: Option<f64>,
+    /// What `fee` is made of
+    #[serde(default)]
+    pub fee_details: Vec<FeeDetail>,
+    /// Gross amount in the settlement currency
+    #[serde(default)]
+    pub amount: i64,
+    /// `amount` less `fee`
+    #[serde(default)]
+    pub net: i64,
+    /// "charge", "payment", "refund", "adjustment", "stripe_fee", "payout", ...
+    #[serde(rename = "type", default)]
+    pub transaction_type: String,
+    /// ID of the object that caused the transaction (a charge, refund, ...)
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub created: i64,
+    /// Stripe's ID for the request it was fetched in, for the audit file
+    #[serde(skip)]
+    pub request_id: Option<String>,
 }
 
-pub struct StripeClient {
-    api_key: String,
-    client: reqwest::Client,
+/// A customer's dispute of a charge (a chargeback)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Dispute {
+    pub id: String,
+    /// Disputed charge ID
+    #[serde(default)]
+    pub charge: String,
+    /// Disputed amount (cents), at most the charge amount
+    #[serde(default)]
+    pub amount: i64,
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// "won", "lost", "needs_response", "under_review", "warning_closed", ...
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub created: i64,
+    /// Funds withdrawn for the dispute (with the dispute fee) and any reinstated
+    #[serde(default)]
+    pub balance_transactions: Vec<BalanceTransaction>,
 }
 
-impl StripeClient {
-    pub fn new(api_key: String) -> Self {
-        StripeClient {
-            api_key,
-            client: reqwest::Client::new(),
-        }
+impl Dispute {
+    /// Whether the customer's bank ruled for them, so the sale is reversed
+    pub fn is_lost(&self) -> bool {
+        self.status == "lost"
     }
 
-    /// Fetch a customer by ID
-    pub async fn fetch_customer(&self, customer_id: &str) -> anyhow::Result<Customer> {
-        let url = format!("https://api.stripe.com/v1/customers/{}", customer_id);
-
-        let response = self
-            .client
-            .get(&url)
-            .basic_auth(&self.api_key, Some(""))
-            .send()
-            .await
-            .context("Failed to reach Stripe API")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Failed to fetch customer {}: {} {}", customer_id, status, body);
-        }
+    /// Whether the dispute is still being decided
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "needs_response" | "under_review" | "warning_needs_response" | "warning_under_review"
+        )
+    }
 
-        response
-            .json()
-            .await
-            .context("Failed to parse customer response")
+    /// Dispute fees charged (cents)
+    pub fn fee(&self) -> i64 {
+        self.balance_transactions.iter().map(|bt| bt.fee).sum()
     }
+}
 
-    /// Fetch charge by ID to get balance_transaction reference
-    pub async fn fetch_charge(&self, charge_id: &str) -> anyhow::Result<Charge> {
-        let url = format!("https://api.stripe.com/v1/charges/{}", charge_id);
+/// A transfer of the Stripe balance to the bank account
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Payout {
+    pub id: String,
+    /// Amount deposited (cents)
+    #[serde(default)]
+    pub amount: i64,
+    /// Lowercase ISO currency code
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// When the payout is expected to reach (or reached) the bank
+    #[serde(default)]
+    pub arrival_date: i64,
+    /// "paid", "pending", "in_transit", "canceled" or "failed"
+    #[serde(default)]
+    pub status: String,
+    /// Whether Stripe created the payout on the account's schedule; only
+    /// automatic payouts list the balance transactions they settled
+    #[serde(default)]
+    pub automatic: bool,
+}
 
-        let response = self
-            .client
-            .get(&url)
-            .basic_auth(&self.api_key, Some(""))
-            .send()
-            .await
-            .context("Failed to reach Stripe API")?;
+/// What a Checkout Session collected from the customer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomerDetails {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub address: Option<Address>,
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Failed to fetch charge {}: {} {}", charge_id, status, body);
-        }
+/// A Checkout Session's discount, shipping and tax totals (cents)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TotalDetails {
+    #[serde(default)]
+    pub amount_discount: i64,
+    #[serde(default)]
+    pub amount_shipping: i64,
+    #[serde(default)]
+    pub amount_tax: i64,
+}
 
-        response
-            .json()
-            .await
-            .context("Failed to parse charge response")
-    }
+/// A Stripe Checkout Session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+    #[serde(default)]
+    pub created: i64,
+    /// Customer ID; absent for guest checkouts
+    #[serde(default)]
+    pub customer: Option<String>,
+    #[serde(default)]
+    pub customer_details: Option<CustomerDetails>,
+    /// Amount charged after discounts, including tax (cents)
+    #[serde(default)]
+    pub amount_total: Option<i64>,
+    #[serde(default)]
+    pub total_details: Option<TotalDetails>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// "payment", "subscription" or "setup"
+    #[serde(default)]
+    pub mode: String,
+    /// "open", "complete" or "expired"
+    #[serde(default)]
+    pub status: Option<String>,
+    /// "paid", "unpaid" or "no_payment_required"
+    #[serde(default)]
+    pub payment_status: String,
+    /// Invoice created for the session (subscriptions, or payments with
+    /// invoice creation enabled)
+    #[serde(default)]
+    pub invoice: Option<String>,
+    #[serde(default)]
+    pub payment_intent: Option<String>,
+}
 
-    /// Fetch balance transaction by ID to get fee information
-    pub async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> anyhow::Result<BalanceTransaction> {
-        let url = format!("https://api.stripe.com/v1/balance_transactions/{}", balance_tx_id);
+/// A Stripe PaymentIntent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaymentIntent {
+    pub id: String,
+    #[serde(default)]
+    pub created: i64,
+    /// Amount collected (cents)
+    #[serde(default)]
+    pub amount_received: i64,
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// "succeeded", "processing", "canceled", ...
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub customer: Option<String>,
+    /// Invoice the payment paid, if any
+    #[serde(default)]
+    pub invoice: Option<String>,
+    /// Most recent charge, which carries the billing address and fee
+    #[serde(default)]
+    pub latest_charge: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
 
-        let response = self
-            .client
-            .get(&url)
-            .basic_auth(&self.api_key, Some(""))
-            .send()
-            .await
-            .context("Failed to reach Stripe API")?;
+/// A credit note against an invoice
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreditNote {
+    pub id: String,
+    /// Invoice ID the credit note is against
+    #[serde(default)]
+    pub invoice: String,
+    /// Total credited, including tax (cents)
+    #[serde(default)]
+    pub amount: i64,
+    /// "pre_payment" (reduces what's due) or "post_payment" (refund or credit)
+    #[serde(rename = "type", default)]
+    pub note_type: String,
+    #[serde(default)]
+    pub created: i64,
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Failed to fetch balance transaction {}: {} {}", balance_tx_id, status, body);
-        }
+/// A Stripe event, as delivered to a webhook endpoint or listed from `/v1/events`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StripeEvent {
+    pub id: String,
+    /// e.g. "invoice.paid", "charge.refunded"
+    #[serde(rename = "type", default)]
+    pub event_type: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub data: EventData,
+}
 
-        response
-            .json()
-            .await
-            .context("Failed to parse balance transaction response")
-    }
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventData {
+    /// The object the event is about, as it was when the event occurred
+    #[serde(default)]
+    pub object: serde_json::Value,
+}
 
-    /// Fetch paid invoices for a date range (Unix timestamps)
-    pub async fn fetch_paid_invoices(
-        &self,
-        start: i64,
-        end: i64,
-    ) -> anyhow::Result<Vec<StripeInvoice>> {
-        let mut all_invoices = Vec::new();
-        let mut starting_after: Option<String> = None;
+/// A Stripe Report Run: a CSV report Stripe builds asynchronously
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReportRun {
+    pub id: String,
+    #[serde(default)]
+    pub report_type: String,
+    /// "pending", "succeeded" or "failed"
+    #[serde(default)]
+    pub status: String,
+    /// Why the run failed
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The report file, once the run succeeded
+    #[serde(default)]
+    pub result: Option<StripeFile>,
+}
 
-        loop {
-            let url = "https://api.stripe.com/v1/invoices";
+/// A file in the Files API
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StripeFile {
+    pub id: String,
+    /// Where to download the contents (authenticated with the API key)
+    #[serde(default)]
+    pub url: Option<String>,
+}
 
-            // Build URL - we'll fetch charge details separately
+/// Where a Stripe Tax registration applies within its country
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegistrationOptions {
+    /// Two-letter state code, for US registrations
+    #[serde(default)]
+    pub state: Option<String>,
+    /// e.g. "state_sales_tax", "local_amusement_tax"
+    #[serde(default, rename = "type")]
+    pub kind: Option<String>,
+}
+
+/// A Stripe Tax registration: where Stripe collects tax for the account
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaxRegistration {
+    pub id: String,
+    /// ISO country code
+    #[serde(default)]
+    pub country: String,
+    /// Options keyed by lowercase country code (`us`)
+    #[serde(default)]
+    pub country_options: std::collections::BTreeMap<String, RegistrationOptions>,
+    /// "active", "expired" or "scheduled"
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub active_from: i64,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl TaxRegistration {
+    /// The US state registered in, if this is a US registration
+    pub fn us_state(&self) -> Option<&str> {
+        if !self.country.eq_ignore_ascii_case("US") {
+            return None;
+        }
+        self.country_options.get("us").and_then(|o| o.state.as_deref())
+    }
+
+    /// Whether the registration was in effect at some point between `start`
+    /// and `end` (Unix timestamps, inclusive)
+    pub fn active_between(&self, start: i64, end: i64) -> bool {
+        self.active_from <= end && self.expires_at.is_none_or(|expires| expires > start)
+    }
+}
+
+/// One page of a Stripe list endpoint
+#[derive(Debug, Deserialize)]
+struct ListResponse<T> {
+    #[serde(default = "Vec::new")]
+    data: Vec<T>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+/// One component of a balance transaction's fee
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FeeDetail {
+    #[serde(default)]
+    pub amount: i64,
+    /// e.g. "Stripe processing fees", "Automatic Taxes"
+    #[serde(default)]
+    pub description: Option<String>,
+    /// "stripe_fee", "application_fee" or "tax" (tax on Stripe's fees)
+    #[serde(rename = "type", default)]
+    pub fee_type: String,
+}
+
+/// A Stripe product, for naming line items in the product breakdown
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Product {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// A Stripe test clock: simulated time for test-mode customers and their invoices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestClock {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The simulated current time (Unix timestamp)
+    #[serde(default)]
+    pub frozen_time: i64,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// Whether an API key talks to live or test-mode data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMode {
+    Live,
+    Test,
+}
+
+impl KeyMode {
+    /// Detect the mode from a secret or restricted key prefix
+    /// (`sk_live_`, `rk_live_`, `sk_test_`, `rk_test_`)
+    pub fn detect(api_key: &str) -> Option<KeyMode> {
+        if api_key.starts_with("sk_live_") || api_key.starts_with("rk_live_") {
+            Some(KeyMode::Live)
+        } else if api_key.starts_with("sk_test_") || api_key.starts_with("rk_test_") {
+            Some(KeyMode::Test)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for KeyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyMode::Live => write!(f, "live"),
+            KeyMode::Test => write!(f, "test"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusinessProfile {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// A Stripe Connect account connected to the platform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedAccount {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub business_profile: Option<BusinessProfile>,
+}
+
+/// The account an API key belongs to (`/v1/account`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub default_currency: Option<String>,
+    #[serde(default)]
+    pub business_profile: Option<BusinessProfile>,
+    #[serde(default)]
+    pub settings: Option<AccountSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSettings {
+    #[serde(default)]
+    pub dashboard: Option<DashboardSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardSettings {
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+impl Account {
+    /// The public business name, else the name shown in the Dashboard
+    pub fn business_name(&self) -> Option<&str> {
+        let dashboard = self.settings.as_ref().and_then(|s| s.dashboard.as_ref()).and_then(|d| d.display_name.as_deref());
+        self.business_profile.as_ref().and_then(|p| p.name.as_deref()).or(dashboard).filter(|name| !name.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountListResponse {
+    #[serde(default)]
+    pub data: Vec<ConnectedAccount>,
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+#[derive(Clone)]
+pub struct StripeClient {
+    api_key: String,
+    client: reqwest::Client,
+    /// Connected account to act on behalf of (sent as the `Stripe-Account` header)
+    account: Option<String>,
+    /// Live or test, from the key's prefix
+    mode: Option<KeyMode>,
+    /// Requests made, shared with the clients cloned from this one
+    usage: Arc<ApiUsage>,
+    /// Fail on responses that don't match the expected schema (`--strict-parse`)
+    strict: bool,
+}
+
+impl StripeClient {
+    pub fn new(api_key: String) -> Self {
+        StripeClient {
+            mode: KeyMode::detect(&api_key),
+            api_key,
+            client: reqwest::Client::new(),
+            account: None,
+            usage: Arc::default(),
+            strict: false,
+        }
+    }
+
+    /// Count requests in `usage`, which may cap them, instead of the
+    /// client's own tracker
+    pub fn with_usage(self, usage: Arc<ApiUsage>) -> Self {
+        StripeClient { usage, ..self }
+    }
+
+    /// Check invoices, customers, charges and balance transactions against
+    /// their schemas, failing with the fields missing or unexpected in each
+    /// object instead of defaulting them
+    pub fn with_strict_parse(self, strict: bool) -> Self {
+        StripeClient { strict, ..self }
+    }
+
+    /// The requests made so far by this client and those cloned from it
+    pub fn usage(&self) -> &ApiUsage {
+        &self.usage
+    }
+
+    /// A client that makes every request on behalf of a connected account
+    pub fn for_account(&self, account_id: &str) -> Self {
+        StripeClient {
+            account: Some(account_id.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// The connected account this client acts on behalf of, if any
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// Whether the client's key is a live or test key, if recognizable
+    pub fn key_mode(&self) -> Option<KeyMode> {
+        self.mode
+    }
+
+    /// Fail unless the key is a `expected` mode key, so a test key can't
+    /// stand in for a live one (or the other way around) unnoticed
+    pub fn require_mode(&self, expected: KeyMode) -> anyhow::Result<()> {
+        match self.mode {
+            Some(mode) if mode == expected => Ok(()),
+            Some(mode) => anyhow::bail!("Expected a {} key, but the API key is a {} key", expected, mode),
+            None => anyhow::bail!(
+                "Expected a {} key, but the API key isn't recognizable as live or test (sk_{}_ or rk_{}_)",
+                expected,
+                expected,
+                expected
+            ),
+        }
+    }
+
+    /// Start an authenticated request, adding the `Stripe-Account` header when
+    /// acting on behalf of a connected account
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let builder = self
+            .client
+            .request(method, url)
+            .basic_auth(&self.api_key, Some(""));
+
+        match &self.account {
+            Some(account) => builder.header("Stripe-Account", account),
+            None => builder,
+        }
+    }
+
+    /// Send a request, counting it in the client's usage and logging the
+    /// response status and latency at debug level. Reads that are rate
+    /// limited, fail on Stripe's side or get no answer are retried up to
+    /// `MAX_RETRIES` times; writes aren't, since a repeat could apply twice.
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let mut request = request.build().context("Failed to build Stripe API request")?;
+        let endpoint = usage::endpoint(request.method(), request.url());
+        let mut attempt = 0;
+        loop {
+            let retry = (attempt < MAX_RETRIES && request.method() == reqwest::Method::GET)
+                .then(|| request.try_clone())
+                .flatten();
+            self.usage.start(&endpoint)?;
+            if attempt > 0 {
+                self.usage.retry(&endpoint);
+            }
+            let started = std::time::Instant::now();
+            let response = self.client.execute(request).await;
+            let elapsed = started.elapsed();
+            self.usage.finish(&endpoint, response.as_ref().ok().map(|response| response.status()), elapsed);
+
+            if let Some(retry) = retry
+                && should_retry(&response)
+            {
+                let delay = retry_delay(response.as_ref().ok(), attempt);
+                let reason = match &response {
+                    Ok(response) => response.status().to_string(),
+                    Err(_) => "no response".to_string(),
+                };
+                tracing::warn!("{} failed ({}); retrying in {:.1}s", endpoint, reason, delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+                request = retry;
+                attempt += 1;
+                continue;
+            }
+
+            let response = response.context("Failed to reach Stripe API")?;
+            tracing::debug!(
+                endpoint = %endpoint,
+                status = %response.status(),
+                request_id = Self::request_id(&response).as_deref(),
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Stripe API response"
+            );
+            return Ok(response);
+        }
+    }
+
+    /// Stripe's ID for the request a response answers (`req_...`), which Stripe
+    /// support asks for when looking into a failed or surprising request
+    pub fn request_id(response: &reqwest::Response) -> Option<String> {
+        response.headers().get("request-id").and_then(|value| value.to_str().ok()).map(str::to_string)
+    }
+
+    /// `message`, naming the request it's about when there is one
+    fn with_request_id(message: String, request_id: Option<&str>) -> String {
+        match request_id {
+            Some(id) => format!("{} (request {})", message, id),
+            None => message,
+        }
+    }
+
+    /// The error for a response with an error status: `action`, the status, the
+    /// body Stripe sent and the request ID
+    async fn api_error(response: reqwest::Response, action: impl std::fmt::Display) -> anyhow::Error {
+        let status = response.status();
+        let request_id = Self::request_id(&response);
+        let body = response.text().await.unwrap_or_default();
+        anyhow::anyhow!(Self::with_request_id(format!("{}: {} {}", action, status, body), request_id.as_deref()))
+    }
+
+    /// Parse a response holding `schema`'s objects, one or a page of them,
+    /// checking them against it first under strict parsing
+    async fn parse_checked<T: serde::de::DeserializeOwned>(
+        &self,
+        response: reqwest::Response,
+        schema: &Schema,
+    ) -> anyhow::Result<T> {
+        if !self.strict {
+            return Self::parse_json(response, schema.name).await;
+        }
+        let request_id = Self::request_id(&response);
+        let value: serde_json::Value = Self::parse_json(response, schema.name).await?;
+        let problems = schema.check_response(&value);
+        if !problems.is_empty() {
+            anyhow::bail!(Self::with_request_id(
+                format!("Stripe's {} response doesn't match the expected schema (--strict-parse): {}", schema.name, problems.join("; ")),
+                request_id.as_deref()
+            ));
+        }
+        serde_json::from_value(value)
+            .with_context(|| Self::with_request_id(format!("Failed to parse {} response", schema.name), request_id.as_deref()))
+    }
+
+    /// Parse a response body as JSON, naming the request when it doesn't parse
+    async fn parse_json<T: serde::de::DeserializeOwned>(response: reqwest::Response, what: &str) -> anyhow::Result<T> {
+        let request_id = Self::request_id(&response);
+        response
+            .json()
+            .await
+            .with_context(|| Self::with_request_id(format!("Failed to parse {} response", what), request_id.as_deref()))
+    }
+
+    /// Fetch a customer by ID
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_customer(&self, customer_id: &str) -> anyhow::Result<Customer> {
+        let url = format!("https://api.stripe.com/v1/customers/{}", customer_id);
+
+        let response = self
+            .send(self.request(reqwest::Method::GET, &url).query(&[("expand[]", "tax_ids")]))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch customer {}", customer_id)).await);
+        }
+
+        self.parse_checked(response, &schema::CUSTOMER).await
+    }
+
+    /// Fetch charge by ID to get balance_transaction reference
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_charge(&self, charge_id: &str) -> anyhow::Result<Charge> {
+        let url = format!("https://api.stripe.com/v1/charges/{}", charge_id);
+
+        let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch charge {}", charge_id)).await);
+        }
+
+        let request_id = Self::request_id(&response);
+        let charge: Charge = self.parse_checked(response, &schema::CHARGE).await?;
+        Ok(Charge { request_id, ..charge })
+    }
+
+    /// Fetch balance transaction by ID to get fee information
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_balance_transaction(&self, balance_tx_id: &str) -> anyhow::Result<BalanceTransaction> {
+        let url = format!("https://api.stripe.com/v1/balance_transactions/{}", balance_tx_id);
+
+        let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch balance transaction {}", balance_tx_id)).await);
+        }
+
+        let request_id = Self::request_id(&response);
+        let balance_transaction: BalanceTransaction = self.parse_checked(response, &schema::BALANCE_TRANSACTION).await?;
+        Ok(BalanceTransaction { request_id, ..balance_transaction })
+    }
+
+    /// Fetch a product by ID
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_product(&self, product_id: &str) -> anyhow::Result<Product> {
+        let url = format!("https://api.stripe.com/v1/products/{}", product_id);
+
+        let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch product {}", product_id)).await);
+        }
+
+        Self::parse_json(response, "product").await
+    }
+
+    /// Fetch the payments made toward an invoice
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_invoice_payments(&self, invoice_id: &str) -> anyhow::Result<Vec<InvoicePayment>> {
+        let mut payments: Vec<InvoicePayment> = Vec::new();
+
+        loop {
+            let mut url = format!("https://api.stripe.com/v1/invoice_payments?invoice={}&limit=100", invoice_id);
+            if let Some(last) = payments.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, format!("Failed to fetch payments for invoice {}", invoice_id)).await);
+            }
+
+            let page: ListResponse<InvoicePayment> = Self::parse_json(response, "invoice payments").await?;
+            payments.extend(page.data);
+
+            if !page.has_more {
+                return Ok(payments);
+            }
+        }
+    }
+
+    /// Fetch an invoice by ID
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_invoice(&self, invoice_id: &str) -> anyhow::Result<StripeInvoice> {
+        let url = format!("https://api.stripe.com/v1/invoices/{}", invoice_id);
+
+        let response = self
+            .send(
+                self.request(reqwest::Method::GET, &url)
+                    .query(&[("expand[]", "total_tax_amounts.tax_rate"), ("expand[]", "subscription")]),
+            )
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch invoice {}", invoice_id)).await);
+        }
+
+        let request_id = Self::request_id(&response);
+        let invoice: StripeInvoice = self.parse_checked(response, &schema::INVOICE).await?;
+        Ok(StripeInvoice { request_id, ..invoice })
+    }
+
+    /// Fetch disputes opened in a date range (Unix timestamps, inclusive)
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_disputes(&self, start: i64, end: i64) -> anyhow::Result<Vec<Dispute>> {
+        let mut disputes: Vec<Dispute> = Vec::new();
+
+        loop {
+            let mut url = format!(
+                "https://api.stripe.com/v1/disputes?limit=100&created[gte]={}&created[lte]={}",
+                start, end
+            );
+            if let Some(last) = disputes.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch disputes").await);
+            }
+
+            let page: ListResponse<Dispute> = Self::parse_json(response, "disputes").await?;
+            disputes.extend(page.data);
+
+            if !page.has_more {
+                return Ok(disputes);
+            }
+        }
+    }
+
+    /// Fetch payouts arriving in a date range (Unix timestamps, inclusive)
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_payouts(&self, start: i64, end: i64) -> anyhow::Result<Vec<Payout>> {
+        let mut payouts: Vec<Payout> = Vec::new();
+
+        loop {
+            let mut url = format!(
+                "https://api.stripe.com/v1/payouts?limit=100&arrival_date[gte]={}&arrival_date[lte]={}",
+                start, end
+            );
+            if let Some(last) = payouts.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch payouts").await);
+            }
+
+            let page: ListResponse<Payout> = Self::parse_json(response, "payouts").await?;
+            payouts.extend(page.data);
+
+            if !page.has_more {
+                return Ok(payouts);
+            }
+        }
+    }
+
+    /// Fetch completed Checkout Sessions created in a date range (Unix
+    /// timestamps, inclusive)
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_checkout_sessions(&self, start: i64, end: i64) -> anyhow::Result<Vec<CheckoutSession>> {
+        let mut sessions: Vec<CheckoutSession> = Vec::new();
+
+        loop {
+            let mut url = format!(
+                "https://api.stripe.com/v1/checkout/sessions?limit=100&status=complete&created[gte]={}&created[lte]={}",
+                start, end
+            );
+            if let Some(last) = sessions.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch checkout sessions").await);
+            }
+
+            let page: ListResponse<CheckoutSession> =
+                Self::parse_json(response, "checkout sessions").await?;
+            sessions.extend(page.data);
+
+            if !page.has_more {
+                return Ok(sessions);
+            }
+        }
+    }
+
+    /// Fetch PaymentIntents created in a date range (Unix timestamps, inclusive)
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_payment_intents(&self, start: i64, end: i64) -> anyhow::Result<Vec<PaymentIntent>> {
+        let mut intents: Vec<PaymentIntent> = Vec::new();
+
+        loop {
+            let mut url = format!(
+                "https://api.stripe.com/v1/payment_intents?limit=100&created[gte]={}&created[lte]={}",
+                start, end
+            );
+            if let Some(last) = intents.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch payment intents").await);
+            }
+
+            let page: ListResponse<PaymentIntent> =
+                Self::parse_json(response, "payment intents").await?;
+            intents.extend(page.data);
+
+            if !page.has_more {
+                return Ok(intents);
+            }
+        }
+    }
+
+    /// Fetch events of the given types created in a date range (Unix
+    /// timestamps, inclusive), oldest first. Stripe keeps events for 30 days.
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_events(&self, types: &[&str], start: i64, end: i64) -> anyhow::Result<Vec<StripeEvent>> {
+        let mut events: Vec<StripeEvent> = Vec::new();
+        let types: String = types.iter().map(|t| format!("&types[]={}", t)).collect();
+
+        loop {
+            let mut url = format!(
+                "https://api.stripe.com/v1/events?limit=100&created[gte]={}&created[lte]={}{}",
+                start, end, types
+            );
+            if let Some(last) = events.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch events").await);
+            }
+
+            let page: ListResponse<StripeEvent> = Self::parse_json(response, "events").await?;
+            events.extend(page.data);
+
+            if !page.has_more {
+                // Listed newest first; replay in the order they happened
+                events.reverse();
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Start a report run of `report_type` over `[interval_start, interval_end)`
+    /// (Unix timestamps), with the given columns or the report's defaults
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn create_report_run(
+        &self,
+        report_type: &str,
+        interval_start: i64,
+        interval_end: i64,
+        columns: &[&str],
+    ) -> anyhow::Result<ReportRun> {
+        let url = "https://api.stripe.com/v1/reporting/report_runs";
+        let mut form = vec![
+            ("report_type".to_string(), report_type.to_string()),
+            ("parameters[interval_start]".to_string(), interval_start.to_string()),
+            ("parameters[interval_end]".to_string(), interval_end.to_string()),
+        ];
+        form.extend(columns.iter().map(|column| ("parameters[columns][]".to_string(), column.to_string())));
+
+        let response = self.send(self.request(reqwest::Method::POST, url).form(&form)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to create {} report run", report_type)).await);
+        }
+
+        Self::parse_json(response, "report run").await
+    }
+
+    /// Fetch a report run by ID, to poll its status
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_report_run(&self, report_run_id: &str) -> anyhow::Result<ReportRun> {
+        let url = format!("https://api.stripe.com/v1/reporting/report_runs/{}", report_run_id);
+
+        let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch report run {}", report_run_id)).await);
+        }
+
+        Self::parse_json(response, "report run").await
+    }
+
+    /// Download a file's contents from its Files API URL
+    #[tracing::instrument(skip(self), fields(account = self.account.as_deref()), err)]
+    pub async fn download_file(&self, url: &str) -> anyhow::Result<String> {
+        let response = self.send(self.request(reqwest::Method::GET, url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to download {}", url)).await);
+        }
+
+        response.text().await.context("Failed to read file contents")
+    }
+
+    /// List the account's Stripe Tax registrations, whatever their status
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_tax_registrations(&self) -> anyhow::Result<Vec<TaxRegistration>> {
+        let mut registrations: Vec<TaxRegistration> = Vec::new();
+
+        loop {
+            let mut url = "https://api.stripe.com/v1/tax/registrations?limit=100&status=all".to_string();
+            if let Some(last) = registrations.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to fetch tax registrations").await);
+            }
+
+            let page: ListResponse<TaxRegistration> =
+                Self::parse_json(response, "tax registrations").await?;
+            registrations.extend(page.data);
+
+            if !page.has_more {
+                return Ok(registrations);
+            }
+        }
+    }
+
+    /// Fetch the balance transactions an automatic payout settled
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_payout_balance_transactions(&self, payout_id: &str) -> anyhow::Result<Vec<BalanceTransaction>> {
+        let mut transactions: Vec<BalanceTransaction> = Vec::new();
+
+        loop {
+            let mut url = format!("https://api.stripe.com/v1/balance_transactions?payout={}&limit=100", payout_id);
+            if let Some(last) = transactions.last() {
+                url.push_str(&format!("&starting_after={}", last.id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, format!("Failed to fetch balance transactions for payout {}", payout_id)).await);
+            }
+
+            let page: ListResponse<BalanceTransaction> = self.parse_checked(response, &schema::BALANCE_TRANSACTION).await?;
+            transactions.extend(page.data);
+
+            if !page.has_more {
+                return Ok(transactions);
+            }
+        }
+    }
+
+    /// Fetch a test clock by ID (test mode keys only)
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_test_clock(&self, test_clock_id: &str) -> anyhow::Result<TestClock> {
+        let url = format!("https://api.stripe.com/v1/test_helpers/test_clocks/{}", test_clock_id);
+
+        let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to fetch test clock {}", test_clock_id)).await);
+        }
+
+        Self::parse_json(response, "test clock").await
+    }
+
+    /// The account the key belongs to, or the connected account when acting
+    /// on behalf of one
+    pub async fn fetch_account(&self) -> anyhow::Result<Account> {
+        let response = self.send(self.request(reqwest::Method::GET, "https://api.stripe.com/v1/account")).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, "Failed to fetch account").await);
+        }
+
+        Self::parse_json(response, "account").await
+    }
+
+    /// Set a single metadata key on an invoice (requires a key with write access)
+    #[tracing::instrument(skip(self), err)]
+    pub async fn update_invoice_metadata(&self, invoice_id: &str, key: &str, value: &str) -> anyhow::Result<()> {
+        let url = format!("https://api.stripe.com/v1/invoices/{}", invoice_id);
+
+        let response = self
+            .send(self.request(reqwest::Method::POST, &url).form(&[(format!("metadata[{}]", key), value)]))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to update invoice {}", invoice_id)).await);
+        }
+
+        Ok(())
+    }
+
+    /// Update a customer's address (requires a key with write access).
+    /// Fields left unset keep their current value.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn update_customer_address(&self, customer_id: &str, address: &Address) -> anyhow::Result<()> {
+        let url = format!("https://api.stripe.com/v1/customers/{}", customer_id);
+        let form = address_form(address);
+
+        let response = self.send(self.request(reqwest::Method::POST, &url).form(&form)).await?;
+
+        if !response.status().is_success() {
+            return Err(Self::api_error(response, format!("Failed to update customer {}", customer_id)).await);
+        }
+
+        Ok(())
+    }
+
+    /// List all accounts connected to this platform (Stripe Connect)
+    #[tracing::instrument(skip(self), err)]
+    pub async fn fetch_connected_accounts(&self) -> anyhow::Result<Vec<ConnectedAccount>> {
+        let mut accounts: Vec<ConnectedAccount> = Vec::new();
+        let mut starting_after: Option<String> = None;
+
+        loop {
+            let mut url = "https://api.stripe.com/v1/accounts?limit=100".to_string();
+            if let Some(starting_after_id) = &starting_after {
+                url.push_str(&format!("&starting_after={}", starting_after_id));
+            }
+
+            let response = self.send(self.request(reqwest::Method::GET, &url)).await?;
+
+            if !response.status().is_success() {
+                return Err(Self::api_error(response, "Failed to list connected accounts").await);
+            }
+
+            let page: AccountListResponse = Self::parse_json(response, "connected accounts").await?;
+
+            accounts.extend(page.data);
+
+            if !page.has_more {
+                break;
+            }
+
+            // Paginate
+            if let Some(last_account) = accounts.last() {
+                starting_after = Some(last_account.id.clone());
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    /// Fetch paid invoices for a date range (Unix timestamps)
+    pub async fn fetch_paid_invoices(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> anyhow::Result<Vec<StripeInvoice>> {
+        self.fetch_paid_invoices_paged(start, end, |_| {}).await
+    }
+
+    /// Like `fetch_paid_invoices`, calling `on_page` with the running invoice
+    /// count after each page is retrieved
+    pub async fn fetch_paid_invoices_paged(
+        &self,
+        start: i64,
+        end: i64,
+        on_page: impl Fn(usize),
+    ) -> anyhow::Result<Vec<StripeInvoice>> {
+        self.fetch_invoices_paged("paid", start, end, on_page).await
+    }
+
+    /// Fetch invoices with a status ("paid", "open", ...) created in a date
+    /// range, calling `on_page` with the running invoice count after each page
+    #[tracing::instrument(skip(self, on_page), fields(account = self.account.as_deref()), err)]
+    pub async fn fetch_invoices_paged(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
+        on_page: impl Fn(usize),
+    ) -> anyhow::Result<Vec<StripeInvoice>> {
+        let mut all_invoices = Vec::new();
+        let mut starting_after: Option<String> = None;
+
+        loop {
+            let url = "https://api.stripe.com/v1/invoices";
+
+            // Build URL - we'll fetch charge details separately; tax rates are
+            // expanded to split tax by jurisdiction, and subscriptions to name plans
             let mut full_url = format!(
-                "{}?status=paid&limit=100&created[gte]={}&created[lte]={}",
-                url, start, end
+                "{}?status={}&limit=100&created[gte]={}&created[lte]={}&expand[]=data.total_tax_amounts.tax_rate&expand[]=data.subscription",
+                url, status, start, end
             );
 
             if let Some(starting_after_id) = &starting_after {
                 full_url.push_str(&format!("&starting_after={}", starting_after_id));
             }
 
-            let response = self
-                .client
-                .get(&full_url)
-                .basic_auth(&self.api_key, Some(""))
-                .send()
-                .await
-                .context("Failed to reach Stripe API")?;
+            let response = self.send(self.request(reqwest::Method::GET, &full_url)).await?;
 
             if !response.status().is_success() {
-                let status = response.status();
-                let body = response.text().await.unwrap_or_default();
-                anyhow::bail!(
-                    "Stripe API error {}: {}",
-                    status,
-                    body
-                );
+                return Err(Self::api_error(response, "Stripe API error").await);
             }
 
-            let invoice_list: InvoiceListResponse = response
-                .json()
-                .await
-                .context("Failed to parse Stripe response")?;
+            let request_id = Self::request_id(&response);
+            let mut invoice_list: InvoiceListResponse = self.parse_checked(response, &schema::INVOICE).await?;
+            for invoice in &mut invoice_list.data {
+                invoice.request_id = request_id.clone();
+            }
 
             all_invoices.extend(invoice_list.data);
+            tracing::debug!(retrieved = all_invoices.len(), has_more = invoice_list.has_more, "Fetched invoice page");
+            on_page(all_invoices.len());
 
             if !invoice_list.has_more {
                 break;
@@ -262,13 +1530,193 @@ impl StripeClient {
     }
 }
 
+/// The form fields that set an address's given parts. Unset parts are left
+/// out, since Stripe clears a field sent as an empty string.
+fn address_form(address: &Address) -> Vec<(&'static str, &str)> {
+    [
+        ("address[line1]", &address.line1),
+        ("address[line2]", &address.line2),
+        ("address[city]", &address.city),
+        ("address[state]", &address.state),
+        ("address[postal_code]", &address.postal_code),
+        ("address[country]", &address.country),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+    .collect()
+}
+
+/// How many times a failed read is sent again
+const MAX_RETRIES: u32 = 2;
+
+/// The longest `Retry-After` the client waits for before retrying
+const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Whether a request is worth sending again: Stripe says so with
+/// `Stripe-Should-Retry`, or it was rate limited, failed on Stripe's side or
+/// got no answer
+fn should_retry(response: &reqwest::Result<reqwest::Response>) -> bool {
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return e.is_connect() || e.is_timeout(),
+    };
+    match response.headers().get("stripe-should-retry").and_then(|value| value.to_str().ok()) {
+        Some("true") => true,
+        Some("false") => false,
+        _ => response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS || response.status().is_server_error(),
+    }
+}
+
+/// How long to wait before retry `attempt` (from 0): the `Retry-After` Stripe
+/// asked for, up to `MAX_RETRY_DELAY`, else half a second doubling each time
+fn retry_delay(response: Option<&reqwest::Response>, attempt: u32) -> std::time::Duration {
+    let retry_after = response
+        .and_then(|response| response.headers().get(reqwest::header::RETRY_AFTER))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    match retry_after {
+        Some(secs) => std::time::Duration::from_secs(secs).min(MAX_RETRY_DELAY),
+        None => std::time::Duration::from_millis(500 << attempt),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_stripe_client_creation() {
-        let client = StripeClient::new("sk_test_123".to_string());
+        let _client = StripeClient::new("sk_test_123".to_string());
         // Just verify it creates without panicking
     }
+
+    #[test]
+    fn test_for_account_keeps_key_and_sets_account() {
+        let platform = StripeClient::new("sk_test_123".to_string());
+        let connected = platform.for_account("acct_123");
+
+        assert_eq!(platform.account(), None);
+        assert_eq!(connected.account(), Some("acct_123"));
+        assert_eq!(connected.api_key, "sk_test_123");
+    }
+
+    #[test]
+    fn test_key_mode_detection() {
+        assert_eq!(KeyMode::detect("sk_live_abc"), Some(KeyMode::Live));
+        assert_eq!(KeyMode::detect("rk_live_abc"), Some(KeyMode::Live));
+        assert_eq!(KeyMode::detect("sk_test_abc"), Some(KeyMode::Test));
+        assert_eq!(KeyMode::detect("pk_live_abc"), None);
+
+        let test = StripeClient::new("sk_test_123".to_string());
+        assert!(test.require_mode(KeyMode::Test).is_ok());
+        assert!(test.require_mode(KeyMode::Live).is_err());
+        assert!(StripeClient::new("abc".to_string()).require_mode(KeyMode::Live).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_errors_name_the_request() {
+        let response = |status: u16, body: &str| {
+            reqwest::Response::from(
+                axum::http::Response::builder()
+                    .status(status)
+                    .header("request-id", "req_Nv0FGQ9")
+                    .body(body.to_string())
+                    .unwrap(),
+            )
+        };
+
+        let error = StripeClient::api_error(response(404, r#"{"error":{"code":"resource_missing"}}"#), "Failed to fetch charge ch_1").await;
+        assert_eq!(
+            error.to_string(),
+            r#"Failed to fetch charge ch_1: 404 Not Found {"error":{"code":"resource_missing"}} (request req_Nv0FGQ9)"#
+        );
+        let error = StripeClient::parse_json::<Charge>(response(200, "not json"), "charge").await.unwrap_err();
+        assert_eq!(error.to_string(), "Failed to parse charge response (request req_Nv0FGQ9)");
+    }
+
+    #[tokio::test]
+    async fn test_strict_parse_is_off_by_default() {
+        let response = || reqwest::Response::from(axum::http::Response::builder().body(r#"{"id": "ch_1"}"#).unwrap());
+
+        let client = StripeClient::new("sk_test_123".to_string());
+        assert!(!client.strict);
+        let charge: Charge = client.parse_checked(response(), &schema::CHARGE).await.unwrap();
+        assert_eq!(charge.id, "ch_1");
+
+        let strict = client.with_strict_parse(true);
+        assert!(strict.for_account("acct_1").strict);
+        let error = strict.parse_checked::<Charge>(response(), &schema::CHARGE).await.unwrap_err();
+        assert!(error.to_string().contains("charge ch_1: missing amount"));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let response = |status: u16, headers: &[(&str, &str)]| {
+            let mut builder = axum::http::Response::builder().status(status);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            reqwest::Response::from(builder.body("").unwrap())
+        };
+
+        assert!(should_retry(&Ok(response(429, &[]))));
+        assert!(should_retry(&Ok(response(503, &[]))));
+        assert!(!should_retry(&Ok(response(404, &[]))));
+        assert!(!should_retry(&Ok(response(500, &[("stripe-should-retry", "false")]))));
+        assert!(should_retry(&Ok(response(409, &[("stripe-should-retry", "true")]))));
+
+        let second = std::time::Duration::from_secs(1);
+        assert_eq!(retry_delay(Some(&response(429, &[("retry-after", "2")])), 0), 2 * second);
+        assert_eq!(retry_delay(Some(&response(429, &[("retry-after", "600")])), 0), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(None, 0), second / 2);
+        assert_eq!(retry_delay(Some(&response(503, &[])), 1), second);
+    }
+
+    #[test]
+    fn test_address_form_leaves_out_unset_fields() {
+        let address = Address { state: Some("TX".to_string()), country: Some("US".to_string()), ..Default::default() };
+        assert_eq!(address_form(&address), vec![("address[state]", "TX"), ("address[country]", "US")]);
+    }
+
+    #[test]
+    fn test_account_business_name() {
+        let account: Account = serde_json::from_value(serde_json::json!({
+            "id": "acct_1", "default_currency": "usd",
+            "business_profile": {"name": null},
+            "settings": {"dashboard": {"display_name": "Acme"}}
+        }))
+        .unwrap();
+        assert_eq!(account.business_name(), Some("Acme"));
+
+        let account: Account = serde_json::from_value(serde_json::json!({
+            "id": "acct_1", "business_profile": {"name": "Acme Inc"}
+        }))
+        .unwrap();
+        assert_eq!(account.business_name(), Some("Acme Inc"));
+    }
+
+    #[test]
+    fn test_plan_name_from_subscription_or_lines() {
+        let invoice: StripeInvoice = serde_json::from_value(serde_json::json!({
+            "id": "in_1",
+            "subscription": {
+                "id": "sub_1",
+                "schedule": "sub_sched_1",
+                "items": {"data": [
+                    {"price": {"id": "price_pro", "nickname": "Pro Annual"}},
+                    {"price": {"id": "price_seats"}}
+                ]}
+            },
+            "lines": {"data": [{"type": "subscription", "price": {"id": "price_old", "nickname": "Legacy"}}]}
+        }))
+        .unwrap();
+        assert_eq!(invoice.subscription_id(), Some("sub_1"));
+        assert_eq!(invoice.subscription().unwrap().schedule.as_deref(), Some("sub_sched_1"));
+        assert_eq!(invoice.plan_name().as_deref(), Some("Pro Annual + price_seats"));
+
+        // Unexpanded, the plan comes from the subscription lines' prices
+        let invoice = StripeInvoice { subscription: Some(serde_json::json!("sub_1")), ..invoice };
+        assert_eq!(invoice.subscription_id(), Some("sub_1"));
+        assert_eq!(invoice.plan_name().as_deref(), Some("Legacy"));
+    }
 }
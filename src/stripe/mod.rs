@@ -1,4 +1,7 @@
 pub mod client;
 pub mod models;
+pub mod schema;
+pub mod usage;
 
-pub use client::StripeClient;
+pub use client::{KeyMode, StripeClient};
+pub use usage::ApiUsage;
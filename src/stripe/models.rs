@@ -1,18 +1,254 @@
-use serde::{Deserialize, Serialize};
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct InvoiceRecord {
-    pub date: String,              // MM/DD/YYYY format
+    #[serde(with = "record_date")]
+    pub date: NaiveDate,            // Payment (or finalization, chargeback) date, UTC
+    #[serde(default)]
+    pub timestamp: i64,             // Unix timestamp the date was taken from
     pub customer: String,           // Customer name
     pub users: u32,                 // Total subscription quantity
-    pub state: String,              // Two-letter state code
-    pub licenses: i64,              // Amount in cents
+    pub state: String,              // Two-letter state code (province/region as entered outside the US)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,    // ISO country code of the address the state came from
+    pub licenses: i64,              // Gross sales: subscription amount before tax (cents)
+    #[serde(default)]
+    pub taxable_sales: i64,         // Part of licenses that was taxed (cents)
+    #[serde(default)]
+    pub non_taxable_sales: i64,     // Part of licenses not taxed: exempt customers, untaxed lines (cents)
     pub tax: i64,                   // Amount in cents
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub local_tax: i64,             // Part of tax collected for counties, cities and districts (cents)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tax_jurisdictions: Vec<JurisdictionTax>, // Tax by jurisdiction as Stripe has it
     pub total: i64,                 // licenses + tax (cents)
     pub fees: i64,                  // Amount in cents
+    #[serde(default)]
+    pub fee_breakdown: FeeBreakdown, // What fees are made of (cents)
+    pub invoice_id: String,         // Stripe invoice ID (in_...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice_number: Option<String>, // Invoice number printed on the invoice (e.g. ACME-0042)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<String>, // Subscription the invoice bills (sub_...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_schedule: Option<String>, // Schedule managing that subscription (sub_sched_...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,       // Plan (price nickname) billed, e.g. "Pro Annual"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,    // Connected account ID (acct_...), when aggregating accounts
+    #[serde(default, skip_serializing_if = "crate::report::RecordSource::is_invoice")]
+    pub source: crate::report::RecordSource, // What the record was built from, when not an invoice
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub state_inferred: bool,       // State was inferred from the ZIP code, not taken from an address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_source: Option<crate::report::StateSource>, // Address (or ZIP code) the state came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<crate::stripe::client::Address>, // That address, for exports that need the full destination
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rate_mismatch: bool,        // Tax is outside the expected rate at the destination (`--check-rates`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refunded: bool,             // Part or all of the invoice was refunded; amounts are net of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,     // Parent entity the customer files under, if mapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,   // ISO currency code of the amounts when not USD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<FxConversion>,   // Conversion applied to reach USD, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tax_ids: Vec<String>,       // Customer tax IDs ("type value"), e.g. for exemption records
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<InvoiceLine>,    // Subscription lines, kept for `--detail line-items`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,    // Product ID, on line-item rows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>, // Line description, on line-item rows
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<Adjustment>, // Figures that differ from the raw Stripe amounts, and why
+}
+
+fn is_zero(cents: &i64) -> bool {
+    *cents == 0
+}
+
+/// Record dates serialize as ISO 8601 (`2025-10-15`); the `MM/DD/YYYY`
+/// strings in archives and checkpoints written before dates were typed still load
+mod record_date {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        date.format("%Y-%m-%d").to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(&text, "%m/%d/%Y"))
+            .map_err(|_| serde::de::Error::custom(format!("invalid date '{}'", text)))
+    }
+}
+
+/// The money columns of a report row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountColumn {
+    Licenses,
+    TaxableSales,
+    NonTaxableSales,
+    Tax,
+    StateTax,
+    LocalTax,
+    Total,
+    Fees,
+    CardFees,
+    AchFees,
+    StripeTaxFees,
+    OtherFees,
+}
+
+impl AmountColumn {
+    pub fn header(self) -> &'static str {
+        match self {
+            AmountColumn::Licenses => "Licenses",
+            AmountColumn::TaxableSales => "Taxable Sales",
+            AmountColumn::NonTaxableSales => "Non-Taxable Sales",
+            AmountColumn::Tax => "Tax",
+            AmountColumn::StateTax => "State Tax",
+            AmountColumn::LocalTax => "Local Tax",
+            AmountColumn::Total => "Total",
+            AmountColumn::Fees => "Fees",
+            AmountColumn::CardFees => "Card Fees",
+            AmountColumn::AchFees => "ACH Fees",
+            AmountColumn::StripeTaxFees => "Stripe Tax Fees",
+            AmountColumn::OtherFees => "Other Fees",
+        }
+    }
+
+    /// Whether the column is per invoice fees rather than sales or tax
+    pub fn is_fee(self) -> bool {
+        matches!(
+            self,
+            AmountColumn::Fees
+                | AmountColumn::CardFees
+                | AmountColumn::AchFees
+                | AmountColumn::StripeTaxFees
+                | AmountColumn::OtherFees
+        )
+    }
+}
+
+/// An invoice's Stripe fees split the way they're booked: processing fees by
+/// payment method, Stripe Tax fees, and everything else. The parts add up to
+/// the record's `fees`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeBreakdown {
+    pub card: i64,                  // Processing fees on card payments
+    pub ach: i64,                   // Processing fees on ACH bank payments
+    pub stripe_tax: i64,            // Stripe Tax fees
+    pub other: i64,                 // Other payment methods, application fees, tax on fees
+}
+
+impl FeeBreakdown {
+    pub fn add(&mut self, other: &FeeBreakdown) {
+        self.card += other.card;
+        self.ach += other.ach;
+        self.stripe_tax += other.stripe_tax;
+        self.other += other.other;
+    }
+}
+
+/// Tax collected for one jurisdiction, from the invoice's `total_tax_amounts`
+/// (cents, in the invoice's currency)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JurisdictionTax {
+    pub jurisdiction: String,       // e.g. TEXAS, AUSTIN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,      // state, county, city, district, ...; unknown if the rate wasn't expanded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<f64>,    // Rate charged
+    pub amount: i64,                // Tax collected
+}
+
+impl JurisdictionTax {
+    /// Whether the tax is local (county, city or district) rather than state
+    pub fn is_local(&self) -> bool {
+        matches!(self.level.as_deref(), Some("county" | "city" | "district"))
+    }
+}
+
+/// A change to a record's figures relative to the raw Stripe amounts,
+/// reported as a footnote on the affected cells
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Adjustment {
+    pub columns: Vec<AmountColumn>, // Cells the note applies to
+    pub note: String,               // Why the figures differ from Stripe's
+}
+
+/// One subscription line of an invoice, with amounts in the record's currency
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InvoiceLine {
+    pub product: Option<String>,    // Product ID (prod_...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_name: Option<String>, // Product name, when products were fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_type: Option<String>,  // Stripe line type: subscription or invoiceitem
+    pub description: Option<String>, // Line description as shown on the invoice
+    pub quantity: u32,              // Subscription quantity
+    pub amount: i64,                // Amount before tax (cents)
+    pub taxable_sales: i64,         // Part of amount that was taxed (cents)
+    pub tax: i64,                   // Tax on this line (cents)
+}
+
+/// How a record's amounts were converted to USD, kept so converted figures
+/// can be reproduced from the original invoice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FxConversion {
+    pub currency: String,           // Original ISO currency code
+    pub rate: f64,                  // USD per unit of the original currency
 }
 
 impl InvoiceRecord {
+    /// Tax collected for the state: everything not collected for a county,
+    /// city or district
+    pub fn state_tax(&self) -> i64 {
+        self.tax - self.local_tax
+    }
+
+    /// Note that `columns` were adjusted. Adjustments with the same note are
+    /// merged, so a rolled-up row lists each reason once.
+    pub fn add_adjustment(&mut self, columns: &[AmountColumn], note: impl Into<String>) {
+        let note = note.into();
+        match self.adjustments.iter_mut().find(|a| a.note == note) {
+            Some(adjustment) => {
+                for column in columns {
+                    if !adjustment.columns.contains(column) {
+                        adjustment.columns.push(*column);
+                    }
+                }
+            }
+            None => self.adjustments.push(Adjustment { columns: columns.to_vec(), note }),
+        }
+    }
+
+    /// Report section the record belongs in: its state for US sales, else
+    /// one section per country (`NON-US GB`), so Canadian provinces and US
+    /// states with the same code never share a section. Non-USD amounts get
+    /// their own section per currency (`TX (EUR)`).
+    pub fn section(&self) -> String {
+        let region = match self.country.as_deref() {
+            Some(country) if !crate::report::states::is_us_country(Some(country)) => {
+                format!("{} {}", crate::report::NON_US_REGION, country)
+            }
+            _ => self.state.clone(),
+        };
+
+        // Amounts in different currencies can't share a subtotal
+        match &self.currency {
+            Some(currency) => format!("{} ({})", region, currency),
+            None => region,
+        }
+    }
+
     pub fn licenses_dollars(&self) -> f64 {
         self.licenses as f64 / 100.0
     }
@@ -29,3 +265,18 @@ impl InvoiceRecord {
         self.fees as f64 / 100.0
     }
 }
+
+/// An invoice left out of the report, kept so the exclusion is visible in the output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SkippedInvoice {
+    pub invoice_id: String,         // Stripe invoice ID (in_...)
+    pub customer: String,           // Customer name, else customer ID
+    pub amount: i64,                // Amount paid in cents
+    pub reason: String,             // Why the invoice was excluded
+}
+
+impl SkippedInvoice {
+    pub fn amount_dollars(&self) -> f64 {
+        self.amount as f64 / 100.0
+    }
+}
@@ -0,0 +1,227 @@
+use serde_json::Value;
+
+/// The fields strict parsing (`--strict-parse`) expects of a Stripe object.
+/// The client's types default every field, so a field Stripe stops sending
+/// would otherwise be read as zero or empty without a word.
+#[derive(Debug)]
+pub struct Schema {
+    /// What the object is called in reports, e.g. `invoice`
+    pub name: &'static str,
+    /// Fields reports depend on, which Stripe sends even when they're null
+    pub required: &'static [&'static str],
+    /// Every field the object has, for the small objects modeled in full;
+    /// any other field is reported as unexpected. `None` for objects where
+    /// only some fields are used.
+    pub fields: Option<&'static [&'static str]>,
+    /// Schemas of fields holding an object, a list of objects or a list
+    /// object (`{"object": "list", "data": [...]}`)
+    pub nested: &'static [(&'static str, &'static Schema)],
+}
+
+pub const ADDRESS: Schema = Schema {
+    name: "address",
+    required: &["city", "country", "line1", "line2", "postal_code", "state"],
+    fields: Some(&["city", "country", "line1", "line2", "postal_code", "state"]),
+    nested: &[],
+};
+
+pub const TAX_AMOUNT: Schema = Schema {
+    name: "tax amount",
+    required: &["amount", "tax_rate"],
+    fields: None,
+    nested: &[],
+};
+
+pub const LINE_ITEM: Schema = Schema {
+    name: "line item",
+    required: &["id", "type", "amount", "quantity", "tax_amounts"],
+    fields: None,
+    nested: &[("tax_amounts", &TAX_AMOUNT)],
+};
+
+pub const INVOICE: Schema = Schema {
+    name: "invoice",
+    required: &[
+        "id",
+        "customer",
+        "status",
+        "created",
+        "currency",
+        "amount_due",
+        "amount_paid",
+        "total",
+        "tax",
+        "total_tax_amounts",
+        "charge",
+        "starting_balance",
+        "ending_balance",
+        "pre_payment_credit_notes_amount",
+        "post_payment_credit_notes_amount",
+        "lines",
+    ],
+    fields: None,
+    nested: &[("customer_address", &ADDRESS), ("lines", &LINE_ITEM), ("total_tax_amounts", &TAX_AMOUNT)],
+};
+
+pub const CUSTOMER: Schema = Schema {
+    name: "customer",
+    required: &["id", "name", "address", "metadata", "tax_exempt"],
+    fields: None,
+    nested: &[("address", &ADDRESS)],
+};
+
+pub const BILLING_DETAILS: Schema = Schema {
+    name: "billing details",
+    required: &["address"],
+    fields: None,
+    nested: &[("address", &ADDRESS)],
+};
+
+pub const CHARGE: Schema = Schema {
+    name: "charge",
+    required: &["id", "amount", "amount_refunded", "balance_transaction", "billing_details", "invoice"],
+    fields: None,
+    nested: &[("billing_details", &BILLING_DETAILS)],
+};
+
+pub const FEE_DETAIL: Schema = Schema {
+    name: "fee detail",
+    required: &["amount", "currency", "description", "type"],
+    fields: Some(&["amount", "application", "currency", "description", "type"]),
+    nested: &[],
+};
+
+pub const BALANCE_TRANSACTION: Schema = Schema {
+    name: "balance transaction",
+    required: &["id", "amount", "fee", "net", "currency", "exchange_rate", "fee_details", "type", "source", "created"],
+    fields: None,
+    nested: &[("fee_details", &FEE_DETAIL)],
+};
+
+impl Schema {
+    /// How `value` differs from the schema: `missing <path>` for each
+    /// required field it lacks and `unexpected <path>` for each field a fully
+    /// modeled object shouldn't have, with paths such as `lines[2].amount`
+    pub fn check(&self, value: &Value) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.check_at("", value, &mut problems);
+        problems
+    }
+
+    /// Check a response holding one object, or a page of them, naming each
+    /// object that doesn't match, e.g. `invoice in_1: missing tax`
+    pub fn check_response(&self, value: &Value) -> Vec<String> {
+        let objects = match value.get("data").and_then(Value::as_array) {
+            Some(data) if value.get("object").and_then(Value::as_str) == Some("list") => data.iter().collect(),
+            _ => vec![value],
+        };
+        objects
+            .into_iter()
+            .filter_map(|object| {
+                let problems = self.check(object);
+                if problems.is_empty() {
+                    return None;
+                }
+                let id = object.get("id").and_then(Value::as_str).unwrap_or("(no id)");
+                Some(format!("{} {}: {}", self.name, id, problems.join(", ")))
+            })
+            .collect()
+    }
+
+    fn check_at(&self, path: &str, value: &Value, problems: &mut Vec<String>) {
+        let Some(object) = value.as_object() else {
+            problems.push(format!("{} isn't a {} object", if path.is_empty() { "response" } else { path }, self.name));
+            return;
+        };
+        let field_path = |field: &str| if path.is_empty() { field.to_string() } else { format!("{}.{}", path, field) };
+
+        for field in self.required {
+            if !object.contains_key(*field) {
+                problems.push(format!("missing {}", field_path(field)));
+            }
+        }
+        if let Some(fields) = self.fields {
+            for field in object.keys().filter(|field| !fields.contains(&field.as_str())) {
+                problems.push(format!("unexpected {}", field_path(field)));
+            }
+        }
+        for (field, schema) in self.nested {
+            let nested = match object.get(*field) {
+                Some(Value::Object(list)) if list.get("object").and_then(Value::as_str) == Some("list") => list.get("data"),
+                other => other,
+            };
+            match nested {
+                Some(Value::Array(items)) => {
+                    for (i, item) in items.iter().enumerate() {
+                        schema.check_at(&format!("{}[{}]", field_path(field), i), item, problems);
+                    }
+                }
+                Some(Value::Null) | None => {}
+                Some(value) => schema.check_at(&field_path(field), value, problems),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_schema_drift() {
+        let address = json!({"city": "Austin", "country": "US", "line1": null, "line2": null, "postal_code": "78701", "state": "TX"});
+        let charge = json!({
+            "id": "ch_1", "amount": 10825, "amount_refunded": 0, "balance_transaction": "txn_1", "invoice": null,
+            "billing_details": {"address": address, "email": null}
+        });
+        assert!(CHARGE.check(&charge).is_empty());
+
+        // Stripe dropped `invoice` and added a field to addresses
+        let mut drifted = charge.clone();
+        drifted.as_object_mut().unwrap().remove("invoice");
+        drifted["billing_details"]["address"]["line3"] = json!("Suite 5");
+        assert_eq!(CHARGE.check(&drifted), vec!["missing invoice", "unexpected billing_details.address.line3"]);
+
+        let page = json!({"object": "list", "data": [
+            {"id": "txn_1", "amount": 10825, "fee": 344, "net": 10481, "currency": "usd", "exchange_rate": null,
+             "type": "charge", "source": "ch_1", "created": 1721000000,
+             "fee_details": [{"amount": 344, "currency": "usd", "description": null, "type": "stripe_fee"}]},
+            {"id": "txn_2", "fee_details": [{"amount": 10}]}
+        ]});
+        let problems = BALANCE_TRANSACTION.check_response(&page);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("balance transaction txn_2: missing amount, missing fee,"));
+        assert!(problems[0].ends_with("missing fee_details[0].currency, missing fee_details[0].description, missing fee_details[0].type"));
+    }
+
+    #[test]
+    fn test_missing_field_in_list_page() {
+        let customer = |id: &str| json!({"id": id, "name": "Lone Star LLC", "address": null, "metadata": {}, "tax_exempt": "none"});
+        let mut page = json!({"object": "list", "has_more": false, "data": [customer("cus_1"), customer("cus_2"), customer("cus_3")]});
+        assert!(CUSTOMER.check_response(&page).is_empty());
+
+        page["data"][1].as_object_mut().unwrap().remove("tax_exempt");
+        assert_eq!(CUSTOMER.check_response(&page), vec!["customer cus_2: missing tax_exempt"]);
+    }
+
+    #[test]
+    fn test_missing_field_in_nested_object() {
+        let customer = json!({
+            "id": "cus_1", "name": "Lone Star LLC", "metadata": {}, "tax_exempt": "none",
+            "address": {"city": "Austin", "country": "US", "line1": "1 Congress Ave", "line2": null, "postal_code": "78701"}
+        });
+        assert_eq!(CUSTOMER.check(&customer), vec!["missing address.state"]);
+        assert_eq!(CUSTOMER.check_response(&customer), vec!["customer cus_1: missing address.state"]);
+
+        // Inside a list object, nested paths carry the item's index
+        let invoice = json!({"lines": {"object": "list", "data": [
+            {"id": "il_1", "type": "subscription", "amount": 10000, "quantity": 1, "tax_amounts": []},
+            {"id": "il_2", "type": "invoiceitem", "quantity": 1, "tax_amounts": [{"amount": 825}]}
+        ]}});
+        let problems = INVOICE.check(&invoice);
+        assert!(problems.contains(&"missing lines[1].amount".to_string()));
+        assert!(problems.contains(&"missing lines[1].tax_amounts[0].tax_rate".to_string()));
+        assert!(!problems.iter().any(|problem| problem.contains("lines[0]")));
+    }
+}
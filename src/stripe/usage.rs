@@ -0,0 +1,205 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Requests made through a `StripeClient` and the clients cloned from it,
+/// per endpoint, with an optional cap on how many may be made
+#[derive(Debug, Default)]
+pub struct ApiUsage {
+    max_requests: Option<usize>,
+    endpoints: Mutex<BTreeMap<String, EndpointUsage>>,
+    /// Set once a request is refused for going over `max_requests`
+    exhausted: AtomicBool,
+}
+
+/// Requests to one endpoint (method and path, IDs replaced by `{id}`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointUsage {
+    pub requests: usize,
+    /// Requests answered with an error status, or not answered at all
+    pub failed: usize,
+    /// Requests refused with 429 Too Many Requests; each is also a failure
+    pub rate_limited: usize,
+    /// Failed requests the client sent again, each retry also counting as a
+    /// request
+    pub retries: usize,
+    pub latency: Duration,
+}
+
+impl ApiUsage {
+    /// Track requests, refusing any beyond `max_requests` (`--max-requests`)
+    pub fn new(max_requests: Option<usize>) -> Self {
+        ApiUsage { max_requests, ..Default::default() }
+    }
+
+    /// Count a request about to be sent, or fail if the budget is spent
+    pub fn start(&self, endpoint: &str) -> Result<()> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if let Some(max) = self.max_requests
+            && endpoints.values().map(|usage| usage.requests).sum::<usize>() >= max
+        {
+            self.exhausted.store(true, Ordering::Relaxed);
+            bail!("Stopped before {}: the budget of {} Stripe API requests is spent (--max-requests)", endpoint, max);
+        }
+        endpoints.entry(endpoint.to_string()).or_default().requests += 1;
+        Ok(())
+    }
+
+    /// Record how a request started with `start` went: its status, or `None`
+    /// if no response came back
+    pub fn finish(&self, endpoint: &str, status: Option<reqwest::StatusCode>, latency: Duration) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let usage = endpoints.entry(endpoint.to_string()).or_default();
+        usage.latency += latency;
+        if !status.is_some_and(|status| status.is_success()) {
+            usage.failed += 1;
+        }
+        if status == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+            usage.rate_limited += 1;
+        }
+    }
+
+    /// Count a request started with `start` as a retry of a failed one
+    pub fn retry(&self, endpoint: &str) {
+        self.endpoints.lock().unwrap().entry(endpoint.to_string()).or_default().retries += 1;
+    }
+
+    /// Whether a request has been refused for going over the budget
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    /// Fail if a request has been refused for going over the budget, so a
+    /// run that carries on past failed requests can still be stopped
+    pub fn check(&self) -> Result<()> {
+        if self.exhausted() {
+            bail!(
+                "Stopped: the budget of {} Stripe API requests is spent (--max-requests)",
+                self.max_requests.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// Usage per endpoint, by endpoint
+    pub fn endpoints(&self) -> BTreeMap<String, EndpointUsage> {
+        self.endpoints.lock().unwrap().clone()
+    }
+
+    /// Usage over all endpoints
+    pub fn total(&self) -> EndpointUsage {
+        self.endpoints.lock().unwrap().values().fold(EndpointUsage::default(), |total, usage| EndpointUsage {
+            requests: total.requests + usage.requests,
+            failed: total.failed + usage.failed,
+            rate_limited: total.rate_limited + usage.rate_limited,
+            retries: total.retries + usage.retries,
+            latency: total.latency + usage.latency,
+        })
+    }
+
+    /// Usage over all endpoints in a line, e.g. `120 requests in 14.2s (3
+    /// failed, 2 rate limited, 3 retried)`, and the budget once it's spent
+    pub fn brief(&self) -> String {
+        let total = self.total();
+        let mut line = format!(
+            "{} requests in {:.1}s ({} failed, {} rate limited, {} retried)",
+            total.requests,
+            total.latency.as_secs_f64(),
+            total.failed,
+            total.rate_limited,
+            total.retries
+        );
+        if self.exhausted() {
+            line.push_str(&format!("; stopped at the --max-requests budget of {}", self.max_requests.unwrap_or_default()));
+        }
+        line
+    }
+
+    /// A table of requests per endpoint, busiest first, and the total
+    pub fn summary(&self) -> String {
+        let mut endpoints: Vec<(String, EndpointUsage)> = self.endpoints().into_iter().collect();
+        endpoints.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then_with(|| a.0.cmp(&b.0)));
+        let width = endpoints.iter().map(|(endpoint, _)| endpoint.len()).max().unwrap_or(0).max("Endpoint".len());
+
+        let mut out = format!(
+            "{:<width$}  {:>8}  {:>6}  {:>12}  {:>7}  {:>9}  {:>8}\n",
+            "Endpoint", "Requests", "Failed", "Rate limited", "Retries", "Total (s)", "Avg (ms)"
+        );
+        let mut row = |name: &str, usage: &EndpointUsage| {
+            let average = usage.latency.as_millis() / (usage.requests.max(1) as u128);
+            out.push_str(&format!(
+                "{:<width$}  {:>8}  {:>6}  {:>12}  {:>7}  {:>9.1}  {:>8}\n",
+                name,
+                usage.requests,
+                usage.failed,
+                usage.rate_limited,
+                usage.retries,
+                usage.latency.as_secs_f64(),
+                average
+            ));
+        };
+        for (endpoint, usage) in &endpoints {
+            row(endpoint, usage);
+        }
+        row("Total", &self.total());
+        out
+    }
+}
+
+/// The endpoint a request goes to, e.g. `GET /v1/customers/{id}`, so
+/// requests for different objects are counted together
+pub fn endpoint(method: &reqwest::Method, url: &reqwest::Url) -> String {
+    let path: Vec<&str> = url
+        .path()
+        .split('/')
+        .map(|segment| if is_object_id(segment) { "{id}" } else { segment })
+        .collect();
+    format!("{} {}", method, path.join("/"))
+}
+
+/// Stripe object IDs are a prefix, an underscore and a random suffix with
+/// digits or capitals (`in_1NvQ...`), unlike resource names (`balance_transactions`)
+fn is_object_id(segment: &str) -> bool {
+    segment.split_once('_').is_some_and(|(_, suffix)| {
+        suffix.chars().any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_by_endpoint_and_budget() {
+        let url = |path: &str| reqwest::Url::parse(&format!("https://api.stripe.com{}", path)).unwrap();
+        assert_eq!(endpoint(&reqwest::Method::GET, &url("/v1/customers/cus_Nv0FGQ9")), "GET /v1/customers/{id}");
+        assert_eq!(
+            endpoint(&reqwest::Method::GET, &url("/v1/balance_transactions/txn_1AB?expand[]=x")),
+            "GET /v1/balance_transactions/{id}"
+        );
+
+        let usage = ApiUsage::new(Some(2));
+        let customers = "GET /v1/customers/{id}";
+        usage.start(customers).unwrap();
+        usage.finish(customers, Some(reqwest::StatusCode::OK), Duration::from_millis(120));
+        usage.start(customers).unwrap();
+        usage.retry(customers);
+        usage.finish(customers, Some(reqwest::StatusCode::TOO_MANY_REQUESTS), Duration::from_millis(30));
+        assert!(usage.check().is_ok());
+        assert!(!usage.brief().contains("--max-requests"));
+        // The retry is refused like any other request over the budget
+        assert!(usage.start(customers).is_err());
+        assert!(usage.check().is_err());
+
+        let total = usage.total();
+        assert_eq!((total.requests, total.failed, total.rate_limited, total.retries), (2, 1, 1, 1));
+        assert_eq!(total.latency, Duration::from_millis(150));
+        assert!(usage.summary().contains("GET /v1/customers/{id}         2       1             1        1        0.1        75"));
+        assert_eq!(
+            usage.brief(),
+            "2 requests in 0.1s (1 failed, 1 rate limited, 1 retried); stopped at the --max-requests budget of 2"
+        );
+    }
+}